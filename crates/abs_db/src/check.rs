@@ -0,0 +1,915 @@
+use std::collections::{HashMap, HashSet};
+
+use abs_syntax::{ast, parse::Span};
+
+use crate::{
+    item_tree::{ClassItem, ItemTree, Ty, Var},
+    resolve::{self, Def, Target},
+    Diagnostic,
+};
+
+/// What checking a unit found
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Check {
+    pub diags: Vec<Diagnostic>,
+    /// The variables and parameters declared in the unit
+    pub defs: Vec<Def>,
+    pub refs: Vec<(Span, Target)>,
+}
+
+/// Name resolution and type checking of the bodies in one unit
+pub struct Checker<'a> {
+    items: &'a ItemTree,
+    class: Option<&'a ClassItem>,
+    ret: Option<Ty>,
+    /// The type of every variable in scope and where it is declared
+    scopes: Vec<HashMap<String, (Ty, Option<Span>)>>,
+    /// Where to report problems of code without any names in it
+    fallback: Option<Span>,
+    check: Check,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(items: &'a ItemTree, class: Option<&'a ClassItem>) -> Self {
+        Checker {
+            items,
+            class,
+            ret: None,
+            scopes: vec![HashMap::new()],
+            fallback: None,
+            check: Check::default(),
+        }
+    }
+
+    pub fn method(mut self, m: &ast::MethodDecl) -> Check {
+        self.fallback = m.sig.ident.span;
+        self.ret = Some(Ty::from_ast(&m.sig.ret));
+        let defined = self.class.map_or(0, |c| {
+            c.methods
+                .iter()
+                .filter(|s| s.name == m.sig.ident.str)
+                .count()
+        });
+        if defined > 1 {
+            self.error(
+                m.sig.ident.span,
+                format!("Method `{}` is defined more than once", m.sig.ident.str),
+            );
+        }
+
+        self.ty(&m.sig.ret);
+        self.scoped(|c| {
+            c.params(&m.sig.params);
+            c.block(&m.body);
+        });
+        self.check
+    }
+
+    /// The parts of a class outside of its methods
+    pub fn class(mut self, decl: &ast::ClassDecl) -> Check {
+        self.fallback = decl.ident.span;
+        if self.items.count_types(&decl.ident.str) > 1 {
+            self.error(
+                decl.ident.span,
+                format!("`{}` is defined more than once", decl.ident.str),
+            );
+        }
+        for i in &decl.implements {
+            self.type_ident(i);
+        }
+
+        let mut fields = HashSet::new();
+        for p in &decl.params {
+            self.ty(&p.ty);
+            self.field_decl(&p.ident, &mut fields);
+        }
+        for f in &decl.fields {
+            self.ty(&f.ty);
+            if let Some(init) = &f.init {
+                let ty = self.pure_expr(init);
+                self.expect(&Ty::from_ast(&f.ty), &ty, Some(init));
+            }
+            self.field_decl(&f.ident, &mut fields);
+        }
+        if let Some(init) = &decl.init {
+            self.block(init);
+        }
+        for b in &decl.recover {
+            self.scoped(|c| {
+                c.pattern(&b.pattern, &Ty::Unknown);
+                c.stmt(&b.right);
+            });
+        }
+        self.check
+    }
+
+    fn field_decl(&mut self, ident: &ast::Ident, fields: &mut HashSet<String>) {
+        if !fields.insert(ident.str.clone()) {
+            self.error(
+                ident.span,
+                format!("Field `{}` is defined more than once", ident.str),
+            );
+        }
+    }
+
+    /// The parts of a module outside of its classes
+    pub fn module(mut self, m: &ast::Module) -> Check {
+        self.duplicates(m);
+
+        for item in &m.children {
+            match item {
+                ast::ModuleItem::FunctionDecl(f) => {
+                    self.fallback = f.ident.span;
+                    self.ty(&f.ret);
+                    self.scoped(|c| {
+                        c.params(&f.params);
+                        let body = match &f.body {
+                            ast::FunctionBody::Expr(e) => e,
+                            ast::FunctionBody::Builtin(_) => return,
+                        };
+                        let ty = c.pure_expr(body);
+                        if f.type_params.is_empty() {
+                            c.expect(&Ty::from_ast(&f.ret), &ty, Some(body));
+                        }
+                    });
+                }
+                ast::ModuleItem::DataTypeDecl(d) => {
+                    for p in d.constr.iter().flat_map(|c| &c.params) {
+                        self.ty(&p.ty);
+                    }
+                }
+                ast::ModuleItem::TypeSynDecl(t) => self.ty(&t.ty),
+                ast::ModuleItem::ExceptionDecl(e) => {
+                    for p in &e.params {
+                        self.ty(&p.ty);
+                    }
+                }
+                ast::ModuleItem::InterfaceDecl(i) => {
+                    for e in &i.extends {
+                        self.type_ident(e);
+                    }
+                    let mut sigs = HashSet::new();
+                    for sig in &i.sigs {
+                        if !sigs.insert(&sig.ident.str) {
+                            self.error(
+                                sig.ident.span,
+                                format!("Method `{}` is defined more than once", sig.ident.str),
+                            );
+                        }
+                        self.ty(&sig.ret);
+                        self.scoped(|c| c.params(&sig.params));
+                    }
+                }
+                ast::ModuleItem::MainBlock(b) => {
+                    self.fallback = m.name.span;
+                    self.block(b);
+                }
+                ast::ModuleItem::ClassDecl(_)
+                | ast::ModuleItem::Import(_)
+                | ast::ModuleItem::Export(_) => {}
+            }
+        }
+        self.check
+    }
+
+    /// Reports names declared more than once in the module. Classes report
+    /// this themselves, as their names are not part of the module's unit.
+    fn duplicates(&mut self, m: &ast::Module) {
+        let mut types = HashSet::new();
+        let mut constrs = HashSet::new();
+        let mut functions = HashSet::new();
+
+        for item in &m.children {
+            let (ident, seen) = match item {
+                ast::ModuleItem::ClassDecl(c) => {
+                    types.insert(c.ident.str.clone());
+                    continue;
+                }
+                ast::ModuleItem::InterfaceDecl(i) => (&i.ident, &mut types),
+                ast::ModuleItem::DataTypeDecl(d) => {
+                    for c in &d.constr {
+                        if !constrs.insert(c.ident.str.clone()) {
+                            self.error(
+                                c.ident.span,
+                                format!("`{}` is defined more than once", c.ident.str),
+                            );
+                        }
+                    }
+                    (&d.ident, &mut types)
+                }
+                ast::ModuleItem::TypeSynDecl(t) => (&t.ident, &mut types),
+                ast::ModuleItem::ExceptionDecl(e) => (&e.ident, &mut constrs),
+                ast::ModuleItem::FunctionDecl(f) => (&f.ident, &mut functions),
+                ast::ModuleItem::Import(_)
+                | ast::ModuleItem::Export(_)
+                | ast::ModuleItem::MainBlock(_) => continue,
+            };
+            if !seen.insert(ident.str.clone()) {
+                self.error(
+                    ident.span,
+                    format!("`{}` is defined more than once", ident.str),
+                );
+            }
+        }
+    }
+
+    fn error(&mut self, span: Option<Span>, msg: String) {
+        if let Some(span) = span.or(self.fallback) {
+            self.check.diags.push(Diagnostic { span, msg })
+        }
+    }
+
+    fn refer(&mut self, ident: &ast::Ident, target: Target) {
+        if let Some(span) = ident.span {
+            self.check.refs.push((span, target))
+        }
+    }
+
+    /// Refers to the types declared in the module that `ty` is made of
+    fn ty(&mut self, ty: &ast::Type) {
+        self.type_ident(&ty.ident);
+        for a in &ty.args {
+            self.ty(a);
+        }
+    }
+
+    fn type_ident(&mut self, ident: &ast::Ident) {
+        if self.items.declares_type(&ident.str) {
+            let name = self.items.local(&ident.str).to_string();
+            self.refer(ident, Target::Type(name));
+        }
+    }
+
+    fn refer_function(&mut self, ident: &ast::Ident) {
+        if self.items.function(&ident.str).is_some() {
+            let name = self.items.local(&ident.str).to_string();
+            self.refer(ident, Target::Function(name));
+        }
+    }
+
+    fn scoped<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// Declares a variable in the innermost scope
+    fn bind(&mut self, ident: &ast::Ident, ty: Ty, def: Option<Def>) {
+        let scope = self.scopes.last_mut().unwrap();
+        let dup = scope.insert(ident.str.clone(), (ty, ident.span)).is_some();
+        if dup {
+            self.error(
+                ident.span,
+                format!("`{}` is defined more than once", ident.str),
+            );
+        }
+        self.check.defs.extend(def);
+    }
+
+    fn params(&mut self, params: &[ast::Param]) {
+        for p in params {
+            self.ty(&p.ty);
+            self.bind(&p.ident, Ty::from_ast(&p.ty), resolve::param_def(p));
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<(Ty, Option<Span>)> {
+        self.scopes.iter().rev().find_map(|s| s.get(name)).cloned()
+    }
+
+    /// Refers to a variable declared in the unit or a field
+    fn refer_var(&mut self, ident: &ast::Ident, local: Option<Span>) {
+        let target = match (local, self.class) {
+            (Some(span), _) => Target::Local(span),
+            (None, Some(c)) => Target::Field(c.name.clone(), ident.str.clone()),
+            (None, None) => return,
+        };
+        self.refer(ident, target);
+    }
+
+    fn field(&self, name: &str) -> Option<Ty> {
+        let c = self.class?;
+        c.params
+            .iter()
+            .chain(&c.fields)
+            .find(|v: &&Var| v.name == name)
+            .map(|v| v.ty.clone())
+    }
+
+    fn var(&mut self, ident: &ast::Ident) -> Ty {
+        if let Some((ty, span)) = self.local(&ident.str) {
+            if let Some(span) = span {
+                self.refer_var(ident, Some(span));
+            }
+            return ty;
+        }
+        match self.field(&ident.str) {
+            Some(ty) => {
+                self.refer_var(ident, None);
+                ty
+            }
+            None => {
+                self.error(ident.span, format!("Unknown variable `{}`", ident.str));
+                Ty::Unknown
+            }
+        }
+    }
+
+    fn this_field(&mut self, ident: &ast::Ident) -> Ty {
+        if self.class.is_none() {
+            self.error(
+                ident.span,
+                "`this` is only available in classes".to_string(),
+            );
+            return Ty::Unknown;
+        }
+        match self.field(&ident.str) {
+            Some(ty) => {
+                self.refer_var(ident, None);
+                ty
+            }
+            None => {
+                self.error(ident.span, format!("Unknown field `{}`", ident.str));
+                Ty::Unknown
+            }
+        }
+    }
+
+    /// Whether a value of type `actual` may be used where `expected` is
+    /// wanted. Types that are not known to the module fit everywhere.
+    fn fits(&self, expected: &Ty, actual: &Ty) -> bool {
+        let expected = self.items.expand(expected);
+        let actual = self.items.expand(actual);
+
+        let (en, ea, an, aa) = match (&expected, &actual) {
+            (Ty::Unknown, _) | (_, Ty::Unknown) => return true,
+            (e, Ty::Null) => return !e.is_primitive(),
+            (Ty::Null, _) => return false,
+            (Ty::Named { name: en, args: ea }, Ty::Named { name: an, args: aa }) => {
+                (en, ea, an, aa)
+            }
+        };
+
+        if !self.is_known(en) || !self.is_known(an) {
+            return true;
+        }
+        if en == an || self.items.is_subtype(an, en) {
+            return ea.is_empty()
+                || aa.is_empty()
+                || ea.len() == aa.len() && ea.iter().zip(aa).all(|(e, a)| self.fits(e, a));
+        }
+        en == "Rat" && an == "Int"
+    }
+
+    fn is_known(&self, name: &str) -> bool {
+        let builtin = ["Int", "Rat", "Float", "Bool", "String", "Unit", "Fut"];
+        builtin.contains(&name) || self.items.declares_type(name)
+    }
+
+    fn expect(&mut self, expected: &Ty, actual: &Ty, at: Option<&ast::PureExpr>) {
+        if !self.fits(expected, actual) {
+            let span = at.and_then(expr_span);
+            self.error(span, format!("Expected `{}`, found `{}`", expected, actual));
+        }
+    }
+
+    fn expect_bool(&mut self, e: &ast::PureExpr) {
+        let ty = self.pure_expr(e);
+        self.expect(&Ty::named("Bool"), &ty, Some(e));
+    }
+
+    fn block(&mut self, b: &ast::Block) {
+        self.scoped(|c| b.stmts.iter().for_each(|s| c.stmt(s)));
+    }
+
+    fn stmt(&mut self, s: &ast::Stmt) {
+        match s {
+            ast::Stmt::Skip | ast::Stmt::Suspend => {}
+            ast::Stmt::VarDecl(v) => {
+                self.ty(&v.ty);
+                let ty = Ty::from_ast(&v.ty);
+                if let Some(init) = &v.init {
+                    let actual = self.expr(init);
+                    self.expect_expr(&ty, &actual, init, v.ident.span);
+                }
+                self.bind(&v.ident, ty, resolve::local_def(Some(&v.ty), &v.ident));
+            }
+            ast::Stmt::Assign(a) => {
+                let actual = self.expr(&a.expr);
+                let target = if a.this {
+                    self.this_field(&a.ident)
+                } else {
+                    self.var(&a.ident)
+                };
+                self.expect_expr(&target, &actual, &a.expr, a.ident.span);
+            }
+            ast::Stmt::Expr(e) => {
+                self.expr(&e.expr);
+            }
+            ast::Stmt::Assert(a) => self.expect_bool(&a.condition),
+            ast::Stmt::Await(a) => self.guard(&a.guard),
+            ast::Stmt::Throw(t) => {
+                self.pure_expr(&t.expr);
+            }
+            ast::Stmt::Return(r) => {
+                let actual = self.expr(&r.expr);
+                if let Some(ret) = self.ret.clone() {
+                    self.expect_expr(&ret, &actual, &r.expr, None);
+                }
+            }
+            ast::Stmt::Block(b) => self.block(b),
+            ast::Stmt::If(i) => {
+                self.expect_bool(&i.condition);
+                self.scoped(|c| c.stmt(&i.then));
+                if let Some(e) = &i.r#else {
+                    self.scoped(|c| c.stmt(e));
+                }
+            }
+            ast::Stmt::Switch(s) => {
+                let ty = self.pure_expr(&s.expr);
+                for b in &s.branches {
+                    self.scoped(|c| {
+                        c.pattern(&b.pattern, &ty);
+                        c.stmt(&b.right);
+                    });
+                }
+            }
+            ast::Stmt::While(w) => {
+                self.expect_bool(&w.condition);
+                self.scoped(|c| c.stmt(&w.body));
+            }
+            ast::Stmt::Foreach(f) => {
+                let ty = match self.pure_expr(&f.iter) {
+                    Ty::Named { name, args } if name == "List" && args.len() == 1 => {
+                        args[0].clone()
+                    }
+                    _ => Ty::Unknown,
+                };
+                self.scoped(|c| {
+                    c.bind(&f.loop_var, ty, resolve::local_def(None, &f.loop_var));
+                    c.stmt(&f.body);
+                });
+            }
+            ast::Stmt::TryCatchFinally(t) => {
+                self.scoped(|c| c.stmt(&t.r#try));
+                for b in &t.catch_branches {
+                    self.scoped(|c| {
+                        c.pattern(&b.pattern, &Ty::Unknown);
+                        c.stmt(&b.right);
+                    });
+                }
+                if let Some(f) = &t.finally {
+                    self.scoped(|c| c.stmt(f));
+                }
+            }
+        }
+    }
+
+    fn expect_expr(&mut self, expected: &Ty, actual: &Ty, e: &ast::Expr, fallback: Option<Span>) {
+        match e {
+            ast::Expr::Pure(p) if expr_span(p).is_some() => self.expect(expected, actual, Some(p)),
+            _ if !self.fits(expected, actual) => self.error(
+                fallback,
+                format!("Expected `{}`, found `{}`", expected, actual),
+            ),
+            _ => {}
+        }
+    }
+
+    fn guard(&mut self, g: &ast::Guard) {
+        match g {
+            ast::Guard::Claim { this, ident } => {
+                let ty = if *this {
+                    self.this_field(ident)
+                } else {
+                    self.var(ident)
+                };
+                if !self.fits(&Ty::named("Fut"), &ty) {
+                    self.error(ident.span, format!("Expected a future, found `{}`", ty));
+                }
+            }
+            ast::Guard::Expr(e) => self.expect_bool(e),
+            ast::Guard::And(l, r) => {
+                self.guard(l);
+                self.guard(r);
+            }
+            ast::Guard::Duration(min, max) => {
+                self.pure_expr(min);
+                self.pure_expr(max);
+            }
+        }
+    }
+
+    fn pattern(&mut self, p: &ast::Pattern, ty: &Ty) {
+        match p {
+            ast::Pattern::Wildcard | ast::Pattern::Literal(_) => {}
+            // A bound variable is compared against, a new one is bound
+            ast::Pattern::Ident(i) => {
+                if let Some((_, span)) = self.local(&i.str) {
+                    if span.is_some() {
+                        self.refer_var(i, span);
+                    }
+                } else if self.field(&i.str).is_some() {
+                    self.refer_var(i, None);
+                } else {
+                    self.bind(i, ty.clone(), resolve::local_def(None, i));
+                }
+            }
+            ast::Pattern::Constr { ident, args } => {
+                self.constr(ident);
+                let arg_tys = self.items.constr(&ident.str).map(|(_, a)| a.to_vec());
+                for (i, a) in args.iter().enumerate() {
+                    let ty = arg_tys
+                        .as_ref()
+                        .and_then(|tys| tys.get(i))
+                        .cloned()
+                        .unwrap_or(Ty::Unknown);
+                    self.pattern(a, &ty);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &ast::Expr) -> Ty {
+        match e {
+            ast::Expr::Pure(e) => self.pure_expr(e),
+            ast::Expr::Eff(e) => self.eff_expr(e),
+        }
+    }
+
+    fn args(&mut self, name: &ast::Ident, params: Option<Vec<Ty>>, args: &[ast::PureExpr]) {
+        let tys: Vec<_> = args.iter().map(|a| self.pure_expr(a)).collect();
+        let params = match params {
+            Some(p) => p,
+            None => return,
+        };
+        if params.len() != args.len() {
+            self.error(
+                name.span,
+                format!(
+                    "`{}` takes {} arguments, but {} were given",
+                    name.str,
+                    params.len(),
+                    args.len()
+                ),
+            );
+            return;
+        }
+        for ((p, ty), a) in params.iter().zip(&tys).zip(args) {
+            self.expect(p, ty, Some(a));
+        }
+    }
+
+    fn eff_expr(&mut self, e: &ast::EffExpr) -> Ty {
+        match e {
+            ast::EffExpr::New(n) => {
+                self.type_ident(&n.ty);
+                let params = self
+                    .items
+                    .class(&n.ty.str)
+                    .map(|c| c.params.iter().map(|p| p.ty.clone()).collect());
+                self.args(&n.ty, params, &n.args);
+                Ty::named(n.ty.str.clone())
+            }
+            ast::EffExpr::SyncCall(c) => self.call(&c.callee, &c.method, &c.args),
+            ast::EffExpr::AsyncCall(c) => {
+                let ret = self.call(&c.callee, &c.method, &c.args);
+                Ty::generic("Fut", vec![ret])
+            }
+            ast::EffExpr::Get(g) => match self.pure_expr(&g.expr) {
+                Ty::Named { name, args } if name == "Fut" => {
+                    args.into_iter().next().unwrap_or(Ty::Unknown)
+                }
+                Ty::Unknown => Ty::Unknown,
+                ty => {
+                    self.error(
+                        expr_span(&g.expr),
+                        format!("Expected a future, found `{}`", ty),
+                    );
+                    Ty::Unknown
+                }
+            },
+            ast::EffExpr::Await(a) => self.call(&a.call.callee, &a.call.method, &a.call.args),
+        }
+    }
+
+    fn call(&mut self, callee: &ast::PureExpr, method: &ast::Ident, args: &[ast::PureExpr]) -> Ty {
+        let receiver = self.pure_expr(callee);
+        let name = match receiver.name() {
+            Some(n) if self.items.class(n).is_some() || self.items.interface(n).is_some() => {
+                n.to_string()
+            }
+            _ => {
+                self.args(method, None, args);
+                return Ty::Unknown;
+            }
+        };
+
+        match self.items.find_method(&name, &method.str) {
+            Some((owner, sig)) => {
+                let target = Target::Method(owner.to_string(), sig.name.clone());
+                self.refer(method, target);
+                let ret = sig.ret.clone();
+                let params = sig.params.iter().map(|p| p.ty.clone()).collect();
+                self.args(method, Some(params), args);
+                ret
+            }
+            None => {
+                self.error(
+                    method.span,
+                    format!("`{}` has no method `{}`", name, method.str),
+                );
+                self.args(method, None, args);
+                Ty::Unknown
+            }
+        }
+    }
+
+    fn pure_expr(&mut self, e: &ast::PureExpr) -> Ty {
+        match e {
+            ast::PureExpr::Ident(i) => self.var(&i.ident),
+            ast::PureExpr::ThisIdent(i) => self.this_field(&i.ident),
+            ast::PureExpr::This => match self.class {
+                Some(c) => Ty::named(c.name.clone()),
+                None => Ty::Unknown,
+            },
+            ast::PureExpr::Null => Ty::Null,
+            ast::PureExpr::Literal(l) => {
+                if l.s.starts_with('"') {
+                    Ty::named("String")
+                } else if l.s.contains('.') {
+                    Ty::named("Float")
+                } else {
+                    Ty::named("Int")
+                }
+            }
+            ast::PureExpr::TemplateString(t) => {
+                for e in &t.exprs {
+                    self.pure_expr(e);
+                }
+                Ty::named("String")
+            }
+            ast::PureExpr::Destiny => Ty::Unknown,
+            ast::PureExpr::ParFnApp(p) => {
+                self.refer_function(&p.ident);
+                for func in &p.funcs {
+                    match func {
+                        ast::FnArg::Named(i) => self.refer_function(i),
+                        ast::FnArg::Anon(a) => self.scoped(|c| {
+                            c.params(&a.params);
+                            c.pure_expr(&a.body);
+                        }),
+                    }
+                }
+                for a in &p.args {
+                    self.pure_expr(a);
+                }
+                Ty::Unknown
+            }
+            ast::PureExpr::Let(l) => {
+                self.ty(&l.ty);
+                let ty = Ty::from_ast(&l.ty);
+                let actual = self.pure_expr(&l.value);
+                self.expect(&ty, &actual, Some(&l.value));
+                let mut inner = Ty::Unknown;
+                self.scoped(|c| {
+                    c.bind(&l.ident, ty, resolve::local_def(Some(&l.ty), &l.ident));
+                    inner = c.pure_expr(&l.inner);
+                });
+                inner
+            }
+            ast::PureExpr::DataConstr(d) => {
+                self.constr(&d.ident);
+                if d.ident.str == "True" || d.ident.str == "False" {
+                    return Ty::named("Bool");
+                }
+                if let Some((data, params)) = self.items.constr(&d.ident.str) {
+                    let name = data.name.clone();
+                    // Arguments of generic data types are not checked
+                    let params = if data.params.is_empty() {
+                        Some(params.to_vec())
+                    } else {
+                        None
+                    };
+                    self.args(&d.ident, params, &d.args);
+                    return Ty::named(name);
+                }
+                let params = self.items.exception(&d.ident.str).map(|p| p.to_vec());
+                let is_exception = params.is_some();
+                self.args(&d.ident, params, &d.args);
+                if is_exception {
+                    Ty::named("Exception")
+                } else {
+                    Ty::Unknown
+                }
+            }
+            ast::PureExpr::FnApp(f) | ast::PureExpr::VariadicFnApp(f) => {
+                self.refer_function(&f.ident);
+                let func = self
+                    .items
+                    .function(&f.ident.str)
+                    .filter(|func| func.type_params.is_empty())
+                    .map(|func| {
+                        let params = func.params.iter().map(|p| p.ty.clone()).collect();
+                        (params, func.ret.clone())
+                    });
+                match (func, e) {
+                    (Some((params, ret)), ast::PureExpr::FnApp(_)) => {
+                        self.args(&f.ident, Some(params), &f.args);
+                        ret
+                    }
+                    _ => {
+                        self.args(&f.ident, None, &f.args);
+                        Ty::Unknown
+                    }
+                }
+            }
+            ast::PureExpr::If(ast::IfExpr {
+                condition,
+                then,
+                r#else,
+            })
+            | ast::PureExpr::When(ast::WhenExpr {
+                condition,
+                then,
+                r#else,
+            }) => {
+                self.expect_bool(condition);
+                let t = self.pure_expr(then);
+                let e = self.pure_expr(r#else);
+                if t == e {
+                    t
+                } else {
+                    Ty::Unknown
+                }
+            }
+            ast::PureExpr::Case(c) => {
+                let ty = self.pure_expr(&c.expr);
+                let mut res = None;
+                for b in &c.branches {
+                    self.scoped(|c| {
+                        c.pattern(&b.pattern, &ty);
+                        let t = c.pure_expr(&b.right);
+                        res = match res.take() {
+                            None => Some(t),
+                            Some(r) if r == t => Some(r),
+                            Some(_) => Some(Ty::Unknown),
+                        };
+                    });
+                }
+                res.unwrap_or(Ty::Unknown)
+            }
+            ast::PureExpr::Operator(ast::OperatorExpr::Unary(u)) => match u.op {
+                ast::UnaryOp::Not => {
+                    self.expect_bool(&u.expr);
+                    Ty::named("Bool")
+                }
+                ast::UnaryOp::Minus => {
+                    let ty = self.pure_expr(&u.expr);
+                    self.expect_numeric(&ty, &u.expr);
+                    ty
+                }
+            },
+            ast::PureExpr::Operator(ast::OperatorExpr::Binary(b)) => self.binary(b),
+            ast::PureExpr::TypeCheck(t) => {
+                self.pure_expr(&t.expr);
+                self.type_ident(&t.ty);
+                Ty::named("Bool")
+            }
+            ast::PureExpr::TypeCast(t) => {
+                self.pure_expr(&t.expr);
+                self.type_ident(&t.ty);
+                Ty::named(t.ty.str.clone())
+            }
+        }
+    }
+
+    /// Refers to a data constructor or exception declared in the module
+    fn constr(&mut self, ident: &ast::Ident) {
+        if self.items.constr(&ident.str).is_some() || self.items.exception(&ident.str).is_some() {
+            let name = self.items.local(&ident.str).to_string();
+            self.refer(ident, Target::Constr(name));
+        }
+    }
+
+    fn expect_numeric(&mut self, ty: &Ty, e: &ast::PureExpr) {
+        let ty = self.items.expand(ty);
+        if ty != Ty::Unknown && !ty.is_numeric() && ty.name().is_some_and(|n| self.is_known(n)) {
+            self.error(expr_span(e), format!("Expected a number, found `{}`", ty));
+        }
+    }
+
+    fn binary(&mut self, b: &ast::BinaryExpr) -> Ty {
+        use ast::BinaryOp::*;
+
+        match b.op {
+            Or | And => {
+                self.expect_bool(&b.left);
+                self.expect_bool(&b.right);
+                return Ty::named("Bool");
+            }
+            Eq | Ne => {
+                self.pure_expr(&b.left);
+                self.pure_expr(&b.right);
+                return Ty::named("Bool");
+            }
+            _ => {}
+        }
+
+        let l = self.items.expand(&self.pure_expr(&b.left));
+        let r = self.items.expand(&self.pure_expr(&b.right));
+
+        if matches!(b.op, Lt | Le | Gt | Ge) {
+            return Ty::named("Bool");
+        }
+        if b.op == Plus && (l.is("String") || r.is("String")) {
+            return Ty::named("String");
+        }
+
+        self.expect_numeric(&l, &b.left);
+        self.expect_numeric(&r, &b.right);
+
+        if !l.is_numeric() || !r.is_numeric() {
+            return Ty::Unknown;
+        }
+        if l.is("Float") || r.is("Float") {
+            Ty::named("Float")
+        } else if b.op == Div || l.is("Rat") || r.is("Rat") {
+            Ty::named("Rat")
+        } else {
+            Ty::named("Int")
+        }
+    }
+}
+
+/// The names an expression starts and ends with, as a span covering most of
+/// the expression
+fn expr_span(e: &ast::PureExpr) -> Option<Span> {
+    let mut spans = Vec::new();
+    collect_spans(e, &mut spans);
+    let start = spans.iter().map(|s| s.start).min()?;
+    let end = spans.iter().map(|s| s.end).max()?;
+    Some(Span { start, end })
+}
+
+fn collect_spans(e: &ast::PureExpr, out: &mut Vec<Span>) {
+    match e {
+        ast::PureExpr::Ident(i) | ast::PureExpr::ThisIdent(i) => out.extend(i.ident.span),
+        ast::PureExpr::This
+        | ast::PureExpr::Null
+        | ast::PureExpr::Literal(_)
+        | ast::PureExpr::Destiny => {}
+        ast::PureExpr::ParFnApp(p) => {
+            out.extend(p.ident.span);
+            p.args.iter().for_each(|a| collect_spans(a, out));
+        }
+        ast::PureExpr::TemplateString(t) => t.exprs.iter().for_each(|e| collect_spans(e, out)),
+        ast::PureExpr::Let(l) => {
+            out.extend(l.ident.span);
+            collect_spans(&l.value, out);
+            collect_spans(&l.inner, out);
+        }
+        ast::PureExpr::DataConstr(d) => {
+            out.extend(d.ident.span);
+            d.args.iter().for_each(|a| collect_spans(a, out));
+        }
+        ast::PureExpr::FnApp(f) | ast::PureExpr::VariadicFnApp(f) => {
+            out.extend(f.ident.span);
+            f.args.iter().for_each(|a| collect_spans(a, out));
+        }
+        ast::PureExpr::If(ast::IfExpr {
+            condition,
+            then,
+            r#else,
+        })
+        | ast::PureExpr::When(ast::WhenExpr {
+            condition,
+            then,
+            r#else,
+        }) => {
+            collect_spans(condition, out);
+            collect_spans(then, out);
+            collect_spans(r#else, out);
+        }
+        ast::PureExpr::Case(c) => {
+            collect_spans(&c.expr, out);
+            c.branches.iter().for_each(|b| collect_spans(&b.right, out));
+        }
+        ast::PureExpr::Operator(ast::OperatorExpr::Unary(u)) => collect_spans(&u.expr, out),
+        ast::PureExpr::Operator(ast::OperatorExpr::Binary(b)) => {
+            collect_spans(&b.left, out);
+            collect_spans(&b.right, out);
+        }
+        ast::PureExpr::TypeCheck(t) => {
+            collect_spans(&t.expr, out);
+            out.extend(t.ty.span);
+        }
+        ast::PureExpr::TypeCast(t) => {
+            collect_spans(&t.expr, out);
+            out.extend(t.ty.span);
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+
+use abs_syntax::ast;
+
+/// A type as far as the checker knows it
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Ty {
+    /// Not known, e.g. because it comes from another module. Fits anywhere.
+    Unknown,
+    /// The type of `null`
+    Null,
+    Named {
+        name: String,
+        args: Vec<Ty>,
+    },
+}
+
+impl Ty {
+    pub fn named<S: Into<String>>(name: S) -> Self {
+        Ty::Named {
+            name: name.into(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn generic<S: Into<String>>(name: S, args: Vec<Ty>) -> Self {
+        Ty::Named {
+            name: name.into(),
+            args,
+        }
+    }
+
+    pub fn from_ast(ty: &ast::Type) -> Self {
+        Ty::generic(
+            ty.ident.str.clone(),
+            ty.args.iter().map(Ty::from_ast).collect(),
+        )
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Ty::Named { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn is(&self, n: &str) -> bool {
+        self.name() == Some(n)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is("Int") || self.is("Rat") || self.is("Float")
+    }
+
+    /// Types that have no `null` value
+    pub fn is_primitive(&self) -> bool {
+        self.is_numeric() || self.is("Bool") || self.is("String") || self.is("Unit")
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Unknown => write!(f, "?"),
+            Ty::Null => write!(f, "null"),
+            Ty::Named { name, args } if args.is_empty() => write!(f, "{}", name),
+            Ty::Named { name, args } => {
+                write!(f, "{}<", name)?;
+                for (i, a) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", a)?;
+                }
+                write!(f, ">")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Var {
+    pub ty: Ty,
+    pub name: String,
+}
+
+impl Var {
+    fn from_param(p: &ast::Param) -> Self {
+        Var {
+            ty: Ty::from_ast(&p.ty),
+            name: p.ident.str.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sig {
+    pub name: String,
+    pub ret: Ty,
+    pub params: Vec<Var>,
+}
+
+impl Sig {
+    fn new(sig: &ast::MethodSig) -> Self {
+        Sig {
+            name: sig.ident.str.clone(),
+            ret: Ty::from_ast(&sig.ret),
+            params: sig.params.iter().map(Var::from_param).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClassItem {
+    pub name: String,
+    pub params: Vec<Var>,
+    pub implements: Vec<String>,
+    pub fields: Vec<Var>,
+    pub methods: Vec<Sig>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InterfaceItem {
+    pub name: String,
+    pub extends: Vec<String>,
+    pub sigs: Vec<Sig>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FunctionItem {
+    pub name: String,
+    pub type_params: Vec<String>,
+    pub ret: Ty,
+    pub params: Vec<Var>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DataItem {
+    pub name: String,
+    pub params: Vec<String>,
+    /// Constructors with the types of their arguments
+    pub constrs: Vec<(String, Vec<Ty>)>,
+}
+
+/// The signatures of everything declared in a module, without any bodies.
+///
+/// Editing the inside of a method leaves the item tree unchanged, so
+/// whatever only depends on it does not have to be recomputed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ItemTree {
+    pub module: String,
+    pub classes: Vec<ClassItem>,
+    pub interfaces: Vec<InterfaceItem>,
+    pub functions: Vec<FunctionItem>,
+    pub data_types: Vec<DataItem>,
+    pub type_syns: Vec<(String, Ty)>,
+    pub exceptions: Vec<(String, Vec<Ty>)>,
+}
+
+impl ItemTree {
+    pub fn new(m: &ast::Module) -> Self {
+        let mut tree = ItemTree {
+            module: m.name.str.clone(),
+            ..ItemTree::default()
+        };
+
+        let idents = |is: &[ast::Ident]| is.iter().map(|i| i.str.clone()).collect();
+        let param_tys =
+            |ps: &[ast::DataConstrParam]| ps.iter().map(|p| Ty::from_ast(&p.ty)).collect();
+
+        for item in &m.children {
+            match item {
+                ast::ModuleItem::ClassDecl(c) => tree.classes.push(ClassItem {
+                    name: c.ident.str.clone(),
+                    params: c.params.iter().map(Var::from_param).collect(),
+                    implements: idents(&c.implements),
+                    fields: c
+                        .fields
+                        .iter()
+                        .map(|f| Var {
+                            ty: Ty::from_ast(&f.ty),
+                            name: f.ident.str.clone(),
+                        })
+                        .collect(),
+                    methods: c.methods.iter().map(|m| Sig::new(&m.sig)).collect(),
+                }),
+                ast::ModuleItem::InterfaceDecl(i) => tree.interfaces.push(InterfaceItem {
+                    name: i.ident.str.clone(),
+                    extends: idents(&i.extends),
+                    sigs: i.sigs.iter().map(Sig::new).collect(),
+                }),
+                ast::ModuleItem::FunctionDecl(f) => tree.functions.push(FunctionItem {
+                    name: f.ident.str.clone(),
+                    type_params: idents(&f.type_params),
+                    ret: Ty::from_ast(&f.ret),
+                    params: f.params.iter().map(Var::from_param).collect(),
+                }),
+                ast::ModuleItem::DataTypeDecl(d) => tree.data_types.push(DataItem {
+                    name: d.ident.str.clone(),
+                    params: idents(&d.params),
+                    constrs: d
+                        .constr
+                        .iter()
+                        .map(|c| (c.ident.str.clone(), param_tys(&c.params)))
+                        .collect(),
+                }),
+                ast::ModuleItem::TypeSynDecl(t) => tree
+                    .type_syns
+                    .push((t.ident.str.clone(), Ty::from_ast(&t.ty))),
+                ast::ModuleItem::ExceptionDecl(e) => tree
+                    .exceptions
+                    .push((e.ident.str.clone(), param_tys(&e.params))),
+                ast::ModuleItem::Import(_)
+                | ast::ModuleItem::Export(_)
+                | ast::ModuleItem::MainBlock(_) => {}
+            }
+        }
+
+        tree
+    }
+
+    /// Strips the name of this module from a qualified name
+    pub fn local<'a>(&self, name: &'a str) -> &'a str {
+        name.strip_prefix(&self.module)
+            .and_then(|n| n.strip_prefix('.'))
+            .unwrap_or(name)
+    }
+
+    pub fn class(&self, name: &str) -> Option<&ClassItem> {
+        let name = self.local(name);
+        self.classes.iter().find(|c| c.name == name)
+    }
+
+    pub fn interface(&self, name: &str) -> Option<&InterfaceItem> {
+        let name = self.local(name);
+        self.interfaces.iter().find(|i| i.name == name)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionItem> {
+        let name = self.local(name);
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    /// The data type a constructor belongs to, and the constructor's
+    /// argument types
+    pub fn constr(&self, name: &str) -> Option<(&DataItem, &[Ty])> {
+        let name = self.local(name);
+        self.data_types.iter().find_map(|d| {
+            d.constrs
+                .iter()
+                .find(|(c, _)| c == name)
+                .map(|(_, args)| (d, args.as_slice()))
+        })
+    }
+
+    pub fn exception(&self, name: &str) -> Option<&[Ty]> {
+        let name = self.local(name);
+        self.exceptions
+            .iter()
+            .find(|(e, _)| e == name)
+            .map(|(_, args)| args.as_slice())
+    }
+
+    /// Whether a type of this name is declared in the module
+    pub fn declares_type(&self, name: &str) -> bool {
+        let local = self.local(name);
+        self.class(name).is_some()
+            || self.interface(name).is_some()
+            || self.data_types.iter().any(|d| d.name == local)
+            || self.type_syns.iter().any(|(t, _)| t == local)
+    }
+
+    /// How many types of this name are declared in the module
+    pub fn count_types(&self, name: &str) -> usize {
+        let name = self.local(name);
+        self.classes.iter().filter(|c| c.name == name).count()
+            + self.interfaces.iter().filter(|i| i.name == name).count()
+            + self.data_types.iter().filter(|d| d.name == name).count()
+            + self.type_syns.iter().filter(|(t, _)| t == name).count()
+    }
+
+    /// Replaces type synonyms declared in this module by what they stand for
+    pub fn expand(&self, ty: &Ty) -> Ty {
+        let mut ty = ty.clone();
+        // Synonyms may refer to each other, but not in a cycle
+        for _ in 0..=self.type_syns.len() {
+            let name = match ty.name() {
+                Some(n) => self.local(n).to_string(),
+                None => return ty,
+            };
+            match self.type_syns.iter().find(|(t, _)| *t == name) {
+                Some((_, target)) => ty = target.clone(),
+                None => return ty,
+            }
+        }
+        ty
+    }
+
+    /// Looks for a method of a class or interface, including the interfaces
+    /// it implements or extends
+    pub fn method(&self, ty: &str, name: &str) -> Option<&Sig> {
+        self.find_method(ty, name).map(|(_, sig)| sig)
+    }
+
+    /// Like `method`, together with the name of the class or interface that
+    /// declares it
+    pub fn find_method(&self, ty: &str, name: &str) -> Option<(&str, &Sig)> {
+        self.method_rec(ty, name, &mut Vec::new())
+    }
+
+    fn method_rec<'a>(
+        &'a self,
+        ty: &str,
+        name: &str,
+        seen: &mut Vec<String>,
+    ) -> Option<(&'a str, &'a Sig)> {
+        if seen.iter().any(|s| s == ty) {
+            return None;
+        }
+        seen.push(ty.to_string());
+
+        let (owner, sigs, sup) = if let Some(c) = self.class(ty) {
+            (&c.name, &c.methods, &c.implements)
+        } else if let Some(i) = self.interface(ty) {
+            (&i.name, &i.sigs, &i.extends)
+        } else {
+            return None;
+        };
+
+        sigs.iter()
+            .find(|s| s.name == name)
+            .map(|s| (owner.as_str(), s))
+            .or_else(|| sup.iter().find_map(|s| self.method_rec(s, name, seen)))
+    }
+
+    /// Whether `sub` is `sup` or implements or extends it
+    pub fn is_subtype(&self, sub: &str, sup: &str) -> bool {
+        self.is_subtype_rec(sub, sup, &mut Vec::new())
+    }
+
+    fn is_subtype_rec(&self, sub: &str, sup: &str, seen: &mut Vec<String>) -> bool {
+        if self.local(sub) == self.local(sup) {
+            return true;
+        }
+        if seen.iter().any(|s| s == sub) {
+            return false;
+        }
+        seen.push(sub.to_string());
+
+        let direct = if let Some(c) = self.class(sub) {
+            &c.implements
+        } else if let Some(i) = self.interface(sub) {
+            &i.extends
+        } else {
+            return false;
+        };
+        direct.iter().any(|d| self.is_subtype_rec(d, sup, seen))
+    }
+}
@@ -0,0 +1,755 @@
+//! An incremental, query-based database for analysing ABS code.
+//!
+//! The text of every file is an input. Everything else is computed by
+//! queries, which remember their result together with the revision it last
+//! changed in. When an input changes, a query is only recomputed if one of
+//! the queries it depends on changed since it was last verified, and if a
+//! recomputed result equals the old one, it keeps its old revision, so the
+//! queries depending on it are not recomputed either.
+//!
+//! The queries, from the input downwards:
+//!
+//! - `parse`: the modules of a file and where their declarations are
+//! - `item_tree`: the signatures declared in a module
+//! - `method_source`, `class_source`, `module_source`: the text of a method,
+//!   of a class without its methods and of a module without its classes
+//! - `check_method`, `check_class`, `check_module`: name resolution and type
+//!   checking of the code in one of these sources, which also record the
+//!   variables declared in it and what every name refers to
+//!
+//! Editing the body of a method changes its source, but neither the item tree
+//! nor the sources of anything else, so only that method is checked again.
+
+use std::{collections::HashMap, hash::Hash, sync::Arc};
+
+use abs_syntax::{
+    ast,
+    parse::{self, ParseError, Span},
+};
+
+mod check;
+mod item_tree;
+mod resolve;
+mod source;
+
+pub use item_tree::{ClassItem, DataItem, FunctionItem, InterfaceItem, ItemTree, Sig, Ty, Var};
+pub use resolve::{Def, DefKind, Resolution, Target};
+pub use source::{Layout, Source};
+
+use check::{Check, Checker};
+use resolve::Globals;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct FileId(pub u32);
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ModuleId {
+    pub file: FileId,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ClassId {
+    pub module: ModuleId,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct MethodId {
+    pub class: ClassId,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub msg: String,
+}
+
+/// A query that was computed, as opposed to taken from its memo
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Event {
+    Parse(FileId),
+    ItemTree(ModuleId),
+    CheckModule(ModuleId),
+    CheckClass(ClassId),
+    CheckMethod(MethodId),
+}
+
+pub struct Parse {
+    pub modules: Vec<ast::Module>,
+    pub error: Option<ParseError>,
+    pub layout: Layout,
+}
+
+impl Parse {
+    pub fn module(&self, name: &str) -> Option<&ast::Module> {
+        self.modules.iter().find(|m| m.name.str == name)
+    }
+}
+
+fn class<'a>(m: &'a ast::Module, name: &str) -> Option<&'a ast::ClassDecl> {
+    m.children.iter().find_map(|i| match i {
+        ast::ModuleItem::ClassDecl(c) if c.ident.str == name => Some(c),
+        _ => None,
+    })
+}
+
+/// The source of a checked unit and its declaration
+pub struct Unit<T> {
+    pub source: Source,
+    pub decl: Option<T>,
+}
+
+impl<T> PartialEq for Unit<T> {
+    /// Units with the same text check the same, relative to their start
+    fn eq(&self, other: &Self) -> bool {
+        self.source.text == other.source.text
+    }
+}
+
+type Revision = u64;
+
+struct Memo<V> {
+    value: Arc<V>,
+    /// The last revision the value changed in
+    changed_at: Revision,
+    /// The last revision the value was known to be up to date in
+    verified_at: Revision,
+}
+
+type Table<K, V> = HashMap<K, Memo<V>>;
+
+/// Returns the memo of `key` if it is up to date
+fn fresh<K: Hash + Eq, V>(
+    table: &Table<K, V>,
+    key: &K,
+    rev: Revision,
+) -> Option<(Arc<V>, Revision)> {
+    table
+        .get(key)
+        .filter(|m| m.verified_at == rev)
+        .map(|m| (m.value.clone(), m.changed_at))
+}
+
+/// Reuses the memo of `key` if none of its dependencies changed since it was
+/// verified, otherwise recomputes it and keeps its old revision if the
+/// result did not change
+fn update<K, V, F>(
+    table: &mut Table<K, V>,
+    key: K,
+    rev: Revision,
+    deps_changed: Revision,
+    compute: F,
+) -> (Arc<V>, Revision, bool)
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    F: FnOnce() -> V,
+{
+    if let Some(m) = table.get_mut(&key) {
+        if deps_changed <= m.verified_at {
+            m.verified_at = rev;
+            return (m.value.clone(), m.changed_at, false);
+        }
+    }
+
+    let value = Arc::new(compute());
+    let changed_at = match table.get(&key) {
+        Some(old) if *old.value == *value => old.changed_at,
+        _ => rev,
+    };
+    table.insert(
+        key,
+        Memo {
+            value: value.clone(),
+            changed_at,
+            verified_at: rev,
+        },
+    );
+    (value, changed_at, true)
+}
+
+#[derive(Default)]
+pub struct Database {
+    revision: Revision,
+    files: HashMap<FileId, (Arc<str>, Revision)>,
+    parses: Table<FileId, Parse>,
+    item_trees: Table<ModuleId, ItemTree>,
+    module_sources: Table<ModuleId, Unit<ast::Module>>,
+    class_sources: Table<ClassId, Unit<ast::ClassDecl>>,
+    method_sources: Table<MethodId, Unit<ast::MethodDecl>>,
+    module_checks: Table<ModuleId, Check>,
+    class_checks: Table<ClassId, Check>,
+    method_checks: Table<MethodId, Check>,
+    events: Option<Vec<Event>>,
+}
+
+impl PartialEq for Parse {
+    /// A new parse is always treated as a change
+    fn eq(&self, _: &Self) -> bool {
+        false
+    }
+}
+
+impl Database {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records which queries are computed, see `take_events`
+    pub fn log_events(&mut self, on: bool) {
+        self.events = if on { Some(Vec::new()) } else { None };
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn event(&mut self, e: Event) {
+        if let Some(events) = &mut self.events {
+            events.push(e)
+        }
+    }
+
+    pub fn set_file_text(&mut self, file: FileId, text: String) {
+        self.revision += 1;
+        self.files.insert(file, (text.into(), self.revision));
+    }
+
+    pub fn remove_file(&mut self, file: FileId) {
+        self.revision += 1;
+        self.files.remove(&file);
+    }
+
+    pub fn file_text(&self, file: FileId) -> Arc<str> {
+        self.files
+            .get(&file)
+            .map(|(t, _)| t.clone())
+            .unwrap_or_else(|| "".into())
+    }
+
+    fn file_changed(&self, file: FileId) -> Revision {
+        // A removed file changes in the revision it is removed in
+        self.files.get(&file).map_or(self.revision, |(_, r)| *r)
+    }
+
+    pub fn parse(&mut self, file: FileId) -> Arc<Parse> {
+        self.parse_query(file).0
+    }
+
+    fn parse_query(&mut self, file: FileId) -> (Arc<Parse>, Revision) {
+        if let Some(res) = fresh(&self.parses, &file, self.revision) {
+            return res;
+        }
+
+        let text = self.file_text(file);
+        let file_changed = self.file_changed(file);
+        let (value, changed, computed) =
+            update(&mut self.parses, file, self.revision, file_changed, || {
+                let tokens = parse::tokenize(&text).unwrap_or_default();
+                match parse::parse_modules(&text) {
+                    Ok(modules) => Parse {
+                        layout: Layout::new(&text, &tokens, &modules),
+                        modules,
+                        error: None,
+                    },
+                    Err(e) => Parse {
+                        modules: Vec::new(),
+                        error: Some(e),
+                        layout: Layout::default(),
+                    },
+                }
+            });
+        if computed {
+            self.event(Event::Parse(file));
+        }
+        (value, changed)
+    }
+
+    pub fn item_tree(&mut self, module: &ModuleId) -> Arc<ItemTree> {
+        self.item_tree_query(module).0
+    }
+
+    fn item_tree_query(&mut self, module: &ModuleId) -> (Arc<ItemTree>, Revision) {
+        if let Some(res) = fresh(&self.item_trees, module, self.revision) {
+            return res;
+        }
+
+        let (parse, parse_changed) = self.parse_query(module.file);
+        let (value, changed, computed) = update(
+            &mut self.item_trees,
+            module.clone(),
+            self.revision,
+            parse_changed,
+            || {
+                parse
+                    .module(&module.name)
+                    .map(ItemTree::new)
+                    .unwrap_or_default()
+            },
+        );
+        if computed {
+            self.event(Event::ItemTree(module.clone()));
+        }
+        (value, changed)
+    }
+
+    /// The modules declared in a file
+    pub fn modules(&mut self, file: FileId) -> Vec<ModuleId> {
+        self.parse(file)
+            .modules
+            .iter()
+            .map(|m| ModuleId {
+                file,
+                name: m.name.str.clone(),
+            })
+            .collect()
+    }
+
+    fn module_source(&mut self, id: &ModuleId) -> (Arc<Unit<ast::Module>>, Revision) {
+        if let Some(res) = fresh(&self.module_sources, id, self.revision) {
+            return res;
+        }
+
+        let (parse, parse_changed) = self.parse_query(id.file);
+        let text = self.file_text(id.file);
+        let (value, changed, _) = update(
+            &mut self.module_sources,
+            id.clone(),
+            self.revision,
+            parse_changed,
+            || match parse.layout.module(&id.name) {
+                Some(l) => {
+                    let holes: Vec<_> = l.classes.iter().map(|c| c.range).collect();
+                    Unit {
+                        source: Source::with_holes(&text, l.range, &holes),
+                        decl: parse.module(&id.name).cloned(),
+                    }
+                }
+                None => Unit {
+                    source: Source::new(&text, Vec::new()),
+                    decl: None,
+                },
+            },
+        );
+        (value, changed)
+    }
+
+    fn class_source(&mut self, id: &ClassId) -> (Arc<Unit<ast::ClassDecl>>, Revision) {
+        if let Some(res) = fresh(&self.class_sources, id, self.revision) {
+            return res;
+        }
+
+        let (parse, parse_changed) = self.parse_query(id.module.file);
+        let text = self.file_text(id.module.file);
+        let (value, changed, _) = update(
+            &mut self.class_sources,
+            id.clone(),
+            self.revision,
+            parse_changed,
+            || {
+                let layout = parse
+                    .layout
+                    .module(&id.module.name)
+                    .and_then(|m| m.class(&id.name));
+                let decl = parse
+                    .module(&id.module.name)
+                    .and_then(|m| class(m, &id.name));
+                match (layout, decl) {
+                    (Some(l), Some(decl)) => {
+                        let holes: Vec<_> = l.methods.iter().map(|(_, s)| *s).collect();
+                        Unit {
+                            source: Source::with_holes(&text, l.range, &holes),
+                            decl: Some(decl.clone()),
+                        }
+                    }
+                    _ => Unit {
+                        source: Source::new(&text, Vec::new()),
+                        decl: None,
+                    },
+                }
+            },
+        );
+        (value, changed)
+    }
+
+    fn method_source(&mut self, id: &MethodId) -> (Arc<Unit<ast::MethodDecl>>, Revision) {
+        if let Some(res) = fresh(&self.method_sources, id, self.revision) {
+            return res;
+        }
+
+        let (parse, parse_changed) = self.parse_query(id.class.module.file);
+        let text = self.file_text(id.class.module.file);
+        let (value, changed, _) = update(
+            &mut self.method_sources,
+            id.clone(),
+            self.revision,
+            parse_changed,
+            || {
+                let span = parse
+                    .layout
+                    .module(&id.class.module.name)
+                    .and_then(|m| m.class(&id.class.name))
+                    .and_then(|c| c.method(&id.name));
+                let decl = parse
+                    .module(&id.class.module.name)
+                    .and_then(|m| class(m, &id.class.name))
+                    .and_then(|c| c.methods.iter().find(|m| m.sig.ident.str == id.name));
+                match (span, decl) {
+                    (Some(span), Some(decl)) => Unit {
+                        source: Source::new(&text, vec![span]),
+                        decl: Some(decl.clone()),
+                    },
+                    _ => Unit {
+                        source: Source::new(&text, Vec::new()),
+                        decl: None,
+                    },
+                }
+            },
+        );
+        (value, changed)
+    }
+
+    fn module_check(&mut self, id: &ModuleId) -> (Arc<Check>, Arc<Unit<ast::Module>>) {
+        if let Some((check, _)) = fresh(&self.module_checks, id, self.revision) {
+            return (check, self.module_source(id).0);
+        }
+
+        let (items, items_changed) = self.item_tree_query(id);
+        let (unit, unit_changed) = self.module_source(id);
+        let (check, _, computed) = update(
+            &mut self.module_checks,
+            id.clone(),
+            self.revision,
+            items_changed.max(unit_changed),
+            || match &unit.decl {
+                Some(m) => to_rel(&unit.source, Checker::new(&items, None).module(m)),
+                None => Check::default(),
+            },
+        );
+        if computed {
+            self.event(Event::CheckModule(id.clone()));
+        }
+        (check, unit)
+    }
+
+    fn class_check(&mut self, id: &ClassId) -> (Arc<Check>, Arc<Unit<ast::ClassDecl>>) {
+        if let Some((check, _)) = fresh(&self.class_checks, id, self.revision) {
+            return (check, self.class_source(id).0);
+        }
+
+        let (items, items_changed) = self.item_tree_query(&id.module);
+        let (unit, unit_changed) = self.class_source(id);
+        let (check, _, computed) = update(
+            &mut self.class_checks,
+            id.clone(),
+            self.revision,
+            items_changed.max(unit_changed),
+            || match &unit.decl {
+                Some(c) => {
+                    let item = items.class(&id.name);
+                    to_rel(&unit.source, Checker::new(&items, item).class(c))
+                }
+                None => Check::default(),
+            },
+        );
+        if computed {
+            self.event(Event::CheckClass(id.clone()));
+        }
+        (check, unit)
+    }
+
+    fn method_check(&mut self, id: &MethodId) -> (Arc<Check>, Arc<Unit<ast::MethodDecl>>) {
+        if let Some((check, _)) = fresh(&self.method_checks, id, self.revision) {
+            return (check, self.method_source(id).0);
+        }
+
+        let (items, items_changed) = self.item_tree_query(&id.class.module);
+        let (unit, unit_changed) = self.method_source(id);
+        let (check, _, computed) = update(
+            &mut self.method_checks,
+            id.clone(),
+            self.revision,
+            items_changed.max(unit_changed),
+            || match &unit.decl {
+                Some(m) => {
+                    let item = items.class(&id.class.name);
+                    to_rel(&unit.source, Checker::new(&items, item).method(m))
+                }
+                None => Check::default(),
+            },
+        );
+        if computed {
+            self.event(Event::CheckMethod(id.clone()));
+        }
+        (check, unit)
+    }
+
+    /// Diagnostics of functions and the main block of a module
+    pub fn check_module(&mut self, id: &ModuleId) -> Vec<Diagnostic> {
+        let (check, unit) = self.module_check(id);
+        to_abs(&unit.source, &check.diags)
+    }
+
+    /// Diagnostics of the fields, init block and recover block of a class
+    pub fn check_class(&mut self, id: &ClassId) -> Vec<Diagnostic> {
+        let (check, unit) = self.class_check(id);
+        to_abs(&unit.source, &check.diags)
+    }
+
+    pub fn check_method(&mut self, id: &MethodId) -> Vec<Diagnostic> {
+        let (check, unit) = self.method_check(id);
+        to_abs(&unit.source, &check.diags)
+    }
+
+    /// The checks of everything in a module, with the sources they are
+    /// relative to
+    fn unit_checks(&mut self, module: &ModuleId) -> Vec<(Arc<Check>, Source)> {
+        let (check, unit) = self.module_check(module);
+        let mut checks = vec![(check, unit.source.clone())];
+
+        let items = self.item_tree(module);
+        for c in &items.classes {
+            let class = ClassId {
+                module: module.clone(),
+                name: c.name.clone(),
+            };
+            let (check, unit) = self.class_check(&class);
+            checks.push((check, unit.source.clone()));
+            for m in &c.methods {
+                let (check, unit) = self.method_check(&MethodId {
+                    class: class.clone(),
+                    name: m.name.clone(),
+                });
+                checks.push((check, unit.source.clone()));
+            }
+        }
+        checks
+    }
+
+    /// All diagnostics of a file, including syntax errors
+    pub fn diagnostics(&mut self, file: FileId) -> Vec<Diagnostic> {
+        let parse = self.parse(file);
+        if let Some(e) = &parse.error {
+            return vec![Diagnostic {
+                span: Span {
+                    start: e.offset,
+                    end: e.offset,
+                },
+                msg: e.msg.clone(),
+            }];
+        }
+
+        let mut diags = Vec::new();
+        for module in self.modules(file) {
+            for (check, source) in self.unit_checks(&module) {
+                diags.extend(to_abs(&source, &check.diags));
+            }
+        }
+
+        diags.sort_by_key(|d| d.span.start);
+        diags
+    }
+
+    /// The definitions of a file and the references to them. Names are
+    /// resolved within the module they are used in.
+    pub fn resolution(&mut self, file: FileId) -> Resolution {
+        let parse = self.parse(file);
+        let mut res = Resolution::default();
+
+        for m in &parse.modules {
+            let module = ModuleId {
+                file,
+                name: m.name.str.clone(),
+            };
+
+            let first = res.defs.len();
+            let mut targets = HashMap::new();
+            for (def, target) in Globals::new(m).defs {
+                if let Some(t) = target {
+                    targets.entry(t).or_insert(res.defs.len());
+                }
+                res.defs.push(Def {
+                    parent: def.parent.map(|p| first + p),
+                    range: parse
+                        .layout
+                        .decls
+                        .get(&def.span.start)
+                        .copied()
+                        .unwrap_or(def.range),
+                    ..def
+                });
+            }
+
+            for (check, source) in self.unit_checks(&module) {
+                // Variables are referred to by where they are declared in
+                // the unit
+                let mut locals = HashMap::new();
+                for def in &check.defs {
+                    locals.insert(Target::Local(def.span), res.defs.len());
+                    res.defs.push(Def {
+                        span: source.span_to_abs(def.span),
+                        range: source.span_to_abs(def.range),
+                        ..def.clone()
+                    });
+                }
+                for (span, target) in &check.refs {
+                    let def = locals.get(target).or_else(|| targets.get(target));
+                    if let Some(def) = def {
+                        res.refs.push((source.span_to_abs(*span), *def));
+                    }
+                }
+            }
+        }
+
+        res
+    }
+}
+
+/// Makes the positions in `check` relative to `source`, dropping what is
+/// outside of it
+fn to_rel(source: &Source, check: Check) -> Check {
+    let target = |t: Target| match t {
+        Target::Local(span) => Some(Target::Local(source.span_to_rel(span)?)),
+        t => Some(t),
+    };
+    Check {
+        diags: check
+            .diags
+            .into_iter()
+            .filter_map(|d| {
+                Some(Diagnostic {
+                    span: source.span_to_rel(d.span)?,
+                    msg: d.msg,
+                })
+            })
+            .collect(),
+        defs: check
+            .defs
+            .into_iter()
+            .filter_map(|d| {
+                Some(Def {
+                    span: source.span_to_rel(d.span)?,
+                    range: source.span_to_rel(d.range)?,
+                    ..d
+                })
+            })
+            .collect(),
+        refs: check
+            .refs
+            .into_iter()
+            .filter_map(|(s, t)| Some((source.span_to_rel(s)?, target(t)?)))
+            .collect(),
+    }
+}
+
+fn to_abs(source: &Source, diags: &[Diagnostic]) -> Vec<Diagnostic> {
+    diags
+        .iter()
+        .map(|d| Diagnostic {
+            span: source.span_to_abs(d.span),
+            msg: d.msg.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "module M;
+interface I { Int m(Int x); }
+class C implements I {
+    Int f = 0;
+    Int m(Int x) {
+        return x + this.f;
+    }
+    Int n() {
+        return this.m(1);
+    }
+}
+{ I i = new C(); }
+";
+
+    fn ids() -> (FileId, ModuleId, ClassId) {
+        let file = FileId(0);
+        let module = ModuleId {
+            file,
+            name: "M".to_string(),
+        };
+        let class = ClassId {
+            module: module.clone(),
+            name: "C".to_string(),
+        };
+        (file, module, class)
+    }
+
+    fn method(class: &ClassId, name: &str) -> MethodId {
+        MethodId {
+            class: class.clone(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn editing_a_method_body_only_checks_that_method() {
+        let (file, module, class) = ids();
+        let mut db = Database::new();
+        db.set_file_text(file, SRC.to_string());
+        assert_eq!(db.diagnostics(file), []);
+
+        db.log_events(true);
+        db.set_file_text(file, SRC.replace("x + this.f", "this.f + x + 1"));
+        assert_eq!(db.diagnostics(file), []);
+        assert_eq!(
+            db.take_events(),
+            [
+                Event::Parse(file),
+                Event::ItemTree(module),
+                Event::CheckMethod(method(&class, "m")),
+            ]
+        );
+    }
+
+    #[test]
+    fn changing_a_signature_checks_the_whole_module() {
+        let (file, module, class) = ids();
+        let mut db = Database::new();
+        db.set_file_text(file, SRC.to_string());
+        db.diagnostics(file);
+
+        db.log_events(true);
+        let edited = SRC.replace("Int f = 0", "Bool f = False");
+        db.set_file_text(file, edited.clone());
+        let diags = db.diagnostics(file);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(&edited[diags[0].span.start..diags[0].span.end], "f");
+        assert_eq!(diags[0].msg, "Expected a number, found `Bool`");
+        assert_eq!(
+            db.take_events(),
+            [
+                Event::Parse(file),
+                Event::ItemTree(module.clone()),
+                Event::CheckModule(module),
+                Event::CheckClass(class.clone()),
+                Event::CheckMethod(method(&class, "m")),
+                Event::CheckMethod(method(&class, "n")),
+            ]
+        );
+    }
+
+    #[test]
+    fn queries_are_not_recomputed_without_changes() {
+        let (file, _, class) = ids();
+        let mut db = Database::new();
+        db.set_file_text(file, SRC.to_string());
+        db.diagnostics(file);
+
+        db.log_events(true);
+        db.diagnostics(file);
+        db.check_class(&class);
+        db.check_method(&method(&class, "n"));
+        assert_eq!(db.take_events(), []);
+    }
+}
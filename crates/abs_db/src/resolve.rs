@@ -0,0 +1,312 @@
+use abs_syntax::{ast, parse::Span};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DefKind {
+    Module,
+    DataType,
+    Constructor,
+    TypeSyn,
+    Exception,
+    Function,
+    Interface,
+    Class,
+    Method,
+    Field,
+    Param,
+    Local,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Def {
+    pub name: String,
+    pub kind: DefKind,
+    /// The name
+    pub span: Span,
+    /// The whole declaration, e.g. a class with its body. Variables are
+    /// declared by their name alone.
+    pub range: Span,
+    /// Shown on hover, e.g. the signature of a method
+    pub detail: String,
+    pub parent: Option<usize>,
+}
+
+/// What a name refers to, by the names of the module it is declared in
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Target {
+    /// A class, interface, data type or type synonym
+    Type(String),
+    /// A data constructor or exception
+    Constr(String),
+    Function(String),
+    /// A method of a class or interface
+    Method(String, String),
+    /// A field or parameter of a class
+    Field(String, String),
+    /// A variable or parameter declared at this position of the same unit
+    Local(Span),
+}
+
+/// Definitions and the references to them in one file
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub defs: Vec<Def>,
+    pub refs: Vec<(Span, usize)>,
+}
+
+impl Resolution {
+    /// The definition that is defined or referenced at `offset`
+    pub fn def_at(&self, offset: usize) -> Option<usize> {
+        let contains = |s: &Span| s.start <= offset && offset <= s.end;
+        self.defs
+            .iter()
+            .position(|d| contains(&d.span))
+            .or_else(|| self.refs.iter().find(|(s, _)| contains(s)).map(|(_, d)| *d))
+    }
+
+    /// The references to `def`, in the order they appear in the file
+    pub fn references(&self, def: usize) -> Vec<Span> {
+        let mut refs: Vec<_> = self
+            .refs
+            .iter()
+            .filter(|(_, d)| *d == def)
+            .map(|(s, _)| *s)
+            .collect();
+        refs.sort_by_key(|s| s.start);
+        refs
+    }
+}
+
+fn typed(ty: &ast::Type, ident: &ast::Ident) -> String {
+    format!("{} {}", ty, ident)
+}
+
+fn params_detail(params: &[ast::Param]) -> String {
+    params
+        .iter()
+        .map(|p| typed(&p.ty, &p.ident))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn sig_detail(sig: &ast::MethodSig) -> String {
+    format!("{} {}({})", sig.ret, sig.ident, params_detail(&sig.params))
+}
+
+pub(crate) fn param_def(p: &ast::Param) -> Option<Def> {
+    Some(Def {
+        name: p.ident.str.clone(),
+        kind: DefKind::Param,
+        span: p.ident.span?,
+        range: p.ident.span?,
+        detail: typed(&p.ty, &p.ident),
+        parent: None,
+    })
+}
+
+pub(crate) fn local_def(ty: Option<&ast::Type>, ident: &ast::Ident) -> Option<Def> {
+    Some(Def {
+        name: ident.str.clone(),
+        kind: DefKind::Local,
+        span: ident.span?,
+        range: ident.span?,
+        detail: match ty {
+            Some(ty) => typed(ty, ident),
+            None => ident.str.clone(),
+        },
+        parent: None,
+    })
+}
+
+fn idents_detail(idents: &[ast::Ident]) -> String {
+    idents
+        .iter()
+        .map(|i| i.str.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The definitions of a module that can be referred to from elsewhere in
+/// it, with what refers to them
+pub(crate) struct Globals {
+    pub defs: Vec<(Def, Option<Target>)>,
+}
+
+impl Globals {
+    pub fn new(m: &ast::Module) -> Self {
+        let mut g = Globals { defs: Vec::new() };
+        let module = g.define(
+            &m.name,
+            DefKind::Module,
+            format!("module {}", m.name),
+            None,
+            None,
+        );
+
+        for item in &m.children {
+            match item {
+                ast::ModuleItem::DataTypeDecl(d) => {
+                    let detail = if d.params.is_empty() {
+                        format!("data {}", d.ident)
+                    } else {
+                        format!("data {}<{}>", d.ident, idents_detail(&d.params))
+                    };
+                    let target = Target::Type(d.ident.str.clone());
+                    let dt = g.define(&d.ident, DefKind::DataType, detail, module, Some(target));
+                    for c in &d.constr {
+                        let target = Target::Constr(c.ident.str.clone());
+                        g.define(
+                            &c.ident,
+                            DefKind::Constructor,
+                            c.to_string(),
+                            dt,
+                            Some(target),
+                        );
+                    }
+                }
+                ast::ModuleItem::TypeSynDecl(t) => {
+                    let detail = format!("type {} = {}", t.ident, t.ty);
+                    let target = Target::Type(t.ident.str.clone());
+                    g.define(&t.ident, DefKind::TypeSyn, detail, module, Some(target));
+                }
+                ast::ModuleItem::ExceptionDecl(e) => {
+                    let detail = e.to_string().trim_end_matches(';').to_string();
+                    let target = Target::Constr(e.ident.str.clone());
+                    g.define(&e.ident, DefKind::Exception, detail, module, Some(target));
+                }
+                ast::ModuleItem::FunctionDecl(f) => {
+                    let type_params = if f.type_params.is_empty() {
+                        String::new()
+                    } else {
+                        format!("<{}>", idents_detail(&f.type_params))
+                    };
+                    let detail = format!(
+                        "def {} {}{}({})",
+                        f.ret,
+                        f.ident,
+                        type_params,
+                        params_detail(&f.params)
+                    );
+                    let target = Target::Function(f.ident.str.clone());
+                    g.define(&f.ident, DefKind::Function, detail, module, Some(target));
+                }
+                ast::ModuleItem::InterfaceDecl(i) => {
+                    let detail = if i.extends.is_empty() {
+                        format!("interface {}", i.ident)
+                    } else {
+                        format!(
+                            "interface {} extends {}",
+                            i.ident,
+                            idents_detail(&i.extends)
+                        )
+                    };
+                    let target = Target::Type(i.ident.str.clone());
+                    let def = g.define(&i.ident, DefKind::Interface, detail, module, Some(target));
+                    for sig in &i.sigs {
+                        g.method(&i.ident, sig, def);
+                    }
+                }
+                ast::ModuleItem::ClassDecl(c) => {
+                    let mut detail = format!("class {}", c.ident);
+                    if !c.params.is_empty() {
+                        detail.push_str(&format!("({})", params_detail(&c.params)));
+                    }
+                    if !c.implements.is_empty() {
+                        detail.push_str(&format!(" implements {}", idents_detail(&c.implements)));
+                    }
+                    let target = Target::Type(c.ident.str.clone());
+                    let def = g.define(&c.ident, DefKind::Class, detail, module, Some(target));
+
+                    let field = |ident: &ast::Ident| {
+                        Some(Target::Field(c.ident.str.clone(), ident.str.clone()))
+                    };
+                    for p in &c.params {
+                        let detail = typed(&p.ty, &p.ident);
+                        g.define(&p.ident, DefKind::Param, detail, def, field(&p.ident));
+                    }
+                    for f in &c.fields {
+                        let detail = typed(&f.ty, &f.ident);
+                        g.define(&f.ident, DefKind::Field, detail, def, field(&f.ident));
+                    }
+                    for m in &c.methods {
+                        g.method(&c.ident, &m.sig, def);
+                    }
+                }
+                ast::ModuleItem::Import(_)
+                | ast::ModuleItem::Export(_)
+                | ast::ModuleItem::MainBlock(_) => {}
+            }
+        }
+
+        g
+    }
+
+    fn define(
+        &mut self,
+        ident: &ast::Ident,
+        kind: DefKind,
+        detail: String,
+        parent: Option<usize>,
+        target: Option<Target>,
+    ) -> Option<usize> {
+        let def = Def {
+            name: ident.str.clone(),
+            kind,
+            span: ident.span?,
+            range: ident.span?,
+            detail,
+            parent,
+        };
+        self.defs.push((def, target));
+        Some(self.defs.len() - 1)
+    }
+
+    fn method(&mut self, owner: &ast::Ident, sig: &ast::MethodSig, parent: Option<usize>) {
+        let target = Target::Method(owner.str.clone(), sig.ident.str.clone());
+        self.define(
+            &sig.ident,
+            DefKind::Method,
+            sig_detail(sig),
+            parent,
+            Some(target),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Database, FileId};
+
+    #[test]
+    fn references_are_resolved_across_units_in_order() {
+        let src = "module M;
+class C(Int p) {
+    Int m(Int x) {
+        Int y = x + p;
+        return this.m(y + p);
+    }
+}
+{ C c = new C(1); Int r = c.m(2); }
+";
+        let mut db = Database::new();
+        let file = FileId(0);
+        db.set_file_text(file, src.to_string());
+        let res = db.resolution(file);
+
+        let at = |needle: &str, skip: usize| src.match_indices(needle).nth(skip).unwrap().0;
+        let text = |start: usize| &src[start..start + 1];
+
+        let p = res.def_at(at("p)", 0)).unwrap();
+        let refs: Vec<_> = res.references(p).iter().map(|s| s.start).collect();
+        assert_eq!(refs, [at("p;", 0), at("p)", 1)]);
+
+        let m = res.def_at(at("m(", 0)).unwrap();
+        let refs: Vec<_> = res.references(m).iter().map(|s| s.start).collect();
+        assert_eq!(refs, [at("m(", 1), at("m(", 2)]);
+
+        let y = res.def_at(at("y =", 0)).unwrap();
+        assert_eq!(res.defs[y].detail, "Int y");
+        let refs = res.references(y);
+        assert_eq!(refs.len(), 1);
+        assert_eq!(text(refs[0].start), "y");
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+
+use abs_syntax::{
+    ast,
+    parse::{Span, Token},
+};
+
+/// The text of one unit of checking, e.g. a method.
+///
+/// A class is checked without its methods and a module without its classes,
+/// so their text has holes. Positions inside the unit are counted in the text
+/// without the holes, which makes them independent of edits elsewhere.
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub text: String,
+    /// The pieces of the file `text` is made of
+    pub segments: Vec<Span>,
+}
+
+impl Source {
+    pub fn new(file: &str, segments: Vec<Span>) -> Self {
+        let text = segments.iter().map(|s| &file[s.start..s.end]).collect();
+        Source { text, segments }
+    }
+
+    /// `range` without the parts covered by `holes`
+    pub fn with_holes(file: &str, range: Span, holes: &[Span]) -> Self {
+        let mut holes = holes.to_vec();
+        holes.sort_by_key(|h| h.start);
+
+        let mut segments = Vec::new();
+        let mut start = range.start;
+        for h in holes {
+            if h.start > start {
+                segments.push(Span {
+                    start,
+                    end: h.start,
+                });
+            }
+            start = start.max(h.end);
+        }
+        if range.end > start {
+            segments.push(Span {
+                start,
+                end: range.end,
+            });
+        }
+
+        Source::new(file, segments)
+    }
+
+    /// Turns a position in the file into one relative to this unit
+    pub fn to_rel(&self, offset: usize) -> Option<usize> {
+        let mut before = 0;
+        for s in &self.segments {
+            if s.start <= offset && offset <= s.end {
+                return Some(before + offset - s.start);
+            }
+            before += s.end - s.start;
+        }
+        None
+    }
+
+    /// Turns a position relative to this unit back into one in the file
+    pub fn to_abs(&self, mut rel: usize) -> usize {
+        for s in &self.segments {
+            let len = s.end - s.start;
+            if rel <= len {
+                return s.start + rel;
+            }
+            rel -= len;
+        }
+        self.segments.last().map_or(0, |s| s.end)
+    }
+
+    pub fn span_to_rel(&self, span: Span) -> Option<Span> {
+        Some(Span {
+            start: self.to_rel(span.start)?,
+            end: self.to_rel(span.end)?,
+        })
+    }
+
+    pub fn span_to_abs(&self, span: Span) -> Span {
+        Span {
+            start: self.to_abs(span.start),
+            end: self.to_abs(span.end),
+        }
+    }
+}
+
+/// Where modules, classes and methods are in the file
+#[derive(Debug, Default)]
+pub struct Layout {
+    pub modules: Vec<ModuleLayout>,
+    /// The whole declaration of every name declared outside of method
+    /// bodies, by where the name starts
+    pub decls: HashMap<usize, Span>,
+}
+
+#[derive(Debug)]
+pub struct ModuleLayout {
+    pub name: String,
+    pub range: Span,
+    pub classes: Vec<ClassLayout>,
+}
+
+#[derive(Debug)]
+pub struct ClassLayout {
+    pub name: String,
+    pub range: Span,
+    pub methods: Vec<(String, Span)>,
+}
+
+impl Layout {
+    /// Finds the declarations of `modules` in the tokens they were parsed
+    /// from
+    pub fn new(text: &str, tokens: &[Token], modules: &[ast::Module]) -> Self {
+        let tokens: Vec<_> = tokens.iter().filter(|t| !t.is_comment()).collect();
+        let at: HashMap<usize, usize> = tokens
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.span.start, i))
+            .collect();
+        let index = |ident: &ast::Ident| ident.span.and_then(|s| at.get(&s.start).copied());
+        let span = |start: usize, end: usize| Span {
+            start: tokens[start].span.start,
+            end: tokens[end].span.end,
+        };
+
+        let mut layout = Layout::default();
+        let starts: Vec<usize> = modules
+            .iter()
+            .filter_map(|m| index(&m.name))
+            .map(|i| tokens[i.saturating_sub(1)].span.start)
+            .collect();
+
+        for (mi, m) in modules.iter().enumerate() {
+            let start = match starts.get(mi) {
+                Some(s) => *s,
+                None => continue,
+            };
+            let end = starts.get(mi + 1).copied().unwrap_or(text.len());
+            if let (Some(name), Some(last)) =
+                (m.name.span, tokens.iter().rev().find(|t| t.span.end <= end))
+            {
+                let range = Span {
+                    start,
+                    end: last.span.end,
+                };
+                layout.decls.insert(name.start, range);
+            }
+
+            let mut classes = Vec::new();
+            for item in &m.children {
+                // From the keyword to the semicolon or, for an interface,
+                // the closing brace
+                let item_decl = |ident: &ast::Ident, keyword: &str| {
+                    let i = index(ident)?;
+                    let start = annotations_start(&tokens, keyword_before(&tokens, i, keyword));
+                    let end = match keyword {
+                        "interface" => next_block_end(&tokens, i)?,
+                        _ => decl_end(&tokens, i)?,
+                    };
+                    Some((ident.span?.start, span(start, end)))
+                };
+                // From the type to the semicolon
+                let member_decl = |ty: &ast::Type, ident: &ast::Ident| {
+                    let start = annotations_start(&tokens, index(&ty.ident)?);
+                    let end = decl_end(&tokens, index(ident)?)?;
+                    Some((ident.span?.start, span(start, end)))
+                };
+
+                let c = match item {
+                    ast::ModuleItem::DataTypeDecl(d) => {
+                        layout.decls.extend(item_decl(&d.ident, "data"));
+                        for c in &d.constr {
+                            let ci = match index(&c.ident) {
+                                Some(i) => i,
+                                None => continue,
+                            };
+                            let end = match tokens.get(ci + 1) {
+                                Some(t) if t.is("(") => matching(&tokens, ci + 1).unwrap_or(ci),
+                                _ => ci,
+                            };
+                            layout
+                                .decls
+                                .extend(c.ident.span.map(|s| (s.start, span(ci, end))));
+                        }
+                        continue;
+                    }
+                    ast::ModuleItem::TypeSynDecl(t) => {
+                        layout.decls.extend(item_decl(&t.ident, "type"));
+                        continue;
+                    }
+                    ast::ModuleItem::ExceptionDecl(e) => {
+                        layout.decls.extend(item_decl(&e.ident, "exception"));
+                        continue;
+                    }
+                    ast::ModuleItem::FunctionDecl(f) => {
+                        layout.decls.extend(item_decl(&f.ident, "def"));
+                        continue;
+                    }
+                    ast::ModuleItem::InterfaceDecl(i) => {
+                        layout.decls.extend(item_decl(&i.ident, "interface"));
+                        for sig in &i.sigs {
+                            layout.decls.extend(member_decl(&sig.ret, &sig.ident));
+                        }
+                        continue;
+                    }
+                    ast::ModuleItem::ClassDecl(c) => c,
+                    _ => continue,
+                };
+                let ci = match index(&c.ident) {
+                    Some(i) => i,
+                    None => continue,
+                };
+                let class_start = annotations_start(&tokens, ci.saturating_sub(1));
+                let class_end = match next_block_end(&tokens, ci) {
+                    Some(e) => e,
+                    None => continue,
+                };
+                for f in &c.fields {
+                    layout.decls.extend(member_decl(&f.ty, &f.ident));
+                }
+
+                let methods = c
+                    .methods
+                    .iter()
+                    .filter_map(|md| {
+                        let ret = index(&md.sig.ret.ident)?;
+                        let name = index(&md.sig.ident)?;
+                        let start = annotations_start(&tokens, ret);
+                        let end = next_block_end(&tokens, name)?;
+                        let range = span(start, end);
+                        layout.decls.insert(md.sig.ident.span?.start, range);
+                        Some((md.sig.ident.str.clone(), range))
+                    })
+                    .collect();
+
+                let range = span(class_start, class_end);
+                layout.decls.extend(c.ident.span.map(|s| (s.start, range)));
+                classes.push(ClassLayout {
+                    name: c.ident.str.clone(),
+                    range,
+                    methods,
+                });
+            }
+
+            layout.modules.push(ModuleLayout {
+                name: m.name.str.clone(),
+                range: Span { start, end },
+                classes,
+            });
+        }
+
+        layout
+    }
+
+    pub fn module(&self, name: &str) -> Option<&ModuleLayout> {
+        self.modules.iter().find(|m| m.name == name)
+    }
+}
+
+impl ModuleLayout {
+    pub fn class(&self, name: &str) -> Option<&ClassLayout> {
+        self.classes.iter().find(|c| c.name == name)
+    }
+}
+
+impl ClassLayout {
+    pub fn method(&self, name: &str) -> Option<Span> {
+        self.methods
+            .iter()
+            .find(|(m, _)| m == name)
+            .map(|(_, s)| *s)
+    }
+}
+
+/// Moves back from the token at `i` over annotations in front of it
+fn annotations_start(tokens: &[&Token], mut i: usize) -> usize {
+    while i > 0 && tokens[i - 1].is("]") {
+        match matching_back(tokens, i - 1) {
+            Some(open) => i = open,
+            None => break,
+        }
+    }
+    i
+}
+
+fn matching_back(tokens: &[&Token], close: usize) -> Option<usize> {
+    let mut depth = 0;
+    for i in (0..=close).rev() {
+        if tokens[i].is("]") {
+            depth += 1;
+        } else if tokens[i].is("[") {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Moves back from the token at `i` to `keyword`, or stays if there is none
+fn keyword_before(tokens: &[&Token], i: usize, keyword: &str) -> usize {
+    (0..i).rev().find(|j| tokens[*j].is(keyword)).unwrap_or(i)
+}
+
+/// The bracket closing the one at `open`
+fn matching(tokens: &[&Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (j, t) in tokens.iter().enumerate().skip(open) {
+        if t.is("(") || t.is("[") || t.is("{") {
+            depth += 1;
+        } else if t.is(")") || t.is("]") || t.is("}") {
+            depth -= 1;
+            if depth == 0 {
+                return Some(j);
+            }
+        }
+    }
+    None
+}
+
+/// The semicolon ending the declaration that the token at `i` is in, outside
+/// of any brackets
+fn decl_end(tokens: &[&Token], i: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (j, t) in tokens.iter().enumerate().skip(i) {
+        if t.is("(") || t.is("[") || t.is("{") {
+            depth += 1;
+        } else if t.is(")") || t.is("]") || t.is("}") {
+            depth -= 1;
+        } else if t.is(";") && depth == 0 {
+            return Some(j);
+        }
+    }
+    None
+}
+
+/// The closing brace of the first block after the token at `i`
+fn next_block_end(tokens: &[&Token], i: usize) -> Option<usize> {
+    let open = (i..tokens.len()).find(|j| tokens[*j].is("{"))?;
+    let mut depth = 0;
+    for (j, t) in tokens.iter().enumerate().skip(open) {
+        if t.is("{") {
+            depth += 1;
+        } else if t.is("}") {
+            depth -= 1;
+            if depth == 0 {
+                return Some(j);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,335 @@
+use abs_db::{DefKind, Resolution};
+use abs_syntax::{
+    fmt::{format_source, FormatOptions, IndentStyle},
+    parse::Span,
+};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DocumentSymbol, Hover, HoverContents, MarkupContent,
+    MarkupKind, Position, Range, SymbolKind, TextEdit,
+};
+
+use crate::{line_index::LineIndex, semantic_tokens};
+
+/// Everything known about one open document
+pub struct Analysis {
+    pub text: String,
+    index: LineIndex,
+    res: Resolution,
+}
+
+impl Analysis {
+    pub fn new(text: String, res: Resolution) -> Self {
+        let index = LineIndex::new(&text);
+        Analysis { text, index, res }
+    }
+
+    fn range(&self, span: Span) -> Range {
+        self.index.range(&self.text, span)
+    }
+
+    fn offset(&self, pos: Position) -> usize {
+        self.index.offset(&self.text, pos)
+    }
+
+    /// The diagnostics the database computed for this document
+    pub fn diagnostics(&self, checked: Vec<abs_db::Diagnostic>) -> Vec<Diagnostic> {
+        checked
+            .into_iter()
+            .map(|d| Diagnostic {
+                range: self.range(d.span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("abs".to_string()),
+                message: d.msg,
+                ..Diagnostic::default()
+            })
+            .collect()
+    }
+
+    pub fn definition(&self, pos: Position) -> Option<Range> {
+        let def = self.res.def_at(self.offset(pos))?;
+        Some(self.range(self.res.defs[def].span))
+    }
+
+    pub fn references(&self, pos: Position, include_declaration: bool) -> Vec<Range> {
+        let def = match self.res.def_at(self.offset(pos)) {
+            Some(d) => d,
+            None => return Vec::new(),
+        };
+
+        let mut refs = self.res.references(def);
+        if include_declaration {
+            refs.push(self.res.defs[def].span);
+        }
+        refs.sort_by_key(|s| s.start);
+        refs.into_iter().map(|s| self.range(s)).collect()
+    }
+
+    pub fn hover(&self, pos: Position) -> Option<Hover> {
+        let def = &self.res.defs[self.res.def_at(self.offset(pos))?];
+
+        let mut value = format!("```abs\n{}\n```", def.detail);
+        if let Some(parent) = def.parent {
+            let parent = &self.res.defs[parent];
+            if parent.kind != DefKind::Module {
+                value.push_str(&format!("\n\nIn `{}`", parent.name));
+            }
+        }
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        })
+    }
+
+    #[allow(deprecated)]
+    pub fn document_symbols(&self) -> Vec<DocumentSymbol> {
+        let kind = |k: DefKind| match k {
+            DefKind::Module => Some(SymbolKind::MODULE),
+            DefKind::DataType => Some(SymbolKind::ENUM),
+            DefKind::Constructor => Some(SymbolKind::ENUM_MEMBER),
+            DefKind::TypeSyn => Some(SymbolKind::TYPE_PARAMETER),
+            DefKind::Exception => Some(SymbolKind::EVENT),
+            DefKind::Function => Some(SymbolKind::FUNCTION),
+            DefKind::Interface => Some(SymbolKind::INTERFACE),
+            DefKind::Class => Some(SymbolKind::CLASS),
+            DefKind::Method => Some(SymbolKind::METHOD),
+            DefKind::Field => Some(SymbolKind::FIELD),
+            DefKind::Param | DefKind::Local => None,
+        };
+
+        // Parents are always defined before their children
+        let mut symbols: Vec<Option<DocumentSymbol>> = Vec::new();
+        let mut children: Vec<Vec<usize>> = Vec::new();
+        for d in &self.res.defs {
+            let sym = kind(d.kind).map(|k| DocumentSymbol {
+                name: d.name.clone(),
+                detail: Some(d.detail.clone()),
+                kind: k,
+                tags: None,
+                deprecated: None,
+                range: self.range(d.range),
+                selection_range: self.range(d.span),
+                children: None,
+            });
+            if let (Some(_), Some(p)) = (&sym, d.parent) {
+                children[p].push(symbols.len());
+            }
+            symbols.push(sym);
+            children.push(Vec::new());
+        }
+
+        fn build(
+            i: usize,
+            symbols: &mut Vec<Option<DocumentSymbol>>,
+            children: &[Vec<usize>],
+        ) -> Option<DocumentSymbol> {
+            let mut sym = symbols[i].take()?;
+            let mut cs: Vec<_> = children[i]
+                .iter()
+                .filter_map(|c| build(*c, symbols, children))
+                .collect();
+            cs.sort_by_key(|c| c.range.start);
+            if !cs.is_empty() {
+                sym.children = Some(cs);
+            }
+            Some(sym)
+        }
+
+        (0..self.res.defs.len())
+            .filter(|i| self.res.defs[*i].parent.is_none())
+            .filter_map(|i| build(i, &mut symbols, &children))
+            .collect()
+    }
+
+    pub fn format(&self, opts: &lsp_types::FormattingOptions) -> Option<Vec<TextEdit>> {
+        let indent_style = if opts.insert_spaces {
+            IndentStyle::Spaces(opts.tab_size as u8)
+        } else {
+            IndentStyle::Tabs
+        };
+        let opts = FormatOptions {
+            indent_style,
+            tab_width: opts.tab_size as u8,
+            ..FormatOptions::default()
+        };
+
+        let formatted = format_source(&self.text, &opts).ok()?;
+        let end = self.index.position(&self.text, self.text.len());
+        Some(vec![TextEdit::new(
+            Range::new(Position::new(0, 0), end),
+            formatted,
+        )])
+    }
+
+    pub fn semantic_tokens(&self) -> Vec<lsp_types::SemanticToken> {
+        semantic_tokens::tokens(&self.text, &self.index, &self.res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abs_db::{Database, FileId};
+    use lsp_types::SemanticTokenType;
+
+    use super::*;
+
+    /// Line 6 has a character outside of the BMP, which is two UTF-16 code
+    /// units, before `y`
+    const SRC: &str = "module M;
+data Shape = Circle(Int) | Square;
+interface I { Int m(Int x); }
+class C(Int p) implements I {
+    Int f = 0;
+    Int m(Int x) {
+        String s = \"é😀\"; Int y = x + p;
+        return y;
+    }
+}
+";
+
+    fn analysis(src: &str) -> Analysis {
+        let mut db = Database::new();
+        db.set_file_text(FileId(0), src.to_string());
+        Analysis::new(src.to_string(), db.resolution(FileId(0)))
+    }
+
+    fn range(l1: u32, c1: u32, l2: u32, c2: u32) -> Range {
+        Range::new(Position::new(l1, c1), Position::new(l2, c2))
+    }
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        let a = analysis(SRC);
+        let y = SRC.find("y =").unwrap();
+        assert_eq!(a.index.position(&a.text, y), Position::new(6, 30));
+        assert_eq!(a.offset(Position::new(6, 30)), y);
+
+        // A position past the end of a line stays on it
+        let end = SRC.find("p;").unwrap() + 2;
+        assert_eq!(a.offset(Position::new(6, 100)), end);
+
+        // `x` after the string refers to the parameter
+        assert_eq!(
+            a.definition(Position::new(6, 34)),
+            Some(range(5, 14, 5, 15))
+        );
+    }
+
+    #[test]
+    fn definitions_and_references_are_found() {
+        let a = analysis(SRC);
+        assert_eq!(
+            a.definition(Position::new(7, 15)),
+            Some(range(6, 30, 6, 31))
+        );
+        assert_eq!(a.definition(Position::new(4, 0)), None);
+
+        let p = Position::new(6, 38);
+        assert_eq!(a.definition(p), Some(range(3, 12, 3, 13)));
+        assert_eq!(
+            a.references(p, true),
+            [range(3, 12, 3, 13), range(6, 38, 6, 39)]
+        );
+        assert_eq!(a.references(p, false), [range(6, 38, 6, 39)]);
+    }
+
+    #[test]
+    fn hover_shows_the_declaration_and_its_parent() {
+        let a = analysis(SRC);
+        let hover = |pos| match a.hover(pos) {
+            Some(Hover {
+                contents: HoverContents::Markup(m),
+                ..
+            }) => Some(m.value),
+            _ => None,
+        };
+        assert_eq!(
+            hover(Position::new(5, 8)).unwrap(),
+            "```abs\nInt m(Int x)\n```\n\nIn `C`"
+        );
+        assert_eq!(hover(Position::new(7, 15)).unwrap(), "```abs\nInt y\n```");
+        assert_eq!(hover(Position::new(0, 7)).unwrap(), "```abs\nmodule M\n```");
+        assert_eq!(hover(Position::new(4, 0)), None);
+    }
+
+    #[test]
+    fn symbols_are_nested_and_span_their_declarations() {
+        fn tree(s: &DocumentSymbol) -> String {
+            match &s.children {
+                Some(cs) => {
+                    let cs: Vec<_> = cs.iter().map(tree).collect();
+                    format!("{}({})", s.name, cs.join(", "))
+                }
+                None => s.name.clone(),
+            }
+        }
+
+        let symbols = analysis(SRC).document_symbols();
+        assert_eq!(symbols.len(), 1);
+        let module = &symbols[0];
+        assert_eq!(tree(module), "M(Shape(Circle, Square), I(m), C(f, m))");
+        assert_eq!(module.range, range(0, 0, 9, 1));
+
+        let children = module.children.as_ref().unwrap();
+        let (shape, i, c) = (&children[0], &children[1], &children[2]);
+        assert_eq!(shape.range, range(1, 0, 1, 34));
+        assert_eq!(shape.selection_range, range(1, 5, 1, 10));
+        let circle = &shape.children.as_ref().unwrap()[0];
+        assert_eq!(circle.range, range(1, 13, 1, 24));
+        assert_eq!(i.range, range(2, 0, 2, 29));
+        assert_eq!(i.children.as_ref().unwrap()[0].range, range(2, 14, 2, 27));
+
+        assert_eq!(c.kind, SymbolKind::CLASS);
+        assert_eq!(c.range, range(3, 0, 9, 1));
+        assert_eq!(c.selection_range, range(3, 6, 3, 7));
+        let members: Vec<_> = c
+            .children
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|m| (m.kind, m.range, m.selection_range))
+            .collect();
+        assert_eq!(
+            members,
+            [
+                (SymbolKind::FIELD, range(4, 4, 4, 14), range(4, 8, 4, 9)),
+                (SymbolKind::METHOD, range(5, 4, 8, 5), range(5, 8, 5, 9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn semantic_tokens_are_relative_to_the_previous_one() {
+        let a = analysis("module M;\n// é😀 x\nclass C { }\n");
+        let types = semantic_tokens::legend().token_types;
+
+        let mut line = 0;
+        let mut start = 0;
+        let tokens: Vec<_> = a
+            .semantic_tokens()
+            .into_iter()
+            .map(|t| {
+                if t.delta_line > 0 {
+                    start = 0;
+                }
+                line += t.delta_line;
+                start += t.delta_start;
+                let ty = types[t.token_type as usize].clone();
+                (line, start, t.length, ty, t.token_modifiers_bitset)
+            })
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (0, 0, 6, SemanticTokenType::KEYWORD, 0),
+                (0, 7, 1, SemanticTokenType::NAMESPACE, 1),
+                (1, 0, 8, SemanticTokenType::COMMENT, 0),
+                (2, 0, 5, SemanticTokenType::KEYWORD, 0),
+                (2, 6, 1, SemanticTokenType::CLASS, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use abs_syntax::parse::Span;
+use lsp_types::{Position, Range};
+
+/// Converts between byte offsets and LSP positions, which count UTF-16 code
+/// units
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { line_starts }
+    }
+
+    pub fn position(&self, text: &str, offset: usize) -> Position {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(l) => l,
+            Err(l) => l - 1,
+        };
+        let start = self.line_starts[line];
+        let character = text[start..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn range(&self, text: &str, span: Span) -> Range {
+        Range::new(
+            self.position(text, span.start),
+            self.position(text, span.end),
+        )
+    }
+
+    pub fn offset(&self, text: &str, pos: Position) -> usize {
+        let start = match self.line_starts.get(pos.line as usize) {
+            Some(s) => *s,
+            None => return text.len(),
+        };
+
+        let mut units = 0;
+        for (i, c) in text[start..].char_indices() {
+            if units >= pos.character as usize || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        text.len()
+    }
+}
@@ -0,0 +1,218 @@
+use std::{collections::HashMap, error::Error};
+
+use abs_db::{Database, FileId};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{
+        DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest, References, Request as _,
+        SemanticTokensFullRequest,
+    },
+    DocumentFormattingParams, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverParams, HoverProviderCapability, InitializeParams,
+    Location, OneOf, PublishDiagnosticsParams, ReferenceParams, SemanticTokens,
+    SemanticTokensFullOptions, SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+mod analysis;
+mod line_index;
+mod semantic_tokens;
+
+use analysis::Analysis;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Sync + Send>>;
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: semantic_tokens::legend(),
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..SemanticTokensOptions::default()
+            },
+        )),
+        ..ServerCapabilities::default()
+    }
+}
+
+struct Server {
+    conn: Connection,
+    docs: HashMap<Url, Analysis>,
+    db: Database,
+    files: HashMap<Url, FileId>,
+}
+
+impl Server {
+    fn run(&mut self) -> Result<()> {
+        while let Ok(msg) = self.conn.receiver.recv() {
+            match msg {
+                Message::Request(req) => {
+                    if self.conn.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    self.request(req)?;
+                }
+                Message::Notification(not) => self.notification(not)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, req: Request) -> Result<()> {
+        let resp = match req.method.as_str() {
+            GotoDefinition::METHOD => self.handle(req, Self::definition),
+            References::METHOD => self.handle(req, Self::references),
+            HoverRequest::METHOD => self.handle(req, Self::hover),
+            DocumentSymbolRequest::METHOD => self.handle(req, Self::document_symbols),
+            Formatting::METHOD => self.handle(req, Self::format),
+            SemanticTokensFullRequest::METHOD => self.handle(req, Self::semantic_tokens),
+            _ => Response::new_err(
+                req.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request `{}`", req.method),
+            ),
+        };
+        self.conn.sender.send(Message::Response(resp))?;
+        Ok(())
+    }
+
+    fn handle<P, R, F>(&self, req: Request, f: F) -> Response
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+        F: FnOnce(&Self, P) -> R,
+    {
+        match serde_json::from_value(req.params) {
+            Ok(params) => Response::new_ok(req.id, f(self, params)),
+            Err(e) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let pos = params.text_document_position_params;
+        let uri = pos.text_document.uri;
+        let range = self.docs.get(&uri)?.definition(pos.position)?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(uri, range)))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let pos = params.text_document_position;
+        let uri = pos.text_document.uri;
+        let refs = self
+            .docs
+            .get(&uri)?
+            .references(pos.position, params.context.include_declaration);
+        Some(
+            refs.into_iter()
+                .map(|r| Location::new(uri.clone(), r))
+                .collect(),
+        )
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let pos = params.text_document_position_params;
+        self.docs.get(&pos.text_document.uri)?.hover(pos.position)
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let a = self.docs.get(&params.text_document.uri)?;
+        Some(DocumentSymbolResponse::Nested(a.document_symbols()))
+    }
+
+    fn format(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        self.docs
+            .get(&params.text_document.uri)?
+            .format(&params.options)
+    }
+
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Option<SemanticTokensResult> {
+        let a = self.docs.get(&params.text_document.uri)?;
+        Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: a.semantic_tokens(),
+        }))
+    }
+
+    fn notification(&mut self, not: Notification) -> Result<()> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                let doc = params.text_document;
+                self.update(doc.uri, doc.text)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                // Only full syncs are announced, so the last change is the
+                // whole document
+                match params.content_changes.into_iter().last() {
+                    Some(c) => self.update(params.text_document.uri, c.text),
+                    None => Ok(()),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                self.docs.remove(&params.text_document.uri);
+                // The id is kept in case the document is opened again
+                if let Some(file) = self.files.get(&params.text_document.uri) {
+                    self.db.remove_file(*file);
+                }
+                self.publish(params.text_document.uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: Url, text: String) -> Result<()> {
+        let next = FileId(self.files.len() as u32);
+        let file = *self.files.entry(uri.clone()).or_insert(next);
+        self.db.set_file_text(file, text.clone());
+
+        let analysis = Analysis::new(text, self.db.resolution(file));
+        let diags = analysis.diagnostics(self.db.diagnostics(file));
+        self.docs.insert(uri.clone(), analysis);
+        self.publish(uri, diags)
+    }
+
+    fn publish(&self, uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let not = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.conn.sender.send(Message::Notification(not))?;
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let (conn, io_threads) = Connection::stdio();
+
+    let caps = serde_json::to_value(capabilities())?;
+    let params = conn.initialize(caps)?;
+    let _params: InitializeParams = serde_json::from_value(params)?;
+
+    let mut server = Server {
+        conn,
+        docs: HashMap::new(),
+        db: Database::new(),
+        files: HashMap::new(),
+    };
+    server.run()?;
+
+    drop(server);
+    io_threads.join()?;
+    Ok(())
+}
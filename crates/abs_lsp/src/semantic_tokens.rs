@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use abs_db::{DefKind, Resolution};
+use abs_syntax::parse::{tokenize, TokenKind};
+use lsp_types::{SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend};
+
+use crate::line_index::LineIndex;
+
+const TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::CLASS,
+    SemanticTokenType::INTERFACE,
+    SemanticTokenType::ENUM,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::METHOD,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::VARIABLE,
+];
+
+const MODIFIERS: &[SemanticTokenModifier] = &[SemanticTokenModifier::DECLARATION];
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TYPES.to_vec(),
+        token_modifiers: MODIFIERS.to_vec(),
+    }
+}
+
+fn type_index(ty: SemanticTokenType) -> u32 {
+    TYPES.iter().position(|t| *t == ty).unwrap() as u32
+}
+
+fn def_type(kind: DefKind) -> SemanticTokenType {
+    match kind {
+        DefKind::Module => SemanticTokenType::NAMESPACE,
+        DefKind::DataType | DefKind::TypeSyn => SemanticTokenType::TYPE,
+        DefKind::Constructor | DefKind::Exception => SemanticTokenType::ENUM_MEMBER,
+        DefKind::Function => SemanticTokenType::FUNCTION,
+        DefKind::Interface => SemanticTokenType::INTERFACE,
+        DefKind::Class => SemanticTokenType::CLASS,
+        DefKind::Method => SemanticTokenType::METHOD,
+        DefKind::Field => SemanticTokenType::PROPERTY,
+        DefKind::Param => SemanticTokenType::PARAMETER,
+        DefKind::Local => SemanticTokenType::VARIABLE,
+    }
+}
+
+/// Classifies the tokens of `text`, using the resolution for identifiers
+/// where possible
+pub fn tokens(text: &str, index: &LineIndex, res: &Resolution) -> Vec<SemanticToken> {
+    let tokens = match tokenize(text) {
+        Ok(t) => t,
+        Err(_) => return Vec::new(),
+    };
+
+    // Start of a name mapped to its end, type and whether it is declared
+    // there
+    let mut names = BTreeMap::new();
+    for d in &res.defs {
+        names.insert(d.span.start, (d.span.end, def_type(d.kind), true));
+    }
+    for (s, d) in &res.refs {
+        names.insert(s.start, (s.end, def_type(res.defs[*d].kind), false));
+    }
+
+    let mut out = Vec::new();
+    let mut prev_line = 0;
+    let mut prev_start = 0;
+    let mut push = |start: usize, end: usize, ty: SemanticTokenType, decl: bool| {
+        let pos = index.position(text, start);
+        let len = text[start..end].encode_utf16().count() as u32;
+        let delta_line = pos.line - prev_line;
+        let delta_start = if delta_line == 0 {
+            pos.character - prev_start
+        } else {
+            pos.character
+        };
+        out.push(SemanticToken {
+            delta_line,
+            delta_start,
+            length: len,
+            token_type: type_index(ty),
+            token_modifiers_bitset: decl as u32,
+        });
+        prev_line = pos.line;
+        prev_start = pos.character;
+    };
+
+    for (i, t) in tokens.iter().enumerate() {
+        let ty = match t.kind {
+            TokenKind::Keyword => SemanticTokenType::KEYWORD,
+            TokenKind::IntLit | TokenKind::FloatLit => SemanticTokenType::NUMBER,
+            TokenKind::StringLit => SemanticTokenType::STRING,
+            TokenKind::Symbol => continue,
+            TokenKind::LineComment | TokenKind::BlockComment | TokenKind::TemplatePart => {
+                let ty = match t.kind {
+                    TokenKind::TemplatePart => SemanticTokenType::STRING,
+                    _ => SemanticTokenType::COMMENT,
+                };
+                // Tokens may not span several lines
+                let mut start = t.span.start;
+                for line in t.text.split('\n') {
+                    let end = start + line.trim_end_matches('\r').len();
+                    if end > start {
+                        push(start, end, ty.clone(), false);
+                    }
+                    start += line.len() + 1;
+                }
+                continue;
+            }
+            TokenKind::Ident => {
+                let known = names
+                    .range(..=t.span.start)
+                    .next_back()
+                    .filter(|(_, (end, _, _))| t.span.end <= *end);
+                if let Some((_, (_, ty, decl))) = known {
+                    push(t.span.start, t.span.end, ty.clone(), *decl);
+                    continue;
+                }
+
+                let next = tokens.get(i + 1).map(|n| n.text);
+                let prev = i.checked_sub(1).map(|p| tokens[p].text);
+                if t.text.starts_with(|c: char| c.is_ascii_uppercase()) {
+                    SemanticTokenType::TYPE
+                } else if next == Some("(") && (prev == Some("!") || prev == Some(".")) {
+                    SemanticTokenType::METHOD
+                } else if next == Some("(") || next == Some("[") {
+                    SemanticTokenType::FUNCTION
+                } else {
+                    SemanticTokenType::VARIABLE
+                }
+            }
+        };
+        push(t.span.start, t.span.end, ty, false);
+    }
+
+    out
+}
@@ -1,11 +1,34 @@
-pub fn chance(prob: f64) -> bool {
-    rand::random::<f64>() < prob
+use rand::Rng;
+
+pub fn chance<R: Rng + ?Sized>(rng: &mut R, prob: f64) -> bool {
+    rng.gen::<f64>() < prob
+}
+
+pub fn exp_rand_val<R: Rng + ?Sized>(rng: &mut R, lambda: f64) -> f64 {
+    (-rng.gen::<f64>().ln()) / lambda
 }
 
-pub fn exp_rand_val(lambda: f64) -> f64 {
-    (-rand::random::<f64>().ln()) / lambda
+pub fn exp_rand_int<R: Rng + ?Sized>(rng: &mut R, expected: f64) -> u64 {
+    exp_rand_val(rng, 1.0 / expected).ceil() as u64
 }
 
-pub fn exp_rand_int(expected: f64) -> u64 {
-    exp_rand_val(1.0 / expected).ceil() as u64
+/// Picks one of the choices, each as likely as its weight. None if all
+/// weights are 0.
+pub fn weighted<R: Rng + ?Sized, T: Copy>(rng: &mut R, choices: &[(T, f64)]) -> Option<T> {
+    let total: f64 = choices.iter().map(|(_, w)| w).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut x = rng.gen::<f64>() * total;
+    for (c, w) in choices {
+        if x < *w {
+            return Some(*c);
+        }
+        x -= w;
+    }
+    choices
+        .iter()
+        .rev()
+        .find(|(_, w)| *w > 0.0)
+        .map(|(c, _)| *c)
 }
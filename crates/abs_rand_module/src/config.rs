@@ -0,0 +1,601 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    enumerate::Enumeration,
+    fuzz::{Fuzz, Tool},
+    mutate::{Mutate, Operator},
+    naming::Naming,
+    sweep::{Dim, Mode, Sweep},
+    target::{self, Target},
+    Options,
+};
+
+/// What is generated and where to. Every field may be left out, both in the
+/// config file and on the command line.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub seed: Option<u64>,
+    pub out_dir: Option<PathBuf>,
+    pub pattern: Option<String>,
+    pub clean: Option<bool>,
+    /// Threads to generate on
+    pub jobs: Option<usize>,
+    /// A config file of learned options, which everything else takes
+    /// precedence over
+    pub profile: Option<PathBuf>,
+    /// Learns a profile from the code in this directory
+    pub learn: Option<PathBuf>,
+    pub max_depth: Option<u8>,
+    pub branch_rate: Option<f64>,
+    pub declare_to_assign: Option<f64>,
+    pub else_ratio: Option<f64>,
+    pub avg_meth_body_size: Option<u32>,
+    pub avg_block_size: Option<u32>,
+    pub num_methods: Option<u32>,
+    pub num_interfaces: Option<u32>,
+    pub num_class_params: Option<u32>,
+    pub async_rate: Option<f64>,
+    pub get_rate: Option<f64>,
+    pub new_rate: Option<f64>,
+    pub sync_rate: Option<f64>,
+    pub num_data_types: Option<u32>,
+    pub num_functions: Option<u32>,
+    pub functional_rate: Option<f64>,
+    pub defect_rate: Option<f64>,
+    pub steer: Option<f64>,
+    /// `random`, `counter` or `typed`
+    pub naming: Option<String>,
+    pub unique_names: Option<bool>,
+    /// The weights of statements, by their keyword
+    pub weights: Option<BTreeMap<String, f64>>,
+    pub targets: Option<Vec<TargetConfig>>,
+    pub sweep: Option<SweepConfig>,
+    pub fuzz: Option<FuzzConfig>,
+    pub enumerate: Option<EnumerateConfig>,
+    pub mutate: Option<MutateConfig>,
+}
+
+/// `[[targets]]` in the config file, `--target <target>=<classes>` on the
+/// command line
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetConfig {
+    pub target: String,
+    pub classes: String,
+}
+
+/// `[sweep]` in the config file, `--sweep`, `--samples`, `--sweep-target`
+/// and `--dim` on the command line
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfig {
+    pub mode: Option<String>,
+    pub samples: Option<usize>,
+    pub target: Option<String>,
+    /// The values, or the minimum and maximum, of every swept option
+    pub dims: Option<BTreeMap<String, Vec<f64>>>,
+}
+
+impl SweepConfig {
+    fn merge(self, other: SweepConfig) -> SweepConfig {
+        SweepConfig {
+            mode: other.mode.or(self.mode),
+            samples: other.samples.or(self.samples),
+            target: other.target.or(self.target),
+            dims: other.dims.or(self.dims),
+        }
+    }
+}
+
+/// `[fuzz]` in the config file, `--fuzz`, `--fuzz-target`, `--fuzz-classes`,
+/// `--triage` and `--timeout` on the command line
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FuzzConfig {
+    pub runs: Option<usize>,
+    pub target: Option<String>,
+    pub classes: Option<String>,
+    pub triage_dir: Option<PathBuf>,
+    /// Seconds after which a tool is killed
+    pub timeout: Option<u64>,
+    pub tools: Option<Vec<ToolConfig>>,
+}
+
+/// `[[fuzz.tools]]` in the config file, `--tool <name>=<command>` on the
+/// command line
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ToolConfig {
+    pub name: String,
+    pub command: Vec<String>,
+}
+
+impl FuzzConfig {
+    fn merge(self, other: FuzzConfig) -> FuzzConfig {
+        FuzzConfig {
+            runs: other.runs.or(self.runs),
+            target: other.target.or(self.target),
+            classes: other.classes.or(self.classes),
+            triage_dir: other.triage_dir.or(self.triage_dir),
+            timeout: other.timeout.or(self.timeout),
+            tools: other.tools.or(self.tools),
+        }
+    }
+}
+
+/// `[enumerate]` in the config file, `--enumerate`, `--enum-depth`,
+/// `--enum-target` and `--per-module` on the command line
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct EnumerateConfig {
+    pub max_stmts: Option<usize>,
+    pub max_depth: Option<u32>,
+    pub per_module: Option<usize>,
+    pub target: Option<String>,
+}
+
+impl EnumerateConfig {
+    fn merge(self, other: EnumerateConfig) -> EnumerateConfig {
+        EnumerateConfig {
+            max_stmts: other.max_stmts.or(self.max_stmts),
+            max_depth: other.max_depth.or(self.max_depth),
+            per_module: other.per_module.or(self.per_module),
+            target: other.target.or(self.target),
+        }
+    }
+}
+
+/// `[mutate]` in the config file, `--mutate` and `--operator` on the
+/// command line
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MutateConfig {
+    pub files: Option<Vec<PathBuf>>,
+    pub operators: Option<Vec<String>>,
+}
+
+impl MutateConfig {
+    fn merge(self, other: MutateConfig) -> MutateConfig {
+        MutateConfig {
+            files: other.files.or(self.files),
+            operators: other.operators.or(self.operators),
+        }
+    }
+}
+
+const OUT_DIR: &str = "out";
+const PATTERN: &str = "generated-{target}-{classes}.abs";
+const SWEEP_PATTERN: &str = "sweep-{point}.abs";
+const SWEEP_SAMPLES: usize = 10;
+const FUZZ_PATTERN: &str = "fuzz-{point}.abs";
+const FUZZ_CLASSES: &str = "1..=10";
+const TRIAGE_DIR: &str = "triage";
+const TIMEOUT: u64 = 60;
+const ENUM_PATTERN: &str = "enum-{point}.abs";
+const ENUM_DEPTH: u32 = 1;
+const PER_MODULE: usize = 100;
+const MUTANT_PATTERN: &str = "mutant-{point}.abs";
+
+/// The config with everything filled in
+pub struct Settings {
+    pub seed: u64,
+    pub out_dir: PathBuf,
+    pub pattern: String,
+    pub clean: bool,
+    pub jobs: usize,
+    /// The options shared by all modules; the target and the number of
+    /// classes are set per module
+    pub opts: Options,
+    pub targets: Vec<(Target, Vec<u32>)>,
+    /// Replaces the targets if set
+    pub sweep: Option<Sweep>,
+    /// Replaces the targets if set
+    pub fuzz: Option<Fuzz>,
+    /// Replaces the targets if set
+    pub enumerate: Option<Enumeration>,
+    /// Replaces the targets if set
+    pub mutate: Option<Mutate>,
+    /// Replaces the targets if set
+    pub learn: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let s = fs::read_to_string(path)?;
+        toml::from_str(&s).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Takes every field that is set in `other` from there
+    /// Reads the profile, if there is one, and lets this config take
+    /// precedence over it
+    pub fn with_profile(self) -> io::Result<Config> {
+        match &self.profile {
+            Some(p) => Ok(Config::load(p)?.merge(self)),
+            None => Ok(self),
+        }
+    }
+
+    pub fn merge(self, other: Config) -> Config {
+        Config {
+            seed: other.seed.or(self.seed),
+            out_dir: other.out_dir.or(self.out_dir),
+            pattern: other.pattern.or(self.pattern),
+            clean: other.clean.or(self.clean),
+            jobs: other.jobs.or(self.jobs),
+            profile: other.profile.or(self.profile),
+            learn: other.learn.or(self.learn),
+            max_depth: other.max_depth.or(self.max_depth),
+            branch_rate: other.branch_rate.or(self.branch_rate),
+            declare_to_assign: other.declare_to_assign.or(self.declare_to_assign),
+            else_ratio: other.else_ratio.or(self.else_ratio),
+            avg_meth_body_size: other.avg_meth_body_size.or(self.avg_meth_body_size),
+            avg_block_size: other.avg_block_size.or(self.avg_block_size),
+            num_methods: other.num_methods.or(self.num_methods),
+            num_interfaces: other.num_interfaces.or(self.num_interfaces),
+            num_class_params: other.num_class_params.or(self.num_class_params),
+            async_rate: other.async_rate.or(self.async_rate),
+            get_rate: other.get_rate.or(self.get_rate),
+            new_rate: other.new_rate.or(self.new_rate),
+            sync_rate: other.sync_rate.or(self.sync_rate),
+            num_data_types: other.num_data_types.or(self.num_data_types),
+            num_functions: other.num_functions.or(self.num_functions),
+            functional_rate: other.functional_rate.or(self.functional_rate),
+            defect_rate: other.defect_rate.or(self.defect_rate),
+            steer: other.steer.or(self.steer),
+            naming: other.naming.or(self.naming),
+            unique_names: other.unique_names.or(self.unique_names),
+            weights: match (self.weights, other.weights) {
+                (Some(mut a), Some(b)) => {
+                    a.extend(b);
+                    Some(a)
+                }
+                (a, b) => b.or(a),
+            },
+            targets: other.targets.or(self.targets),
+            sweep: match (self.sweep, other.sweep) {
+                (Some(a), Some(b)) => Some(a.merge(b)),
+                (a, b) => b.or(a),
+            },
+            fuzz: match (self.fuzz, other.fuzz) {
+                (Some(a), Some(b)) => Some(a.merge(b)),
+                (a, b) => b.or(a),
+            },
+            enumerate: match (self.enumerate, other.enumerate) {
+                (Some(a), Some(b)) => Some(a.merge(b)),
+                (a, b) => b.or(a),
+            },
+            mutate: match (self.mutate, other.mutate) {
+                (Some(a), Some(b)) => Some(a.merge(b)),
+                (a, b) => b.or(a),
+            },
+        }
+    }
+
+    pub fn settings(self) -> Result<Settings, String> {
+        let mut opts = Options::default();
+        if let Some(d) = self.max_depth {
+            opts.max_depth = d;
+        }
+        for (name, field, value) in [
+            ("branch_rate", &mut opts.branch_rate, self.branch_rate),
+            (
+                "declare_to_assign",
+                &mut opts.declare_to_assign,
+                self.declare_to_assign,
+            ),
+            ("else_ratio", &mut opts.else_ratio, self.else_ratio),
+            ("async_rate", &mut opts.async_rate, self.async_rate),
+            ("get_rate", &mut opts.get_rate, self.get_rate),
+            ("new_rate", &mut opts.new_rate, self.new_rate),
+            ("sync_rate", &mut opts.sync_rate, self.sync_rate),
+            (
+                "functional_rate",
+                &mut opts.functional_rate,
+                self.functional_rate,
+            ),
+            ("defect_rate", &mut opts.defect_rate, self.defect_rate),
+        ] {
+            if let Some(p) = value {
+                if !(0.0..=1.0).contains(&p) {
+                    return Err(format!("{} must be between 0 and 1", name));
+                }
+                *field = p;
+            }
+        }
+        if let Some(s) = self.steer {
+            if s < 0.0 {
+                return Err("steer must not be negative".to_string());
+            }
+            opts.steer = s;
+        }
+        if let Some(n) = &self.naming {
+            opts.naming = Naming::from_name(n)?;
+        }
+        if let Some(u) = self.unique_names {
+            opts.unique_names = u;
+        }
+        if let Some(n) = self.avg_meth_body_size {
+            opts.avg_meth_body_size = n;
+        }
+        if let Some(n) = self.avg_block_size {
+            opts.avg_block_size = n;
+        }
+        if let Some(n) = self.num_methods {
+            opts.num_methods = n;
+        }
+        if let Some(n) = self.num_interfaces {
+            opts.num_interfaces = n;
+        }
+        if let Some(n) = self.num_class_params {
+            opts.num_class_params = n;
+        }
+        if let Some(n) = self.num_data_types {
+            opts.num_data_types = n;
+        }
+        if let Some(n) = self.num_functions {
+            opts.num_functions = n;
+        }
+        for (stmt, w) in self.weights.unwrap_or_default() {
+            opts.weights.set(&stmt, w)?;
+        }
+        let out_dir: PathBuf = self.out_dir.unwrap_or_else(|| OUT_DIR.into());
+
+        let sweep = match self.sweep {
+            Some(SweepConfig {
+                mode: Some(mode),
+                samples,
+                target,
+                dims,
+            }) => {
+                if let Some(t) = target {
+                    opts.target = target::from_name(&t)?;
+                }
+                let dims = dims
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(d, vs)| Ok((Dim::from_name(&d)?, vs)))
+                    .collect::<Result<_, String>>()?;
+                let samples = samples.unwrap_or(SWEEP_SAMPLES);
+                Some(Sweep::new(Mode::from_name(&mode)?, samples, dims)?)
+            }
+            Some(_) => return Err("A sweep needs a mode, grid or lhs".to_string()),
+            None => None,
+        };
+
+        let fuzz = match self.fuzz {
+            Some(FuzzConfig {
+                runs: Some(runs),
+                target,
+                classes,
+                triage_dir,
+                timeout,
+                tools,
+            }) => {
+                if sweep.is_some() {
+                    return Err("A sweep and fuzzing cannot be combined".to_string());
+                }
+                if let Some(t) = target {
+                    opts.target = target::from_name(&t)?;
+                }
+                let classes = parse_classes(classes.as_deref().unwrap_or(FUZZ_CLASSES))?;
+                if classes.is_empty() {
+                    return Err("Fuzzing needs at least one number of classes".to_string());
+                }
+                let tools = tools
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|t| {
+                        if t.command.is_empty() {
+                            return Err(format!("The tool {} needs a command", t.name));
+                        }
+                        if t.name.is_empty()
+                            || !t
+                                .name
+                                .chars()
+                                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+                        {
+                            return Err(format!(
+                                "`{}` is not a tool name, use letters, digits, - and _",
+                                t.name
+                            ));
+                        }
+                        Ok(Tool {
+                            name: t.name,
+                            command: t.command,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                if tools.len() < 2 {
+                    return Err("Fuzzing needs at least two tools to compare".to_string());
+                }
+                if (1..tools.len()).any(|i| tools[..i].iter().any(|t| t.name == tools[i].name)) {
+                    return Err("Every tool needs its own name".to_string());
+                }
+                Some(Fuzz {
+                    runs,
+                    classes,
+                    tools,
+                    triage_dir: out_dir.join(triage_dir.unwrap_or_else(|| TRIAGE_DIR.into())),
+                    timeout: Duration::from_secs(timeout.unwrap_or(TIMEOUT)),
+                })
+            }
+            Some(_) => return Err("Fuzzing needs a number of runs".to_string()),
+            None => None,
+        };
+
+        let enumerate = match self.enumerate {
+            Some(EnumerateConfig {
+                max_stmts: Some(max_stmts),
+                max_depth,
+                per_module,
+                target,
+            }) => {
+                if sweep.is_some() || fuzz.is_some() {
+                    return Err(
+                        "An enumeration cannot be combined with a sweep or fuzzing".to_string()
+                    );
+                }
+                if let Some(t) = target {
+                    opts.target = target::from_name(&t)?;
+                }
+                let per_module = per_module.unwrap_or(PER_MODULE);
+                if per_module == 0 {
+                    return Err("An enumeration needs at least one body per module".to_string());
+                }
+                Some(Enumeration {
+                    max_stmts,
+                    max_depth: max_depth.unwrap_or(ENUM_DEPTH),
+                    per_module,
+                })
+            }
+            Some(_) => return Err("An enumeration needs a number of statements".to_string()),
+            None => None,
+        };
+
+        let mutate = match self.mutate {
+            Some(MutateConfig {
+                files: Some(files),
+                operators,
+            }) if !files.is_empty() => {
+                if sweep.is_some() || fuzz.is_some() || enumerate.is_some() {
+                    return Err(
+                        "Mutating cannot be combined with a sweep, fuzzing or an enumeration"
+                            .to_string(),
+                    );
+                }
+                let operators = match operators {
+                    Some(ops) => ops
+                        .iter()
+                        .map(|o| Operator::from_name(o))
+                        .collect::<Result<_, String>>()?,
+                    None => Operator::all().to_vec(),
+                };
+                Some(Mutate { files, operators })
+            }
+            Some(_) => return Err("Mutating needs at least one file".to_string()),
+            None => None,
+        };
+
+        if self.learn.is_some()
+            && (sweep.is_some() || fuzz.is_some() || enumerate.is_some() || mutate.is_some())
+        {
+            return Err("Learning cannot be combined with another mode".to_string());
+        }
+
+        let jobs = match self.jobs {
+            Some(0) => return Err("jobs must be at least 1".to_string()),
+            Some(n) => n,
+            None => thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+
+        let targets = match self.targets {
+            Some(ts) => ts
+                .iter()
+                .map(|t| Ok((target::from_name(&t.target)?, parse_classes(&t.classes)?)))
+                .collect::<Result<_, String>>()?,
+            None => default_targets(),
+        };
+
+        Ok(Settings {
+            seed: self.seed.unwrap_or_else(rand::random),
+            out_dir,
+            pattern: self
+                .pattern
+                .unwrap_or_else(|| match (&sweep, &fuzz, &enumerate, &mutate) {
+                    (Some(_), _, _, _) => SWEEP_PATTERN.to_string(),
+                    (_, Some(_), _, _) => FUZZ_PATTERN.to_string(),
+                    (_, _, Some(_), _) => ENUM_PATTERN.to_string(),
+                    (_, _, _, Some(_)) => MUTANT_PATTERN.to_string(),
+                    _ => PATTERN.to_string(),
+                }),
+            clean: self.clean.unwrap_or(false),
+            jobs,
+            opts,
+            targets,
+            sweep,
+            fuzz,
+            enumerate,
+            mutate,
+            learn: self.learn,
+        })
+    }
+}
+
+/// The benchmark inputs generated so far
+fn default_targets() -> Vec<(Target, Vec<u32>)> {
+    vec![
+        (&target::Crowbar as Target, (1..=20).collect()),
+        (&target::Location, (1..=100).collect()),
+        (&target::NullableExtension, (1..=300).collect()),
+    ]
+}
+
+/// Parses numbers of classes like `1,5,10`, `1..=300` or `10..=300/10`, the
+/// last meaning every tenth number
+pub fn parse_classes(s: &str) -> Result<Vec<u32>, String> {
+    let num = |n: &str| {
+        n.trim()
+            .parse::<u32>()
+            .map_err(|_| format!("`{}` is not a number of classes", n.trim()))
+    };
+
+    let mut classes = Vec::new();
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (r, num(s)?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err("The step of a range must not be 0".to_string());
+        }
+        match range.split_once("..=") {
+            Some((from, to)) => {
+                let (from, to) = (num(from)?, num(to)?);
+                if from > to {
+                    return Err(format!("`{}` is an empty range", range.trim()));
+                }
+                classes.extend((from..=to).step_by(step as usize))
+            }
+            None if step == 1 => classes.push(num(range)?),
+            None => return Err(format!("`{}` is not a range", range.trim())),
+        }
+    }
+    Ok(classes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_are_lists_and_ranges() {
+        assert_eq!(parse_classes("1, 5,10"), Ok(vec![1, 5, 10]));
+        assert_eq!(parse_classes("3..=5,8"), Ok(vec![3, 4, 5, 8]));
+        assert_eq!(parse_classes("10..=40/10"), Ok(vec![10, 20, 30, 40]));
+        assert_eq!(parse_classes("2..=2"), Ok(vec![2]));
+    }
+
+    #[test]
+    fn reversed_ranges_are_rejected() {
+        assert_eq!(
+            parse_classes("5..=1"),
+            Err("`5..=1` is an empty range".to_string())
+        );
+        assert!(parse_classes("1,300..=10/10").is_err());
+        assert!(parse_classes("1..=3/0").is_err());
+    }
+}
@@ -0,0 +1,485 @@
+//! Which kinds of statements and expressions were generated, and in which
+//! places.
+//!
+//! A place is a slot of a statement, like `IfStmt.condition` or
+//! `WhileStmt.body`, or the kind of the expression an expression is part
+//! of. The statements of a block count as being in the slot of the block.
+//! With `steer` above 0, the generator prefers what is rarely covered in the
+//! place it generates for, see `boost`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, prelude::*},
+    path::Path,
+};
+
+use abs_syntax::ast;
+
+/// The places statements are generated in
+const STMT_PLACES: [&str; 9] = [
+    "MethodDecl.body",
+    "IfStmt.then",
+    "IfStmt.else",
+    "SwitchStmt.branch",
+    "WhileStmt.body",
+    "ForeachStmt.body",
+    "TryCatchFinallyStmt.try",
+    "TryCatchFinallyStmt.catch",
+    "TryCatchFinallyStmt.finally",
+];
+
+/// The statements generated in every one of `STMT_PLACES`
+const STMTS: [&str; 11] = [
+    "VarDeclStmt",
+    "AssignStmt",
+    "AssertStmt",
+    "AwaitStmt",
+    "SuspendStmt",
+    "ThrowStmt",
+    "IfStmt",
+    "SwitchStmt",
+    "WhileStmt",
+    "ForeachStmt",
+    "TryCatchFinallyStmt",
+];
+
+/// The places enumeration generates statements in
+const ENUM_PLACES: [&str; 4] = [
+    "MethodDecl.body",
+    "IfStmt.then",
+    "IfStmt.else",
+    "WhileStmt.body",
+];
+
+/// The statements only enumeration generates
+const ENUM_STMTS: [&str; 2] = ["SkipStmt", "ExprStmt"];
+
+/// The places of expressions that may have side effects
+const EFF_PLACES: [&str; 3] = ["VarDeclStmt.init", "AssignStmt.expr", "ReturnStmt.expr"];
+
+/// The expressions with side effects generated in `EFF_PLACES`
+const EFFS: [&str; 4] = ["NewExpr", "SyncCallExpr", "AsyncCallExpr", "GetExpr"];
+
+/// The places of pure expressions, other than the expressions they are part
+/// of, in which any of `PURES` is generated
+const PURE_PLACES: [&str; 6] = [
+    "FunctionDecl.body",
+    "AssertStmt.condition",
+    "AwaitStmt.guard",
+    "IfStmt.condition",
+    "SwitchStmt.expr",
+    "WhileStmt.condition",
+];
+
+/// The pure expressions generated wherever one is needed
+const PURES: [&str; 10] = [
+    "IdentExpr",
+    "NullExpr",
+    "LiteralExpr",
+    "LetExpr",
+    "DataConstrExpr",
+    "FnAppExpr",
+    "WhenExpr",
+    "CaseExpr",
+    "UnaryExpr",
+    "BinaryExpr",
+];
+
+/// The expressions that other expressions are generated in
+const PARENTS: [&str; 12] = [
+    "LetExpr",
+    "DataConstrExpr",
+    "FnAppExpr",
+    "VariadicFnAppExpr",
+    "WhenExpr",
+    "CaseExpr",
+    "UnaryExpr",
+    "BinaryExpr",
+    "NewExpr",
+    "SyncCallExpr",
+    "AsyncCallExpr",
+    "GetExpr",
+];
+
+/// The places with only a few kinds in them, like the literals and `null`
+/// that fields start with, the exception that is thrown, the list a
+/// `foreach` loops over, `this.fint` in the object invariant and the calls
+/// that enumeration makes statements of
+const FIXED: [(&str, &str); 8] = [
+    ("MethodDecl.body", "ReturnStmt"),
+    ("FieldDecl.init", "LiteralExpr"),
+    ("FieldDecl.init", "NullExpr"),
+    ("ThrowStmt.expr", "DataConstrExpr"),
+    ("ForeachStmt.iter", "VariadicFnAppExpr"),
+    ("BinaryExpr", "ThisIdentExpr"),
+    ("ExprStmt.expr", "SyncCallExpr"),
+    ("ExprStmt.expr", "AsyncCallExpr"),
+];
+
+#[derive(Clone, Default)]
+pub struct Coverage {
+    kinds: BTreeMap<&'static str, u64>,
+    /// How often a kind was generated in a place
+    pairs: BTreeMap<(&'static str, &'static str), u64>,
+}
+
+impl Coverage {
+    /// How much more likely `kind` should be in `place`, the rarer it is
+    /// there the more, and not at all if `steer` is 0
+    pub fn boost(&self, steer: f64, place: &'static str, kind: &'static str) -> f64 {
+        let n = self.pairs.get(&(place, kind)).copied().unwrap_or(0);
+        1.0 + steer / (1 + n) as f64
+    }
+
+    fn add(&mut self, place: &'static str, kind: &'static str) {
+        *self.kinds.entry(kind).or_default() += 1;
+        *self.pairs.entry((place, kind)).or_default() += 1;
+    }
+
+    /// Adds the counts of `other`
+    pub fn merge(&mut self, other: &Coverage) {
+        for (k, n) in &other.kinds {
+            *self.kinds.entry(k).or_default() += n;
+        }
+        for (p, n) in &other.pairs {
+            *self.pairs.entry(*p).or_default() += n;
+        }
+    }
+
+    /// Adds a statement just generated in `place`, but not what is in it,
+    /// which is added as it is generated
+    pub fn add_stmt(&mut self, place: &'static str, s: &ast::Stmt) {
+        self.add(place, s.kind());
+    }
+
+    /// Adds an expression just generated in `place`, with everything in it
+    pub fn add_expr(&mut self, place: &'static str, e: &ast::Expr) {
+        self.expr(place, e);
+    }
+
+    /// Like `add_expr`, for a pure expression
+    pub fn add_pure_expr(&mut self, place: &'static str, e: &ast::PureExpr) {
+        self.pure_expr(place, e);
+    }
+
+    pub fn add_module(&mut self, m: &ast::Module) {
+        for i in &m.children {
+            match i {
+                ast::ModuleItem::FunctionDecl(f) => self.add_function(f),
+                ast::ModuleItem::ClassDecl(c) => self.add_class(c),
+                ast::ModuleItem::MainBlock(b) => self.block("MainBlock", b),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn add_function(&mut self, f: &ast::FunctionDecl) {
+        if let ast::FunctionBody::Expr(e) = &f.body {
+            self.pure_expr("FunctionDecl.body", e);
+        }
+    }
+
+    pub fn add_class(&mut self, c: &ast::ClassDecl) {
+        for f in &c.fields {
+            if let Some(e) = &f.init {
+                self.pure_expr("FieldDecl.init", e);
+            }
+        }
+        if let Some(b) = &c.init {
+            self.block("ClassDecl.init", b);
+        }
+        for b in &c.recover {
+            self.slot("ClassDecl.recover", &b.right);
+        }
+        for m in &c.methods {
+            self.block("MethodDecl.body", &m.body);
+        }
+    }
+
+    fn block(&mut self, place: &'static str, b: &ast::Block) {
+        for s in &b.stmts {
+            self.stmt(place, s);
+        }
+    }
+
+    /// A statement in a slot, which is usually a block
+    fn slot(&mut self, place: &'static str, s: &ast::Stmt) {
+        match s {
+            ast::Stmt::Block(b) => self.block(place, b),
+            _ => self.stmt(place, s),
+        }
+    }
+
+    fn stmt(&mut self, place: &'static str, s: &ast::Stmt) {
+        self.add(place, s.kind());
+        match s {
+            ast::Stmt::VarDecl(d) => {
+                if let Some(e) = &d.init {
+                    self.expr("VarDeclStmt.init", e);
+                }
+            }
+            ast::Stmt::Assign(a) => self.expr("AssignStmt.expr", &a.expr),
+            ast::Stmt::Expr(e) => self.expr("ExprStmt.expr", &e.expr),
+            ast::Stmt::Assert(a) => self.pure_expr("AssertStmt.condition", &a.condition),
+            ast::Stmt::Await(a) => self.guard(&a.guard),
+            ast::Stmt::Throw(t) => self.pure_expr("ThrowStmt.expr", &t.expr),
+            ast::Stmt::Return(r) => self.expr("ReturnStmt.expr", &r.expr),
+            ast::Stmt::Block(b) => self.block("Block", b),
+            ast::Stmt::If(i) => {
+                self.pure_expr("IfStmt.condition", &i.condition);
+                self.slot("IfStmt.then", &i.then);
+                if let Some(e) = &i.r#else {
+                    self.slot("IfStmt.else", e);
+                }
+            }
+            ast::Stmt::Switch(s) => {
+                self.pure_expr("SwitchStmt.expr", &s.expr);
+                for b in &s.branches {
+                    self.slot("SwitchStmt.branch", &b.right);
+                }
+            }
+            ast::Stmt::While(w) => {
+                self.pure_expr("WhileStmt.condition", &w.condition);
+                self.slot("WhileStmt.body", &w.body);
+            }
+            ast::Stmt::Foreach(f) => {
+                self.pure_expr("ForeachStmt.iter", &f.iter);
+                self.slot("ForeachStmt.body", &f.body);
+            }
+            ast::Stmt::TryCatchFinally(t) => {
+                self.slot("TryCatchFinallyStmt.try", &t.r#try);
+                for b in &t.catch_branches {
+                    self.slot("TryCatchFinallyStmt.catch", &b.right);
+                }
+                if let Some(f) = &t.finally {
+                    self.slot("TryCatchFinallyStmt.finally", f);
+                }
+            }
+            ast::Stmt::Skip | ast::Stmt::Suspend => {}
+        }
+    }
+
+    fn guard(&mut self, g: &ast::Guard) {
+        match g {
+            ast::Guard::Expr(e) => self.pure_expr("AwaitStmt.guard", e),
+            ast::Guard::And(a, b) => {
+                self.guard(a);
+                self.guard(b);
+            }
+            ast::Guard::Duration(min, max) => {
+                self.pure_expr("AwaitStmt.guard", min);
+                self.pure_expr("AwaitStmt.guard", max);
+            }
+            ast::Guard::Claim { .. } => {}
+        }
+    }
+
+    fn expr(&mut self, place: &'static str, e: &ast::Expr) {
+        match e {
+            ast::Expr::Pure(e) => self.pure_expr(place, e),
+            ast::Expr::Eff(e) => self.eff_expr(place, e),
+        }
+    }
+
+    fn pure_expr(&mut self, place: &'static str, e: &ast::PureExpr) {
+        let kind = e.kind();
+        self.add(place, kind);
+        let mut sub = |e: &ast::PureExpr| self.pure_expr(kind, e);
+        match e {
+            ast::PureExpr::Let(l) => {
+                sub(&l.value);
+                sub(&l.inner);
+            }
+            ast::PureExpr::DataConstr(ast::DataConstrExpr { args, .. })
+            | ast::PureExpr::FnApp(ast::FnAppExpr { args, .. })
+            | ast::PureExpr::VariadicFnApp(ast::FnAppExpr { args, .. }) => {
+                args.iter().for_each(sub)
+            }
+            ast::PureExpr::If(ast::IfExpr {
+                condition,
+                then,
+                r#else,
+            })
+            | ast::PureExpr::When(ast::WhenExpr {
+                condition,
+                then,
+                r#else,
+            }) => {
+                sub(condition);
+                sub(then);
+                sub(r#else);
+            }
+            ast::PureExpr::Case(c) => {
+                sub(&c.expr);
+                c.branches.iter().for_each(|b| sub(&b.right));
+            }
+            ast::PureExpr::Operator(ast::OperatorExpr::Unary(u)) => sub(&u.expr),
+            ast::PureExpr::Operator(ast::OperatorExpr::Binary(b)) => {
+                sub(&b.left);
+                sub(&b.right);
+            }
+            ast::PureExpr::TypeCheck(ast::TypeCheckExpr { expr, .. })
+            | ast::PureExpr::TypeCast(ast::TypeCastExpr { expr, .. }) => sub(expr),
+            ast::PureExpr::Ident(_)
+            | ast::PureExpr::ThisIdent(_)
+            | ast::PureExpr::This
+            | ast::PureExpr::Null
+            | ast::PureExpr::Literal(_)
+            | ast::PureExpr::Destiny
+            | ast::PureExpr::ParFnApp(_) => {}
+            ast::PureExpr::TemplateString(t) => t.exprs.iter().for_each(sub),
+        }
+    }
+
+    fn eff_expr(&mut self, place: &'static str, e: &ast::EffExpr) {
+        let kind = e.kind();
+        self.add(place, kind);
+        let (callee, args): (_, &[ast::PureExpr]) = match e {
+            ast::EffExpr::New(n) => (None, &n.args),
+            ast::EffExpr::SyncCall(c) => (Some(&c.callee), &c.args),
+            ast::EffExpr::AsyncCall(c) => (Some(&c.callee), &c.args),
+            ast::EffExpr::Get(g) => (Some(&g.expr), &[]),
+            ast::EffExpr::Await(a) => (Some(&a.call.callee), &a.call.args),
+        };
+        for e in callee.into_iter().chain(args) {
+            self.pure_expr(kind, e);
+        }
+    }
+
+    /// Every pair of place and kind that generation or enumeration can
+    /// produce: statements in the places of statements, expressions with
+    /// side effects where they are allowed and pure expressions everywhere
+    /// else
+    fn all_pairs() -> Vec<(&'static str, &'static str)> {
+        let pairs = |places: &'static [&'static str], kinds: &'static [&'static str]| {
+            places
+                .iter()
+                .flat_map(move |p| kinds.iter().map(move |k| (*p, *k)))
+        };
+        pairs(&STMT_PLACES, &STMTS)
+            .chain(pairs(&ENUM_PLACES, &ENUM_STMTS))
+            .chain(pairs(&EFF_PLACES, &EFFS))
+            .chain(pairs(&EFF_PLACES, &PURES))
+            .chain(pairs(&PURE_PLACES, &PURES))
+            .chain(pairs(&PARENTS, &PURES))
+            .chain(FIXED.iter().copied())
+            .collect()
+    }
+
+    /// How many of the pairs that can be produced were generated
+    fn covered_pairs(&self) -> usize {
+        Self::all_pairs()
+            .iter()
+            .filter(|p| self.pairs.contains_key(p))
+            .count()
+    }
+
+    /// Writes how many of the kinds and pairs that can be produced were
+    /// covered, which were not and how often every one was
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let pairs = Self::all_pairs();
+        let kinds: Vec<_> = ast::Stmt::KINDS
+            .iter()
+            .chain(&ast::PureExpr::KINDS)
+            .chain(&ast::EffExpr::KINDS)
+            .filter(|k| pairs.iter().any(|(_, kind)| kind == *k))
+            .copied()
+            .collect();
+
+        let mut f = File::create(path)?;
+        writeln!(
+            f,
+            "kinds {} of {}",
+            kinds.iter().filter(|k| self.kinds.contains_key(*k)).count(),
+            kinds.len()
+        )?;
+        writeln!(f, "pairs {} of {}", self.covered_pairs(), pairs.len())?;
+        for k in &kinds {
+            writeln!(f, "kind {} {}", k, self.kinds.get(k).copied().unwrap_or(0))?;
+        }
+        for ((place, kind), n) in &self.pairs {
+            writeln!(f, "pair {} {} {}", place, kind, n)?;
+        }
+        for (place, kind) in pairs.iter().filter(|p| !self.pairs.contains_key(p)) {
+            writeln!(f, "uncovered {} {}", place, kind)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{target, Options, StmtWeights};
+
+    /// What the modules generated from `seeds` cover, each one steered by
+    /// the ones before it
+    fn cover(opts: Options, seeds: std::ops::Range<u64>) -> Coverage {
+        let mut corpus = Coverage::default();
+        for seed in seeds {
+            let (module, _) =
+                crate::gen_mock_module(opts, &corpus, &mut StdRng::seed_from_u64(seed));
+            corpus.add_module(&module);
+        }
+        corpus
+    }
+
+    /// Every kind of statement and expression, with functions and data
+    /// types to call and match
+    fn everything(target: &str) -> Options {
+        Options {
+            num_rand_classes: 10,
+            num_methods: 3,
+            num_interfaces: 2,
+            num_class_params: 2,
+            num_data_types: 2,
+            num_functions: 3,
+            functional_rate: 0.1,
+            branch_rate: 0.3,
+            weights: StmtWeights {
+                r#if: 1.0,
+                r#while: 1.0,
+                foreach: 1.0,
+                switch: 1.0,
+                r#try: 1.0,
+                r#await: 1.0,
+                assert: 1.0,
+                suspend: 1.0,
+                throw: 1.0,
+            },
+            target: target::from_name(target).unwrap(),
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn steering_covers_more_pairs() {
+        let opts = everything("nullable");
+        let unsteered = cover(opts, 0..5).covered_pairs();
+        let steered = cover(Options { steer: 3.0, ..opts }, 0..5).covered_pairs();
+        assert!(
+            steered > unsteered,
+            "{} pairs steered, {} unsteered",
+            steered,
+            unsteered
+        );
+    }
+
+    #[test]
+    fn every_pair_generated_can_be_produced() {
+        let all = Coverage::all_pairs();
+        for t in &["crowbar", "location", "nullable"] {
+            let opts = Options {
+                steer: 20.0,
+                defect_rate: 0.5,
+                ..everything(t)
+            };
+            for pair in cover(opts, 0..3).pairs.keys() {
+                assert!(all.contains(pair), "{:?} in modules for {}", pair, t);
+            }
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! Every method body up to a number of statements and a depth of
+//! expressions, in the manner of SmallCheck.
+//!
+//! The bodies are made of declarations, assignments, calls, `skip`,
+//! `suspend`, `assert`, `await`, `throw`, `if` and `while`, as far as the
+//! target allows them. Expressions are literals `0`, `1`, `True` and `False`,
+//! `null`, variables, `+`, `-`, `!`, `&&`, `==` and `<`, with calls, `new`
+//! and `get` at the top. A compound statement counts as one statement plus
+//! the ones in it. Variables are named `v0`, `v1`, ... in the order they are
+//! declared, so no two bodies differ only in their names.
+
+use std::{
+    fs::File,
+    io::{self, prelude::*, BufWriter},
+};
+
+use abs_syntax::ast::{self, DisplayABS};
+
+use crate::{
+    config::Settings,
+    coverage::Coverage,
+    gen::{self, ty},
+    generator::{EntryKind, Scope, EXCEPTION},
+    landscape::{ClassShape, Landscape},
+    target::{Form, Target},
+};
+
+pub struct Enumeration {
+    pub max_stmts: usize,
+    pub max_depth: u32,
+    /// How many bodies go into one module, each as a method
+    pub per_module: usize,
+}
+
+/// The class the bodies are methods of
+const CLASS: &str = "C";
+
+/// The types of declared variables
+fn var_types() -> Vec<ast::Type> {
+    vec![
+        ty::create_int(),
+        ty::create_bool(),
+        ty::simple_ty("I"),
+        ty::simple_ty("J"),
+        ty::create_fut(ty::create_int()),
+        ty::create_fut(ty::create_bool()),
+    ]
+}
+
+/// A statement, the variable it declares and the number of the next
+/// variable after it
+type Enumerated = (ast::Stmt, Option<(ast::Type, ast::Ident)>, usize);
+
+struct Enumerator<'a> {
+    target: Target,
+    landscape: &'a Landscape,
+    max_depth: u32,
+}
+
+impl Enumerator<'_> {
+    /// Literals, variables and `null` of type `ty`
+    fn atoms(&self, scope: &Scope, ty: &ast::Type) -> Vec<ast::PureExpr> {
+        let lits: &[&str] = if ty.is_int() {
+            &["0", "1"]
+        } else if ty.is_bool() {
+            &["True", "False"]
+        } else {
+            &[]
+        };
+        let mut atoms: Vec<ast::PureExpr> =
+            lits.iter().map(|l| gen::create_lit(*l).into()).collect();
+        atoms.extend(scope.of_type(ty).map(|e| {
+            ast::PureExpr::Ident(ast::IdentExpr {
+                ident: e.ident.clone(),
+            })
+        }));
+        if self.landscape.is_object(ty) {
+            atoms.push(gen::create_null());
+        }
+        atoms
+    }
+
+    /// The pure expressions of type `ty` up to `depth`
+    fn pure(&self, scope: &Scope, ty: &ast::Type, depth: u32) -> Vec<ast::PureExpr> {
+        let mut es = self.atoms(scope, ty);
+        if depth == 0 {
+            return es;
+        }
+
+        let int = ty::create_int();
+        let bin = |op, ls: &[ast::PureExpr], rs: &[ast::PureExpr]| -> Vec<ast::PureExpr> {
+            ls.iter()
+                .flat_map(|l| {
+                    rs.iter()
+                        .map(move |r| gen::create_bin_expr(op, l.clone(), r.clone()).into())
+                })
+                .collect()
+        };
+        if ty.is_int() {
+            let sub = self.pure(scope, &int, depth - 1);
+            es.extend(bin(ast::BinaryOp::Plus, &sub, &sub));
+            es.extend(bin(ast::BinaryOp::Minus, &sub, &sub));
+        } else if ty.is_bool() {
+            let sub = self.pure(scope, ty, depth - 1);
+            let ints = self.pure(scope, &int, depth - 1);
+            es.extend(sub.iter().map(|e| {
+                ast::PureExpr::from(ast::UnaryExpr {
+                    op: ast::UnaryOp::Not,
+                    expr: e.clone().into(),
+                })
+            }));
+            es.extend(bin(ast::BinaryOp::And, &sub, &sub));
+            es.extend(bin(ast::BinaryOp::Eq, &ints, &ints));
+            es.extend(bin(ast::BinaryOp::Lt, &ints, &ints));
+        }
+        es
+    }
+
+    /// Every call of a method returning `ret` on a variable in scope, with
+    /// atoms as arguments
+    fn calls(
+        &self,
+        scope: &Scope,
+        ret: &ast::Type,
+    ) -> Vec<(ast::PureExpr, ast::Ident, Vec<ast::PureExpr>)> {
+        let mut calls = Vec::new();
+        for f in scope.callable_of_type(ret) {
+            let mut arg_lists = vec![Vec::new()];
+            for a in &f.args {
+                let atoms = self.atoms(scope, a);
+                arg_lists = arg_lists
+                    .iter()
+                    .flat_map(|args| {
+                        atoms.iter().map(move |a| {
+                            let mut args: Vec<_> = args.clone();
+                            args.push(a.clone());
+                            args
+                        })
+                    })
+                    .collect();
+            }
+            for t in &f.defined_for {
+                for callee in scope.of_type(t) {
+                    for args in &arg_lists {
+                        let callee = ast::PureExpr::Ident(ast::IdentExpr {
+                            ident: callee.ident.clone(),
+                        });
+                        calls.push((callee, f.ident.clone(), args.clone()));
+                    }
+                }
+            }
+        }
+        calls
+    }
+
+    /// The expressions with side effects of type `ty`
+    fn effectful(&self, scope: &Scope, ty: &ast::Type) -> Vec<ast::EffExpr> {
+        let mut es: Vec<ast::EffExpr> = Vec::new();
+        if self.target.allows(Form::New) {
+            for c in self.landscape.constructors(ty) {
+                es.push(gen::start_new_expr(false, c.class).complete().into());
+            }
+        }
+        if self.target.allows(Form::SyncCall) {
+            for (callee, method, args) in self.calls(scope, ty) {
+                es.push(
+                    ast::SyncCallExpr {
+                        callee,
+                        method,
+                        args,
+                    }
+                    .into(),
+                );
+            }
+        }
+        if ty.is_fut() && self.target.allows(Form::AsyncCall) {
+            for (callee, method, args) in self.calls(scope, &ty.args[0]) {
+                es.push(
+                    ast::AsyncCallExpr {
+                        callee,
+                        method,
+                        args,
+                    }
+                    .into(),
+                );
+            }
+        }
+        if !ty.is_fut() && self.target.allows(Form::Get) {
+            for f in scope.of_type(&ty::create_fut(ty.clone())) {
+                es.push(
+                    ast::GetExpr {
+                        expr: ast::PureExpr::Ident(ast::IdentExpr {
+                            ident: f.ident.clone(),
+                        }),
+                    }
+                    .into(),
+                );
+            }
+        }
+        es
+    }
+
+    /// The right-hand sides of declarations and assignments
+    fn exprs(&self, scope: &Scope, ty: &ast::Type) -> Vec<ast::Expr> {
+        let mut es: Vec<ast::Expr> = self
+            .pure(scope, ty, self.max_depth)
+            .into_iter()
+            .map(ast::Expr::Pure)
+            .collect();
+        es.extend(self.effectful(scope, ty).into_iter().map(ast::Expr::Eff));
+        es
+    }
+
+    /// The statements that are `size` statements long, declaring `v<next>`
+    /// if they declare a variable
+    fn stmts(&self, scope: &Scope, size: usize, next: usize) -> Vec<Enumerated> {
+        let mut res: Vec<Enumerated> = Vec::new();
+        let bool_ty = ty::create_bool();
+        if size == 1 {
+            res.push((ast::Stmt::Skip, None, next));
+            for ty in var_types() {
+                let ident = gen::ident(format!("v{}", next));
+                for init in self.exprs(scope, &ty) {
+                    let decl =
+                        gen::create_var_decl_init(ty.clone(), &ident.str, init, gen::empty_annos());
+                    res.push((decl.into(), Some((ty.clone(), ident.clone())), next + 1));
+                }
+            }
+            for v in scope.assignable() {
+                for expr in self.exprs(scope, &v.ty) {
+                    let assign = ast::AssignStmt {
+                        this: false,
+                        ident: v.ident.clone(),
+                        expr,
+                    };
+                    res.push((assign.into(), None, next));
+                }
+            }
+            for f in scope.functions() {
+                let calls = self
+                    .calls(scope, &f.ty)
+                    .into_iter()
+                    .filter(|(_, m, _)| *m == f.ident);
+                for (callee, method, args) in calls {
+                    if self.target.allows(Form::SyncCall) {
+                        let call = ast::SyncCallExpr {
+                            callee: callee.clone(),
+                            method: method.clone(),
+                            args: args.clone(),
+                        };
+                        let expr = ast::Expr::Eff(call.into());
+                        res.push((ast::ExprStmt { expr }.into(), None, next));
+                    }
+                    if self.target.allows(Form::AsyncCall) {
+                        let call = ast::AsyncCallExpr {
+                            callee,
+                            method,
+                            args,
+                        };
+                        let expr = ast::Expr::Eff(call.into());
+                        res.push((ast::ExprStmt { expr }.into(), None, next));
+                    }
+                }
+            }
+            if self.target.allows(Form::Suspend) {
+                res.push((ast::Stmt::Suspend, None, next));
+            }
+            if self.target.allows(Form::Assert) {
+                for condition in self.pure(scope, &bool_ty, self.max_depth) {
+                    res.push((ast::Stmt::Assert(ast::AssertStmt { condition }), None, next));
+                }
+            }
+            if self.target.allows(Form::Await) {
+                let futs = scope.futures().map(|e| ast::Guard::Claim {
+                    this: e.kind == EntryKind::Field,
+                    ident: e.ident.clone(),
+                });
+                let conds = self
+                    .pure(scope, &bool_ty, self.max_depth)
+                    .into_iter()
+                    .map(ast::Guard::Expr);
+                for guard in futs.chain(conds) {
+                    res.push((ast::Stmt::Await(ast::AwaitStmt { guard }), None, next));
+                }
+            }
+            if self.target.allows(Form::Throw) {
+                let expr = gen::create_data_constr(EXCEPTION).into();
+                res.push((ast::Stmt::Throw(ast::ThrowStmt { expr }), None, next));
+            }
+        }
+
+        let inner = size - 1;
+        for condition in self.pure(scope, &bool_ty, self.max_depth) {
+            for (then, after_then) in self.blocks(scope, inner, next) {
+                let stmt = ast::IfStmt {
+                    condition: condition.clone(),
+                    then: Box::new(then.into()),
+                    r#else: None,
+                };
+                res.push((stmt.into(), None, after_then));
+            }
+            for then_size in 0..=inner {
+                for (then, after_then) in self.blocks(scope, then_size, next) {
+                    for (r#else, after_else) in self.blocks(scope, inner - then_size, after_then) {
+                        let stmt = ast::IfStmt {
+                            condition: condition.clone(),
+                            then: Box::new(then.clone().into()),
+                            r#else: Some(Box::new(r#else.into())),
+                        };
+                        res.push((stmt.into(), None, after_else));
+                    }
+                }
+            }
+            if self.target.allows(Form::While) {
+                for (body, after) in self.blocks(scope, inner, next) {
+                    let stmt = ast::WhileStmt {
+                        annotations: gen::empty_annos(),
+                        condition: condition.clone(),
+                        body: Box::new(body.into()),
+                    };
+                    res.push((stmt.into(), None, after));
+                }
+            }
+        }
+        res
+    }
+
+    /// The blocks of `size` statements, each with the number of the next
+    /// variable after it
+    fn blocks(&self, scope: &Scope, size: usize, next: usize) -> Vec<(ast::Block, usize)> {
+        let mut scope = scope.clone();
+        scope.open();
+        let mut blocks = Vec::new();
+        let _ = self.seqs(&scope, size, next, &mut Vec::new(), &mut |stmts, after| {
+            blocks.push((
+                ast::Block {
+                    stmts: stmts.to_vec(),
+                },
+                after,
+            ));
+            Ok(())
+        });
+        blocks
+    }
+
+    /// Calls `f` with every sequence of `size` statements after `prefix`
+    fn seqs(
+        &self,
+        scope: &Scope,
+        size: usize,
+        next: usize,
+        prefix: &mut Vec<ast::Stmt>,
+        f: &mut dyn FnMut(&[ast::Stmt], usize) -> io::Result<()>,
+    ) -> io::Result<()> {
+        if size == 0 {
+            return f(prefix, next);
+        }
+        for first in 1..=size {
+            for (stmt, decl, after) in self.stmts(scope, first, next) {
+                let mut scope = scope.clone();
+                if let Some((ty, ident)) = decl {
+                    scope.define_var(ty, ident);
+                }
+                prefix.push(stmt);
+                self.seqs(&scope, size - first, after, prefix, f)?;
+                prefix.pop();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes every body of up to `max_stmts` statements, `per_module` of them
+/// to a module, as the methods `body_0`, `body_1`, ... of the class `C`
+pub fn run(settings: &Settings, e: &Enumeration) -> io::Result<()> {
+    let target = settings.opts.target;
+    let landscape = Landscape::empty();
+    let class = ClassShape {
+        name: CLASS.to_string(),
+        implements: Vec::new(),
+        params: Vec::new(),
+        obj_fields: Vec::new(),
+    };
+    let enumerator = Enumerator {
+        target,
+        landscape: &landscape,
+        max_depth: e.max_depth,
+    };
+    let scope = Scope::for_class(&landscape, &class);
+
+    let mut coverage = Coverage::default();
+    let mut methods = Vec::new();
+    let mut bodies = 0;
+    let mut modules = 0;
+    let mut write = |methods: &mut Vec<ast::MethodDecl>, first: usize| -> io::Result<()> {
+        let name = settings
+            .pattern
+            .replace("{target}", target.short_name())
+            .replace("{classes}", "1")
+            .replace("{point}", &modules.to_string());
+        let path = settings.out_dir.join(name);
+
+        let mut class = crate::with_std_fields(gen::start_class_decl(CLASS));
+        for m in methods.drain(..) {
+            class.add_method(m);
+        }
+        let module = crate::start_mock_module(target, target.allows(Form::Throw))
+            .with_child(class.complete())
+            .with_child(ast::ModuleItem::MainBlock(ast::Block { stmts: Vec::new() }))
+            .complete();
+        coverage.add_module(&module);
+
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(
+            out,
+            "// Enumerated by abs_rand_module: bodies {} to {} of up to {} statements with \
+             expressions of depth up to {}\n",
+            first,
+            first + e.per_module - 1,
+            e.max_stmts,
+            e.max_depth
+        )?;
+        module.write_abs(&mut out)?;
+        out.flush()?;
+        modules += 1;
+        Ok(())
+    };
+
+    for size in 0..=e.max_stmts {
+        enumerator.seqs(&scope, size, 0, &mut Vec::new(), &mut |stmts, _| {
+            let sig = gen::start_method_sig(format!("body_{}", bodies))
+                .with_ret(ty::create_unit())
+                .complete();
+            methods.push(gen::create_method_decl(
+                sig,
+                ast::Block {
+                    stmts: stmts.to_vec(),
+                },
+            ));
+            bodies += 1;
+            if methods.len() == e.per_module {
+                write(&mut methods, bodies - e.per_module)?;
+            }
+            Ok(())
+        })?;
+    }
+    if !methods.is_empty() {
+        let first = bodies - methods.len();
+        write(&mut methods, first)?;
+    }
+
+    coverage.write(&settings.out_dir.join("coverage.txt"))?;
+    println!("Enumerated {} bodies into {} modules", bodies, modules);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use abs_syntax::ast::AlphaEq;
+
+    use super::*;
+    use crate::Options;
+
+    /// `code` with the numbers of the variables `v<n>` left out
+    fn unnamed(code: &str) -> String {
+        let mut out = String::new();
+        let mut chars = code.chars().peekable();
+        let mut in_ident = false;
+        while let Some(c) = chars.next() {
+            out.push(c);
+            if c == 'v' && !in_ident {
+                while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    chars.next();
+                }
+            }
+            in_ident = c.is_alphanumeric() || c == '_';
+        }
+        out
+    }
+
+    #[test]
+    fn no_two_bodies_differ_only_in_their_names() {
+        let landscape = Landscape::empty();
+        let class = ClassShape {
+            name: CLASS.to_string(),
+            implements: Vec::new(),
+            params: Vec::new(),
+            obj_fields: Vec::new(),
+        };
+        let enumerator = Enumerator {
+            target: Options::default().target,
+            landscape: &landscape,
+            max_depth: 0,
+        };
+        let scope = Scope::for_class(&landscape, &class);
+
+        let mut bodies: Vec<ast::Block> = Vec::new();
+        for size in 0..=2 {
+            enumerator
+                .seqs(&scope, size, 0, &mut Vec::new(), &mut |stmts, _| {
+                    bodies.push(ast::Block {
+                        stmts: stmts.to_vec(),
+                    });
+                    Ok(())
+                })
+                .unwrap();
+        }
+
+        assert!(bodies.len() > 100);
+        // Bodies can only be alike if they are once the numbers of their
+        // variables are left out
+        let mut alike: HashMap<String, Vec<&ast::Block>> = HashMap::new();
+        for body in &bodies {
+            alike
+                .entry(unnamed(&body.to_string()))
+                .or_default()
+                .push(body);
+        }
+        for group in alike.values() {
+            for (i, a) in group.iter().enumerate() {
+                if let Some(b) = group[..i].iter().find(|b| a.alpha_eq(b)) {
+                    panic!("{} and {} differ only in their names", b, a);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,492 @@
+//! Differential testing: the same module is given to several tools, and the
+//! modules they disagree on are kept for triage.
+//!
+//! A tool is a local command, run with the path of the module as its last
+//! argument. It accepts the module by exiting with 0 and rejects it with any
+//! other exit code. It crashes if it is killed, runs out of time or reports
+//! an uncaught exception or a panic. The lines of a rejected module it
+//! reports a diagnostic at are the numbers after `<module>:` in its output.
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{config::Settings, coverage::Coverage, sweep::Dim, Manifest, Options};
+
+/// Output that means the tool crashed, whatever its exit code
+const CRASH_MARKERS: [&str; 3] = ["Exception in thread", "panicked at", "StackOverflowError"];
+
+/// How often a running tool is checked for having exited
+const POLL: Duration = Duration::from_millis(10);
+
+/// A tool as named in the triage files and the command running it
+pub struct Tool {
+    pub name: String,
+    pub command: Vec<String>,
+}
+
+pub struct Fuzz {
+    pub runs: usize,
+    /// The numbers of classes, used in turn
+    pub classes: Vec<u32>,
+    pub tools: Vec<Tool>,
+    pub triage_dir: PathBuf,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Verdict {
+    Accepted,
+    Rejected,
+    /// Why the tool is considered to have crashed
+    Crashed(String),
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Accepted => write!(f, "accepted"),
+            Verdict::Rejected => write!(f, "rejected"),
+            Verdict::Crashed(why) => write!(f, "crashed ({})", why),
+        }
+    }
+}
+
+/// What a tool made of a module. Its output is kept in `log`.
+struct Outcome {
+    verdict: Verdict,
+    lines: BTreeSet<usize>,
+    log: PathBuf,
+}
+
+/// How the tools disagree, the worst first
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Disagreement {
+    /// A tool crashed
+    Crash,
+    /// One tool accepted the module and another rejected it
+    Verdict,
+    /// All tools rejected the module, but not at the same lines
+    Diagnostics,
+}
+
+impl Disagreement {
+    fn name(&self) -> &'static str {
+        match self {
+            Disagreement::Crash => "crash",
+            Disagreement::Verdict => "verdict",
+            Disagreement::Diagnostics => "diagnostics",
+        }
+    }
+
+    fn of(outcomes: &[Outcome]) -> Option<Self> {
+        if outcomes
+            .iter()
+            .any(|o| matches!(o.verdict, Verdict::Crashed(_)))
+        {
+            return Some(Disagreement::Crash);
+        }
+        let mut rejected = outcomes.iter().filter(|o| o.verdict == Verdict::Rejected);
+        let first = rejected.next()?;
+        if outcomes.iter().any(|o| o.verdict == Verdict::Accepted) {
+            return Some(Disagreement::Verdict);
+        }
+        if rejected.any(|o| o.lines != first.lines) {
+            return Some(Disagreement::Diagnostics);
+        }
+        None
+    }
+}
+
+/// Generates `runs` modules, runs every tool on each of them and copies the
+/// modules the tools disagree on to the triage directory
+pub fn run(settings: &Settings, fuzz: &Fuzz) -> io::Result<()> {
+    fs::create_dir_all(&fuzz.triage_dir)?;
+
+    let mut seeds = StdRng::seed_from_u64(settings.seed);
+    let mut manifest = Manifest::new(settings);
+    let mut disagreed = 0;
+    let mut coverage = Coverage::default();
+    for point in 0..fuzz.runs {
+        let opts = Options {
+            num_rand_classes: fuzz.classes[point % fuzz.classes.len()],
+            ..settings.opts
+        };
+        let name = settings
+            .pattern
+            .replace("{target}", opts.target.short_name())
+            .replace("{classes}", &opts.num_rand_classes.to_string())
+            .replace("{point}", &point.to_string());
+        let path = settings.out_dir.join(name);
+
+        let seed = seeds.gen();
+        manifest.add(&path, seed, &opts);
+        let covered = crate::write_module(&path, opts, seed, &coverage)?;
+        coverage.merge(&covered);
+        let outcomes = fuzz
+            .tools
+            .iter()
+            .map(|t| run_tool(t, &path, fuzz.timeout))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        match Disagreement::of(&outcomes) {
+            Some(d) => {
+                disagreed += 1;
+                let verdicts: Vec<_> = fuzz
+                    .tools
+                    .iter()
+                    .zip(&outcomes)
+                    .map(|(t, o)| format!("{} {}", t.name, o.verdict))
+                    .collect();
+                println!("seed {}: {}, {}", seed, d.name(), verdicts.join(", "));
+                triage(fuzz, &path, seed, &opts, d, &outcomes)?;
+            }
+            None => {
+                for o in &outcomes {
+                    fs::remove_file(&o.log)?;
+                }
+            }
+        }
+        remove_module(&path)?;
+    }
+
+    coverage.write(&settings.out_dir.join("coverage.txt"))?;
+    manifest.write(&settings.out_dir)?;
+    println!(
+        "The tools disagreed on {} of {} modules, kept in {}",
+        disagreed,
+        fuzz.runs,
+        fuzz.triage_dir.display()
+    );
+    Ok(())
+}
+
+/// Runs `tool` on the module at `path`, killing it after `timeout`
+fn run_tool(tool: &Tool, path: &Path, timeout: Duration) -> io::Result<Outcome> {
+    let log = path.with_extension(format!("{}.log", tool.name));
+    let out = File::create(&log)?;
+    let mut child = Command::new(&tool.command[0])
+        .args(&tool.command[1..])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(out.try_clone()?)
+        .stderr(out)
+        .spawn()
+        .map_err(|e| {
+            let _ = fs::remove_file(&log);
+            io::Error::new(e.kind(), format!("{}: {}", tool.command[0], e))
+        })?;
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if start.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            break None;
+        }
+        thread::sleep(POLL);
+    };
+
+    let output = String::from_utf8_lossy(&fs::read(&log)?).into_owned();
+    let verdict = match status {
+        None => Verdict::Crashed(format!("timed out after {}s", timeout.as_secs())),
+        Some(s) => match s.code() {
+            None => Verdict::Crashed("killed by a signal".to_string()),
+            Some(_) if CRASH_MARKERS.iter().any(|m| output.contains(m)) => {
+                Verdict::Crashed("uncaught exception or panic".to_string())
+            }
+            Some(0) => Verdict::Accepted,
+            Some(_) => Verdict::Rejected,
+        },
+    };
+    let lines = match verdict {
+        Verdict::Rejected => diagnostic_lines(&output, path),
+        _ => BTreeSet::new(),
+    };
+
+    Ok(Outcome {
+        verdict,
+        lines,
+        log,
+    })
+}
+
+/// The lines of the module that `output` reports diagnostics at, as in
+/// `generated.abs:12:5: ...` or `out/generated.abs:12`
+fn diagnostic_lines(output: &str, path: &Path) -> BTreeSet<usize> {
+    let file = format!("{}:", path.file_name().unwrap().to_string_lossy());
+    output
+        .match_indices(&file)
+        .filter_map(|(i, _)| {
+            let rest = &output[i + file.len()..];
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            rest[..end].parse().ok()
+        })
+        .collect()
+}
+
+/// Moves the module and the output of the tools to the triage directory,
+/// named after the seed, describes the disagreement in `<seed>.txt` and
+/// lists it in `disagreements.csv`
+fn triage(
+    fuzz: &Fuzz,
+    path: &Path,
+    seed: u64,
+    opts: &Options,
+    d: Disagreement,
+    outcomes: &[Outcome],
+) -> io::Result<()> {
+    let dir = &fuzz.triage_dir;
+    fs::copy(path, dir.join(format!("{}.abs", seed)))?;
+
+    let mut report = File::create(dir.join(format!("{}.txt", seed)))?;
+    writeln!(report, "seed {}", seed)?;
+    writeln!(report, "target {}", opts.target.name())?;
+    for dim in Dim::all() {
+        writeln!(report, "{} {}", dim.name(), dim.get(opts))?;
+    }
+    for (name, w) in opts.weights.iter() {
+        writeln!(report, "weight {} {}", name, w)?;
+    }
+    writeln!(report, "naming {}", opts.naming.name())?;
+    writeln!(report, "unique_names {}", opts.unique_names)?;
+    writeln!(report, "disagreement {}", d.name())?;
+    for (t, o) in fuzz.tools.iter().zip(outcomes) {
+        match o.verdict {
+            Verdict::Rejected => {
+                let lines: Vec<_> = o.lines.iter().map(|l| l.to_string()).collect();
+                writeln!(
+                    report,
+                    "{} rejected at lines [{}]",
+                    t.name,
+                    lines.join(", ")
+                )?
+            }
+            _ => writeln!(report, "{} {}", t.name, o.verdict)?,
+        }
+        fs::rename(&o.log, dir.join(format!("{}.{}.log", seed, t.name)))?;
+    }
+
+    let csv = dir.join("disagreements.csv");
+    let new = !csv.exists();
+    let mut csv = OpenOptions::new().create(true).append(true).open(csv)?;
+    if new {
+        let names: Vec<_> = fuzz.tools.iter().map(|t| t.name.as_str()).collect();
+        writeln!(csv, "seed,target,classes,disagreement,{}", names.join(","))?;
+    }
+    let verdicts: Vec<_> = outcomes.iter().map(|o| o.verdict.to_string()).collect();
+    writeln!(
+        csv,
+        "{},{},{},{},{}",
+        seed,
+        opts.target.short_name(),
+        opts.num_rand_classes,
+        d.name(),
+        verdicts.join(",")
+    )
+}
+
+/// Removes the module and, if defects were planted, its expected diagnostics
+fn remove_module(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    let expected = path.with_extension("expected.json");
+    if expected.exists() {
+        fs::remove_file(expected)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tool running `script` with `sh`, which gets the module as `$0`
+    fn sh(name: &str, script: &str) -> Tool {
+        Tool {
+            name: name.to_string(),
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+        }
+    }
+
+    /// Fuzzes `runs` modules with `tools` in `dir` and reads back the
+    /// triage directory, sorted by file name
+    fn fuzz(dir: &Path, tools: Vec<Tool>, runs: usize) -> Vec<(String, String)> {
+        let settings = Settings {
+            seed: 7,
+            out_dir: dir.to_path_buf(),
+            pattern: "fuzz-{point}.abs".to_string(),
+            clean: false,
+            jobs: 1,
+            opts: Options::default(),
+            targets: Vec::new(),
+            sweep: None,
+            fuzz: None,
+            enumerate: None,
+            mutate: None,
+            learn: None,
+        };
+        let fuzz = Fuzz {
+            runs,
+            classes: vec![1, 2],
+            tools,
+            triage_dir: dir.join("triage"),
+            timeout: Duration::from_secs(60),
+        };
+        run(&settings, &fuzz).unwrap();
+
+        // Only the manifest and the coverage are left next to the triage
+        let mut left: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["coverage.txt", "manifest.txt", "triage"]);
+
+        let mut files: Vec<_> = fs::read_dir(&fuzz.triage_dir)
+            .unwrap()
+            .map(|e| {
+                let path = e.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, fs::read_to_string(&path).unwrap())
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("abs_rand_module-{}-{}", name, std::process::id()))
+    }
+
+    fn outcome(verdict: Verdict, lines: &[usize]) -> Outcome {
+        Outcome {
+            verdict,
+            lines: lines.iter().copied().collect(),
+            log: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn modules_the_tools_agree_on_are_not_kept() {
+        let dir = temp_dir("fuzz-agree");
+        let tools = vec![sh("a", "exit 0"), sh("b", "true")];
+        let files = fuzz(&dir, tools, 2);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(files.is_empty());
+    }
+
+    #[test]
+    fn disagreements_are_kept_for_triage() {
+        let dir = temp_dir("fuzz-disagree");
+        let tools = vec![
+            sh("accept", "exit 0"),
+            sh("reject", r#"echo "$(basename "$0"):3:5: error"; exit 1"#),
+            sh(
+                "panic",
+                "echo 'thread main panicked at src/main.rs:1'; exit 0",
+            ),
+        ];
+        let files = fuzz(&dir, tools, 2);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (csv, lines) = files.last().unwrap();
+        assert_eq!(csv, "disagreements.csv");
+        let rows: Vec<_> = lines.lines().collect();
+        assert_eq!(
+            rows[0],
+            "seed,target,classes,disagreement,accept,reject,panic"
+        );
+        assert_eq!(rows.len(), 3);
+
+        for (row, classes) in rows[1..].iter().zip(["1", "2"]) {
+            let cols: Vec<_> = row.split(',').collect();
+            let seed = cols[0];
+            assert_eq!(
+                cols[1..],
+                [
+                    "cb",
+                    classes,
+                    "crash",
+                    "accepted",
+                    "rejected",
+                    "crashed (uncaught exception or panic)"
+                ]
+            );
+
+            let file = |ext: &str| {
+                let name = format!("{}.{}", seed, ext);
+                &files.iter().find(|(n, _)| *n == name).unwrap().1
+            };
+            assert!(file("abs").contains("module MockABS;"));
+            assert!(file("reject.log").contains(":3:5: error"));
+            assert!(file("panic.log").contains("panicked at"));
+            assert!(file("accept.log").is_empty());
+
+            let report = file("txt");
+            assert!(report.starts_with(&format!("seed {}\ntarget crowbar\n", seed)));
+            for line in [
+                "naming random",
+                "unique_names false",
+                "disagreement crash",
+                "accept accepted",
+                "reject rejected at lines [3]",
+                "panic crashed (uncaught exception or panic)",
+            ] {
+                assert!(report.lines().any(|l| l == line), "{} in {}", line, report);
+            }
+        }
+        // A module, its report and three logs per disagreement
+        assert_eq!(files.len(), 2 * 5 + 1);
+    }
+
+    #[test]
+    fn the_worst_disagreement_is_found() {
+        use Verdict::*;
+
+        let crashed = Crashed("killed by a signal".to_string());
+        let of = |outcomes: &[Outcome]| Disagreement::of(outcomes);
+        assert_eq!(
+            of(&[outcome(Accepted, &[]), outcome(crashed, &[])]),
+            Some(Disagreement::Crash)
+        );
+        assert_eq!(
+            of(&[outcome(Accepted, &[]), outcome(Rejected, &[1])]),
+            Some(Disagreement::Verdict)
+        );
+        assert_eq!(
+            of(&[outcome(Rejected, &[1, 2]), outcome(Rejected, &[2])]),
+            Some(Disagreement::Diagnostics)
+        );
+        assert_eq!(
+            of(&[outcome(Rejected, &[2]), outcome(Rejected, &[2])]),
+            None
+        );
+        assert_eq!(of(&[outcome(Accepted, &[]), outcome(Accepted, &[])]), None);
+    }
+
+    #[test]
+    fn diagnostics_are_found_at_the_lines_of_the_module() {
+        let output = "out/fuzz-1.abs:12:5: error\n\
+                      fuzz-1.abs:3 warning\n\
+                      other.abs:7: error\n\
+                      fuzz-1.abs: no line\n\
+                      at fuzz-1.abs:12\n";
+        let lines = diagnostic_lines(output, Path::new("out/fuzz-1.abs"));
+        assert_eq!(lines.into_iter().collect::<Vec<_>>(), [3, 12]);
+    }
+}
@@ -1,6 +1,6 @@
 use abs_syntax::ast;
 
-use crate::{gen, Target};
+use crate::gen;
 
 pub fn empty_annos() -> ast::Annotations {
     ast::Annotations::default()
@@ -22,34 +22,28 @@ pub fn create_nullable_non_null() -> ast::Annotation {
     create_untyped_anno(gen::create_data_constr("NonNull").into())
 }
 
-pub fn create_crowbar_non_null_param<S: Into<String>>(var: S) -> ast::Annotation {
+/// A `Spec` annotation, e.g. `[Spec: Requires(x > 0)]`
+pub fn create_crowbar_spec<S: Into<String>>(kind: S, e: ast::PureExpr) -> ast::Annotation {
     create_typed_anno(
         gen::ty::simple_ty("Spec"),
-        gen::create_data_constr_args(
-            "Requires",
-            vec![
-                gen::create_ne_expr(gen::create_var_use(var).into(), gen::create_null().into())
-                    .into(),
-            ],
-        )
-        .into(),
+        gen::create_data_constr_args(kind, vec![e]).into(),
+    )
+}
+
+pub fn create_crowbar_non_null_param<S: Into<String>>(var: S) -> ast::Annotation {
+    create_crowbar_spec(
+        "Requires",
+        gen::create_ne_expr(gen::create_var_use(var).into(), gen::create_null()).into(),
+    )
+}
+
+pub fn create_crowbar_non_null_ret() -> ast::Annotation {
+    create_crowbar_spec(
+        "Ensures",
+        gen::create_ne_expr(gen::create_var_use("result").into(), gen::create_null()).into(),
     )
 }
 
-pub fn create_non_null_ret_anno(target: Target) -> ast::Annotation {
-    match target {
-        Target::Crowbar => create_typed_anno(
-            gen::ty::simple_ty("Spec"),
-            gen::create_data_constr_args(
-                "Ensures",
-                vec![gen::create_ne_expr(
-                    gen::create_var_use("result").into(),
-                    gen::create_null().into(),
-                )
-                .into()],
-            )
-            .into(),
-        ),
-        Target::NullableExtension | Target::Location => create_nullable_non_null(),
-    }
+pub fn create_crowbar_while_inv(inv: ast::PureExpr) -> ast::Annotation {
+    create_crowbar_spec("WhileInv", inv)
 }
@@ -12,7 +12,7 @@ pub use lit::*;
 pub use stmt::*;
 
 pub fn ident<S: Into<String>>(str: S) -> ast::Ident {
-    ast::Ident { str: str.into() }
+    ast::Ident::new(str)
 }
 
 pub struct ModuleBuilder {
@@ -285,6 +285,13 @@ pub fn create_data_constr_param(ty: ast::Type) -> ast::DataConstrParam {
     ast::DataConstrParam { ty, ident: None }
 }
 
+pub fn create_exception_decl<S: Into<String>>(name: S) -> ast::ExceptionDecl {
+    ast::ExceptionDecl {
+        ident: ident(name.into()),
+        params: Vec::new(),
+    }
+}
+
 pub struct MethodSigBuilder {
     annotations: ast::Annotations,
     ret: Option<ast::Type>,
@@ -319,6 +326,13 @@ impl MethodSigBuilder {
         self
     }
 
+    pub fn with_annotations(mut self, annos: &ast::Annotations) -> Self {
+        for a in annos.iter() {
+            self.add_annotation(a.clone());
+        }
+        self
+    }
+
     pub fn with_ret(mut self, ret: ast::Type) -> Self {
         self.add_ret(ret);
         self
@@ -385,3 +399,19 @@ pub fn create_field_init<S: Into<String>>(
 pub fn create_method_decl(sig: ast::MethodSig, body: ast::Block) -> ast::MethodDecl {
     ast::MethodDecl { sig, body }
 }
+
+pub fn create_function_decl<S: Into<String>>(
+    ret: ast::Type,
+    name: S,
+    params: Vec<ast::Param>,
+    body: ast::PureExpr,
+) -> ast::FunctionDecl {
+    ast::FunctionDecl {
+        annotations: empty_annos(),
+        ret,
+        ident: ident(name),
+        type_params: Vec::new(),
+        params,
+        body: ast::FunctionBody::Expr(body),
+    }
+}
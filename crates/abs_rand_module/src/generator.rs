@@ -1,71 +1,147 @@
-use rand::seq::SliceRandom;
+use std::collections::HashMap;
 
-use abs_syntax::ast;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
 
-use crate::{chance, gen, Options, Target};
+use abs_syntax::ast;
 
-pub fn ty_is_obj(ty: &ast::Type) -> bool {
-    let str = &ty.ident.str;
-    str == "I" || str == "J"
+use crate::{
+    chance,
+    coverage::Coverage,
+    gen,
+    landscape::{ClassShape, FunctionShape, Landscape, SigShape},
+    naming::Names,
+    target::Form,
+    Options,
+};
+
+/// The exception declared in every module, thrown and caught by the
+/// generated code
+pub const EXCEPTION: &str = "GenError";
+
+/// The field that the object invariant is about
+const INV_FIELD: &str = "fint";
+
+/// `this.fint >= 0`, which holds for every generated class, as `fint`
+/// starts at 0 and only grows if the target specifies the code
+pub fn object_invariant() -> ast::PureExpr {
+    gen::create_bin_expr(
+        ast::BinaryOp::Ge,
+        ast::PureExpr::ThisIdent(gen::create_var_use(INV_FIELD)),
+        gen::create_lit("0").into(),
+    )
+    .into()
 }
 
-pub struct RandGenerator {
+/// How deeply `case`, `let`, `when`, function calls and constructors nest
+const MAX_EXPR_DEPTH: u32 = 2;
+
+/// How many operators and functional forms the expressions of a single
+/// statement contain at most
+const MAX_EXPR_SIZE: u32 = 12;
+
+pub struct RandGenerator<'r> {
     scope: Scope,
     opts: Options,
     has_null_check_if: bool,
+    landscape: &'r Landscape,
+    /// How many of the generated functions may be called
+    functions: usize,
+    expr_depth: u32,
+    /// What is left of `MAX_EXPR_SIZE` for the current statement
+    expr_budget: u32,
+    /// What was generated so far, in the modules before and in this one up
+    /// to here, to steer towards what was not
+    coverage: &'r mut Coverage,
+    /// The names taken in the module
+    names: &'r mut Names,
+    /// Where the statements generated next go, as named in `coverage`
+    place: &'static str,
+    /// Where the expressions generated next go, set by `generate_in`
+    expr_place: &'static str,
+    rng: &'r mut StdRng,
 }
 
-impl RandGenerator {
-    pub fn new(opts: Options) -> Self {
+impl<'r> RandGenerator<'r> {
+    /// A generator for the methods of `class`, which see its fields and
+    /// parameters and may call the methods of every interface
+    pub fn new(
+        opts: Options,
+        landscape: &'r Landscape,
+        class: &ClassShape,
+        coverage: &'r mut Coverage,
+        names: &'r mut Names,
+        rng: &'r mut StdRng,
+    ) -> Self {
+        names.start_body(&opts);
+        Self {
+            scope: Scope::for_class(landscape, class),
+            opts,
+            has_null_check_if: false,
+            landscape,
+            functions: landscape.functions.len(),
+            expr_depth: 0,
+            expr_budget: MAX_EXPR_SIZE,
+            coverage,
+            names,
+            place: "MethodDecl.body",
+            expr_place: "",
+            rng,
+        }
+    }
+
+    /// A generator for the body of the `i`th function, which sees only its
+    /// parameters and may only call the functions before it
+    pub fn for_function(
+        opts: Options,
+        landscape: &'r Landscape,
+        i: usize,
+        coverage: &'r mut Coverage,
+        names: &'r mut Names,
+        rng: &'r mut StdRng,
+    ) -> Self {
+        names.start_body(&opts);
         Self {
             scope: Scope::new(),
             opts,
             has_null_check_if: false,
+            landscape,
+            functions: i,
+            expr_depth: 0,
+            expr_budget: MAX_EXPR_SIZE,
+            coverage,
+            names,
+            place: "FunctionDecl.body",
+            expr_place: "",
+            rng,
         }
     }
 
-    pub fn generate_body(&mut self) -> ast::Block {
-        let size = chance::exp_rand_int(self.opts.avg_meth_body_size as f64);
-
-        self.scope
-            .define_field(gen::ty::create_int(), gen::ident("fint"));
-        self.scope
-            .define_field(gen::ty::create_bool(), gen::ident("fb"));
-        self.scope
-            .define_field(gen::ty::create_fut(gen::ty::create_int()), gen::ident("ff"));
-        self.scope.define_field(
-            gen::ty::create_fut(gen::ty::create_bool()),
-            gen::ident("ffb"),
-        );
-        self.scope
-            .define_field(gen::ty::simple_ty("I"), gen::ident("fi"));
-        self.scope
-            .define_field(gen::ty::simple_ty("J"), gen::ident("fj"));
+    /// The body of a function, matching its first parameter of a data type
+    /// if it has one
+    pub fn generate_function(&mut self, f: &FunctionShape) -> ast::PureExpr {
+        for (ty, name) in &f.params {
+            self.scope.define_var(ty.clone(), gen::ident(name));
+        }
 
-        self.scope.define_fn(
-            gen::ty::create_int(),
-            gen::ident("n"),
-            vec![gen::ty::simple_ty("I")],
-            vec![],
-        );
-        self.scope.define_fn(
-            gen::ty::create_bool(),
-            gen::ident("b"),
-            vec![gen::ty::simple_ty("I")],
-            vec![],
-        );
-        self.scope.define_fn(
-            gen::ty::create_unit(),
-            gen::ident("m"),
-            vec![gen::ty::simple_ty("J")],
-            vec![gen::ty::create_int()],
-        );
-        self.scope.define_fn(
-            gen::ty::simple_ty("I"),
-            gen::ident("getI"),
-            vec![gen::ty::simple_ty("J")],
-            vec![gen::ty::create_bool(), gen::ty::create_int()],
-        );
+        self.expr_budget = MAX_EXPR_SIZE;
+        let data_param = f.params.iter().find(|(t, _)| self.landscape.is_data(t));
+        let e = self.generate_in(self.place, |g| match data_param {
+            Some((t, name)) if g.opts.target.allows(Form::Case) => {
+                g.expr_depth += 1;
+                let e = g.generate_case_of(gen::create_var_use(name).into(), t, f.ret.clone());
+                g.expr_depth -= 1;
+                e
+            }
+            _ => g.generate_pure_exp(f.ret.clone()),
+        });
+        self.coverage.add_pure_expr(self.place, &e);
+        e
+    }
+
+    /// The body of a `gen` method, which makes sure its parameter `i` is
+    /// not `null` and returns it
+    pub fn generate_body(&mut self) -> ast::Block {
+        let size = chance::exp_rand_int(self.rng, self.opts.avg_meth_body_size as f64);
 
         let mut builder = self.generate_sized_block(size);
 
@@ -78,12 +154,94 @@ impl RandGenerator {
         builder.complete()
     }
 
+    /// The body of a method of an interface, with a postcondition about
+    /// its `result` if one follows from the returned expression
+    pub fn generate_method(&mut self, sig: &SigShape) -> (ast::Block, Option<ast::PureExpr>) {
+        let size = chance::exp_rand_int(self.rng, self.opts.avg_meth_body_size as f64);
+
+        for (ty, name) in &sig.params {
+            self.scope.define_var(ty.clone(), gen::ident(name));
+        }
+        let mut builder = self.generate_sized_block(size);
+        self.expr_budget = MAX_EXPR_SIZE;
+        let expr = self.generate_expr(sig.ret.clone(), "ReturnStmt.expr");
+        let post = self.postcondition(&expr);
+        builder.add_stmt(gen::create_ret_stmt(expr).into());
+
+        (builder.complete(), post)
+    }
+
+    /// `result != null` for a new object, `result == e` for an expression
+    /// `e` of literals and fields, which keep their values until the method
+    /// returns
+    fn postcondition(&self, expr: &ast::Expr) -> Option<ast::PureExpr> {
+        let result = gen::create_var_use("result").into();
+        match expr {
+            ast::Expr::Eff(ast::EffExpr::New(_)) => {
+                Some(gen::create_ne_expr(result, gen::create_null()).into())
+            }
+            ast::Expr::Pure(e) => {
+                let e = self.over_fields(e)?;
+                Some(gen::create_bin_expr(ast::BinaryOp::Eq, result, e).into())
+            }
+            _ => None,
+        }
+    }
+
+    /// `e` with its fields accessed through `this`, if it consists of
+    /// operators, literals and fields only
+    fn over_fields(&self, e: &ast::PureExpr) -> Option<ast::PureExpr> {
+        Some(match e {
+            ast::PureExpr::Literal(_) | ast::PureExpr::Null => e.clone(),
+            ast::PureExpr::Ident(i) => {
+                self.scope.fields().find(|f| f.ident.str == i.ident.str)?;
+                ast::PureExpr::ThisIdent(i.clone())
+            }
+            ast::PureExpr::Operator(ast::OperatorExpr::Unary(u)) => ast::UnaryExpr {
+                op: u.op.clone(),
+                expr: self.over_fields(&u.expr)?.into(),
+            }
+            .into(),
+            ast::PureExpr::Operator(ast::OperatorExpr::Binary(b)) => ast::BinaryExpr {
+                op: b.op,
+                left: self.over_fields(&b.left)?.into(),
+                right: self.over_fields(&b.right)?.into(),
+            }
+            .into(),
+            _ => return None,
+        })
+    }
+
     pub fn generate_block(&mut self) -> ast::Block {
-        let size = chance::exp_rand_int(self.opts.avg_block_size as f64);
+        let size = chance::exp_rand_int(self.rng, self.opts.avg_block_size as f64);
 
         self.generate_sized_block(size).complete()
     }
 
+    /// A block that goes to `place`, like the `then` branch of an `if`
+    fn generate_block_in(&mut self, place: &'static str) -> ast::Block {
+        let outer = std::mem::replace(&mut self.place, place);
+        let b = self.generate_block();
+        self.place = outer;
+        b
+    }
+
+    /// A block in which `entry` is defined, like the body of a loop
+    fn generate_block_with(&mut self, place: &'static str, entry: ScopeEntry) -> ast::Block {
+        let size = chance::exp_rand_int(self.rng, self.opts.avg_block_size as f64);
+
+        let outer = std::mem::replace(&mut self.place, place);
+        self.scope.open();
+        self.scope.define(entry);
+        let mut builder = gen::start_block();
+        for _ in 0..size {
+            builder.add_stmt(self.generate_stmt())
+        }
+        self.scope.close();
+        self.place = outer;
+        builder.complete()
+    }
+
     pub fn generate_sized_block(&mut self, size: u64) -> gen::BlockBuilder {
         self.scope.open();
         let mut builder = gen::start_block();
@@ -95,19 +253,75 @@ impl RandGenerator {
         builder
     }
 
+    /// A statement, which counts as covered as soon as it is generated
     pub fn generate_stmt(&mut self) -> ast::Stmt {
+        let s = self.generate_any_stmt();
+        self.coverage.add_stmt(self.place, &s);
+        s
+    }
+
+    fn generate_any_stmt(&mut self) -> ast::Stmt {
+        self.expr_budget = MAX_EXPR_SIZE;
         if self.scope.depth() < self.opts.max_depth as usize
-            && chance::chance(self.opts.branch_rate)
+            && chance::chance(self.rng, self.opts.branch_rate)
         {
-            if !self.has_null_check_if && self.scope.depth() == 0 && chance::chance(0.3) {
-                self.generate_null_check_if()
-            } else {
-                self.generate_if()
+            if !self.has_null_check_if && self.scope.depth() == 0 && chance::chance(self.rng, 0.3) {
+                return self.generate_null_check_if();
             }
-        } else if chance::chance(self.opts.declare_to_assign) {
-            self.generate_decl()
-        } else {
-            self.generate_assign()
+            let w = self.opts.weights;
+            let choice = self.choose(&[
+                (Kind::If, w.r#if),
+                (Kind::While, w.r#while),
+                (Kind::Foreach, w.foreach),
+                (Kind::Switch, w.switch),
+                (Kind::Try, w.r#try),
+            ]);
+            match choice {
+                Some(Kind::While) => return self.generate_while(),
+                Some(Kind::Foreach) => return self.generate_foreach(),
+                Some(Kind::Switch) => return self.generate_switch(),
+                Some(Kind::Try) => return self.generate_try(),
+                Some(_) => return self.generate_if(),
+                None => {}
+            }
+        }
+
+        let w = self.opts.weights;
+        let choice = self.choose(&[
+            (Kind::Decl, self.opts.declare_to_assign),
+            (Kind::Assign, 1.0 - self.opts.declare_to_assign),
+            (Kind::Await, w.r#await),
+            (Kind::Assert, w.assert),
+            (Kind::Suspend, w.suspend),
+            (Kind::Throw, w.throw),
+        ]);
+        match choice {
+            Some(Kind::Decl) => self.generate_decl(),
+            Some(Kind::Await) => self.generate_await(),
+            Some(Kind::Assert) => self.generate_assert(),
+            Some(Kind::Suspend) => ast::Stmt::Suspend,
+            Some(Kind::Throw) => self.generate_throw(),
+            _ => self.generate_assign(),
+        }
+    }
+
+    /// Picks a kind of statement the target allows, preferring the ones
+    /// rarely generated in this place so far. If only one has a weight, it
+    /// is picked without drawing, so that kinds weighted 0 leave the modules
+    /// as they are without them.
+    fn choose(&mut self, weights: &[(Kind, f64)]) -> Option<Kind> {
+        let target = self.opts.target;
+        let allowed: Vec<_> = weights
+            .iter()
+            .filter(|(k, w)| *w > 0.0 && k.form().is_none_or(|f| target.allows(f)))
+            .map(|(k, w)| {
+                let boost = self.coverage.boost(self.opts.steer, self.place, k.name());
+                (*k, w * boost)
+            })
+            .collect();
+        match allowed[..] {
+            [(k, _)] => Some(k),
+            _ => chance::weighted(self.rng, &allowed),
         }
     }
 
@@ -120,12 +334,12 @@ impl RandGenerator {
     }
 
     pub fn generate_if(&mut self) -> ast::Stmt {
-        let condition = self.generate_pure_exp(gen::ty::create_bool());
+        let condition = self.generate_pure_in(gen::ty::create_bool(), "IfStmt.condition");
 
-        let then = Box::new(self.generate_block().into());
+        let then = Box::new(self.generate_block_in("IfStmt.then").into());
 
-        let r#else = if chance::chance(self.opts.else_ratio) {
-            Some(Box::new(self.generate_block().into()))
+        let r#else = if chance::chance(self.rng, self.opts.else_ratio) {
+            Some(Box::new(self.generate_block_in("IfStmt.else").into()))
         } else {
             None
         };
@@ -138,6 +352,132 @@ impl RandGenerator {
         .into()
     }
 
+    pub fn generate_while(&mut self) -> ast::Stmt {
+        let condition = self.generate_pure_in(gen::ty::create_bool(), "WhileStmt.condition");
+        let body = Box::new(self.generate_block_in("WhileStmt.body").into());
+
+        ast::WhileStmt {
+            annotations: self.opts.target.loop_invariant(object_invariant()),
+            condition,
+            body,
+        }
+        .into()
+    }
+
+    /// Loops over a list of a few numbers
+    pub fn generate_foreach(&mut self) -> ast::Stmt {
+        let len = self.rng.gen_range(1, 4);
+        let args = self.generate_in("VariadicFnAppExpr", |g| {
+            (0..len)
+                .map(|_| g.generate_pure_exp(gen::ty::create_int()))
+                .collect()
+        });
+        let iter = ast::PureExpr::VariadicFnApp(ast::FnAppExpr {
+            ident: gen::ident("list"),
+            args,
+        });
+        self.coverage.add_pure_expr("ForeachStmt.iter", &iter);
+
+        let loop_var = self.fresh_ident(&gen::ty::create_int());
+        let body = self
+            .generate_block_with(
+                "ForeachStmt.body",
+                ScopeEntry::loop_var(gen::ty::create_int(), loop_var.clone()),
+            )
+            .into();
+
+        ast::Stmt::Foreach(ast::ForeachStmt {
+            loop_var,
+            iter,
+            body: Box::new(body),
+        })
+    }
+
+    /// Switches over a number, with a branch for some literals and one for
+    /// the rest
+    pub fn generate_switch(&mut self) -> ast::Stmt {
+        let expr = self.generate_pure_in(gen::ty::create_int(), "SwitchStmt.expr");
+
+        let mut lits: Vec<u32> = (0..10).collect();
+        lits.shuffle(self.rng);
+        let len = self.rng.gen_range(1, 3);
+
+        let mut branches: Vec<_> = lits[..len]
+            .iter()
+            .map(|l| ast::CaseBranch {
+                pattern: ast::Pattern::Literal(gen::create_lit(l.to_string())),
+                right: self.generate_block_in("SwitchStmt.branch").into(),
+            })
+            .collect();
+        branches.push(ast::CaseBranch {
+            pattern: ast::Pattern::Wildcard,
+            right: self.generate_block_in("SwitchStmt.branch").into(),
+        });
+
+        ast::Stmt::Switch(ast::SwitchStmt {
+            expr,
+            branches,
+            case: false,
+        })
+    }
+
+    /// Catches the exception thrown by the generated code
+    pub fn generate_try(&mut self) -> ast::Stmt {
+        let r#try = Box::new(self.generate_block_in("TryCatchFinallyStmt.try").into());
+        let catch_branches = vec![ast::CaseBranch {
+            pattern: ast::Pattern::Constr {
+                ident: gen::ident(EXCEPTION),
+                args: Vec::new(),
+            },
+            right: self.generate_block_in("TryCatchFinallyStmt.catch").into(),
+        }];
+        let finally = if chance::chance(self.rng, self.opts.else_ratio) {
+            Some(Box::new(
+                self.generate_block_in("TryCatchFinallyStmt.finally").into(),
+            ))
+        } else {
+            None
+        };
+
+        ast::Stmt::TryCatchFinally(ast::TryCatchFinallyStmt {
+            r#try,
+            catch_branches,
+            finally,
+        })
+    }
+
+    /// Waits for a future if there is one, for a condition otherwise
+    pub fn generate_await(&mut self) -> ast::Stmt {
+        let guard = match self.scope.choose_future(self.rng) {
+            Some(e) => ast::Guard::Claim {
+                this: e.kind == EntryKind::Field,
+                ident: e.ident.clone(),
+            },
+            None => {
+                ast::Guard::Expr(self.generate_pure_in(gen::ty::create_bool(), "AwaitStmt.guard"))
+            }
+        };
+
+        ast::Stmt::Await(ast::AwaitStmt { guard })
+    }
+
+    /// Asserts a random condition, or the object invariant if the target
+    /// has to prove every assertion
+    pub fn generate_assert(&mut self) -> ast::Stmt {
+        let condition = if self.opts.target.specifies() {
+            object_invariant()
+        } else {
+            self.generate_pure_in(gen::ty::create_bool(), "AssertStmt.condition")
+        };
+        ast::Stmt::Assert(ast::AssertStmt { condition })
+    }
+
+    pub fn generate_throw(&mut self) -> ast::Stmt {
+        ast::Stmt::Throw(ast::ThrowStmt {
+            expr: gen::create_data_constr(EXCEPTION).into(),
+        })
+    }
+
     pub fn generate_null_check_if(&mut self) -> ast::Stmt {
         self.has_null_check_if = true;
 
@@ -153,7 +493,7 @@ impl RandGenerator {
         }
         .into();
 
-        let then = self.generate_block().into();
+        let then = self.generate_block_in("IfStmt.then").into();
 
         let then = match then {
             ast::Stmt::Block(mut b) => {
@@ -176,8 +516,8 @@ impl RandGenerator {
             _ => then,
         };
 
-        let r#else = if chance::chance(self.opts.else_ratio) {
-            Some(Box::new(self.generate_block().into()))
+        let r#else = if chance::chance(self.rng, self.opts.else_ratio) {
+            Some(Box::new(self.generate_block_in("IfStmt.else").into()))
         } else {
             None
         };
@@ -190,10 +530,17 @@ impl RandGenerator {
         .into()
     }
 
+    /// A name for a new variable of type `ty`
+    fn fresh_ident(&mut self, ty: &ast::Type) -> ast::Ident {
+        let scope = &self.scope;
+        self.names
+            .fresh(&self.opts, self.rng, ty, &|n| scope.has_name(n))
+    }
+
     pub fn generate_decl(&mut self) -> ast::Stmt {
         let ty = self.rand_avail_ty(true);
-        let ident = self.scope.free_var_ident(ty.clone());
-        let init = self.generate_expr(ty.clone());
+        let ident = self.fresh_ident(&ty);
+        let init = self.generate_expr(ty.clone(), "VarDeclStmt.init");
 
         self.scope.define_var(ty.clone(), ident.clone());
 
@@ -206,9 +553,21 @@ impl RandGenerator {
         .into()
     }
 
+    /// An assignment, which only adds to `fint` if the target specifies
+    /// the code, so that the object invariant holds
     pub fn generate_assign(&mut self) -> ast::Stmt {
-        let ScopeEntry { ident, ty, .. } = self.scope.get_assignable_ident();
-        let expr = self.generate_expr(ty.clone());
+        let e = self.scope.get_assignable_ident(self.rng);
+        let (kind, ident, ty) = (e.kind, e.ident.clone(), e.ty.clone());
+        let expr =
+            if self.opts.target.specifies() && kind == EntryKind::Field && ident.str == INV_FIELD {
+                let old = gen::create_var_use(INV_FIELD).into();
+                let add = self.generate_nat();
+                let e = ast::Expr::Pure(gen::create_bin_expr(ast::BinaryOp::Plus, old, add).into());
+                self.coverage.add_expr("AssignStmt.expr", &e);
+                e
+            } else {
+                self.generate_expr(ty, "AssignStmt.expr")
+            };
         ast::AssignStmt {
             this: false,
             ident,
@@ -217,87 +576,155 @@ impl RandGenerator {
         .into()
     }
 
-    fn generate_expr(&mut self, ty: ast::Type) -> ast::Expr {
+    /// An expression that goes to `place`, which may have side effects
+    fn generate_expr(&mut self, ty: ast::Type, place: &'static str) -> ast::Expr {
+        let e = self.generate_in(place, |g| g.generate_any_expr(ty));
+        self.coverage.add_expr(place, &e);
+        e
+    }
+
+    fn generate_any_expr(&mut self, ty: ast::Type) -> ast::Expr {
+        let place = self.expr_place;
         let fut_ty = gen::ty::create_fut(ty.clone());
-        let e: ast::EffExpr =
-            if !ty.is_fut() && self.scope.has_of_type(fut_ty.clone()) && chance::chance(0.1) {
-                ast::GetExpr {
-                    expr: self.generate_pure_exp(fut_ty),
-                }
-                .into()
-            } else if ty_is_obj(&ty) && chance::chance(0.5) {
-                let ident = if ty.ident.str == "I" { "D" } else { "E" };
-                let ident = gen::ident(ident);
-                ast::NewExpr {
-                    ty: ident,
-                    args: vec![],
-                    local: false,
-                }
-                .into()
-            } else if ty.is_fut()
-                && chance::chance(0.7)
-                && self.scope.fn_of_type(ty.args[0].clone()).count() > 0
-            {
-                let f = self
-                    .scope
-                    .fn_of_type(ty.args[0].clone())
-                    .collect::<Vec<_>>()
-                    .choose(&mut rand::thread_rng())
-                    .unwrap()
-                    .clone();
-                let callee = f
-                    .defined_for
-                    .choose(&mut rand::thread_rng())
-                    .expect(&f.ident.str)
-                    .clone();
-                let callee = self.generate_pure_exp(callee);
-                let args = f
-                    .args
-                    .iter()
-                    .map(|a| self.generate_pure_exp(a.clone()))
-                    .collect();
-                ast::AsyncCallExpr {
-                    callee,
-                    args,
-                    method: f.ident,
-                }
-                .into()
-            } else if self.opts.target != Target::Location
-                && chance::chance(0.1)
-                && self.scope.fn_of_type(ty.clone()).count() > 0
-            {
-                let f = self
-                    .scope
-                    .fn_of_type(ty.clone())
-                    .collect::<Vec<_>>()
-                    .choose(&mut rand::thread_rng())
-                    .unwrap()
-                    .clone();
-                let callee = f
-                    .defined_for
-                    .choose(&mut rand::thread_rng())
-                    .unwrap()
-                    .clone();
-                let callee = self.generate_pure_exp(callee);
-                let args = f
-                    .args
+        let e: ast::EffExpr = if !ty.is_fut()
+            && self.opts.target.allows(Form::Get)
+            && self.scope.has_of_type(&fut_ty)
+            && self.steered(self.opts.get_rate, place, "GetExpr")
+        {
+            ast::GetExpr {
+                expr: self.generate_in("GetExpr", |g| g.generate_pure_exp(fut_ty)),
+            }
+            .into()
+        } else if self.opts.target.allows(Form::New)
+            && !self.landscape.constructors(&ty).is_empty()
+            && self.steered(self.opts.new_rate, place, "NewExpr")
+        {
+            let cs = self.landscape.constructors(&ty);
+            let c = cs.choose(self.rng).unwrap();
+            let args = self.generate_in("NewExpr", |g| {
+                c.params
                     .iter()
-                    .map(|a| self.generate_pure_exp(a.clone()))
-                    .collect();
-                ast::SyncCallExpr {
-                    callee,
-                    args,
-                    method: f.ident,
-                }
-                .into()
-            } else {
-                return self.generate_pure_exp(ty).into();
-            };
+                    .map(|t| g.generate_pure_exp(t.clone()))
+                    .collect()
+            });
+            ast::NewExpr {
+                ty: gen::ident(&c.class),
+                args,
+                local: false,
+            }
+            .into()
+        } else if ty.is_fut()
+            && self.opts.target.allows(Form::AsyncCall)
+            && self.steered(self.opts.async_rate, place, "AsyncCallExpr")
+            && self.scope.callable_of_type(&ty.args[0]).next().is_some()
+        {
+            let (callee, method, args) = self.generate_call(&ty.args[0], "AsyncCallExpr");
+            ast::AsyncCallExpr {
+                callee,
+                args,
+                method,
+            }
+            .into()
+        } else if self.opts.target.allows(Form::SyncCall)
+            && self.steered(self.opts.sync_rate, place, "SyncCallExpr")
+            && self.scope.callable_of_type(&ty).next().is_some()
+        {
+            let (callee, method, args) = self.generate_call(&ty, "SyncCallExpr");
+            ast::SyncCallExpr {
+                callee,
+                args,
+                method,
+            }
+            .into()
+        } else {
+            return self.generate_pure_exp(ty).into();
+        };
         e.into()
     }
 
+    /// The callee, method and arguments of a call of a method returning
+    /// `ret` on an object in scope, for a call of kind `kind`
+    fn generate_call(
+        &mut self,
+        ret: &ast::Type,
+        kind: &'static str,
+    ) -> (ast::PureExpr, ast::Ident, Vec<ast::PureExpr>) {
+        let fs: Vec<_> = self.scope.callable_of_type(ret).collect();
+        let f = fs.choose(self.rng).unwrap();
+        let on: Vec<_> = f
+            .defined_for
+            .iter()
+            .filter(|t| self.scope.has_of_type(t))
+            .collect();
+        let callee = (*on.choose(self.rng).unwrap()).clone();
+        let (method, params) = (f.ident.clone(), f.args.clone());
+
+        self.generate_in(kind, |g| {
+            let callee = g.generate_pure_exp(callee);
+            let args = params.into_iter().map(|a| g.generate_arg(a)).collect();
+            (callee, method, args)
+        })
+    }
+
+    /// A pure expression that goes to `place`, like the condition of an `if`
+    fn generate_pure_in(&mut self, ty: ast::Type, place: &'static str) -> ast::PureExpr {
+        let e = self.generate_in(place, |g| g.generate_pure_exp(ty));
+        self.coverage.add_pure_expr(place, &e);
+        e
+    }
+
+    /// Runs `generate` for expressions that go to `place`, a slot of a
+    /// statement or the kind of the expression they are part of
+    fn generate_in<T>(&mut self, place: &'static str, generate: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = std::mem::replace(&mut self.expr_place, place);
+        let e = generate(self);
+        self.expr_place = outer;
+        e
+    }
+
+    /// A chance of `prob` to generate `kind` in `place`, raised if it was
+    /// rarely generated there so far
+    fn steered(&mut self, prob: f64, place: &'static str, kind: &'static str) -> bool {
+        let boost = self.coverage.boost(self.opts.steer, place, kind);
+        chance::chance(self.rng, (prob * boost).min(1.0))
+    }
+
+    /// A chance of `prob` to generate `kind` rather than a variable in
+    /// `expr_place`, moved towards whichever of the two is rarer there
+    fn steered_over_ident(&mut self, prob: f64, kind: &'static str) -> bool {
+        let steer = self.opts.steer;
+        let boost = self.coverage.boost(steer, self.expr_place, kind);
+        let ident = self.coverage.boost(steer, self.expr_place, "IdentExpr");
+        if boost == ident {
+            return chance::chance(self.rng, prob);
+        }
+        let prob = prob * boost / (prob * boost + (1.0 - prob) * ident);
+        chance::chance(self.rng, prob)
+    }
+
+    /// An argument of a method call, which is not negative if it is a
+    /// number and the target specifies the code, as the preconditions require
+    fn generate_arg(&mut self, ty: ast::Type) -> ast::PureExpr {
+        if self.opts.target.specifies() && ty.is_int() {
+            self.generate_nat()
+        } else {
+            self.generate_pure_exp(ty)
+        }
+    }
+
+    /// `fint` or a small literal, neither of which is negative
+    fn generate_nat(&mut self) -> ast::PureExpr {
+        if chance::chance(self.rng, 0.3) {
+            gen::create_var_use(INV_FIELD).into()
+        } else {
+            gen::create_lit(self.rng.gen_range(0, 10).to_string()).into()
+        }
+    }
+
+    /// A pure expression that goes to `expr_place`, where operators,
+    /// literals and constructors are steered towards the ones rare there
     fn generate_pure_exp(&mut self, ty: ast::Type) -> ast::PureExpr {
-        if ty.is_bool() && chance::chance(0.15) {
+        if ty.is_bool() && self.steered(0.15, self.expr_place, "BinaryExpr") && self.grow() {
             let ty = gen::ty::create_int();
             let op = [
                 ast::BinaryOp::Eq,
@@ -307,59 +734,64 @@ impl RandGenerator {
                 ast::BinaryOp::Gt,
                 ast::BinaryOp::Lt,
             ]
-            .choose(&mut rand::thread_rng())
-            .unwrap()
-            .clone();
-            return ast::BinaryExpr {
-                op,
-                left: self.generate_or(ty.clone()).into(),
-                right: self.generate_or(ty).into(),
-            }
-            .into();
-        } else if ty.is_bool() && chance::chance(0.15) {
+            .choose(self.rng)
+            .copied()
+            .unwrap();
+            self.generate_bin(op, ty, Self::generate_or, Self::generate_or)
+        } else if ty.is_bool() && self.steered(0.15, self.expr_place, "BinaryExpr") && self.grow() {
             let ty = self.rand_avail_ty(true);
-            return ast::BinaryExpr {
-                op: ast::BinaryOp::Eq,
-                left: self.generate_or(ty.clone()).into(),
-                right: self.generate_or(ty).into(),
-            }
-            .into();
+            self.generate_bin(ast::BinaryOp::Eq, ty, Self::generate_or, Self::generate_or)
         } else {
             self.generate_or(ty)
         }
     }
 
-    fn generate_or(&mut self, ty: ast::Type) -> ast::PureExpr {
-        if ty.is_bool() && chance::chance(0.2) {
+    /// A binary expression, with operands from `left` and `right`
+    fn generate_bin(
+        &mut self,
+        op: ast::BinaryOp,
+        ty: ast::Type,
+        left: fn(&mut Self, ast::Type) -> ast::PureExpr,
+        right: fn(&mut Self, ast::Type) -> ast::PureExpr,
+    ) -> ast::PureExpr {
+        self.generate_in("BinaryExpr", |g| {
             ast::BinaryExpr {
-                op: ast::BinaryOp::Eq,
-                left: self.generate_and(ty.clone()).into(),
-                right: self.generate_or(ty).into(),
+                op,
+                left: left(g, ty.clone()).into(),
+                right: right(g, ty).into(),
             }
             .into()
+        })
+    }
+
+    fn generate_or(&mut self, ty: ast::Type) -> ast::PureExpr {
+        if ty.is_bool() && self.steered(0.2, self.expr_place, "BinaryExpr") && self.grow() {
+            self.generate_bin(ast::BinaryOp::Eq, ty, Self::generate_and, Self::generate_or)
         } else {
             self.generate_and(ty)
         }
     }
 
     fn generate_and(&mut self, ty: ast::Type) -> ast::PureExpr {
-        if ty.is_bool() && chance::chance(0.2) {
-            ast::BinaryExpr {
-                op: ast::BinaryOp::Eq,
-                left: self.generate_not(ty.clone()).into(),
-                right: self.generate_and(ty).into(),
-            }
-            .into()
+        if ty.is_bool() && self.steered(0.2, self.expr_place, "BinaryExpr") && self.grow() {
+            self.generate_bin(
+                ast::BinaryOp::Eq,
+                ty,
+                Self::generate_not,
+                Self::generate_and,
+            )
         } else {
             self.generate_not(ty)
         }
     }
 
     fn generate_not(&mut self, ty: ast::Type) -> ast::PureExpr {
-        if ty.is_bool() && chance::chance(0.2) {
+        if ty.is_bool() && self.steered(0.2, self.expr_place, "UnaryExpr") && self.grow() {
             ast::UnaryExpr {
                 op: ast::UnaryOp::Not,
-                expr: self.generate_add_sub(ty).into(),
+                expr: self
+                    .generate_in("UnaryExpr", |g| g.generate_add_sub(ty))
+                    .into(),
             }
             .into()
         } else {
@@ -368,41 +800,33 @@ impl RandGenerator {
     }
 
     fn generate_add_sub(&mut self, ty: ast::Type) -> ast::PureExpr {
-        if ty.is_int() && chance::chance(0.2) {
+        if ty.is_int() && self.steered(0.2, self.expr_place, "BinaryExpr") && self.grow() {
             let op = [ast::BinaryOp::Plus, ast::BinaryOp::Minus]
-                .choose(&mut rand::thread_rng())
-                .unwrap()
-                .clone();
-            ast::BinaryExpr {
-                op,
-                left: self.generate_add_sub(ty.clone()).into(),
-                right: self.generate_mul_div(ty).into(),
-            }
-            .into()
+                .choose(self.rng)
+                .copied()
+                .unwrap();
+            self.generate_bin(op, ty, Self::generate_add_sub, Self::generate_mul_div)
         } else {
             self.generate_mul_div(ty)
         }
     }
 
     fn generate_mul_div(&mut self, ty: ast::Type) -> ast::PureExpr {
-        if ty.is_int() && chance::chance(0.2) {
+        if ty.is_int() && self.steered(0.2, self.expr_place, "BinaryExpr") && self.grow() {
             let op = ast::BinaryOp::Mult;
-            ast::BinaryExpr {
-                op,
-                left: self.generate_mul_div(ty.clone()).into(),
-                right: self.generate_unary_minus(ty).into(),
-            }
-            .into()
+            self.generate_bin(op, ty, Self::generate_mul_div, Self::generate_unary_minus)
         } else {
             self.generate_unary_minus(ty)
         }
     }
 
     fn generate_unary_minus(&mut self, ty: ast::Type) -> ast::PureExpr {
-        if ty.is_int() && chance::chance(0.0) {
+        if ty.is_int() && self.steered(0.0, self.expr_place, "UnaryExpr") && self.grow() {
             ast::UnaryExpr {
                 op: ast::UnaryOp::Minus,
-                expr: self.generate_atom(ty).into(),
+                expr: self
+                    .generate_in("UnaryExpr", |g| g.generate_atom(ty))
+                    .into(),
             }
             .into()
         } else {
@@ -411,80 +835,390 @@ impl RandGenerator {
     }
 
     fn generate_atom(&mut self, ty: ast::Type) -> ast::PureExpr {
-        if ty.has_lit() && chance::chance(0.7) {
-            ty.get_lit().unwrap().into()
-        } else {
-            let avail_idents = self.scope.of_type(ty.clone()).collect::<Vec<_>>();
-            if avail_idents.is_empty() {
-                panic!("Found no suitable ident for type {}", ty);
+        if self.expr_depth < MAX_EXPR_DEPTH && self.opts.functional_rate > 0.0 {
+            let forms = self.functional_forms(&ty);
+            // As likely as the rarest of the forms in this place makes it
+            let boost = forms
+                .iter()
+                .map(|f| {
+                    self.coverage
+                        .boost(self.opts.steer, self.expr_place, form_kind(*f))
+                })
+                .fold(1.0, f64::max);
+            let prob = (self.opts.functional_rate * boost).min(1.0);
+            if chance::chance(self.rng, prob) && self.grow() {
+                self.expr_depth += 1;
+                let e = self.generate_functional(ty.clone(), &forms);
+                self.expr_depth -= 1;
+                if let Some(e) = e {
+                    return e;
+                }
             }
-            let ident = avail_idents
-                .choose(&mut rand::thread_rng())
-                .unwrap()
-                .clone()
-                .ident;
-            ast::PureExpr::Ident(ast::IdentExpr { ident })
         }
-    }
 
-    fn rand_avail_ty(&self, allow_fut: bool) -> ast::Type {
-        let mut ty;
+        let no_idents = !self.scope.has_of_type(&ty);
+        if self.landscape.is_data(&ty)
+            && (no_idents || self.steered_over_ident(0.5, "DataConstrExpr"))
+        {
+            self.expr_depth += 1;
+            let e = self.generate_constr(ty);
+            self.expr_depth -= 1;
+            e
+        } else if ty.has_lit() && (self.steered_over_ident(0.7, "LiteralExpr") || no_idents) {
+            ty.get_lit(self.rng).unwrap().into()
+        } else if let Some(e) = self.scope.choose_of_type(&ty, self.rng) {
+            ast::PureExpr::Ident(ast::IdentExpr {
+                ident: e.ident.clone(),
+            })
+        } else {
+            // Only object types are left, as every other type asked for is
+            // inhabited
+            gen::create_null()
+        }
+    }
 
-        loop {
-            let t = self.rand_ty(allow_fut);
-            ty = Some(t.clone());
-            if t.has_lit() || self.scope.has_of_type(t) {
-                break;
-            }
+    /// Whether the current statement may get another operator or
+    /// functional form, which then uses up a part of its budget
+    fn grow(&mut self) -> bool {
+        if self.expr_budget == 0 {
+            return false;
         }
+        self.expr_budget -= 1;
+        true
+    }
 
-        ty.unwrap()
+    /// Whether an expression of type `ty` can be generated, as a literal, a
+    /// constructor, `null` or a variable in scope
+    fn inhabited(&self, ty: &ast::Type) -> bool {
+        ty.has_lit()
+            || self.landscape.is_data(ty)
+            || self.landscape.is_object(ty)
+            || self.scope.has_of_type(ty)
     }
 
-    fn rand_ty(&self, allow_fut: bool) -> ast::Type {
-        if !allow_fut || chance::chance(0.8) {
-            [
-                gen::ty::create_int(),
-                gen::ty::create_int(),
-                gen::ty::create_bool(),
-                gen::ty::create_bool(),
-                gen::ty::create_i(),
-                gen::ty::create_j(),
-            ]
-            .choose(&mut rand::thread_rng())
-            .unwrap()
-            .clone()
+    /// The functional forms the target allows that can be of type `ty`
+    fn functional_forms(&self, ty: &ast::Type) -> Vec<Form> {
+        let has_data = !self.landscape.data.is_empty();
+        let has_fn = self.landscape.functions[..self.functions]
+            .iter()
+            .any(|f| f.ret == *ty);
+        let target = self.opts.target;
+        [
+            (Form::Case, has_data),
+            (Form::Let, true),
+            (Form::When, true),
+            (Form::FnApp, has_fn),
+        ]
+        .iter()
+        .filter(|(f, possible)| *possible && target.allows(*f))
+        .map(|(f, _)| *f)
+        .collect()
+    }
+
+    /// A `case`, `let` or `when` expression or a function call of type
+    /// `ty`, of one of `forms`
+    fn generate_functional(&mut self, ty: ast::Type, forms: &[Form]) -> Option<ast::PureExpr> {
+        let form = if self.opts.steer > 0.0 {
+            let weights: Vec<_> = forms
+                .iter()
+                .map(|f| {
+                    let boost =
+                        self.coverage
+                            .boost(self.opts.steer, self.expr_place, form_kind(*f));
+                    (*f, boost)
+                })
+                .collect();
+            chance::weighted(self.rng, &weights)?
         } else {
-            gen::ty::create_fut(self.rand_ty(false))
+            *forms.choose(self.rng)?
+        };
+
+        Some(match form {
+            Form::Case => self.generate_case(ty),
+            Form::Let => self.generate_let(ty),
+            Form::When => self.generate_in("WhenExpr", |g| {
+                ast::PureExpr::When(ast::WhenExpr {
+                    condition: Box::new(g.generate_pure_exp(gen::ty::create_bool())),
+                    then: Box::new(g.generate_pure_exp(ty.clone())),
+                    r#else: Box::new(g.generate_pure_exp(ty)),
+                })
+            }),
+            _ => self.generate_fn_app(ty),
+        })
+    }
+
+    /// Matches a value of a random data type, with a branch for every
+    /// constructor
+    fn generate_case(&mut self, ty: ast::Type) -> ast::PureExpr {
+        let data_ty = self
+            .landscape
+            .data_types()
+            .choose(self.rng)
+            .unwrap()
+            .clone();
+        let expr = self.generate_in("CaseExpr", |g| g.generate_pure_exp(data_ty.clone()));
+        self.generate_case_of(expr, &data_ty, ty)
+    }
+
+    fn generate_case_of(
+        &mut self,
+        expr: ast::PureExpr,
+        data_ty: &ast::Type,
+        ty: ast::Type,
+    ) -> ast::PureExpr {
+        let mut branches = Vec::new();
+        for (constr, arg_tys) in self.landscape.data_constrs(data_ty) {
+            self.scope.open();
+            let args = arg_tys
+                .into_iter()
+                .map(|t| {
+                    if chance::chance(self.rng, 0.3) {
+                        return ast::Pattern::Wildcard;
+                    }
+                    let ident = self.fresh_ident(&t);
+                    self.scope.define_var(t, ident.clone());
+                    ast::Pattern::Ident(ident)
+                })
+                .collect();
+            let right = self.generate_in("CaseExpr", |g| g.generate_pure_exp(ty.clone()));
+            self.scope.close();
+
+            branches.push(ast::CaseBranch {
+                pattern: ast::Pattern::Constr {
+                    ident: gen::ident(constr),
+                    args,
+                },
+                right,
+            });
         }
+
+        ast::PureExpr::Case(ast::CaseExpr {
+            expr: Box::new(expr),
+            branches,
+        })
+    }
+
+    fn generate_let(&mut self, ty: ast::Type) -> ast::PureExpr {
+        let var_ty = self.rand_avail_ty(false);
+        let ident = self.fresh_ident(&var_ty);
+        let value = Box::new(self.generate_in("LetExpr", |g| g.generate_pure_exp(var_ty.clone())));
+
+        self.scope.open();
+        self.scope.define_var(var_ty.clone(), ident.clone());
+        let inner = Box::new(self.generate_in("LetExpr", |g| g.generate_pure_exp(ty)));
+        self.scope.close();
+
+        ast::PureExpr::Let(ast::LetExpr {
+            ty: var_ty,
+            ident,
+            value,
+            inner,
+        })
+    }
+
+    fn generate_fn_app(&mut self, ty: ast::Type) -> ast::PureExpr {
+        let landscape = self.landscape;
+        let fs: Vec<_> = landscape.functions[..self.functions]
+            .iter()
+            .filter(|f| f.ret == ty)
+            .collect();
+        let f = fs.choose(self.rng).unwrap();
+        let args = self.generate_in("FnAppExpr", |g| {
+            f.params
+                .iter()
+                .map(|(t, _)| g.generate_pure_exp(t.clone()))
+                .collect()
+        });
+
+        ast::PureExpr::FnApp(ast::FnAppExpr {
+            ident: gen::ident(&f.name),
+            args,
+        })
+    }
+
+    fn generate_constr(&mut self, ty: ast::Type) -> ast::PureExpr {
+        let constrs = self.landscape.data_constrs(&ty);
+        let (constr, arg_tys) = constrs.choose(self.rng).unwrap();
+        let args = self.generate_in("DataConstrExpr", |g| {
+            arg_tys
+                .iter()
+                .map(|t| g.generate_pure_exp(t.clone()))
+                .collect()
+        });
+
+        gen::create_data_constr_args(constr, args).into()
+    }
+
+    /// A random type that an expression can be generated for, which is a
+    /// future only if a variable of that type is in scope
+    fn rand_avail_ty(&mut self, allow_fut: bool) -> ast::Type {
+        let mut tys = vec![
+            gen::ty::create_int(),
+            gen::ty::create_int(),
+            gen::ty::create_bool(),
+            gen::ty::create_bool(),
+        ];
+        tys.extend(self.landscape.object_types());
+        tys.extend(self.landscape.data_types());
+
+        if allow_fut && chance::chance(self.rng, 0.2) {
+            let futs: Vec<_> = tys
+                .iter()
+                .map(|t| gen::ty::create_fut(t.clone()))
+                .filter(|t| self.inhabited(t))
+                .collect();
+            if let Some(t) = futs.choose(self.rng) {
+                return t.clone();
+            }
+        }
+        tys.choose(self.rng).unwrap().clone()
     }
 }
 
-struct Scope {
-    stack: Vec<Vec<ScopeEntry>>,
+/// The fields, variables and functions in scope.
+///
+/// The entries are kept in the order they are defined, and closing a scope
+/// drops the ones defined since it was opened, so nothing is copied on the
+/// way. Every type is interned once, and the entries of a type are indexed
+/// by it, so that looking them up does not go through the others. Fields,
+/// functions and the entries that may be assigned to have indexes of their
+/// own. Entries come out innermost scope first, and in the order they were
+/// defined within a scope, and are chosen from an index directly.
+#[derive(Clone)]
+pub struct Scope {
+    entries: Vec<ScopeEntry>,
+    /// Where every open scope starts in `entries`
+    starts: Vec<usize>,
+    types: HashMap<ast::Type, usize>,
+    /// The fields and variables of every interned type
+    values: Vec<Vec<usize>>,
+    /// The functions returning every interned type
+    fns: Vec<Vec<usize>>,
+    fields: Vec<usize>,
+    functions: Vec<usize>,
+    /// The fields and variables that may be assigned to
+    assignable: Vec<usize>,
+    /// The fields and variables of future types
+    futures: Vec<usize>,
+    /// How often every name is defined
+    names: HashMap<String, usize>,
 }
 
 impl Scope {
     pub fn new() -> Self {
         Scope {
-            stack: vec![Vec::new()],
+            entries: Vec::new(),
+            starts: vec![0],
+            types: HashMap::new(),
+            values: Vec::new(),
+            fns: Vec::new(),
+            fields: Vec::new(),
+            functions: Vec::new(),
+            assignable: Vec::new(),
+            futures: Vec::new(),
+            names: HashMap::new(),
         }
     }
 
+    /// The scope of the methods of `class`: its fields and parameters, and
+    /// the methods of every interface
+    pub fn for_class(landscape: &Landscape, class: &ClassShape) -> Self {
+        let mut scope = Scope::new();
+
+        scope.define_field(gen::ty::create_int(), gen::ident("fint"));
+        scope.define_field(gen::ty::create_bool(), gen::ident("fb"));
+        scope.define_field(gen::ty::create_fut(gen::ty::create_int()), gen::ident("ff"));
+        scope.define_field(
+            gen::ty::create_fut(gen::ty::create_bool()),
+            gen::ident("ffb"),
+        );
+        scope.define_field(gen::ty::simple_ty("I"), gen::ident("fi"));
+        scope.define_field(gen::ty::simple_ty("J"), gen::ident("fj"));
+        for (ty, name) in class.params.iter().chain(&class.obj_fields) {
+            scope.define_field(ty.clone(), gen::ident(name));
+        }
+
+        for c in landscape.callables() {
+            scope.define_fn(
+                c.sig.ret,
+                gen::ident(c.sig.name),
+                c.defined_for,
+                c.sig.params.into_iter().map(|(t, _)| t).collect(),
+            );
+        }
+        scope
+    }
+
     pub fn depth(&self) -> usize {
-        self.stack.len() - 1
+        self.starts.len() - 1
     }
 
     pub fn open(&mut self) {
-        self.stack.push(Vec::new())
+        self.starts.push(self.entries.len())
     }
 
     pub fn close(&mut self) {
-        self.stack.pop();
+        let start = self.starts.pop().unwrap();
+        while self.entries.len() > start {
+            let i = self.entries.len() - 1;
+            let e = self.entries.pop().unwrap();
+            // Every index is in ascending order, so the entry is last in
+            // the ones it is in
+            let ty = self.types[&e.ty];
+            for index in [
+                &mut self.values[ty],
+                &mut self.fns[ty],
+                &mut self.fields,
+                &mut self.functions,
+                &mut self.assignable,
+                &mut self.futures,
+            ] {
+                if index.last() == Some(&i) {
+                    index.pop();
+                }
+            }
+            if let Some(n) = self.names.get_mut(&e.ident.str) {
+                *n -= 1;
+                if *n == 0 {
+                    self.names.remove(&e.ident.str);
+                }
+            }
+        }
+    }
+
+    /// The interned `ty`. Types are told apart with all their arguments, as
+    /// a `GenData_0<Int>` cannot be used for a `GenData_0<Bool>`.
+    fn intern(&mut self, ty: &ast::Type) -> usize {
+        if let Some(id) = self.types.get(ty) {
+            return *id;
+        }
+        let id = self.values.len();
+        self.types.insert(ty.clone(), id);
+        self.values.push(Vec::new());
+        self.fns.push(Vec::new());
+        id
     }
 
     pub fn define(&mut self, entry: ScopeEntry) {
-        self.stack.last_mut().unwrap().push(entry)
+        let i = self.entries.len();
+        let ty = self.intern(&entry.ty);
+        if entry.kind == EntryKind::Fn {
+            self.fns[ty].push(i);
+            self.functions.push(i);
+        } else {
+            if entry.kind == EntryKind::Field {
+                self.fields.push(i);
+            }
+            self.values[ty].push(i);
+            if entry.kind != EntryKind::LoopVar {
+                self.assignable.push(i);
+            }
+            if entry.ty.is_fut() {
+                self.futures.push(i);
+            }
+        }
+        *self.names.entry(entry.ident.str.clone()).or_default() += 1;
+        self.entries.push(entry)
     }
 
     pub fn define_field(&mut self, ty: ast::Type, ident: ast::Ident) {
@@ -505,87 +1239,109 @@ impl Scope {
         self.define(ScopeEntry::function(ty, ident, defined_for, args))
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = ScopeEntry> {
-        let mut flattened = Vec::new();
+    /// Where every open scope starts and ends in `entries`, innermost first
+    fn levels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.starts.len()).rev().map(move |i| {
+            let end = self.starts.get(i + 1).copied();
+            (self.starts[i], end.unwrap_or(self.entries.len()))
+        })
+    }
 
-        for level in self.stack.iter().rev() {
-            for e in level {
-                flattened.push(e.clone())
-            }
-        }
+    /// The entries at the positions in `index`, innermost scope first
+    fn indexed<'a>(&'a self, index: &'a [usize]) -> impl Iterator<Item = &'a ScopeEntry> + 'a {
+        self.levels().flat_map(move |(start, end)| {
+            let from = index.partition_point(|i| *i < start);
+            let to = index.partition_point(|i| *i < end);
+            index[from..to].iter().map(move |i| &self.entries[*i])
+        })
+    }
 
-        flattened.into_iter()
+    /// The positions of the entries of type `ty` in `by_type`
+    fn of<'a>(&self, by_type: &'a [Vec<usize>], ty: &ast::Type) -> &'a [usize] {
+        match self.types.get(ty) {
+            Some(id) => &by_type[*id],
+            None => &[],
+        }
     }
 
-    pub fn functions(&self) -> impl Iterator<Item = ScopeEntry> {
-        self.iter().filter(|e| e.kind == EntryKind::Fn)
+    pub fn functions(&self) -> impl Iterator<Item = &ScopeEntry> {
+        self.indexed(&self.functions)
     }
 
-    pub fn of_type(&self, ty: ast::Type) -> impl Iterator<Item = ScopeEntry> {
-        let name = &ty.ident.str;
-        let is_fut = ty.is_fut();
-        let arg0 = ty.args.get(0).map(|t| &t.ident.str);
+    pub fn fields(&self) -> impl Iterator<Item = &ScopeEntry> {
+        self.indexed(&self.fields)
+    }
 
-        let v: Vec<ScopeEntry> = self
-            .iter()
-            .filter(move |e| {
-                e.kind != EntryKind::Fn
-                    && e.is_of_ty(name)
-                    && (!is_fut || &e.ty.args[0].ident.str == arg0.unwrap())
-            })
-            .collect();
-        v.into_iter()
+    /// One of the entries at the positions in `index`
+    fn choose<R: Rng + ?Sized>(&self, index: &[usize], rng: &mut R) -> Option<&ScopeEntry> {
+        index.choose(rng).map(|i| &self.entries[*i])
     }
 
-    pub fn has_of_type(&self, ty: ast::Type) -> bool {
-        self.of_type(ty).next().is_some()
+    /// The fields and variables of type `ty`
+    pub fn of_type<'a>(&'a self, ty: &ast::Type) -> impl Iterator<Item = &'a ScopeEntry> + 'a {
+        self.indexed(self.of(&self.values, ty))
     }
 
-    pub fn fn_of_type(&self, ty: ast::Type) -> impl Iterator<Item = ScopeEntry> {
-        let name = &ty.ident.str;
-        let v: Vec<ScopeEntry> = self.functions().filter(move |e| e.is_of_ty(name)).collect();
-        v.into_iter()
+    pub fn has_of_type(&self, ty: &ast::Type) -> bool {
+        !self.of(&self.values, ty).is_empty()
     }
 
-    fn sample(&self, i: impl Iterator<Item = ScopeEntry>) -> ScopeEntry {
-        let v: Vec<ScopeEntry> = i.collect();
+    /// One of the fields and variables of type `ty`
+    pub fn choose_of_type<R: Rng + ?Sized>(
+        &self,
+        ty: &ast::Type,
+        rng: &mut R,
+    ) -> Option<&ScopeEntry> {
+        self.choose(self.of(&self.values, ty), rng)
+    }
 
-        v.choose(&mut rand::thread_rng()).unwrap().clone()
+    pub fn fn_of_type<'a>(&'a self, ty: &ast::Type) -> impl Iterator<Item = &'a ScopeEntry> + 'a {
+        self.indexed(self.of(&self.fns, ty))
     }
 
-    pub fn get_fut(&self) -> ScopeEntry {
-        self.sample(
-            self.iter()
-                .filter(|e| e.kind != EntryKind::Fn && e.ty.is_fut()),
-        )
+    /// The fields and variables that may be assigned to
+    pub fn assignable(&self) -> impl Iterator<Item = &ScopeEntry> {
+        self.indexed(&self.assignable)
     }
 
-    pub fn get_fn(&self) -> ScopeEntry {
-        self.sample(self.functions())
+    /// The fields and variables of future types
+    pub fn futures(&self) -> impl Iterator<Item = &ScopeEntry> {
+        self.indexed(&self.futures)
     }
 
-    pub fn get_assignable_ident(&self) -> ScopeEntry {
-        self.sample(self.iter().filter(|e| e.kind != EntryKind::Fn))
+    /// The functions of type `ty` that can be called on an object in scope
+    pub fn callable_of_type<'a>(
+        &'a self,
+        ty: &ast::Type,
+    ) -> impl Iterator<Item = &'a ScopeEntry> + 'a {
+        self.fn_of_type(ty)
+            .filter(move |f| f.defined_for.iter().any(|t| self.has_of_type(t)))
     }
 
-    pub fn free_var_ident(&self, _ty: ast::Type) -> ast::Ident {
-        let mut name = generate_name();
+    pub fn get_assignable_ident<R: Rng + ?Sized>(&self, rng: &mut R) -> &ScopeEntry {
+        self.choose(&self.assignable, rng).unwrap()
+    }
 
-        while self.iter().any(|e| e.ident.str == name) {
-            name = generate_name();
-        }
+    /// One of the fields and variables of future types, if there is one
+    pub fn choose_future<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<&ScopeEntry> {
+        self.choose(&self.futures, rng)
+    }
 
-        crate::gen::ident(name)
+    /// Whether anything in scope is called `name`
+    pub fn has_name(&self, name: &str) -> bool {
+        self.names.contains_key(name)
     }
 }
 
 #[derive(Clone)]
-struct ScopeEntry {
-    kind: EntryKind,
-    ty: ast::Type,
-    ident: ast::Ident,
-    defined_for: Vec<ast::Type>,
-    args: Vec<ast::Type>,
+pub struct ScopeEntry {
+    pub kind: EntryKind,
+    pub ty: ast::Type,
+    pub ident: ast::Ident,
+    /// The types of objects a method can be called on
+    pub defined_for: Vec<ast::Type>,
+    /// The types of the parameters of a method
+    pub args: Vec<ast::Type>,
 }
 
 impl ScopeEntry {
@@ -613,6 +1369,10 @@ impl ScopeEntry {
         Self::new(EntryKind::Var, ty, ident, Vec::new(), Vec::new())
     }
 
+    fn loop_var(ty: ast::Type, ident: ast::Ident) -> Self {
+        Self::new(EntryKind::LoopVar, ty, ident, Vec::new(), Vec::new())
+    }
+
     fn function(
         ty: ast::Type,
         ident: ast::Ident,
@@ -621,30 +1381,143 @@ impl ScopeEntry {
     ) -> Self {
         Self::new(EntryKind::Fn, ty, ident, defined_for, args)
     }
-
-    fn is_of_ty(&self, name: &str) -> bool {
-        self.ty.ident.str == name
-    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
-enum EntryKind {
+pub enum EntryKind {
     Field,
     Var,
+    /// A variable that may not be assigned to
+    LoopVar,
     Fn,
 }
 
-fn rand_char(i: u32) -> char {
-    let mut range = if i == 0 || chance::chance(0.5) {
-        'a'..='z'
-    } else {
-        'A'..='Z'
-    };
-    let idx = (rand::random::<f64>() * 26.0) as usize;
+/// The kinds of statements chosen by weight
+#[derive(Copy, Clone)]
+enum Kind {
+    If,
+    While,
+    Foreach,
+    Switch,
+    Try,
+    Decl,
+    Assign,
+    Await,
+    Assert,
+    Suspend,
+    Throw,
+}
+
+impl Kind {
+    /// The kind of the statement as named in `coverage`
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::If => "IfStmt",
+            Kind::While => "WhileStmt",
+            Kind::Foreach => "ForeachStmt",
+            Kind::Switch => "SwitchStmt",
+            Kind::Try => "TryCatchFinallyStmt",
+            Kind::Decl => "VarDeclStmt",
+            Kind::Assign => "AssignStmt",
+            Kind::Await => "AwaitStmt",
+            Kind::Assert => "AssertStmt",
+            Kind::Suspend => "SuspendStmt",
+            Kind::Throw => "ThrowStmt",
+        }
+    }
+
+    fn form(&self) -> Option<Form> {
+        match self {
+            Kind::While => Some(Form::While),
+            Kind::Foreach => Some(Form::Foreach),
+            Kind::Switch => Some(Form::Switch),
+            Kind::Try => Some(Form::Try),
+            Kind::Await => Some(Form::Await),
+            Kind::Assert => Some(Form::Assert),
+            Kind::Suspend => Some(Form::Suspend),
+            Kind::Throw => Some(Form::Throw),
+            Kind::If | Kind::Decl | Kind::Assign => None,
+        }
+    }
+}
 
-    range.nth(idx).unwrap()
+/// The kind of expression a functional form is, as named in `coverage`
+fn form_kind(f: Form) -> &'static str {
+    match f {
+        Form::Case => "CaseExpr",
+        Form::Let => "LetExpr",
+        Form::When => "WhenExpr",
+        _ => "FnAppExpr",
+    }
 }
 
-fn generate_name() -> String {
-    (0..).take(6).map(|i| rand_char(i)).collect()
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::gen::ty;
+
+    fn names<'a>(entries: impl Iterator<Item = &'a ScopeEntry>) -> Vec<&'a str> {
+        entries.map(|e| e.ident.str.as_str()).collect()
+    }
+
+    #[test]
+    fn closing_a_scope_restores_the_one_around_it() {
+        let int = ty::create_int();
+        let fut = ty::create_fut(ty::create_int());
+        let mut scope = Scope::new();
+        scope.define_field(int.clone(), gen::ident("f"));
+        scope.define_fn(
+            int.clone(),
+            gen::ident("m"),
+            vec![ty::simple_ty("I")],
+            Vec::new(),
+        );
+        scope.define_var(fut.clone(), gen::ident("x"));
+        assert_eq!(scope.callable_of_type(&int).count(), 0);
+
+        scope.open();
+        scope.define_var(int.clone(), gen::ident("y"));
+        scope.define(ScopeEntry::loop_var(int.clone(), gen::ident("z")));
+        scope.define_var(ty::simple_ty("I"), gen::ident("o"));
+        assert_eq!(scope.depth(), 1);
+        assert_eq!(names(scope.of_type(&int)), ["y", "z", "f"]);
+        assert_eq!(names(scope.assignable()), ["y", "o", "f", "x"]);
+        assert_eq!(names(scope.callable_of_type(&int)), ["m"]);
+        assert!(scope.has_name("z"));
+        scope.close();
+
+        assert_eq!(scope.depth(), 0);
+        assert_eq!(names(scope.of_type(&int)), ["f"]);
+        assert_eq!(names(scope.assignable()), ["f", "x"]);
+        assert_eq!(names(scope.futures()), ["x"]);
+        assert_eq!(names(scope.fields()), ["f"]);
+        assert_eq!(names(scope.functions()), ["m"]);
+        assert_eq!(scope.callable_of_type(&int).count(), 0);
+        assert!(!scope.has_name("z"));
+    }
+
+    #[test]
+    fn a_type_is_looked_up_among_what_is_left_after_closing_a_scope() {
+        let data = |arg| ty::start_type("GenData_0").with_args(arg).complete();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut scope = Scope::new();
+        scope.open();
+        scope.define_var(data(ty::create_int()), gen::ident("x"));
+        scope.close();
+        scope.define_var(data(ty::create_bool()), gen::ident("b"));
+
+        assert!(!scope.has_of_type(&data(ty::create_int())));
+        assert!(scope
+            .choose_of_type(&data(ty::create_int()), &mut rng)
+            .is_none());
+
+        scope.define_var(data(ty::create_int()), gen::ident("y"));
+        for _ in 0..8 {
+            let e = scope.choose_of_type(&data(ty::create_int()), &mut rng);
+            assert_eq!(e.unwrap().ident.str, "y");
+        }
+        assert_eq!(names(scope.of_type(&data(ty::create_bool()))), ["b"]);
+    }
 }
@@ -0,0 +1,470 @@
+//! The data types, functions, interfaces and classes of a module, before any
+//! body is generated.
+//!
+//! Besides the fixed `I`, `J`, `D` and `E`, every module has random
+//! interfaces, each extending some of the ones before it, and random classes
+//! implementing some of them. The generator builds its scope from these
+//! shapes, so that methods call the methods of other classes through their
+//! interfaces.
+//!
+//! The functional layer consists of random data types, some with a type
+//! parameter, whose constructors take numbers, booleans or values of the data
+//! types before them, and of functions over them, each of which may call the
+//! functions before it.
+
+use abs_syntax::ast;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    gen::{self, ty},
+    target::Target,
+    Options,
+};
+
+/// The most interfaces an interface extends
+const MAX_EXTENDS: usize = 2;
+/// The most methods an interface declares itself
+const MAX_SIGS: usize = 3;
+/// The most parameters of a method or class
+const MAX_PARAMS: usize = 2;
+/// The most interfaces a class implements
+const MAX_IMPLEMENTS: usize = 2;
+/// The most fields of a class holding objects of generated interfaces
+const MAX_OBJ_FIELDS: usize = 2;
+/// The most constructors of a data type
+const MAX_CONSTRS: usize = 3;
+/// The most arguments of a constructor
+const MAX_CONSTR_ARGS: usize = 2;
+/// The name of the type parameter of a parametric data type
+const TYPE_PARAM: &str = "A";
+
+pub struct Landscape {
+    pub data: Vec<DataShape>,
+    pub functions: Vec<FunctionShape>,
+    pub interfaces: Vec<InterfaceShape>,
+    pub classes: Vec<ClassShape>,
+}
+
+pub struct DataShape {
+    pub name: String,
+    /// Whether the type has the parameter `A`
+    pub parametric: bool,
+    pub constrs: Vec<ConstrShape>,
+}
+
+pub struct ConstrShape {
+    pub name: String,
+    /// May contain the type parameter
+    pub args: Vec<ast::Type>,
+}
+
+pub struct FunctionShape {
+    pub name: String,
+    pub ret: ast::Type,
+    pub params: Vec<(ast::Type, String)>,
+}
+
+pub struct InterfaceShape {
+    pub name: String,
+    /// Indices of earlier interfaces
+    pub extends: Vec<usize>,
+    /// The methods declared by the interface itself
+    pub sigs: Vec<SigShape>,
+}
+
+#[derive(Clone)]
+pub struct SigShape {
+    pub name: String,
+    pub ret: ast::Type,
+    pub params: Vec<(ast::Type, String)>,
+}
+
+pub struct ClassShape {
+    pub name: String,
+    /// Indices of the interfaces
+    pub implements: Vec<usize>,
+    pub params: Vec<(ast::Type, String)>,
+    /// Fields holding objects of generated interfaces, initially `null`
+    pub obj_fields: Vec<(ast::Type, String)>,
+}
+
+/// A class that can be created with `new`
+pub struct Constructor {
+    pub class: String,
+    pub params: Vec<ast::Type>,
+}
+
+/// A method that can be called on objects of the types in `defined_for`
+pub struct Callable {
+    pub sig: SigShape,
+    pub defined_for: Vec<ast::Type>,
+}
+
+pub fn interface_name(i: usize) -> String {
+    format!("GeneratedI_{}", i)
+}
+
+pub fn data_name(i: usize) -> String {
+    format!("GenData_{}", i)
+}
+
+pub fn function_name(i: usize) -> String {
+    format!("genf_{}", i)
+}
+
+pub fn class_name(i: usize) -> String {
+    format!("Generated_{}", i)
+}
+
+/// The types whose values can be passed around without a generated interface
+fn base_ty<R: Rng + ?Sized>(rng: &mut R) -> ast::Type {
+    [
+        ty::create_int(),
+        ty::create_bool(),
+        ty::create_i(),
+        ty::create_j(),
+    ]
+    .choose(rng)
+    .unwrap()
+    .clone()
+}
+
+fn params<R: Rng + ?Sized>(rng: &mut R, prefix: &str) -> Vec<(ast::Type, String)> {
+    (0..rng.gen_range(0, MAX_PARAMS + 1))
+        .map(|i| (base_ty(rng), format!("{}{}", prefix, i)))
+        .collect()
+}
+
+/// Up to `max` distinct numbers below `n`
+fn subset<R: Rng + ?Sized>(rng: &mut R, n: usize, max: usize) -> Vec<usize> {
+    let mut all: Vec<usize> = (0..n).collect();
+    all.shuffle(rng);
+    all.truncate(rng.gen_range(0, max.min(n) + 1));
+    all.sort_unstable();
+    all
+}
+
+/// Replaces the type parameter in `ty`
+fn subst(ty: &ast::Type, arg: &ast::Type) -> ast::Type {
+    if ty.ident.str == TYPE_PARAM {
+        return arg.clone();
+    }
+    ast::Type {
+        ident: ty.ident.clone(),
+        args: ty.args.iter().map(|t| subst(t, arg)).collect(),
+    }
+}
+
+impl Landscape {
+    /// Nothing but the fixed interfaces `I` and `J`
+    pub fn empty() -> Self {
+        Landscape {
+            data: Vec::new(),
+            functions: Vec::new(),
+            interfaces: Vec::new(),
+            classes: Vec::new(),
+        }
+    }
+
+    pub fn generate<R: Rng + ?Sized>(opts: &Options, rng: &mut R) -> Self {
+        let mut landscape = Landscape::empty();
+
+        for i in 0..opts.num_data_types as usize {
+            let parametric = rng.gen::<bool>();
+            let mut arg_tys = vec![ty::create_int(), ty::create_bool()];
+            if parametric {
+                arg_tys.push(ty::simple_ty(TYPE_PARAM));
+            }
+            arg_tys.extend(landscape.data_types());
+            let constrs = (0..rng.gen_range(1, MAX_CONSTRS + 1))
+                .map(|j| ConstrShape {
+                    name: format!("GenC{}_{}", i, j),
+                    args: (0..rng.gen_range(0, MAX_CONSTR_ARGS + 1))
+                        .map(|_| arg_tys.choose(rng).unwrap().clone())
+                        .collect(),
+                })
+                .collect();
+            landscape.data.push(DataShape {
+                name: data_name(i),
+                parametric,
+                constrs,
+            });
+        }
+
+        let mut value_tys = vec![ty::create_int(), ty::create_bool()];
+        value_tys.extend(landscape.data_types());
+        for i in 0..opts.num_functions as usize {
+            landscape.functions.push(FunctionShape {
+                name: function_name(i),
+                ret: value_tys.choose(rng).unwrap().clone(),
+                params: (0..rng.gen_range(1, MAX_PARAMS + 1))
+                    .map(|k| (value_tys.choose(rng).unwrap().clone(), format!("x{}", k)))
+                    .collect(),
+            });
+        }
+
+        let n = opts.num_interfaces as usize;
+
+        let mut interfaces = Vec::with_capacity(n);
+        for i in 0..n {
+            let extends = subset(rng, i, MAX_EXTENDS);
+            let sigs = (0..rng.gen_range(1, MAX_SIGS + 1))
+                .map(|j| {
+                    // Results may be objects of the interfaces declared so far
+                    let ret = match rng.gen_range(0, i + 2) {
+                        0 => base_ty(rng),
+                        k if k <= i => ty::simple_ty(interface_name(k - 1)),
+                        _ => base_ty(rng),
+                    };
+                    SigShape {
+                        name: format!("m{}_{}", i, j),
+                        ret,
+                        params: params(rng, "p"),
+                    }
+                })
+                .collect();
+            interfaces.push(InterfaceShape {
+                name: interface_name(i),
+                extends,
+                sigs,
+            });
+        }
+
+        let classes = (0..opts.num_rand_classes as usize)
+            .map(|i| ClassShape {
+                name: class_name(i),
+                implements: subset(rng, n, MAX_IMPLEMENTS),
+                params: (0..opts.num_class_params as usize)
+                    .map(|k| (base_ty(rng), format!("cp{}", k)))
+                    .collect(),
+                obj_fields: subset(rng, n, MAX_OBJ_FIELDS)
+                    .into_iter()
+                    .map(|k| (ty::simple_ty(interface_name(k)), format!("fo{}", k)))
+                    .collect(),
+            })
+            .collect();
+
+        landscape.interfaces = interfaces;
+        landscape.classes = classes;
+        landscape
+    }
+
+    /// The types of the generated data types, those with a parameter for
+    /// numbers and for booleans
+    pub fn data_types(&self) -> Vec<ast::Type> {
+        let mut res = Vec::new();
+        for d in &self.data {
+            if d.parametric {
+                for arg in [ty::create_int(), ty::create_bool()] {
+                    res.push(ty::start_type(&d.name).with_args(arg).complete());
+                }
+            } else {
+                res.push(ty::simple_ty(&d.name));
+            }
+        }
+        res
+    }
+
+    pub fn is_data(&self, ty: &ast::Type) -> bool {
+        self.data.iter().any(|d| d.name == ty.ident.str)
+    }
+
+    /// The constructors of a data type with the types of their arguments
+    pub fn data_constrs(&self, ty: &ast::Type) -> Vec<(String, Vec<ast::Type>)> {
+        let d = match self.data.iter().find(|d| d.name == ty.ident.str) {
+            Some(d) => d,
+            None => return Vec::new(),
+        };
+        d.constrs
+            .iter()
+            .map(|c| {
+                let args = match ty.args.first() {
+                    Some(arg) => c.args.iter().map(|t| subst(t, arg)).collect(),
+                    None => c.args.clone(),
+                };
+                (c.name.clone(), args)
+            })
+            .collect()
+    }
+
+    /// The interface and every interface it extends, directly or not
+    fn ancestors(&self, i: usize) -> Vec<usize> {
+        let mut res = vec![i];
+        let mut k = 0;
+        while k < res.len() {
+            for e in &self.interfaces[res[k]].extends {
+                if !res.contains(e) {
+                    res.push(*e);
+                }
+            }
+            k += 1;
+        }
+        res.sort_unstable();
+        res
+    }
+
+    /// The methods a class has to implement
+    pub fn class_sigs(&self, class: &ClassShape) -> Vec<SigShape> {
+        let mut ifaces: Vec<usize> = class
+            .implements
+            .iter()
+            .flat_map(|i| self.ancestors(*i))
+            .collect();
+        ifaces.sort_unstable();
+        ifaces.dedup();
+        ifaces
+            .into_iter()
+            .flat_map(|i| self.interfaces[i].sigs.iter().cloned())
+            .collect()
+    }
+
+    /// The methods of the fixed interfaces and of the generated ones
+    pub fn callables(&self) -> Vec<Callable> {
+        let sig = |name: &str, ret, params: Vec<ast::Type>| SigShape {
+            name: name.to_string(),
+            ret,
+            params: params
+                .into_iter()
+                .enumerate()
+                .map(|(i, t)| (t, format!("p{}", i)))
+                .collect(),
+        };
+        let mut res = vec![
+            Callable {
+                sig: sig("n", ty::create_int(), vec![]),
+                defined_for: vec![ty::create_i()],
+            },
+            Callable {
+                sig: sig("b", ty::create_bool(), vec![]),
+                defined_for: vec![ty::create_i()],
+            },
+            Callable {
+                sig: sig("m", ty::create_unit(), vec![ty::create_int()]),
+                defined_for: vec![ty::create_j()],
+            },
+            Callable {
+                sig: sig(
+                    "getI",
+                    ty::create_i(),
+                    vec![ty::create_bool(), ty::create_int()],
+                ),
+                defined_for: vec![ty::create_j()],
+            },
+        ];
+
+        for (i, iface) in self.interfaces.iter().enumerate() {
+            // Sub-interfaces have the methods too
+            let defined_for: Vec<_> = (i..self.interfaces.len())
+                .filter(|k| self.ancestors(*k).contains(&i))
+                .map(|k| ty::simple_ty(interface_name(k)))
+                .collect();
+            for s in &iface.sigs {
+                res.push(Callable {
+                    sig: s.clone(),
+                    defined_for: defined_for.clone(),
+                });
+            }
+        }
+        res
+    }
+
+    /// The classes whose objects have type `ty`
+    pub fn constructors(&self, ty: &ast::Type) -> Vec<Constructor> {
+        let name = &ty.ident.str;
+        if name == "I" || name == "J" {
+            let class = if name == "I" { "D" } else { "E" };
+            return vec![Constructor {
+                class: class.to_string(),
+                params: Vec::new(),
+            }];
+        }
+
+        let i = match self.interfaces.iter().position(|i| &i.name == name) {
+            Some(i) => i,
+            None => return Vec::new(),
+        };
+        self.classes
+            .iter()
+            .filter(|c| c.implements.iter().any(|k| self.ancestors(*k).contains(&i)))
+            .map(|c| Constructor {
+                class: c.name.clone(),
+                params: c.params.iter().map(|(t, _)| t.clone()).collect(),
+            })
+            .collect()
+    }
+
+    pub fn is_object(&self, ty: &ast::Type) -> bool {
+        let name = &ty.ident.str;
+        name == "I" || name == "J" || self.interfaces.iter().any(|i| &i.name == name)
+    }
+
+    /// The types of objects, the fixed ones first
+    pub fn object_types(&self) -> Vec<ast::Type> {
+        let mut res = vec![ty::create_i(), ty::create_j()];
+        res.extend(self.interfaces.iter().map(|i| ty::simple_ty(&i.name)));
+        res
+    }
+}
+
+impl SigShape {
+    pub fn to_sig(&self, annotations: &ast::Annotations) -> ast::MethodSig {
+        let mut builder = gen::start_method_sig(&self.name)
+            .with_annotations(annotations)
+            .with_ret(self.ret.clone());
+        for (t, name) in &self.params {
+            builder.add_param(gen::create_param(t.clone(), name, gen::empty_annos()));
+        }
+        builder.complete()
+    }
+
+    /// That every parameter that is a number is not negative, if there is one
+    pub fn precondition(&self) -> Option<ast::PureExpr> {
+        self.params
+            .iter()
+            .filter(|(t, _)| t.is_int())
+            .map(|(_, name)| -> ast::PureExpr {
+                gen::create_bin_expr(
+                    ast::BinaryOp::Ge,
+                    gen::create_var_use(name).into(),
+                    gen::create_lit("0").into(),
+                )
+                .into()
+            })
+            .reduce(|a, b| gen::create_bin_expr(ast::BinaryOp::And, a, b).into())
+    }
+}
+
+impl DataShape {
+    pub fn to_decl(&self) -> ast::DataTypeDecl {
+        let mut builder = gen::start_data_type(&self.name);
+        if self.parametric {
+            builder.add_param(TYPE_PARAM);
+        }
+        for c in &self.constrs {
+            let mut constr = gen::start_data_constr(&c.name);
+            for t in &c.args {
+                constr.add_param(gen::create_data_constr_param(t.clone()));
+            }
+            builder.add_constr(constr.complete());
+        }
+        builder.complete()
+    }
+}
+
+impl InterfaceShape {
+    /// The declaration, with the preconditions of its methods for `target`
+    pub fn to_decl(&self, target: Target) -> ast::InterfaceDecl {
+        let mut builder = gen::start_interface_decl(&self.name);
+        for e in &self.extends {
+            builder.add_extends(interface_name(*e));
+        }
+        for s in &self.sigs {
+            let annos = match s.precondition() {
+                Some(cond) => target.precondition(cond),
+                None => gen::empty_annos(),
+            };
+            builder.add_sig(s.to_sig(&annos));
+        }
+        builder.complete()
+    }
+}
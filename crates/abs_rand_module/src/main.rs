@@ -1,69 +1,111 @@
 use std::io::prelude::*;
 use std::{
     fs::{self, File},
-    io,
-    path::Path,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
 };
 
-use abs_syntax::ast;
+use abs_syntax::ast::{self, DisplayABS};
 use gen::ty;
 use generator::RandGenerator;
+use landscape::{ClassShape, Landscape};
+use naming::{Names, Naming};
+use oracle::Planted;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 mod chance;
+mod config;
+mod coverage;
+mod enumerate;
+mod fuzz;
 pub mod gen;
 mod generator;
+mod landscape;
+mod mutate;
+mod naming;
+mod oracle;
+mod profile;
+mod sweep;
+mod target;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum Target {
-    Crowbar,
-    NullableExtension,
-    Location,
+use config::{
+    Config, EnumerateConfig, FuzzConfig, MutateConfig, Settings, SweepConfig, TargetConfig,
+    ToolConfig,
+};
+use coverage::Coverage;
+use sweep::Dim;
+use target::Target;
+
+/// The module and the defects planted into it. Every statement and
+/// expression is generated knowing what `corpus`, the modules before this
+/// one, and the module up to it cover.
+fn gen_mock_module(
+    opts: Options,
+    corpus: &Coverage,
+    rng: &mut StdRng,
+) -> (ast::Module, Vec<Planted>) {
+    let mut builder = start_mock_module(opts.target, opts.weights.throws());
+
+    let landscape = Landscape::generate(&opts, rng);
+    let mut coverage = corpus.clone();
+    let mut names = Names::new(&landscape);
+    for data in &landscape.data {
+        builder.add_child(data.to_decl());
+    }
+    for (i, f) in landscape.functions.iter().enumerate() {
+        let body = RandGenerator::for_function(opts, &landscape, i, &mut coverage, &mut names, rng)
+            .generate_function(f);
+        let params = f
+            .params
+            .iter()
+            .map(|(ty, name)| gen::create_param(ty.clone(), name, gen::empty_annos()))
+            .collect();
+        let decl = gen::create_function_decl(f.ret.clone(), &f.name, params, body);
+        builder.add_child(decl);
+    }
+    for iface in &landscape.interfaces {
+        builder.add_child(iface.to_decl(opts.target));
+    }
+    let mut planted = Vec::new();
+    for class in &landscape.classes {
+        let decl = class_generated(
+            class,
+            &landscape,
+            opts,
+            &mut coverage,
+            &mut names,
+            rng,
+            &mut planted,
+        );
+        builder.add_child(decl);
+    }
+
+    builder.add_child(ast::ModuleItem::MainBlock(ast::Block { stmts: Vec::new() }));
+
+    (builder.complete(), planted)
 }
 
-fn gen_mock_module(opts: Options) -> ast::Module {
+/// The declarations every module starts with: the prelude of the target, the
+/// exception if it is thrown, the interfaces `I` and `J` and the classes `D`
+/// and `E`
+fn start_mock_module(target: Target, throws: bool) -> gen::ModuleBuilder {
     let mut builder = gen::start_module("MockABS");
 
-    if let Target::Crowbar = opts.target {
-        builder.add_child(
-            gen::start_data_type("Spec")
-                .with_const(
-                    gen::start_data_constr("ObjInv")
-                        .with_param(gen::create_data_constr_param(ty::create_bool()))
-                        .complete(),
-                )
-                .with_const(
-                    gen::start_data_constr("Ensures")
-                        .with_param(gen::create_data_constr_param(ty::create_bool()))
-                        .complete(),
-                )
-                .with_const(
-                    gen::start_data_constr("Requires")
-                        .with_param(gen::create_data_constr_param(ty::create_bool()))
-                        .complete(),
-                )
-                .with_const(
-                    gen::start_data_constr("WhileInv")
-                        .with_param(gen::create_data_constr_param(ty::create_bool()))
-                        .complete(),
-                )
-                .complete(),
-        )
+    for item in target.prelude() {
+        builder.add_child(item);
+    }
+    if throws {
+        builder.add_child(gen::create_exception_decl(generator::EXCEPTION));
     }
 
-    let mut builder = builder
+    builder
         .with_child(interface_i())
-        .with_child(interface_j(opts.target))
+        .with_child(interface_j(target))
         .with_child(class_d())
-        .with_child(class_e(opts.target));
-
-    for i in 0..opts.num_rand_classes {
-        let name = format!("Generated_{}", i);
-        builder.add_child(class_generated(&name, opts));
-    }
-
-    builder.add_child(ast::ModuleItem::MainBlock(ast::Block { stmts: Vec::new() }));
-
-    builder.complete()
+        .with_child(class_e(target))
 }
 
 fn interface_i() -> ast::InterfaceDecl {
@@ -91,7 +133,7 @@ fn interface_j(target: Target) -> ast::InterfaceDecl {
         )
         .with_sig(
             gen::start_method_sig("getI")
-                .with_annotation(gen::create_non_null_ret_anno(target))
+                .with_annotations(&target.non_null_ret())
                 .with_ret(ty::simple_ty("I"))
                 .with_param(gen::create_param(
                     ty::create_bool(),
@@ -127,18 +169,15 @@ fn class_d() -> ast::ClassDecl {
 }
 
 fn class_e(target: Target) -> ast::ClassDecl {
-    let mut get_i_sig = gen::start_method_sig("getI")
+    let get_i_sig = gen::start_method_sig("getI")
         .with_ret(ty::simple_ty("I"))
         .with_param(gen::create_param(
             ty::create_bool(),
             "flag",
             gen::empty_annos(),
         ))
-        .with_param(gen::create_param(ty::create_int(), "c", gen::empty_annos()));
-
-    if let Target::NullableExtension = target {
-        get_i_sig.add_annotation(gen::create_nullable_non_null())
-    }
+        .with_param(gen::create_param(ty::create_int(), "c", gen::empty_annos()))
+        .with_annotations(&target.non_null_impl());
 
     let builder = gen::start_class_decl("E")
         .with_implements("J")
@@ -167,8 +206,67 @@ fn class_e(target: Target) -> ast::ClassDecl {
     builder.complete()
 }
 
-fn class_generated(name: &str, opts: Options) -> ast::ClassDecl {
-    let mut builder = gen::start_class_decl(name)
+fn class_generated(
+    class: &ClassShape,
+    landscape: &Landscape,
+    opts: Options,
+    coverage: &mut Coverage,
+    names: &mut Names,
+    rng: &mut StdRng,
+    planted: &mut Vec<Planted>,
+) -> ast::ClassDecl {
+    let mut builder = gen::start_class_decl(&class.name);
+    for a in opts
+        .target
+        .object_invariant(generator::object_invariant())
+        .iter()
+    {
+        builder.add_annotation(a.clone());
+    }
+    for (ty, name) in &class.params {
+        builder.add_param(gen::create_param(ty.clone(), name, gen::empty_annos()));
+    }
+    for i in &class.implements {
+        builder.add_implements(landscape::interface_name(*i));
+    }
+
+    let mut builder = with_std_fields(builder);
+    for (ty, name) in &class.obj_fields {
+        builder.add_field(gen::create_field_init(
+            ty.clone(),
+            name,
+            gen::create_null(),
+            gen::empty_annos(),
+        ));
+    }
+
+    for m in 0..opts.num_methods {
+        let mut body =
+            RandGenerator::new(opts, landscape, class, coverage, names, rng).generate_body();
+        let mut sig = rand_method_sig(m, opts.target);
+        if let Some(defect) = opts.target.defect() {
+            if m == 0 && opts.defect_rate > 0.0 && chance::chance(rng, opts.defect_rate) {
+                planted.push(defect.plant(&class.name, &mut sig, &mut body));
+            }
+        }
+        builder.add_method(gen::create_method_decl(sig, body));
+    }
+    for sig in landscape.class_sigs(class) {
+        let (body, post) =
+            RandGenerator::new(opts, landscape, class, coverage, names, rng).generate_method(&sig);
+        let annos = match post {
+            Some(cond) => opts.target.postcondition(cond),
+            None => gen::empty_annos(),
+        };
+        builder.add_method(gen::create_method_decl(sig.to_sig(&annos), body));
+    }
+
+    builder.complete()
+}
+
+/// The fields every generated class has
+fn with_std_fields(builder: gen::ClassDeclBuilder) -> gen::ClassDeclBuilder {
+    builder
         .with_field(gen::create_field_init(
             ty::create_int(),
             "fint",
@@ -202,56 +300,164 @@ fn class_generated(name: &str, opts: Options) -> ast::ClassDecl {
             "fj",
             gen::create_null(),
             gen::empty_annos(),
-        ));
-
-    builder.add_method(create_rand_method(opts));
-
-    builder.complete()
+        ))
 }
 
-fn create_rand_method(opts: Options) -> ast::MethodDecl {
-    let mut sig = gen::start_method_sig("gen")
+/// `gen` for the first method of a class, `gen_1`, `gen_2`, ... for the others
+fn rand_method_sig(i: u32, target: Target) -> ast::MethodSig {
+    let name = match i {
+        0 => "gen".to_string(),
+        _ => format!("gen_{}", i),
+    };
+    gen::start_method_sig(name)
         .with_ret(ty::simple_ty("I"))
-        .with_annotation(gen::create_non_null_ret_anno(opts.target));
+        .with_annotations(&target.non_null_ret())
+        .with_param(gen::create_param(
+            ty::simple_ty("I"),
+            "i",
+            target.nullable_param(),
+        ))
+        .complete()
+}
 
-    let param = match opts.target {
-        Target::Crowbar | Target::Location => {
-            //sig.add_annotation(gen::create_crowbar_non_null_param("i"));
-            gen::create_param(ty::simple_ty("I"), "i", gen::empty_annos())
+/// Removes the files generated by an earlier run
+fn clear_out(dir: &Path) -> io::Result<()> {
+    for e in fs::read_dir(dir)? {
+        let path = e?.path();
+        if path.is_file() {
+            fs::remove_file(path)?;
         }
-        Target::NullableExtension => {
-            let mut annos = gen::empty_annos();
-            annos.push(gen::create_nullable_nullable());
-            gen::create_param(ty::simple_ty("I"), "i", annos)
-        }
-    };
+    }
+
+    Ok(())
+}
 
-    sig.add_param(param);
+/// Generates a module from `seed` and what `corpus` covers, and returns what
+/// it covers itself. With `steer` at 0, the seed in its header is enough to
+/// generate it again. If defects may be planted, the diagnostics expected for
+/// them are written next to it.
+fn write_module(path: &Path, opts: Options, seed: u64, corpus: &Coverage) -> io::Result<Coverage> {
+    let (module, planted) = gen_mock_module(opts, corpus, &mut StdRng::seed_from_u64(seed));
+    let mut coverage = Coverage::default();
+    coverage.add_module(&module);
 
-    let body = RandGenerator::new(opts).generate_body();
+    let out = BufWriter::new(File::create(path)?);
+    let mut out = oracle::Locator::new(out, &planted);
+    writeln!(out, "// Generated by abs_rand_module with seed {}\n", seed)?;
+    module.write_abs(&mut out)?;
+    let (mut out, expected) = out.finish();
+    out.flush()?;
 
-    gen::create_method_decl(sig.complete(), body)
+    if opts.defect_rate > 0.0 {
+        oracle::write_expected(path, opts.target.name(), &expected)?;
+    }
+
+    Ok(coverage)
 }
 
-fn clear_out() -> io::Result<()> {
-    let path = Path::new("./out/");
-    for e in fs::read_dir(path)? {
-        fs::remove_file(e?.path())?;
+/// Writes the modules on `threads` threads, and returns what they cover.
+/// Every module is generated from its own seed, so the files are the same
+/// however many threads there are. If any of them is steered, every module
+/// is generated knowing what the ones before it cover, so they are written
+/// one after another.
+fn write_modules(modules: &[(PathBuf, Options, u64)], threads: usize) -> io::Result<Coverage> {
+    if modules.iter().any(|(_, opts, _)| opts.steer > 0.0) {
+        let mut corpus = Coverage::default();
+        for (path, opts, seed) in modules {
+            let coverage = write_module(path, *opts, *seed, &corpus)?;
+            corpus.merge(&coverage);
+        }
+        return Ok(corpus);
     }
 
-    Ok(())
+    let next = AtomicUsize::new(0);
+    let work = || {
+        let mut coverage = Coverage::default();
+        let none = Coverage::default();
+        while let Some((path, opts, seed)) = modules.get(next.fetch_add(1, Ordering::Relaxed)) {
+            match write_module(path, *opts, *seed, &none) {
+                Ok(c) => coverage.merge(&c),
+                Err(e) => {
+                    // The other threads stop after their current module
+                    next.store(modules.len(), Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(coverage)
+    };
+    let results: Vec<io::Result<Coverage>> = thread::scope(|s| {
+        let workers: Vec<_> = (0..threads).map(|_| s.spawn(work)).collect();
+        workers
+            .into_iter()
+            .map(|w| w.join().expect("A thread generating modules panicked"))
+            .collect()
+    });
+
+    let mut coverage = Coverage::default();
+    for r in results {
+        coverage.merge(&r?);
+    }
+    Ok(coverage)
 }
 
-fn write_module(path_str: &str, opts: Options) -> io::Result<()> {
-    let module = gen_mock_module(opts);
+/// Lists the seed of the run and of every module generated in it. For a
+/// sweep, the options of every module are listed as CSV as well.
+struct Manifest {
+    lines: Vec<String>,
+    points: Option<Vec<String>>,
+}
 
-    let path = Path::new(path_str);
+impl Manifest {
+    fn new(settings: &Settings) -> Self {
+        let mut lines = vec![format!("seed {}", settings.seed)];
+        for d in Dim::all().filter(|d| *d != Dim::NumRandClasses) {
+            lines.push(format!("{} {}", d.name(), d.get(&settings.opts)));
+        }
+        lines.push(format!("naming {}", settings.opts.naming.name()));
+        lines.push(format!("unique_names {}", settings.opts.unique_names));
+        for (name, w) in settings.opts.weights.iter() {
+            lines.push(format!("weight {} {}", name, w));
+        }
 
-    let mut f = File::create(path)?;
+        let points = settings.sweep.as_ref().map(|_| {
+            let dims: Vec<_> = Dim::all().map(|d| d.name()).collect();
+            vec![format!("file,seed,target,{}", dims.join(","))]
+        });
 
-    f.write_all(module.to_string().as_bytes())?;
+        Manifest { lines, points }
+    }
 
-    Ok(())
+    fn add(&mut self, path: &Path, seed: u64, opts: &Options) {
+        let name = path.file_name().unwrap().to_string_lossy();
+        self.lines.push(format!("{} {}", name, seed));
+
+        if let Some(points) = &mut self.points {
+            let values: Vec<_> = Dim::all().map(|d| d.get(opts)).collect();
+            points.push(format!(
+                "{},{},{},{}",
+                name,
+                seed,
+                opts.target.short_name(),
+                values.join(",")
+            ));
+        }
+    }
+
+    fn write(&self, dir: &Path) -> io::Result<()> {
+        let mut f = File::create(dir.join("manifest.txt"))?;
+        for l in &self.lines {
+            writeln!(f, "{}", l)?;
+        }
+
+        if let Some(points) = &self.points {
+            let mut f = File::create(dir.join("sweep.csv"))?;
+            for p in points {
+                writeln!(f, "{}", p)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -263,9 +469,110 @@ pub struct Options {
     pub else_ratio: f64,
     pub avg_meth_body_size: u32,
     pub avg_block_size: u32,
+    pub num_methods: u32,
+    pub num_interfaces: u32,
+    pub num_class_params: u32,
+    pub async_rate: f64,
+    /// Chance of a `get` where a future of the type is in scope
+    pub get_rate: f64,
+    /// Chance of a `new` where a class of the type exists
+    pub new_rate: f64,
+    /// Chance of a synchronous call otherwise
+    pub sync_rate: f64,
+    pub num_data_types: u32,
+    pub num_functions: u32,
+    pub functional_rate: f64,
+    /// Chance of a generated class having a defect that the target should
+    /// report, see `oracle`
+    pub defect_rate: f64,
+    /// How strongly the generator prefers statements and expressions that
+    /// are rarely covered so far, see `coverage`
+    pub steer: f64,
+    /// How variables are named, see `naming`
+    pub naming: Naming,
+    /// Whether no two variables of a module have the same name
+    pub unique_names: bool,
+    pub weights: StmtWeights,
     pub target: Target,
 }
 
+/// How often each kind of statement is generated. Where `branch_rate` allows
+/// a compound statement, it is chosen by the weights of `if` to `try`.
+/// Otherwise a declaration, an assignment or one of `await` to `throw` is
+/// chosen, with `declare_to_assign` splitting a weight of 1 between the first
+/// two. Only `if` has a weight by default, so that the other kinds are
+/// generated only when asked for.
+#[derive(Copy, Clone)]
+pub struct StmtWeights {
+    pub r#if: f64,
+    pub r#while: f64,
+    pub foreach: f64,
+    pub switch: f64,
+    pub r#try: f64,
+    pub r#await: f64,
+    pub assert: f64,
+    pub suspend: f64,
+    pub throw: f64,
+}
+
+impl StmtWeights {
+    /// Whether the exception may be thrown or caught
+    pub fn throws(&self) -> bool {
+        self.throw > 0.0 || self.r#try > 0.0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, f64)> {
+        vec![
+            ("if", self.r#if),
+            ("while", self.r#while),
+            ("foreach", self.foreach),
+            ("switch", self.switch),
+            ("try", self.r#try),
+            ("await", self.r#await),
+            ("assert", self.assert),
+            ("suspend", self.suspend),
+            ("throw", self.throw),
+        ]
+        .into_iter()
+    }
+
+    pub fn set(&mut self, name: &str, w: f64) -> Result<(), String> {
+        let field = match name {
+            "if" => &mut self.r#if,
+            "while" => &mut self.r#while,
+            "foreach" => &mut self.foreach,
+            "switch" => &mut self.switch,
+            "try" => &mut self.r#try,
+            "await" => &mut self.r#await,
+            "assert" => &mut self.assert,
+            "suspend" => &mut self.suspend,
+            "throw" => &mut self.throw,
+            _ => return Err(format!("There is no weight for `{}`", name)),
+        };
+        if w < 0.0 {
+            return Err(format!("The weight of {} must not be negative", name));
+        }
+        *field = w;
+        Ok(())
+    }
+}
+
+impl Default for StmtWeights {
+    fn default() -> Self {
+        StmtWeights {
+            r#if: 1.0,
+            r#while: 0.0,
+            foreach: 0.0,
+            switch: 0.0,
+            r#try: 0.0,
+            r#await: 0.0,
+            assert: 0.0,
+            suspend: 0.0,
+            throw: 0.0,
+        }
+    }
+}
+
 const NUM_RAND_CLASSES: u32 = 300;
 const MAX_DEPTH: u8 = 2;
 const BRANCH_RATE: f64 = 0.1;
@@ -273,6 +580,18 @@ const DECLARE_TO_ASSIGN: f64 = 0.3;
 const ELSE_RATIO: f64 = 0.7;
 const AVG_METH_BODY_SIZE: u32 = 3;
 const AVG_BLOCK_SIZE: u32 = 2;
+const NUM_METHODS: u32 = 1;
+const NUM_INTERFACES: u32 = 0;
+const NUM_CLASS_PARAMS: u32 = 0;
+const ASYNC_RATE: f64 = 0.7;
+const GET_RATE: f64 = 0.1;
+const NEW_RATE: f64 = 0.5;
+const SYNC_RATE: f64 = 0.1;
+const NUM_DATA_TYPES: u32 = 0;
+const NUM_FUNCTIONS: u32 = 0;
+const FUNCTIONAL_RATE: f64 = 0.0;
+const DEFECT_RATE: f64 = 0.0;
+const STEER: f64 = 0.0;
 
 impl Default for Options {
     fn default() -> Self {
@@ -284,28 +603,525 @@ impl Default for Options {
             else_ratio: ELSE_RATIO,
             avg_meth_body_size: AVG_METH_BODY_SIZE,
             avg_block_size: AVG_BLOCK_SIZE,
-            target: Target::Crowbar,
+            num_methods: NUM_METHODS,
+            num_interfaces: NUM_INTERFACES,
+            num_class_params: NUM_CLASS_PARAMS,
+            async_rate: ASYNC_RATE,
+            get_rate: GET_RATE,
+            new_rate: NEW_RATE,
+            sync_rate: SYNC_RATE,
+            num_data_types: NUM_DATA_TYPES,
+            num_functions: NUM_FUNCTIONS,
+            functional_rate: FUNCTIONAL_RATE,
+            defect_rate: DEFECT_RATE,
+            steer: STEER,
+            naming: Naming::Random,
+            unique_names: false,
+            weights: StmtWeights::default(),
+            target: &target::Crowbar,
         }
     }
 }
 
+const USAGE: &str = "\
+Usage: abs_rand_module [options]
+
+Generates random ABS modules, by default 20 for Crowbar, 100 for the location
+type checker and 300 for the nullable extension, with 1 up to that many
+classes each.
+
+Options:
+    --config <file>               Read these options from a TOML file
+    --seed <n>                    Generate everything from this seed
+    --out <dir>                   Write to this directory [default: out]
+    --pattern <pattern>           Name files like this, with {target},
+                                  {classes} and {point} replaced
+                                  [default: generated-{target}-{classes}.abs]
+    --clean                       Remove the files in the output directory first
+    --jobs <n>                    Generate on this many threads, with the same
+                                  files for any number [default: the number
+                                  of CPUs]
+    --profile <file>              Read options learned with --learn, which all
+                                  other options take precedence over
+    --target <target>=<classes>   Generate modules for crowbar, nullable or
+                                  location with these numbers of classes, e.g.
+                                  1,5,10 or 1..=300 or 10..=300/10. Can be
+                                  given more than once.
+    --max-depth <n>               Nesting depth of statements [default: 2]
+    --branch-rate <p>             Chance of an if statement [default: 0.1]
+    --declare-to-assign <p>       Chance of a declaration instead of an
+                                  assignment [default: 0.3]
+    --else-ratio <p>              Chance of an else branch [default: 0.7]
+    --avg-meth-body-size <n>      Average statements per method [default: 3]
+    --avg-block-size <n>          Average statements per block [default: 2]
+    --num-methods <n>             Methods per generated class [default: 1]
+    --num-interfaces <n>          Interfaces implemented by the generated
+                                  classes [default: 0]
+    --num-class-params <n>        Parameters of the generated classes
+                                  [default: 0]
+    --async-rate <p>              Chance of an asynchronous call where a future
+                                  is needed [default: 0.7]
+    --get-rate <p>                Chance of a get where a future is in scope
+                                  [default: 0.1]
+    --new-rate <p>                Chance of a new object where one fits
+                                  [default: 0.5]
+    --sync-rate <p>               Chance of a synchronous call otherwise
+                                  [default: 0.1]
+    --num-data-types <n>          Random data types [default: 0]
+    --num-functions <n>           Random functions over them [default: 0]
+    --functional-rate <p>         Chance of a case, let or when expression or
+                                  a function call [default: 0]
+    --defect-rate <p>             Chance of a class with a defect that the
+                                  target should report, listed next to the
+                                  module in <module>.expected.json
+                                  [default: 0]
+    --steer <s>                   How strongly to prefer statements and
+                                  expressions rarely generated in a place so
+                                  far, 0 for not at all [default: 0]
+    --naming <naming>             Name variables with six random letters,
+                                  counter for v0, v1, ... or typed for names
+                                  like x_int_3 [default: random]
+    --unique-names                Never give two variables of a module the
+                                  same name, not even in different methods
+    --weight <statement>=<w>      How often to generate if, while, foreach,
+                                  switch, try, await, assert, suspend or throw
+                                  statements, e.g. while=0.5. Can be given more
+                                  than once. [default: 1 for if, 0 for the
+                                  others]
+
+Sweeps:
+    --sweep <grid|lhs>            Instead of the targets, generate one module
+                                  per point of a grid or of a Latin hypercube
+                                  sample, named sweep-{point}.abs by default,
+                                  and list their options in sweep.csv
+    --dim <option>=<values>       Vary an option, e.g. max_depth=1,2,3. For a
+                                  Latin hypercube sample, give its minimum and
+                                  maximum. Can be given more than once. Modules
+                                  have 300 classes unless num_rand_classes is
+                                  varied.
+    --samples <n>                 Points of a Latin hypercube sample [default: 10]
+    --sweep-target <target>       The target of the sweep [default: crowbar]
+
+Fuzzing:
+    --fuzz <runs>                 Instead of the targets, generate this many
+                                  modules, named fuzz-{point}.abs by default,
+                                  run every tool on each and keep the ones
+                                  they disagree on in the triage directory
+    --tool <name>=<command>       A tool, run as <command> <module>, which
+                                  accepts the module by exiting with 0. Give
+                                  at least two.
+    --fuzz-target <target>        The target of the modules [default: crowbar]
+    --fuzz-classes <classes>      Numbers of classes, used in turn
+                                  [default: 1..=10]
+    --triage <dir>                Keep disagreements here, in the output
+                                  directory [default: triage]
+    --timeout <secs>              Kill a tool after this long [default: 60]
+
+Enumeration:
+    --enumerate <n>               Instead of the targets, generate every method
+                                  body of up to n statements, as methods of
+                                  modules named enum-{point}.abs by default
+    --enum-depth <d>              The depth of expressions [default: 1]
+    --enum-target <target>        The target of the bodies [default: crowbar]
+    --per-module <n>              Bodies per module [default: 100]
+
+Mutation:
+    --mutate <file>               Instead of the targets, write variants of an
+                                  existing module, each with one change, named
+                                  mutant-{point}.abs by default and listed in
+                                  mutants.txt. Can be given more than once.
+    --operator <op>               Only apply flip-op, drop-null-check,
+                                  toggle-local, remove-await or swap-call. Can
+                                  be given more than once. [default: all]
+
+Learning:
+    --learn <dir>                 Instead of generating, count the statements
+                                  and expressions of the .abs files in a
+                                  directory and write the options that
+                                  generate code like them to profile.toml
+
+    -h, --help                    Print this message";
+
+/// Reads the command line into a config that takes precedence over the
+/// config file
+fn parse_args() -> Result<(Option<PathBuf>, Config), String> {
+    let mut file = None;
+    let mut config = Config::default();
+    let mut targets = Vec::new();
+    let mut sweep = SweepConfig::default();
+    let mut fuzz = FuzzConfig::default();
+    let mut tools = Vec::new();
+    let mut enumerate = EnumerateConfig::default();
+    let mut mutate_files = Vec::new();
+    let mut operators = Vec::new();
+    let mut dims = std::collections::BTreeMap::new();
+    let mut weights = std::collections::BTreeMap::new();
+
+    let mut it = std::env::args().skip(1);
+    while let Some(a) = it.next() {
+        if a == "-h" || a == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        if a == "--clean" {
+            config.clean = Some(true);
+            continue;
+        }
+        if a == "--unique-names" {
+            config.unique_names = Some(true);
+            continue;
+        }
+
+        let value = it.next().ok_or_else(|| match a.starts_with("--") {
+            true => format!("{} needs a value", a),
+            false => format!("Unexpected argument `{}`", a),
+        })?;
+        let num = |what: &str| format!("{} needs {}, not `{}`", a, what, value);
+        match a.as_str() {
+            "--config" => file = Some(value.into()),
+            "--seed" => config.seed = Some(value.parse().map_err(|_| num("a number"))?),
+            "--out" => config.out_dir = Some(value.into()),
+            "--pattern" => config.pattern = Some(value),
+            "--jobs" => config.jobs = Some(value.parse().map_err(|_| num("a number"))?),
+            "--profile" => config.profile = Some(value.into()),
+            "--learn" => config.learn = Some(value.into()),
+            "--target" => {
+                let (target, classes) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--target needs <target>=<classes>, not `{}`", value))?;
+                targets.push(TargetConfig {
+                    target: target.to_string(),
+                    classes: classes.to_string(),
+                });
+            }
+            "--max-depth" => config.max_depth = Some(value.parse().map_err(|_| num("a number"))?),
+            "--branch-rate" => {
+                config.branch_rate = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--declare-to-assign" => {
+                config.declare_to_assign = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--else-ratio" => {
+                config.else_ratio = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--avg-meth-body-size" => {
+                config.avg_meth_body_size = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--avg-block-size" => {
+                config.avg_block_size = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--num-methods" => {
+                config.num_methods = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--num-interfaces" => {
+                config.num_interfaces = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--num-class-params" => {
+                config.num_class_params = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--async-rate" => {
+                config.async_rate = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--get-rate" => {
+                config.get_rate = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--new-rate" => {
+                config.new_rate = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--sync-rate" => {
+                config.sync_rate = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--num-data-types" => {
+                config.num_data_types = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--num-functions" => {
+                config.num_functions = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--functional-rate" => {
+                config.functional_rate = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--defect-rate" => {
+                config.defect_rate = Some(value.parse().map_err(|_| num("a probability"))?)
+            }
+            "--steer" => config.steer = Some(value.parse().map_err(|_| num("a number"))?),
+            "--naming" => config.naming = Some(value),
+            "--weight" => {
+                let (stmt, w) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--weight needs <statement>=<w>, not `{}`", value))?;
+                let w = w.trim().parse().map_err(|_| num("a number"))?;
+                weights.insert(stmt.to_string(), w);
+            }
+            "--sweep" => sweep.mode = Some(value),
+            "--samples" => sweep.samples = Some(value.parse().map_err(|_| num("a number"))?),
+            "--sweep-target" => sweep.target = Some(value),
+            "--dim" => {
+                let (dim, values) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--dim needs <option>=<values>, not `{}`", value))?;
+                let values = values
+                    .split(',')
+                    .map(|v| v.trim().parse().map_err(|_| num("numbers")))
+                    .collect::<Result<Vec<f64>, _>>()?;
+                dims.insert(dim.to_string(), values);
+            }
+            "--fuzz" => fuzz.runs = Some(value.parse().map_err(|_| num("a number"))?),
+            "--tool" => {
+                let (name, command) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--tool needs <name>=<command>, not `{}`", value))?;
+                tools.push(ToolConfig {
+                    name: name.to_string(),
+                    command: command.split_whitespace().map(String::from).collect(),
+                });
+            }
+            "--fuzz-target" => fuzz.target = Some(value),
+            "--fuzz-classes" => fuzz.classes = Some(value),
+            "--triage" => fuzz.triage_dir = Some(value.into()),
+            "--timeout" => fuzz.timeout = Some(value.parse().map_err(|_| num("seconds"))?),
+            "--enumerate" => {
+                enumerate.max_stmts = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--enum-depth" => {
+                enumerate.max_depth = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--enum-target" => enumerate.target = Some(value),
+            "--per-module" => {
+                enumerate.per_module = Some(value.parse().map_err(|_| num("a number"))?)
+            }
+            "--mutate" => mutate_files.push(value.into()),
+            "--operator" => operators.push(value),
+            _ => return Err(format!("Unknown option `{}`", a)),
+        }
+    }
+
+    if !targets.is_empty() {
+        config.targets = Some(targets);
+    }
+    if !weights.is_empty() {
+        config.weights = Some(weights);
+    }
+    if !dims.is_empty() {
+        sweep.dims = Some(dims);
+    }
+    if sweep.mode.is_some() || sweep.samples.is_some() || sweep.dims.is_some() {
+        config.sweep = Some(sweep);
+    }
+    if !tools.is_empty() {
+        fuzz.tools = Some(tools);
+    }
+    if fuzz.runs.is_some()
+        || fuzz.target.is_some()
+        || fuzz.classes.is_some()
+        || fuzz.triage_dir.is_some()
+        || fuzz.timeout.is_some()
+        || fuzz.tools.is_some()
+    {
+        config.fuzz = Some(fuzz);
+    }
+    if enumerate.max_stmts.is_some()
+        || enumerate.max_depth.is_some()
+        || enumerate.target.is_some()
+        || enumerate.per_module.is_some()
+    {
+        config.enumerate = Some(enumerate);
+    }
+    if !mutate_files.is_empty() || !operators.is_empty() {
+        config.mutate = Some(MutateConfig {
+            files: Some(mutate_files).filter(|f| !f.is_empty()),
+            operators: Some(operators).filter(|o| !o.is_empty()),
+        });
+    }
+    Ok((file, config))
+}
+
+fn settings() -> Result<Settings, String> {
+    let (file, args) = parse_args()?;
+    let config = match file {
+        Some(f) => Config::load(&f).map_err(|e| e.to_string())?,
+        None => Config::default(),
+    };
+    let config = config
+        .merge(args)
+        .with_profile()
+        .map_err(|e| e.to_string())?;
+    config.settings()
+}
+
+fn run(settings: &Settings) -> io::Result<()> {
+    fs::create_dir_all(&settings.out_dir)?;
+    if settings.clean {
+        clear_out(&settings.out_dir)?;
+    }
+    if let Some(dir) = &settings.learn {
+        return profile::learn(settings, dir);
+    }
+    if let Some(fuzz) = &settings.fuzz {
+        return fuzz::run(settings, fuzz);
+    }
+    if let Some(e) = &settings.enumerate {
+        return enumerate::run(settings, e);
+    }
+    if let Some(m) = &settings.mutate {
+        return mutate::run(settings, m);
+    }
+
+    // The seeds of the modules are drawn from the seed of the run
+    let mut seeds = StdRng::seed_from_u64(settings.seed);
+
+    let jobs: Vec<Options> = match &settings.sweep {
+        Some(sweep) => sweep.points(settings.opts, &mut seeds),
+        None => {
+            // Modules with fewer classes come first, whatever their target
+            let mut jobs: Vec<_> = settings
+                .targets
+                .iter()
+                .enumerate()
+                .flat_map(|(i, (t, classes))| classes.iter().map(move |c| (*c, i, *t)))
+                .collect();
+            jobs.sort_by_key(|(c, i, _)| (*c, *i));
+            jobs.into_iter()
+                .map(|(classes, _, target)| Options {
+                    num_rand_classes: classes,
+                    target,
+                    ..settings.opts
+                })
+                .collect()
+        }
+    };
+
+    let mut manifest = Manifest::new(settings);
+    let mut modules = Vec::new();
+    for (point, opts) in jobs.iter().enumerate() {
+        let name = settings
+            .pattern
+            .replace("{target}", opts.target.short_name())
+            .replace("{classes}", &opts.num_rand_classes.to_string())
+            .replace("{point}", &point.to_string());
+        let path = settings.out_dir.join(name);
+
+        let module_seed = seeds.gen();
+        manifest.add(&path, module_seed, opts);
+        modules.push((path, *opts, module_seed));
+    }
+
+    let coverage = write_modules(&modules, settings.jobs)?;
+    coverage.write(&settings.out_dir.join("coverage.txt"))?;
+    manifest.write(&settings.out_dir)
+}
+
 fn main() {
-    clear_out().expect("Err while clearing out dir");
-
-    for i in 1..=NUM_RAND_CLASSES {
-        let mut opts = Options::default();
-        opts.num_rand_classes = i;
-        if i <= 20 {
-            let path = format!("./out/generated-cb-{}.abs", i);
-            write_module(&path, opts).expect("An error occurred while writing the module");
+    let settings = match settings() {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
         }
-        if i <= 100 {
-            opts.target = Target::Location;
-            let path = format!("./out/generated-loc-{}.abs", i);
-            write_module(&path, opts).expect("An error occurred while writing the module");
+    };
+
+    if let Err(e) = run(&settings) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes modules for every target on `jobs` threads to `dir` and reads
+    /// back every file, sorted by name
+    fn generate(dir: &Path, jobs: usize) -> Vec<(PathBuf, String)> {
+        fs::create_dir_all(dir).unwrap();
+        let modules: Vec<_> = ["crowbar", "location", "nullable"]
+            .iter()
+            .cycle()
+            .take(6)
+            .enumerate()
+            .map(|(i, t)| {
+                let opts = Options {
+                    num_rand_classes: 3,
+                    defect_rate: 0.5,
+                    target: target::from_name(t).unwrap(),
+                    ..Options::default()
+                };
+                (dir.join(format!("m{}.abs", i)), opts, i as u64)
+            })
+            .collect();
+        let coverage = write_modules(&modules, jobs).unwrap();
+        coverage.write(&dir.join("coverage.txt")).unwrap();
+
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| {
+                let path = e.unwrap().path();
+                let text = fs::read_to_string(&path).unwrap();
+                (path.strip_prefix(dir).unwrap().to_path_buf(), text)
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn modules_are_the_same_on_any_number_of_threads() {
+        let dir = std::env::temp_dir().join(format!("abs_rand_module-jobs-{}", process::id()));
+        let one = generate(&dir.join("1"), 1);
+        let four = generate(&dir.join("4"), 4);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Modules, their expected diagnostics and the coverage
+        assert_eq!(one.len(), 13);
+        assert!(one.iter().any(|(_, text)| text.contains("\"line\"")));
+        assert_eq!(one, four);
+    }
+
+    #[test]
+    fn generated_modules_have_no_diagnostics() {
+        let mut all = StmtWeights::default();
+        for (name, _) in StmtWeights::default().iter() {
+            all.set(name, 1.0).unwrap();
+        }
+        let features = [
+            Options::default(),
+            Options {
+                num_interfaces: 2,
+                num_class_params: 2,
+                num_data_types: 2,
+                num_functions: 3,
+                functional_rate: 0.2,
+                ..Options::default()
+            },
+            Options {
+                max_depth: 3,
+                branch_rate: 0.3,
+                num_methods: 2,
+                naming: Naming::Typed,
+                unique_names: true,
+                steer: 2.0,
+                weights: all,
+                ..Options::default()
+            },
+        ];
+
+        let mut db = abs_db::Database::new();
+        let file = abs_db::FileId(0);
+        for features in &features {
+            for t in ["crowbar", "location", "nullable"] {
+                for seed in 0..8 {
+                    let opts = Options {
+                        num_rand_classes: 1 + seed as u32,
+                        target: target::from_name(t).unwrap(),
+                        ..*features
+                    };
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    let (module, _) = gen_mock_module(opts, &Coverage::default(), &mut rng);
+                    let code = module.to_string();
+                    db.set_file_text(file, code.clone());
+                    let diags = db.diagnostics(file);
+                    assert!(diags.is_empty(), "{:?} in\n{}", diags, code);
+                }
+            }
         }
-        opts.target = Target::NullableExtension;
-        let path = format!("./out/generated-nc-{}.abs", i);
-        write_module(&path, opts).expect("An error occurred while writing the module");
     }
 }
@@ -0,0 +1,414 @@
+//! Variants of existing modules, each with one small change to what the
+//! module does, for mutation testing of the checkers.
+//!
+//! Every operator is applied at every place it fits, one place per mutant,
+//! in the order the modules are walked.
+
+use std::{
+    fs::{self, File},
+    io::{self, prelude::*},
+    path::PathBuf,
+};
+
+use abs_syntax::{
+    ast::{
+        self,
+        visit::{self, VisitMut},
+        DisplayABS,
+    },
+    fmt::ABSFormatter,
+    parse::parse_modules,
+};
+
+use crate::{config::Settings, gen};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Operator {
+    /// `==` to `!=`, `<` to `>=`, `&&` to `||`, `+` to `-`, `*` to `%` and
+    /// so on
+    FlipOp,
+    /// `if (x != null) s` to `s`, `if (x == null) s else t` to `t` and
+    /// `x != null && e` to `e`
+    DropNullCheck,
+    /// `new C()` to `new local C()` and back
+    ToggleLocal,
+    /// Removes an `await` statement, or `await o!m()` to `o.m()`
+    RemoveAwait,
+    /// `o!m();` to `o.m();` and back
+    SwapCall,
+}
+
+impl Operator {
+    pub fn all() -> [Operator; 5] {
+        [
+            Operator::FlipOp,
+            Operator::DropNullCheck,
+            Operator::ToggleLocal,
+            Operator::RemoveAwait,
+            Operator::SwapCall,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operator::FlipOp => "flip-op",
+            Operator::DropNullCheck => "drop-null-check",
+            Operator::ToggleLocal => "toggle-local",
+            Operator::RemoveAwait => "remove-await",
+            Operator::SwapCall => "swap-call",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|o| o.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::all().iter().map(|o| o.name()).collect();
+                format!(
+                    "Unknown mutation operator `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+pub struct Mutate {
+    pub files: Vec<PathBuf>,
+    pub operators: Vec<Operator>,
+}
+
+/// The operator `op` is flipped to. `*` becomes `%` rather than `/`, which
+/// would turn an `Int` into a `Rat`.
+fn flipped(op: ast::BinaryOp) -> ast::BinaryOp {
+    use ast::BinaryOp::*;
+    match op {
+        Or => And,
+        And => Or,
+        Eq => Ne,
+        Ne => Eq,
+        Lt => Ge,
+        Ge => Lt,
+        Le => Gt,
+        Gt => Le,
+        Plus => Minus,
+        Minus => Plus,
+        Mult => Mod,
+        Mod => Mult,
+        Div => Mult,
+    }
+}
+
+/// Whether `e` compares something with `null`, and how
+fn null_check(e: &ast::PureExpr) -> Option<ast::BinaryOp> {
+    match e {
+        ast::PureExpr::Operator(ast::OperatorExpr::Binary(b))
+            if matches!(b.op, ast::BinaryOp::Eq | ast::BinaryOp::Ne)
+                && (*b.left == ast::PureExpr::Null || *b.right == ast::PureExpr::Null) =>
+        {
+            Some(b.op)
+        }
+        _ => None,
+    }
+}
+
+/// Applies an operator at the `n`th place it fits
+struct Mutator {
+    op: Operator,
+    wanted: usize,
+    seen: usize,
+    /// The class, method or function being walked
+    place: String,
+    class: String,
+    /// What was changed, once it was
+    done: Option<String>,
+}
+
+impl Mutator {
+    /// Whether this is the place to change
+    fn hit(&mut self) -> bool {
+        self.seen += 1;
+        self.seen == self.wanted + 1
+    }
+
+    /// Records the change, described with `one_line`
+    fn changed(&mut self, what: String) {
+        self.done = Some(format!("{} in {}", what, self.place));
+    }
+}
+
+/// The code of `x` on one line, however long it is
+fn one_line(x: &dyn DisplayABS) -> String {
+    let mut f = ABSFormatter::new();
+    x.to_abs(&mut f);
+    f.one_line_code()
+}
+
+impl VisitMut for Mutator {
+    fn visit_item(&mut self, i: &mut ast::ModuleItem) {
+        if let ast::ModuleItem::MainBlock(_) = i {
+            self.place = "the main block".to_string();
+        }
+        visit::walk_item(self, i)
+    }
+
+    fn visit_function(&mut self, f: &mut ast::FunctionDecl) {
+        self.place = format!("function {}", f.ident.str);
+        visit::walk_function(self, f)
+    }
+
+    fn visit_class(&mut self, c: &mut ast::ClassDecl) {
+        self.class = c.ident.str.clone();
+        self.place = format!("class {}", c.ident.str);
+        visit::walk_class(self, c)
+    }
+
+    fn visit_method(&mut self, m: &mut ast::MethodDecl) {
+        self.place = format!("method {}.{}", self.class, m.sig.ident.str);
+        visit::walk_method(self, m)
+    }
+
+    fn visit_block(&mut self, b: &mut ast::Block) {
+        if self.done.is_some() {
+            return;
+        }
+        if self.op == Operator::RemoveAwait {
+            for i in 0..b.stmts.len() {
+                if let ast::Stmt::Await(_) = &b.stmts[i] {
+                    if self.hit() {
+                        let removed = b.stmts.remove(i);
+                        self.changed(format!("removed `{}`", one_line(&removed)));
+                        return;
+                    }
+                }
+            }
+        }
+        visit::walk_block(self, b)
+    }
+
+    fn visit_stmt(&mut self, s: &mut ast::Stmt) {
+        if self.done.is_some() {
+            return;
+        }
+        match (self.op, &*s) {
+            (Operator::DropNullCheck, ast::Stmt::If(i)) => {
+                if let Some(op) = null_check(&i.condition) {
+                    if self.hit() {
+                        let kept = match op {
+                            ast::BinaryOp::Ne => Some(&i.then),
+                            _ => i.r#else.as_ref(),
+                        };
+                        let what = format!("dropped the null check `{}`", one_line(&i.condition));
+                        *s = kept.map_or(ast::Stmt::Skip, |k| (**k).clone());
+                        return self.changed(what);
+                    }
+                }
+            }
+            (Operator::SwapCall, ast::Stmt::Expr(ast::ExprStmt { expr })) => {
+                let swapped: Option<ast::EffExpr> = match expr {
+                    ast::Expr::Eff(ast::EffExpr::AsyncCall(c)) => Some(
+                        ast::SyncCallExpr {
+                            callee: c.callee.clone(),
+                            method: c.method.clone(),
+                            args: c.args.clone(),
+                        }
+                        .into(),
+                    ),
+                    ast::Expr::Eff(ast::EffExpr::SyncCall(c)) => Some(
+                        ast::AsyncCallExpr {
+                            callee: c.callee.clone(),
+                            method: c.method.clone(),
+                            args: c.args.clone(),
+                        }
+                        .into(),
+                    ),
+                    _ => None,
+                };
+                if let Some(swapped) = swapped {
+                    if self.hit() {
+                        let what = format!("`{}` to `{}`", one_line(expr), one_line(&swapped));
+                        *s = ast::ExprStmt {
+                            expr: ast::Expr::Eff(swapped),
+                        }
+                        .into();
+                        return self.changed(what);
+                    }
+                }
+            }
+            _ => {}
+        }
+        visit::walk_stmt(self, s)
+    }
+
+    fn visit_pure_expr(&mut self, e: &mut ast::PureExpr) {
+        if self.done.is_some() {
+            return;
+        }
+        if let ast::PureExpr::Operator(ast::OperatorExpr::Binary(b)) = &*e {
+            let op = self.op;
+            match op {
+                Operator::FlipOp if self.hit() => {
+                    let (left, right) = ((*b.left).clone(), (*b.right).clone());
+                    let new = gen::create_bin_expr(flipped(b.op), left, right);
+                    let what = format!("`{}` to `{}`", one_line(b), one_line(&new));
+                    *e = new.into();
+                    return self.changed(what);
+                }
+                Operator::DropNullCheck if b.op == ast::BinaryOp::And => {
+                    let kept = match (null_check(&b.left), null_check(&b.right)) {
+                        (Some(ast::BinaryOp::Ne), _) => Some(&b.right),
+                        (_, Some(ast::BinaryOp::Ne)) => Some(&b.left),
+                        _ => None,
+                    };
+                    if let Some(kept) = kept {
+                        if self.hit() {
+                            let what = format!("dropped the null check in `{}`", one_line(b));
+                            *e = (**kept).clone();
+                            return self.changed(what);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        visit::walk_pure_expr(self, e)
+    }
+
+    fn visit_eff_expr(&mut self, e: &mut ast::EffExpr) {
+        if self.done.is_some() {
+            return;
+        }
+        match (self.op, &*e) {
+            (Operator::ToggleLocal, ast::EffExpr::New(n)) if self.hit() => {
+                let mut new = gen::start_new_expr(!n.local, n.ty.str.clone());
+                for a in &n.args {
+                    new.add_arg(a.clone());
+                }
+                let new = new.complete();
+                let what = format!("`{}` to `{}`", one_line(n), one_line(&new));
+                *e = new.into();
+                return self.changed(what);
+            }
+            (Operator::RemoveAwait, ast::EffExpr::Await(a)) if self.hit() => {
+                let call = ast::SyncCallExpr {
+                    callee: a.call.callee.clone(),
+                    method: a.call.method.clone(),
+                    args: a.call.args.clone(),
+                };
+                let what = format!("`{}` to `{}`", one_line(a), one_line(&call));
+                *e = call.into();
+                return self.changed(what);
+            }
+            _ => {}
+        }
+        visit::walk_eff_expr(self, e)
+    }
+}
+
+/// Applies `op` at the `n`th place it fits, and tells what it changed, or
+/// `None` if there are fewer places
+fn apply(op: Operator, ms: &mut [ast::Module], n: usize) -> Option<String> {
+    let mut m = Mutator {
+        op,
+        wanted: n,
+        seen: 0,
+        place: String::new(),
+        class: String::new(),
+        done: None,
+    };
+    for module in ms.iter_mut() {
+        m.visit_module(module);
+    }
+    m.done
+}
+
+/// Writes every mutant of every file and lists them in `mutants.txt`
+pub fn run(settings: &Settings, mutate: &Mutate) -> io::Result<()> {
+    let mut list = File::create(settings.out_dir.join("mutants.txt"))?;
+    let mut point = 0;
+    for file in &mutate.files {
+        let src = fs::read_to_string(file)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file.display(), e)))?;
+        let modules = parse_modules(&src).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", file.display(), e),
+            )
+        })?;
+
+        for op in &mutate.operators {
+            for n in 0.. {
+                let mut mutant = modules.clone();
+                let what = match apply(*op, &mut mutant, n) {
+                    Some(what) => what,
+                    None => break,
+                };
+                let name = settings.pattern.replace("{point}", &point.to_string());
+                let code: Vec<_> = mutant.iter().map(|m| m.to_string()).collect();
+                let code = format!(
+                    "// Mutant of {} by abs_rand_module, {}: {}\n\n{}",
+                    file.display(),
+                    op.name(),
+                    what,
+                    code.join("\n")
+                );
+                File::create(settings.out_dir.join(&name))?.write_all(code.as_bytes())?;
+                writeln!(list, "{} {} {} {}", name, file.display(), op.name(), what)?;
+                point += 1;
+            }
+        }
+    }
+    println!("Wrote {} mutants", point);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "module M;
+class C(Int p) {
+    Unit m(C i) {
+        C c = new C((case p { 0 => 1; _ => p + 1; }) + p + p + p + p + p + p + p + p + p + p + p + p + p + p);
+        if (i == null) { skip; } else { c = i; }
+    }
+}
+";
+
+    fn mutants(op: Operator) -> Vec<(String, String)> {
+        let modules = parse_modules(SRC).unwrap();
+        (0..)
+            .map_while(|n| {
+                let mut mutant = modules.clone();
+                let what = apply(op, &mut mutant, n)?;
+                Some((what, mutant[0].to_string()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_change_is_described_on_one_line() {
+        let new = "new C((case p { 0 => 1; _ => p + 1; }) \
+                   + p + p + p + p + p + p + p + p + p + p + p + p + p + p)";
+        let (what, _) = &mutants(Operator::ToggleLocal)[0];
+        assert_eq!(
+            *what,
+            format!(
+                "`{}` to `{}` in method C.m",
+                new,
+                new.replace("new", "new local")
+            )
+        );
+    }
+
+    #[test]
+    fn a_null_check_for_equality_keeps_the_else_branch() {
+        let mutants = mutants(Operator::DropNullCheck);
+        assert_eq!(mutants.len(), 1);
+        let (what, code) = &mutants[0];
+        assert_eq!(what, "dropped the null check `i == null` in method C.m");
+        assert!(code.contains("c = i;") && !code.contains("null"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Names of the variables the generator declares.
+//!
+//! A name is never an ABS keyword, nor a name of the landscape, nor the name
+//! of anything in scope or declared before in the same body. With
+//! `unique_names`, it is not the name of any other variable of the module
+//! either, and the counters of `counter` and `typed` naming go on from one
+//! body to the next instead of starting again.
+
+use std::collections::{HashMap, HashSet};
+
+use abs_syntax::{ast, parse::is_keyword};
+use rand::Rng;
+
+use crate::{chance, landscape::Landscape, Options};
+
+/// The fields every generated class has, and the parameter of `gen`
+const STD_NAMES: [&str; 7] = ["fint", "fb", "ff", "ffb", "fi", "fj", "i"];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Naming {
+    /// Six random letters, like `qXzAbT`
+    Random,
+    /// `v0`, `v1`, ... in the order the variables are declared
+    Counter,
+    /// The type and a counter per type, like `x_int_3`
+    Typed,
+}
+
+impl Naming {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "random" => Ok(Naming::Random),
+            "counter" => Ok(Naming::Counter),
+            "typed" => Ok(Naming::Typed),
+            _ => Err(format!(
+                "Unknown naming `{}`, expected random, counter or typed",
+                name
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Naming::Random => "random",
+            Naming::Counter => "counter",
+            Naming::Typed => "typed",
+        }
+    }
+}
+
+/// The names taken in a module
+pub struct Names {
+    /// The fields, parameters, functions and methods of the landscape
+    landscape: HashSet<String>,
+    /// The variables declared so far in the body, or in the module
+    declared: HashSet<String>,
+    /// The next number of every prefix
+    counters: HashMap<String, usize>,
+}
+
+impl Names {
+    pub fn new(landscape: &Landscape) -> Self {
+        let mut names: HashSet<String> = STD_NAMES.iter().map(|n| n.to_string()).collect();
+        for f in &landscape.functions {
+            names.insert(f.name.clone());
+            names.extend(f.params.iter().map(|(_, n)| n.clone()));
+        }
+        for sig in landscape.interfaces.iter().flat_map(|i| &i.sigs) {
+            names.insert(sig.name.clone());
+            names.extend(sig.params.iter().map(|(_, n)| n.clone()));
+        }
+        for c in &landscape.classes {
+            let fields = c.params.iter().chain(&c.obj_fields);
+            names.extend(fields.map(|(_, n)| n.clone()));
+        }
+        Names {
+            landscape: names,
+            declared: HashSet::new(),
+            counters: HashMap::new(),
+        }
+    }
+
+    /// Starts a new body, which may reuse the names of the ones before it
+    /// unless names are unique in the module
+    pub fn start_body(&mut self, opts: &Options) {
+        if !opts.unique_names {
+            self.declared.clear();
+            self.counters.clear();
+        }
+    }
+
+    fn taken(&self, name: &str, in_scope: &dyn Fn(&str) -> bool) -> bool {
+        is_keyword(name)
+            || self.landscape.contains(name)
+            || self.declared.contains(name)
+            || in_scope(name)
+    }
+
+    /// A name for a new variable of type `ty`, other than the ones for which
+    /// `in_scope` holds
+    pub fn fresh<R: Rng + ?Sized>(
+        &mut self,
+        opts: &Options,
+        rng: &mut R,
+        ty: &ast::Type,
+        in_scope: &dyn Fn(&str) -> bool,
+    ) -> ast::Ident {
+        let name = match opts.naming {
+            Naming::Random => {
+                let mut name = random_name(rng);
+                while self.taken(&name, in_scope) {
+                    name = random_name(rng);
+                }
+                name
+            }
+            Naming::Counter => self.counted("v".to_string(), in_scope),
+            Naming::Typed => self.counted(format!("x_{}_", type_name(ty)), in_scope),
+        };
+        self.declared.insert(name.clone());
+        crate::gen::ident(name)
+    }
+
+    /// `prefix` with the next number after it that is not taken
+    fn counted(&mut self, prefix: String, in_scope: &dyn Fn(&str) -> bool) -> String {
+        let mut n = self.counters.get(&prefix).copied().unwrap_or(0);
+        let name = loop {
+            let name = format!("{}{}", prefix, n);
+            n += 1;
+            if !self.taken(&name, in_scope) {
+                break name;
+            }
+        };
+        self.counters.insert(prefix, n);
+        name
+    }
+}
+
+/// `ty` in lower case, with underscores for everything but letters and
+/// digits, like `fut_int` for `Fut<Int>`
+fn type_name(ty: &ast::Type) -> String {
+    let lower = ty.to_string().to_lowercase();
+    let parts: Vec<_> = lower
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|p| !p.is_empty())
+        .collect();
+    parts.join("_")
+}
+
+fn rand_char<R: Rng + ?Sized>(rng: &mut R, i: u32) -> char {
+    let mut range = if i == 0 || chance::chance(rng, 0.5) {
+        'a'..='z'
+    } else {
+        'A'..='Z'
+    };
+    let idx = (rng.gen::<f64>() * 26.0) as usize;
+
+    range.nth(idx).unwrap()
+}
+
+fn random_name<R: Rng + ?Sized>(rng: &mut R) -> String {
+    (0..).take(6).map(|i| rand_char(rng, i)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{gen::ty, landscape::ClassShape};
+
+    const NAMINGS: [Naming; 3] = [Naming::Random, Naming::Counter, Naming::Typed];
+
+    /// A landscape with a class whose fields are named like the first
+    /// variables of `counter` and `typed` naming
+    fn landscape() -> Landscape {
+        let mut landscape = Landscape::empty();
+        landscape.classes.push(ClassShape {
+            name: "Generated_0".to_string(),
+            implements: Vec::new(),
+            params: vec![(ty::create_int(), "v0".to_string())],
+            obj_fields: vec![(ty::simple_ty("I"), "x_int_0".to_string())],
+        });
+        landscape
+    }
+
+    #[test]
+    fn a_name_is_never_a_keyword_or_a_field() {
+        let landscape = landscape();
+        let mut names = Names::new(&landscape);
+        assert!(names.taken("while", &|_| false));
+        assert!(names.taken("fint", &|_| false));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for naming in &NAMINGS {
+            let opts = Options {
+                naming: *naming,
+                ..Options::default()
+            };
+            names.start_body(&opts);
+            for _ in 0..100 {
+                let name = names.fresh(&opts, &mut rng, &ty::create_int(), &|_| false);
+                assert!(!is_keyword(&name.str), "{}", name);
+                assert!(!STD_NAMES.contains(&name.str.as_str()), "{}", name);
+                assert!(name.str != "v0" && name.str != "x_int_0", "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn names_are_unique_in_a_module_with_unique_names() {
+        let landscape = landscape();
+        let mut rng = StdRng::seed_from_u64(0);
+        for naming in &NAMINGS {
+            let opts = Options {
+                naming: *naming,
+                unique_names: true,
+                ..Options::default()
+            };
+            let mut names = Names::new(&landscape);
+            let mut seen = HashSet::new();
+            for _ in 0..10 {
+                names.start_body(&opts);
+                for t in &[ty::create_int(), ty::create_bool()] {
+                    let name = names.fresh(&opts, &mut rng, t, &|_| false);
+                    assert!(seen.insert(name.str.clone()), "{} again", name);
+                }
+            }
+        }
+    }
+}
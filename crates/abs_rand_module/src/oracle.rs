@@ -0,0 +1,370 @@
+//! Defects planted on purpose, so that an analysis can be measured by what
+//! it reports and not only by how fast it is.
+//!
+//! Every target has a defect its analysis should find. The defect is
+//! planted into the `gen` method of some classes, found again in that method
+//! as the module is written and listed with its position in
+//! `<module>.expected.json`.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use abs_syntax::ast;
+use serde::Serialize;
+
+use crate::gen::{self, ty};
+
+/// A defect that the analysis of a target is expected to report
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Defect {
+    /// A call on the parameter `i` of `gen` before it is checked for `null`
+    NullDeref,
+    /// A new object, which lives in another cog, declared `Near`
+    FarAsNear,
+    /// `gen` ensuring that its result is `null`, although it returns `i`
+    /// after making sure that it is not
+    UnprovablePost,
+}
+
+impl Defect {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Defect::NullDeref => "null-dereference",
+            Defect::FarAsNear => "far-as-near",
+            Defect::UnprovablePost => "unprovable-postcondition",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Defect::NullDeref => "`i` may be null when `b` is called on it",
+            Defect::FarAsNear => "a far object is used as a near one",
+            Defect::UnprovablePost => "the postcondition `result == null` cannot be proven",
+        }
+    }
+
+    /// The code of the defect as it is printed
+    fn needle(&self) -> &'static str {
+        match self {
+            Defect::NullDeref => "i.b()",
+            Defect::FarAsNear => "new D()",
+            Defect::UnprovablePost => "Ensures(result == null)",
+        }
+    }
+
+    /// Plants the defect into `gen` of `class`, given its signature and
+    /// body
+    pub fn plant(&self, class: &str, sig: &mut ast::MethodSig, body: &mut ast::Block) -> Planted {
+        let var = format!("defect_{}", class.to_lowercase());
+        match self {
+            Defect::NullDeref => {
+                let call: ast::EffExpr = ast::SyncCallExpr {
+                    callee: gen::create_var_use("i").into(),
+                    args: Vec::new(),
+                    method: gen::ident("b"),
+                }
+                .into();
+                let decl = gen::create_var_decl_init(
+                    ty::create_bool(),
+                    &var,
+                    call.into(),
+                    gen::empty_annos(),
+                );
+                body.stmts.insert(0, decl.into());
+            }
+            Defect::FarAsNear => {
+                let mut annos = gen::empty_annos();
+                annos.push(gen::create_untyped_anno(
+                    gen::create_data_constr("Near").into(),
+                ));
+                let new: ast::EffExpr = gen::start_new_expr(false, "D").complete().into();
+                let decl = gen::create_var_decl_init(ty::create_i(), &var, new.into(), annos);
+                body.stmts.insert(0, decl.into());
+            }
+            Defect::UnprovablePost => {
+                let post = gen::create_bin_expr(
+                    ast::BinaryOp::Eq,
+                    gen::create_var_use("result").into(),
+                    gen::create_null(),
+                );
+                sig.annotations
+                    .push(gen::create_crowbar_spec("Ensures", post.into()));
+            }
+        }
+
+        Planted {
+            defect: *self,
+            class: class.to_string(),
+        }
+    }
+}
+
+/// A defect planted into the `gen` method of `class`
+pub struct Planted {
+    defect: Defect,
+    class: String,
+}
+
+/// A diagnostic the analysis should report, at a line and column counted
+/// from 1, with a tab counting as one column
+#[derive(Serialize)]
+pub struct Expected {
+    pub kind: &'static str,
+    pub message: &'static str,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// How far the search for a planted defect got
+#[derive(Copy, Clone)]
+enum Search {
+    /// For its class
+    Class,
+    /// For `gen` in its class, with where the code of the defect was last
+    /// seen before it
+    Method(Option<(usize, usize)>),
+    /// For its code in the body of `gen`
+    Body,
+    Found(usize, usize),
+    Lost,
+}
+
+/// Passes the printed module on to `out` and finds the planted defects in
+/// it line by line as it goes, so the module need not be read back. A
+/// defect in the body of `gen` is the first occurrence of its code after
+/// the name of the method, and one in its annotations the last before it.
+pub struct Locator<'p, W> {
+    out: W,
+    planted: &'p [Planted],
+    searches: Vec<Search>,
+    /// The part of the current line written so far
+    line: Vec<u8>,
+    /// The number of complete lines
+    lines: usize,
+}
+
+impl<'p, W: Write> Locator<'p, W> {
+    pub fn new(out: W, planted: &'p [Planted]) -> Self {
+        Locator {
+            out,
+            planted,
+            searches: vec![Search::Class; planted.len()],
+            line: Vec::new(),
+            lines: 0,
+        }
+    }
+
+    /// The writer and the diagnostics expected for the defects that were
+    /// found
+    pub fn finish(mut self) -> (W, Vec<Expected>) {
+        if !self.line.is_empty() {
+            self.end_line();
+        }
+        let expected = self
+            .planted
+            .iter()
+            .zip(&self.searches)
+            .filter_map(|(p, s)| match s {
+                Search::Found(line, column) => Some(Expected {
+                    kind: p.defect.kind(),
+                    message: p.defect.message(),
+                    line: *line,
+                    column: *column,
+                }),
+                _ => None,
+            })
+            .collect();
+        (self.out, expected)
+    }
+
+    fn end_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        self.lines += 1;
+        // Lines and columns are counted from 1
+        let n = self.lines;
+        let at = |i: usize| (n, line[..i].chars().count() + 1);
+
+        for (p, search) in self.planted.iter().zip(&mut self.searches) {
+            let needle = p.defect.needle();
+            let mut from = 0;
+            loop {
+                match *search {
+                    Search::Class => match find_token(&line, from, &format!("class {}", p.class)) {
+                        Some(i) => {
+                            *search = Search::Method(None);
+                            from = i;
+                        }
+                        None => break,
+                    },
+                    Search::Method(mut before) => {
+                        let method = find_token(&line, from, "gen");
+                        if p.defect == Defect::UnprovablePost {
+                            let to = method.unwrap_or(line.len());
+                            if let Some(i) = line[from..to].rfind(needle) {
+                                before = Some(at(from + i));
+                            }
+                        }
+                        let i = match method {
+                            Some(i) => i,
+                            None => {
+                                *search = Search::Method(before);
+                                break;
+                            }
+                        };
+                        *search = match (p.defect, before) {
+                            (Defect::UnprovablePost, Some((l, c))) => Search::Found(l, c),
+                            (Defect::UnprovablePost, None) => Search::Lost,
+                            _ => Search::Body,
+                        };
+                        from = i;
+                    }
+                    Search::Body => {
+                        if let Some(i) = find_token(&line, from, needle) {
+                            let (l, c) = at(i);
+                            *search = Search::Found(l, c);
+                        }
+                        break;
+                    }
+                    Search::Found(..) | Search::Lost => break,
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for Locator<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        if self.planted.is_empty() {
+            return Ok(n);
+        }
+        let mut lines = buf[..n].split(|b| *b == b'\n');
+        if let Some(first) = lines.next() {
+            self.line.extend_from_slice(first);
+        }
+        for rest in lines {
+            self.end_line();
+            self.line.extend_from_slice(rest);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The first occurrence of `token` from `from` on that is not part of a
+/// longer identifier
+fn find_token(code: &str, from: usize, token: &str) -> Option<usize> {
+    let ident = |c: char| c.is_alphanumeric() || c == '_';
+    code[from..]
+        .match_indices(token)
+        .map(|(i, _)| from + i)
+        .find(|i| {
+            !code[..*i].chars().next_back().is_some_and(ident)
+                && !code[i + token.len()..].chars().next().is_some_and(ident)
+        })
+}
+
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    module: &'a str,
+    target: &'a str,
+    expected: &'a [Expected],
+}
+
+/// Writes the expected diagnostics of the module at `path` next to it
+pub fn write_expected(path: &Path, target: &str, expected: &[Expected]) -> io::Result<()> {
+    let sidecar = Sidecar {
+        module: &path.file_name().unwrap().to_string_lossy(),
+        target,
+        expected,
+    };
+    let json = serde_json::to_string_pretty(&sidecar)?;
+    fs::write(path.with_extension("expected.json"), json + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use abs_syntax::ast::DisplayABS;
+
+    use super::*;
+
+    fn planted(defect: Defect, class: &str) -> Planted {
+        Planted {
+            defect,
+            class: class.to_string(),
+        }
+    }
+
+    fn position(e: &Expected) -> (usize, usize) {
+        (e.line, e.column)
+    }
+
+    /// Writes `code` in pieces that do not end at the ends of lines
+    fn locate(code: &str, planted: &[Planted]) -> Vec<Expected> {
+        let mut out = Locator::new(io::sink(), planted);
+        for piece in code.as_bytes().chunks(7) {
+            out.write_all(piece).unwrap();
+        }
+        out.finish().1
+    }
+
+    #[test]
+    fn a_planted_defect_is_found_where_it_is_printed() {
+        let mut sig = gen::start_method_sig("gen")
+            .with_ret(ty::simple_ty("I"))
+            .complete();
+        let mut body = gen::start_block().complete();
+        let p = Defect::UnprovablePost.plant("C", &mut sig, &mut body);
+        let mut class = gen::start_class_decl("C");
+        class.add_method(gen::create_method_decl(sig, body));
+        let planted = [p];
+        let mut out = Locator::new(Vec::new(), &planted);
+        class.complete().write_abs(&mut out).unwrap();
+        let (code, expected) = out.finish();
+        let code = String::from_utf8(code).unwrap();
+
+        assert_eq!(expected.len(), 1);
+        let line = code.lines().nth(expected[0].line - 1).unwrap();
+        let column = expected[0].column - 1;
+        assert!(line[column..].starts_with("Ensures(result == null)"));
+    }
+
+    #[test]
+    fn a_defect_is_only_looked_for_in_gen_of_its_class() {
+        let code = "module M;
+class C_1 {
+\t[Spec: Ensures(result == null)]
+\tI gen(I i) {
+\t\treturn i.b();
+\t}
+}
+class C {
+\t[Spec: Ensures(result == null)]
+\tBool regen(I i) {
+\t\treturn i.b();
+\t}
+\t[Spec: Ensures(result == null)]
+\tI gen(I i) {
+\t\tBool defect_c = i.b();
+\t\treturn i;
+\t}
+}
+";
+        let expected = locate(
+            code,
+            &[
+                planted(Defect::NullDeref, "C"),
+                planted(Defect::UnprovablePost, "C"),
+            ],
+        );
+        let positions: Vec<_> = expected.iter().map(position).collect();
+        assert_eq!(positions, [(15, 19), (13, 9)]);
+    }
+}
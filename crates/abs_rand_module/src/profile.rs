@@ -0,0 +1,424 @@
+//! Options learned from real ABS code, so that generated modules resemble
+//! it.
+//!
+//! `learn` counts the statements and expressions of every `.abs` file under
+//! a directory, and how deeply the statements are nested, and writes the
+//! options that generate code like it to `profile.toml`. A profile is a
+//! config file, given with `--profile`, and the config file and the command
+//! line take precedence over it.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+};
+
+use abs_syntax::{
+    ast::{
+        self,
+        visit::{self, VisitMut},
+    },
+    parse::parse_modules,
+};
+
+use crate::config::Settings;
+
+/// The functional forms `functional_rate` is the chance of
+const FUNCTIONAL: [&str; 5] = ["CaseExpr", "LetExpr", "WhenExpr", "IfExpr", "FnAppExpr"];
+
+/// The weights of compound statements, and the kinds they count
+const COMPOUND: [(&str, &str); 5] = [
+    ("if", "IfStmt"),
+    ("while", "WhileStmt"),
+    ("foreach", "ForeachStmt"),
+    ("switch", "SwitchStmt"),
+    ("try", "TryCatchFinallyStmt"),
+];
+
+/// The weights of the other statements, and the kinds they count
+const SIMPLE: [(&str, &str); 4] = [
+    ("await", "AwaitStmt"),
+    ("assert", "AssertStmt"),
+    ("suspend", "SuspendStmt"),
+    ("throw", "ThrowStmt"),
+];
+
+/// What was found in the code
+#[derive(Default)]
+struct Counts {
+    methods: u64,
+    method_stmts: u64,
+    /// Blocks nested in statements
+    blocks: u64,
+    block_stmts: u64,
+    stmts: BTreeMap<&'static str, u64>,
+    ifs_with_else: u64,
+    /// Statements by how many statements they are nested in
+    depths: BTreeMap<usize, u64>,
+    depth: usize,
+    /// Right-hand sides of declarations and assignments, by kind
+    rhs: BTreeMap<&'static str, u64>,
+    fut_decls: u64,
+    fut_async_decls: u64,
+    pure: u64,
+    functional: u64,
+}
+
+fn expr_kind(e: &ast::Expr) -> &'static str {
+    match e {
+        ast::Expr::Pure(e) => e.kind(),
+        ast::Expr::Eff(e) => e.kind(),
+    }
+}
+
+impl VisitMut for Counts {
+    fn visit_method(&mut self, m: &mut ast::MethodDecl) {
+        self.methods += 1;
+        self.method_stmts += m.body.stmts.len() as u64;
+        visit::walk_method(self, m)
+    }
+
+    fn visit_block(&mut self, b: &mut ast::Block) {
+        if self.depth > 0 {
+            self.blocks += 1;
+            self.block_stmts += b.stmts.len() as u64;
+        }
+        visit::walk_block(self, b)
+    }
+
+    fn visit_stmt(&mut self, s: &mut ast::Stmt) {
+        // A block in a statement is counted as the statements in it
+        if let ast::Stmt::Block(_) = s {
+            return visit::walk_stmt(self, s);
+        }
+        *self.stmts.entry(s.kind()).or_default() += 1;
+        *self.depths.entry(self.depth).or_default() += 1;
+        match &*s {
+            ast::Stmt::If(i) if i.r#else.is_some() => self.ifs_with_else += 1,
+            ast::Stmt::VarDecl(d) => {
+                if let Some(init) = &d.init {
+                    *self.rhs.entry(expr_kind(init)).or_default() += 1;
+                    if d.ty.is_fut() {
+                        self.fut_decls += 1;
+                        if let ast::Expr::Eff(ast::EffExpr::AsyncCall(_)) = init {
+                            self.fut_async_decls += 1;
+                        }
+                    }
+                }
+            }
+            ast::Stmt::Assign(a) => *self.rhs.entry(expr_kind(&a.expr)).or_default() += 1,
+            _ => {}
+        }
+
+        let compound = COMPOUND.iter().any(|(_, k)| *k == s.kind());
+        self.depth += compound as usize;
+        visit::walk_stmt(self, s);
+        self.depth -= compound as usize;
+    }
+
+    fn visit_pure_expr(&mut self, e: &mut ast::PureExpr) {
+        self.pure += 1;
+        if FUNCTIONAL.contains(&e.kind()) {
+            self.functional += 1;
+        }
+        visit::walk_pure_expr(self, e)
+    }
+}
+
+/// `part` of `all`, if there is anything to divide
+fn ratio(part: u64, all: u64) -> Option<f64> {
+    match all {
+        0 => None,
+        _ => Some(part as f64 / all as f64),
+    }
+}
+
+impl Counts {
+    fn stmt(&self, kind: &str) -> u64 {
+        self.stmts.get(kind).copied().unwrap_or(0)
+    }
+
+    fn rhs(&self, kind: &str) -> u64 {
+        self.rhs.get(kind).copied().unwrap_or(0)
+    }
+
+    /// The options, as lines of a config file, and the weights of the
+    /// statements. Options the code says nothing about are left out.
+    fn options(&self) -> (Vec<String>, Vec<String>) {
+        let mut opts = Vec::new();
+        let mut rate = |name: &str, r: Option<f64>| {
+            if let Some(r) = r {
+                opts.push(format!("{} = {:.3}", name, r));
+            }
+        };
+
+        let all: u64 = self.stmts.values().sum();
+        let compound: u64 = COMPOUND.iter().map(|(_, k)| self.stmt(k)).sum();
+        let (decl, assign) = (self.stmt("VarDeclStmt"), self.stmt("AssignStmt"));
+        rate("branch_rate", ratio(compound, all));
+        rate("declare_to_assign", ratio(decl, decl + assign));
+        rate("else_ratio", ratio(self.ifs_with_else, self.stmt("IfStmt")));
+        rate("async_rate", ratio(self.fut_async_decls, self.fut_decls));
+
+        // The generator tries a get, a new, an asynchronous and a synchronous
+        // call in turn, so each is a chance among what the ones before it left
+        let rhs: u64 = self.rhs.values().sum();
+        let (get, new) = (self.rhs("GetExpr"), self.rhs("NewExpr"));
+        let (async_call, sync) = (self.rhs("AsyncCallExpr"), self.rhs("SyncCallExpr"));
+        rate("get_rate", ratio(get, rhs));
+        rate("new_rate", ratio(new, rhs - get));
+        rate("sync_rate", ratio(sync, rhs - get - new - async_call));
+        rate("functional_rate", ratio(self.functional, self.pure));
+
+        if let Some(depth) = self.depths.keys().next_back() {
+            opts.push(format!("max_depth = {}", (*depth).min(u8::MAX as usize)));
+        }
+        if let Some(r) = ratio(self.method_stmts, self.methods) {
+            opts.push(format!("avg_meth_body_size = {}", r.round().max(1.0)));
+        }
+        if let Some(r) = ratio(self.block_stmts, self.blocks) {
+            opts.push(format!("avg_block_size = {}", r.round().max(1.0)));
+        }
+
+        // Compound statements are weighed against each other, the others
+        // against a weight of 1 for declarations and assignments
+        let mut weights = Vec::new();
+        for (name, kind) in &COMPOUND {
+            if let Some(w) = ratio(self.stmt(kind), compound) {
+                weights.push(format!("{} = {:.3}", name, w));
+            }
+        }
+        for (name, kind) in &SIMPLE {
+            if let Some(w) = ratio(self.stmt(kind), decl + assign) {
+                weights.push(format!("{} = {:.3}", name, w));
+            }
+        }
+        (opts, weights)
+    }
+}
+
+/// The `.abs` files at `path`, in the order of their names
+fn abs_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for e in entries {
+        if e.is_dir() {
+            abs_files(&e, files)?;
+        } else if e.extension().is_some_and(|x| x == "abs") {
+            files.push(e);
+        }
+    }
+    Ok(())
+}
+
+/// Learns the options from the code under `dir` and writes them to
+/// `profile.toml` in the output directory. Files that do not parse are
+/// skipped.
+pub fn learn(settings: &Settings, dir: &Path) -> io::Result<()> {
+    let mut files = Vec::new();
+    abs_files(dir, &mut files)?;
+
+    let mut counts = Counts::default();
+    let mut parsed = 0;
+    for file in &files {
+        let src = fs::read_to_string(file)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file.display(), e)))?;
+        match parse_modules(&src) {
+            Ok(mut modules) => {
+                modules.iter_mut().for_each(|m| counts.visit_module(m));
+                parsed += 1;
+            }
+            Err(e) => eprintln!("Skipping {}: {}", file.display(), e),
+        }
+    }
+    if parsed == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("No ABS code to learn from in {}", dir.display()),
+        ));
+    }
+
+    let path = settings.out_dir.join("profile.toml");
+    let mut f = File::create(&path)?;
+    writeln!(
+        f,
+        "# Learned by abs_rand_module from {} of {} files in {}",
+        parsed,
+        files.len(),
+        dir.display()
+    )?;
+    writeln!(f, "#")?;
+    writeln!(f, "# {} methods", counts.methods)?;
+    for (kind, n) in &counts.stmts {
+        writeln!(f, "# statement {} {}", kind, n)?;
+    }
+    for (depth, n) in &counts.depths {
+        writeln!(f, "# statements nested {} deep {}", depth, n)?;
+    }
+    for (kind, n) in &counts.rhs {
+        writeln!(f, "# right-hand side {} {}", kind, n)?;
+    }
+    writeln!(f)?;
+    let (opts, weights) = counts.options();
+    for o in &opts {
+        writeln!(f, "{}", o)?;
+    }
+    if !weights.is_empty() {
+        writeln!(f, "\n[weights]")?;
+        for w in &weights {
+            writeln!(f, "{}", w)?;
+        }
+    }
+
+    println!(
+        "Learned from {} files, {} statements, into {}",
+        parsed,
+        counts.stmts.values().sum::<u64>(),
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const CORPUS: &str = "\
+module Corpus;
+
+interface I {
+    Int n();
+}
+
+class C implements I {
+    Int n() {
+        Int x = 1;
+        Fut<Int> f = this!n();
+        x = f.get;
+        if (x > 0) {
+            x = x + 1;
+            await f?;
+        } else {
+            x = 0;
+        }
+        return x;
+    }
+
+    Unit m(I i) {
+        while (True) {
+            i = new C();
+            suspend;
+        }
+    }
+}
+";
+
+    #[test]
+    fn a_learned_profile_sets_the_options() {
+        let dir =
+            std::env::temp_dir().join(format!("abs_rand_module-profile-{}", std::process::id()));
+        let (code, out) = (dir.join("code"), dir.join("out"));
+        fs::create_dir_all(&code).unwrap();
+        fs::create_dir_all(&out).unwrap();
+        fs::write(code.join("Corpus.abs"), CORPUS).unwrap();
+        fs::write(code.join("Broken.abs"), "module Broken; class {").unwrap();
+        fs::write(code.join("notes.txt"), "not ABS").unwrap();
+
+        let settings = Config {
+            out_dir: Some(out.clone()),
+            ..Config::default()
+        }
+        .settings()
+        .unwrap();
+        learn(&settings, &code).unwrap();
+        let profile = fs::read_to_string(out.join("profile.toml")).unwrap();
+        assert!(profile.starts_with("# Learned by abs_rand_module from 1 of 2 files in "));
+        for line in [
+            "# 2 methods",
+            "# statement AssignStmt 4",
+            "# statement VarDeclStmt 2",
+            "# statements nested 0 deep 6",
+            "# statements nested 1 deep 5",
+            "# right-hand side GetExpr 1",
+            "# right-hand side NewExpr 1",
+        ] {
+            assert!(profile.lines().any(|l| l == line), "{}", line);
+        }
+        let (_, learned) = profile.split_once("\n\n").unwrap();
+        assert_eq!(
+            learned,
+            "\
+branch_rate = 0.182
+declare_to_assign = 0.333
+else_ratio = 1.000
+async_rate = 1.000
+get_rate = 0.167
+new_rate = 0.200
+sync_rate = 0.000
+functional_rate = 0.000
+max_depth = 1
+avg_meth_body_size = 3
+avg_block_size = 2
+
+[weights]
+if = 0.500
+while = 0.500
+foreach = 0.000
+switch = 0.000
+try = 0.000
+await = 0.167
+assert = 0.000
+suspend = 0.167
+throw = 0.000
+"
+        );
+
+        // The command line takes precedence over the profile
+        let settings = Config {
+            profile: Some(out.join("profile.toml")),
+            avg_block_size: Some(5),
+            ..Config::default()
+        }
+        .with_profile()
+        .unwrap()
+        .settings()
+        .unwrap();
+        let opts = settings.opts;
+        assert_eq!(opts.branch_rate, 0.182);
+        assert_eq!(opts.declare_to_assign, 0.333);
+        assert_eq!(opts.else_ratio, 1.0);
+        assert_eq!(opts.async_rate, 1.0);
+        assert_eq!(opts.get_rate, 0.167);
+        assert_eq!(opts.new_rate, 0.2);
+        assert_eq!(opts.sync_rate, 0.0);
+        assert_eq!(opts.functional_rate, 0.0);
+        assert_eq!(opts.max_depth, 1);
+        assert_eq!(opts.avg_meth_body_size, 3);
+        assert_eq!(opts.avg_block_size, 5);
+        let weights: Vec<_> = opts.weights.iter().collect();
+        assert_eq!(
+            weights,
+            [
+                ("if", 0.5),
+                ("while", 0.5),
+                ("foreach", 0.0),
+                ("switch", 0.0),
+                ("try", 0.0),
+                ("await", 0.167),
+                ("assert", 0.0),
+                ("suspend", 0.167),
+                ("throw", 0.0),
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
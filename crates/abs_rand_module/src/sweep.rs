@@ -0,0 +1,300 @@
+use rand::{seq::SliceRandom, Rng};
+
+use crate::Options;
+
+/// An option that can be varied in a sweep
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Dim {
+    NumRandClasses,
+    MaxDepth,
+    BranchRate,
+    DeclareToAssign,
+    ElseRatio,
+    AvgMethBodySize,
+    AvgBlockSize,
+    NumMethods,
+    NumInterfaces,
+    NumClassParams,
+    AsyncRate,
+    GetRate,
+    NewRate,
+    SyncRate,
+    NumDataTypes,
+    NumFunctions,
+    FunctionalRate,
+    DefectRate,
+    Steer,
+}
+
+const DIMS: &[(&str, Dim)] = &[
+    ("num_rand_classes", Dim::NumRandClasses),
+    ("max_depth", Dim::MaxDepth),
+    ("branch_rate", Dim::BranchRate),
+    ("declare_to_assign", Dim::DeclareToAssign),
+    ("else_ratio", Dim::ElseRatio),
+    ("avg_meth_body_size", Dim::AvgMethBodySize),
+    ("avg_block_size", Dim::AvgBlockSize),
+    ("num_methods", Dim::NumMethods),
+    ("num_interfaces", Dim::NumInterfaces),
+    ("num_class_params", Dim::NumClassParams),
+    ("async_rate", Dim::AsyncRate),
+    ("get_rate", Dim::GetRate),
+    ("new_rate", Dim::NewRate),
+    ("sync_rate", Dim::SyncRate),
+    ("num_data_types", Dim::NumDataTypes),
+    ("num_functions", Dim::NumFunctions),
+    ("functional_rate", Dim::FunctionalRate),
+    ("defect_rate", Dim::DefectRate),
+    ("steer", Dim::Steer),
+];
+
+impl Dim {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        DIMS.iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, d)| *d)
+            .ok_or_else(|| format!("`{}` cannot be swept", name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        DIMS.iter().find(|(_, d)| d == self).unwrap().0
+    }
+
+    pub fn all() -> impl Iterator<Item = Dim> {
+        DIMS.iter().map(|(_, d)| *d)
+    }
+
+    fn is_probability(&self) -> bool {
+        matches!(
+            self,
+            Dim::BranchRate
+                | Dim::DeclareToAssign
+                | Dim::ElseRatio
+                | Dim::AsyncRate
+                | Dim::GetRate
+                | Dim::NewRate
+                | Dim::SyncRate
+                | Dim::FunctionalRate
+                | Dim::DefectRate
+        )
+    }
+
+    fn is_count(&self) -> bool {
+        !self.is_probability() && *self != Dim::Steer
+    }
+
+    /// The largest value the option can take
+    fn max(&self) -> f64 {
+        match self {
+            Dim::MaxDepth => u8::MAX as f64,
+            Dim::Steer => f64::INFINITY,
+            d if d.is_probability() => 1.0,
+            _ => u32::MAX as f64,
+        }
+    }
+
+    /// Sets the option; counts are rounded to the nearest number
+    pub fn set(&self, opts: &mut Options, v: f64) {
+        let n = v.round() as u32;
+        match self {
+            Dim::NumRandClasses => opts.num_rand_classes = n,
+            Dim::MaxDepth => opts.max_depth = n as u8,
+            Dim::BranchRate => opts.branch_rate = v,
+            Dim::DeclareToAssign => opts.declare_to_assign = v,
+            Dim::ElseRatio => opts.else_ratio = v,
+            Dim::AvgMethBodySize => opts.avg_meth_body_size = n,
+            Dim::AvgBlockSize => opts.avg_block_size = n,
+            Dim::NumMethods => opts.num_methods = n,
+            Dim::NumInterfaces => opts.num_interfaces = n,
+            Dim::NumClassParams => opts.num_class_params = n,
+            Dim::AsyncRate => opts.async_rate = v,
+            Dim::GetRate => opts.get_rate = v,
+            Dim::NewRate => opts.new_rate = v,
+            Dim::SyncRate => opts.sync_rate = v,
+            Dim::NumDataTypes => opts.num_data_types = n,
+            Dim::NumFunctions => opts.num_functions = n,
+            Dim::FunctionalRate => opts.functional_rate = v,
+            Dim::DefectRate => opts.defect_rate = v,
+            Dim::Steer => opts.steer = v,
+        }
+    }
+
+    pub fn get(&self, opts: &Options) -> String {
+        match self {
+            Dim::NumRandClasses => opts.num_rand_classes.to_string(),
+            Dim::MaxDepth => opts.max_depth.to_string(),
+            Dim::BranchRate => opts.branch_rate.to_string(),
+            Dim::DeclareToAssign => opts.declare_to_assign.to_string(),
+            Dim::ElseRatio => opts.else_ratio.to_string(),
+            Dim::AvgMethBodySize => opts.avg_meth_body_size.to_string(),
+            Dim::AvgBlockSize => opts.avg_block_size.to_string(),
+            Dim::NumMethods => opts.num_methods.to_string(),
+            Dim::NumInterfaces => opts.num_interfaces.to_string(),
+            Dim::NumClassParams => opts.num_class_params.to_string(),
+            Dim::AsyncRate => opts.async_rate.to_string(),
+            Dim::GetRate => opts.get_rate.to_string(),
+            Dim::NewRate => opts.new_rate.to_string(),
+            Dim::SyncRate => opts.sync_rate.to_string(),
+            Dim::NumDataTypes => opts.num_data_types.to_string(),
+            Dim::NumFunctions => opts.num_functions.to_string(),
+            Dim::FunctionalRate => opts.functional_rate.to_string(),
+            Dim::DefectRate => opts.defect_rate.to_string(),
+            Dim::Steer => opts.steer.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Mode {
+    /// Every combination of the given values
+    Grid,
+    /// A Latin hypercube sample of the given ranges
+    Lhs,
+}
+
+impl Mode {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "grid" => Ok(Mode::Grid),
+            "lhs" => Ok(Mode::Lhs),
+            _ => Err(format!("Unknown sweep `{}`, expected grid or lhs", name)),
+        }
+    }
+}
+
+/// Varies several options at once, generating one module per point
+pub struct Sweep {
+    pub mode: Mode,
+    /// The number of points of a Latin hypercube sample
+    pub samples: usize,
+    /// The values of every dimension for a grid, or its minimum and maximum
+    /// for a Latin hypercube sample
+    pub dims: Vec<(Dim, Vec<f64>)>,
+}
+
+impl Sweep {
+    pub fn new(mode: Mode, samples: usize, dims: Vec<(Dim, Vec<f64>)>) -> Result<Self, String> {
+        for (d, vs) in &dims {
+            if vs.is_empty() {
+                return Err(format!("{} needs at least one value", d.name()));
+            }
+            if mode == Mode::Lhs && vs.len() != 2 {
+                return Err(format!("{} needs a minimum and a maximum", d.name()));
+            }
+            if mode == Mode::Lhs && vs[0] > vs[1] {
+                return Err(format!("The minimum of {} is above its maximum", d.name()));
+            }
+            if vs.iter().any(|v| {
+                let v = if d.is_count() { v.round() } else { *v };
+                v < 0.0 || v > d.max()
+            }) {
+                return Err(format!("{} is out of range", d.name()));
+            }
+        }
+        if mode == Mode::Lhs && samples == 0 {
+            return Err("A Latin hypercube sample needs at least one sample".to_string());
+        }
+        Ok(Sweep {
+            mode,
+            samples,
+            dims,
+        })
+    }
+
+    /// The options at every point of the sweep, the others taken from `base`
+    pub fn points<R: Rng + ?Sized>(&self, base: Options, rng: &mut R) -> Vec<Options> {
+        match self.mode {
+            Mode::Grid => self.grid(base),
+            Mode::Lhs => self.lhs(base, rng),
+        }
+    }
+
+    fn grid(&self, base: Options) -> Vec<Options> {
+        let mut points = vec![base];
+        for (d, vs) in &self.dims {
+            points = points
+                .iter()
+                .flat_map(|p| {
+                    vs.iter().map(move |v| {
+                        let mut p = *p;
+                        d.set(&mut p, *v);
+                        p
+                    })
+                })
+                .collect();
+        }
+        points
+    }
+
+    /// Splits the range of every dimension into `samples` strata and uses
+    /// each stratum exactly once, at a random place inside it. Counts are
+    /// taken from `[min, max + 1)` and rounded down, so that `min` and `max`
+    /// are as likely as the counts between them
+    fn lhs<R: Rng + ?Sized>(&self, base: Options, rng: &mut R) -> Vec<Options> {
+        let n = self.samples;
+        let mut points = vec![base; n];
+        for (d, vs) in &self.dims {
+            let (min, max) = (vs[0], vs[1]);
+            let mut strata: Vec<usize> = (0..n).collect();
+            strata.shuffle(rng);
+            for (p, s) in points.iter_mut().zip(strata) {
+                let x = (s as f64 + rng.gen::<f64>()) / n as f64;
+                let v = if d.is_count() {
+                    (min + x * (max + 1.0 - min)).floor()
+                } else {
+                    min + x * (max - min)
+                };
+                d.set(p, v);
+            }
+        }
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn a_reversed_range_is_rejected() {
+        let dims = vec![(Dim::MaxDepth, vec![4.0, 1.0])];
+        assert_eq!(
+            Sweep::new(Mode::Lhs, 5, dims).err().unwrap(),
+            "The minimum of max_depth is above its maximum"
+        );
+    }
+
+    #[test]
+    fn a_depth_above_255_is_rejected() {
+        let dims = vec![(Dim::MaxDepth, vec![1.0, 256.0])];
+        assert_eq!(
+            Sweep::new(Mode::Grid, 0, dims).err().unwrap(),
+            "max_depth is out of range"
+        );
+        assert!(Sweep::new(Mode::Lhs, 5, vec![(Dim::MaxDepth, vec![1.0, 255.0])]).is_ok());
+    }
+
+    #[test]
+    fn counts_are_sampled_evenly() {
+        let dims = vec![(Dim::MaxDepth, vec![1.0, 4.0])];
+        let sweep = Sweep::new(Mode::Lhs, 8, dims).unwrap();
+        let points = sweep.points(Options::default(), &mut StdRng::seed_from_u64(0));
+        let mut depths: Vec<u8> = points.iter().map(|p| p.max_depth).collect();
+        depths.sort_unstable();
+        assert_eq!(depths, [1, 1, 2, 2, 3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn a_sample_stays_in_its_range() {
+        let dims = vec![(Dim::AsyncRate, vec![0.25, 0.5])];
+        let sweep = Sweep::new(Mode::Lhs, 8, dims).unwrap();
+        let points = sweep.points(Options::default(), &mut StdRng::seed_from_u64(0));
+        assert_eq!(points.len(), 8);
+        for p in points {
+            assert!((0.25..=0.5).contains(&p.async_rate));
+            assert_eq!(p.num_rand_classes, 300);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! What the modules generated for an analysis have to look like.
+//!
+//! Every analysis that is benchmarked has a [`TargetProfile`], which decides
+//! what the generated code is annotated with and which expressions it may
+//! contain. Another analysis is added by implementing the trait and listing
+//! the profile in [`TARGETS`].
+
+use abs_syntax::ast;
+
+use crate::{
+    gen::{self, ty},
+    oracle::Defect,
+};
+
+/// The profile of a target, as stored in the options
+pub type Target = &'static dyn TargetProfile;
+
+/// The targets that can be chosen by name
+pub const TARGETS: &[Target] = &[&Crowbar, &NullableExtension, &Location];
+
+pub fn from_name(name: &str) -> Result<Target, String> {
+    TARGETS
+        .iter()
+        .find(|t| t.name() == name || t.short_name() == name)
+        .copied()
+        .ok_or_else(|| {
+            let names: Vec<_> = TARGETS.iter().map(|t| t.name()).collect();
+            format!(
+                "Unknown target `{}`, expected one of {}",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+/// Expressions and statements that not every analysis supports
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Form {
+    Get,
+    New,
+    AsyncCall,
+    SyncCall,
+    While,
+    Foreach,
+    Switch,
+    Try,
+    Await,
+    Assert,
+    Suspend,
+    Throw,
+    Case,
+    Let,
+    When,
+    FnApp,
+}
+
+pub trait TargetProfile: Sync {
+    /// The name used in the options
+    fn name(&self) -> &'static str;
+
+    /// The name used in file names
+    fn short_name(&self) -> &'static str;
+
+    /// Declarations put before everything else in the module
+    fn prelude(&self) -> Vec<ast::ModuleItem> {
+        Vec::new()
+    }
+
+    /// The annotations of a method signature whose result is never `null`
+    fn non_null_ret(&self) -> ast::Annotations {
+        gen::empty_annos()
+    }
+
+    /// The annotations of a method implementing such a signature of an
+    /// interface
+    fn non_null_impl(&self) -> ast::Annotations {
+        gen::empty_annos()
+    }
+
+    /// The annotations of a parameter that may be `null`
+    fn nullable_param(&self) -> ast::Annotations {
+        gen::empty_annos()
+    }
+
+    /// Whether the generated code keeps to the conditions given to the
+    /// methods below, which e.g. only lets `fint` grow
+    fn specifies(&self) -> bool {
+        false
+    }
+
+    /// The annotations of a class whose fields always satisfy `inv`
+    fn object_invariant(&self, _inv: ast::PureExpr) -> ast::Annotations {
+        gen::empty_annos()
+    }
+
+    /// The annotations of a method signature whose callers establish `cond`
+    fn precondition(&self, _cond: ast::PureExpr) -> ast::Annotations {
+        gen::empty_annos()
+    }
+
+    /// The annotations of a method that establishes `cond` for its `result`
+    fn postcondition(&self, _cond: ast::PureExpr) -> ast::Annotations {
+        gen::empty_annos()
+    }
+
+    /// The annotations of a `while` loop that preserves `inv`
+    fn loop_invariant(&self, _inv: ast::PureExpr) -> ast::Annotations {
+        gen::empty_annos()
+    }
+
+    fn allows(&self, _form: Form) -> bool {
+        true
+    }
+
+    /// The defect planted for the analysis to find, if there is one
+    fn defect(&self) -> Option<Defect> {
+        None
+    }
+}
+
+fn annos(a: ast::Annotation) -> ast::Annotations {
+    let mut annos = gen::empty_annos();
+    annos.push(a);
+    annos
+}
+
+/// The deductive verifier Crowbar, which reads specifications from `Spec`
+/// annotations
+pub struct Crowbar;
+
+impl TargetProfile for Crowbar {
+    fn name(&self) -> &'static str {
+        "crowbar"
+    }
+
+    fn short_name(&self) -> &'static str {
+        "cb"
+    }
+
+    fn prelude(&self) -> Vec<ast::ModuleItem> {
+        let bool_constr = |name| {
+            gen::start_data_constr(name)
+                .with_param(gen::create_data_constr_param(ty::create_bool()))
+                .complete()
+        };
+        vec![gen::start_data_type("Spec")
+            .with_const(bool_constr("ObjInv"))
+            .with_const(bool_constr("Ensures"))
+            .with_const(bool_constr("Requires"))
+            .with_const(bool_constr("WhileInv"))
+            .complete()
+            .into()]
+    }
+
+    fn non_null_ret(&self) -> ast::Annotations {
+        annos(gen::create_crowbar_non_null_ret())
+    }
+
+    fn specifies(&self) -> bool {
+        true
+    }
+
+    fn object_invariant(&self, inv: ast::PureExpr) -> ast::Annotations {
+        annos(gen::create_crowbar_spec("ObjInv", inv))
+    }
+
+    fn precondition(&self, cond: ast::PureExpr) -> ast::Annotations {
+        annos(gen::create_crowbar_spec("Requires", cond))
+    }
+
+    fn postcondition(&self, cond: ast::PureExpr) -> ast::Annotations {
+        annos(gen::create_crowbar_spec("Ensures", cond))
+    }
+
+    fn loop_invariant(&self, inv: ast::PureExpr) -> ast::Annotations {
+        annos(gen::create_crowbar_while_inv(inv))
+    }
+
+    fn defect(&self) -> Option<Defect> {
+        Some(Defect::UnprovablePost)
+    }
+}
+
+/// The nullable type extension, which reads `Nullable` and `NonNull`
+pub struct NullableExtension;
+
+impl TargetProfile for NullableExtension {
+    fn name(&self) -> &'static str {
+        "nullable"
+    }
+
+    fn short_name(&self) -> &'static str {
+        "nc"
+    }
+
+    fn non_null_ret(&self) -> ast::Annotations {
+        annos(gen::create_nullable_non_null())
+    }
+
+    fn non_null_impl(&self) -> ast::Annotations {
+        annos(gen::create_nullable_non_null())
+    }
+
+    fn nullable_param(&self) -> ast::Annotations {
+        annos(gen::create_nullable_nullable())
+    }
+
+    fn defect(&self) -> Option<Defect> {
+        Some(Defect::NullDeref)
+    }
+}
+
+/// The location type checker, which does not support synchronous calls
+pub struct Location;
+
+impl TargetProfile for Location {
+    fn name(&self) -> &'static str {
+        "location"
+    }
+
+    fn short_name(&self) -> &'static str {
+        "loc"
+    }
+
+    fn non_null_ret(&self) -> ast::Annotations {
+        annos(gen::create_nullable_non_null())
+    }
+
+    fn allows(&self, form: Form) -> bool {
+        form != Form::SyncCall
+    }
+
+    fn defect(&self) -> Option<Defect> {
+        Some(Defect::FarAsNear)
+    }
+}
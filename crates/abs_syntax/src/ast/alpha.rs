@@ -0,0 +1,481 @@
+//! Comparison of trees up to the names of their variables.
+//!
+//! Both trees are copied and every variable, parameter and field in them is
+//! renamed to a name determined by the order in which it is declared. If the
+//! copies are then equal, the trees are alpha-equivalent. Names that are not
+//! declared in the tree, like those of classes, methods or functions, have to
+//! be equal.
+
+use std::collections::HashMap;
+
+use super::*;
+
+/// Equality that ignores how variables are named
+pub trait AlphaEq {
+    fn alpha_eq(&self, other: &Self) -> bool;
+}
+
+macro_rules! impl_alpha_eq {
+    ($($t:ty),*) => {
+        $(impl AlphaEq for $t {
+            fn alpha_eq(&self, other: &Self) -> bool {
+                let (mut a, mut b) = (self.clone(), other.clone());
+                a.rename(&mut Renamer::default());
+                b.rename(&mut Renamer::default());
+                a == b
+            }
+        })*
+    };
+}
+
+impl_alpha_eq!(
+    Module,
+    ModuleItem,
+    FunctionDecl,
+    InterfaceDecl,
+    ClassDecl,
+    MethodDecl,
+    Block,
+    Stmt,
+    Expr,
+    PureExpr
+);
+
+#[derive(Default)]
+struct Renamer {
+    /// Variables in scope, innermost last
+    scopes: Vec<HashMap<String, String>>,
+    /// Fields of the current class
+    fields: HashMap<String, String>,
+    count: usize,
+}
+
+impl Renamer {
+    fn scoped<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// A name that no variable in ABS code can have
+    fn fresh(&mut self) -> String {
+        self.count += 1;
+        format!("#{}", self.count)
+    }
+
+    fn bind(&mut self, ident: &mut Ident) {
+        let name = self.fresh();
+        if self.scopes.is_empty() {
+            self.scopes.push(HashMap::new());
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        scope.insert(std::mem::replace(&mut ident.str, name.clone()), name);
+    }
+
+    fn bind_field(&mut self, ident: &mut Ident) {
+        let name = self.fresh();
+        self.fields
+            .insert(std::mem::replace(&mut ident.str, name.clone()), name);
+    }
+
+    /// Renames a use of a variable; fields may be used without `this.`
+    fn var(&self, ident: &mut Ident) {
+        let name = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(&ident.str))
+            .or_else(|| self.fields.get(&ident.str));
+        if let Some(n) = name {
+            ident.str = n.clone();
+        }
+    }
+
+    fn is_bound(&self, name: &str) -> bool {
+        self.scopes.iter().any(|s| s.contains_key(name)) || self.fields.contains_key(name)
+    }
+
+    fn field(&self, ident: &mut Ident) {
+        if let Some(n) = self.fields.get(&ident.str) {
+            ident.str = n.clone();
+        }
+    }
+}
+
+trait Rename {
+    fn rename(&mut self, r: &mut Renamer);
+}
+
+impl<T: Rename> Rename for Vec<T> {
+    fn rename(&mut self, r: &mut Renamer) {
+        self.iter_mut().for_each(|x| x.rename(r))
+    }
+}
+
+impl<T: Rename> Rename for Option<T> {
+    fn rename(&mut self, r: &mut Renamer) {
+        if let Some(x) = self {
+            x.rename(r)
+        }
+    }
+}
+
+impl<T: Rename> Rename for Box<T> {
+    fn rename(&mut self, r: &mut Renamer) {
+        (**self).rename(r)
+    }
+}
+
+impl Rename for Module {
+    fn rename(&mut self, r: &mut Renamer) {
+        self.children.rename(r)
+    }
+}
+
+impl Rename for ModuleItem {
+    fn rename(&mut self, r: &mut Renamer) {
+        match self {
+            ModuleItem::FunctionDecl(d) => d.rename(r),
+            ModuleItem::InterfaceDecl(i) => i.rename(r),
+            ModuleItem::ClassDecl(c) => c.rename(r),
+            ModuleItem::MainBlock(b) => b.rename(r),
+            ModuleItem::Import(_)
+            | ModuleItem::Export(_)
+            | ModuleItem::DataTypeDecl(_)
+            | ModuleItem::TypeSynDecl(_)
+            | ModuleItem::ExceptionDecl(_) => {}
+        }
+    }
+}
+
+impl Rename for Annotations {
+    fn rename(&mut self, r: &mut Renamer) {
+        for a in self.iter_mut() {
+            match a {
+                Annotation::Typed(t) => t.expr.rename(r),
+                Annotation::Untyped(u) => u.0.rename(r),
+            }
+        }
+    }
+}
+
+/// Declares parameters; their annotations may refer to earlier ones
+fn bind_params(params: &mut [Param], r: &mut Renamer) {
+    for p in params {
+        p.annotations.rename(r);
+        r.bind(&mut p.ident);
+    }
+}
+
+impl Rename for FunctionDecl {
+    fn rename(&mut self, r: &mut Renamer) {
+        r.scoped(|r| {
+            bind_params(&mut self.params, r);
+            self.annotations.rename(r);
+            match &mut self.body {
+                FunctionBody::Builtin(args) => args.rename(r),
+                FunctionBody::Expr(e) => e.rename(r),
+            }
+        })
+    }
+}
+
+impl Rename for MethodSig {
+    /// Declares the parameters in the current scope
+    fn rename(&mut self, r: &mut Renamer) {
+        bind_params(&mut self.params, r);
+        self.annotations.rename(r);
+    }
+}
+
+impl Rename for InterfaceDecl {
+    fn rename(&mut self, r: &mut Renamer) {
+        for s in &mut self.sigs {
+            r.scoped(|r| s.rename(r))
+        }
+    }
+}
+
+impl Rename for ClassDecl {
+    fn rename(&mut self, r: &mut Renamer) {
+        let outer = std::mem::take(&mut r.fields);
+
+        for p in &mut self.params {
+            p.annotations.rename(r);
+            r.bind_field(&mut p.ident);
+        }
+        for f in &mut self.fields {
+            f.annotations.rename(r);
+            f.init.rename(r);
+            r.bind_field(&mut f.ident);
+        }
+        self.annotations.rename(r);
+        self.init.rename(r);
+        self.recover.rename(r);
+        self.methods.rename(r);
+
+        r.fields = outer;
+    }
+}
+
+impl Rename for MethodDecl {
+    fn rename(&mut self, r: &mut Renamer) {
+        r.scoped(|r| {
+            self.sig.rename(r);
+            self.body.rename(r);
+        })
+    }
+}
+
+impl Rename for Block {
+    fn rename(&mut self, r: &mut Renamer) {
+        r.scoped(|r| self.stmts.rename(r))
+    }
+}
+
+impl<K: Rename> Rename for CaseBranch<K> {
+    fn rename(&mut self, r: &mut Renamer) {
+        r.scoped(|r| {
+            self.pattern.rename(r);
+            self.right.rename(r);
+        })
+    }
+}
+
+impl Rename for Pattern {
+    /// Declares the variables of the pattern. A variable that is already
+    /// declared is compared with instead.
+    fn rename(&mut self, r: &mut Renamer) {
+        match self {
+            Pattern::Ident(i) if r.is_bound(&i.str) => r.var(i),
+            Pattern::Ident(i) => r.bind(i),
+            Pattern::Constr { args, .. } => args.rename(r),
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
+    }
+}
+
+impl Rename for Stmt {
+    fn rename(&mut self, r: &mut Renamer) {
+        match self {
+            Stmt::VarDecl(v) => {
+                v.annotations.rename(r);
+                v.init.rename(r);
+                r.bind(&mut v.ident);
+            }
+            Stmt::Assign(a) => {
+                a.expr.rename(r);
+                if a.this {
+                    r.field(&mut a.ident)
+                } else {
+                    r.var(&mut a.ident)
+                }
+            }
+            Stmt::Expr(e) => e.expr.rename(r),
+            Stmt::Assert(a) => a.condition.rename(r),
+            Stmt::Await(a) => a.guard.rename(r),
+            Stmt::Throw(t) => t.expr.rename(r),
+            Stmt::Return(ret) => ret.expr.rename(r),
+            Stmt::Block(b) => b.rename(r),
+            Stmt::If(i) => {
+                i.condition.rename(r);
+                i.then.rename(r);
+                i.r#else.rename(r);
+            }
+            Stmt::Switch(s) => {
+                s.expr.rename(r);
+                s.branches.rename(r);
+            }
+            Stmt::While(w) => {
+                w.annotations.rename(r);
+                w.condition.rename(r);
+                w.body.rename(r);
+            }
+            Stmt::Foreach(f) => {
+                f.iter.rename(r);
+                r.scoped(|r| {
+                    r.bind(&mut f.loop_var);
+                    f.body.rename(r);
+                })
+            }
+            Stmt::TryCatchFinally(t) => {
+                t.r#try.rename(r);
+                t.catch_branches.rename(r);
+                t.finally.rename(r);
+            }
+            Stmt::Skip | Stmt::Suspend => {}
+        }
+    }
+}
+
+impl Rename for Guard {
+    fn rename(&mut self, r: &mut Renamer) {
+        match self {
+            Guard::Claim { this: true, ident } => r.field(ident),
+            Guard::Claim { this: false, ident } => r.var(ident),
+            Guard::Expr(e) => e.rename(r),
+            Guard::And(a, b) => {
+                a.rename(r);
+                b.rename(r);
+            }
+            Guard::Duration(min, max) => {
+                min.rename(r);
+                max.rename(r);
+            }
+        }
+    }
+}
+
+impl Rename for Expr {
+    fn rename(&mut self, r: &mut Renamer) {
+        match self {
+            Expr::Pure(e) => e.rename(r),
+            Expr::Eff(e) => e.rename(r),
+        }
+    }
+}
+
+impl Rename for PureExpr {
+    fn rename(&mut self, r: &mut Renamer) {
+        match self {
+            PureExpr::Ident(i) => r.var(&mut i.ident),
+            PureExpr::ThisIdent(i) => r.field(&mut i.ident),
+            PureExpr::Let(l) => {
+                l.value.rename(r);
+                r.scoped(|r| {
+                    r.bind(&mut l.ident);
+                    l.inner.rename(r);
+                })
+            }
+            PureExpr::DataConstr(d) => d.args.rename(r),
+            PureExpr::FnApp(f) | PureExpr::VariadicFnApp(f) => f.args.rename(r),
+            PureExpr::If(i) => {
+                i.condition.rename(r);
+                i.then.rename(r);
+                i.r#else.rename(r);
+            }
+            PureExpr::When(w) => {
+                w.condition.rename(r);
+                w.then.rename(r);
+                w.r#else.rename(r);
+            }
+            PureExpr::Case(c) => {
+                c.expr.rename(r);
+                c.branches.rename(r);
+            }
+            PureExpr::Operator(OperatorExpr::Unary(u)) => u.expr.rename(r),
+            PureExpr::Operator(OperatorExpr::Binary(b)) => {
+                b.left.rename(r);
+                b.right.rename(r);
+            }
+            PureExpr::TypeCheck(t) => t.expr.rename(r),
+            PureExpr::TypeCast(t) => t.expr.rename(r),
+            PureExpr::TemplateString(t) => t.exprs.rename(r),
+            PureExpr::This | PureExpr::Null | PureExpr::Literal(_) | PureExpr::Destiny => {}
+            PureExpr::ParFnApp(p) => {
+                for func in &mut p.funcs {
+                    if let FnArg::Anon(a) = func {
+                        r.scoped(|r| {
+                            bind_params(&mut a.params, r);
+                            a.body.rename(r);
+                        })
+                    }
+                }
+                p.args.rename(r);
+            }
+        }
+    }
+}
+
+impl Rename for EffExpr {
+    fn rename(&mut self, r: &mut Renamer) {
+        match self {
+            EffExpr::New(n) => n.args.rename(r),
+            EffExpr::SyncCall(c) => {
+                c.callee.rename(r);
+                c.args.rename(r);
+            }
+            EffExpr::AsyncCall(c) => c.rename(r),
+            EffExpr::Get(g) => g.expr.rename(r),
+            EffExpr::Await(a) => a.call.rename(r),
+        }
+    }
+}
+
+impl Rename for AsyncCallExpr {
+    fn rename(&mut self, r: &mut Renamer) {
+        self.callee.rename(r);
+        self.args.rename(r);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_modules;
+
+    fn module(src: &str) -> Module {
+        parse_modules(src).unwrap().remove(0)
+    }
+
+    fn alpha_eq(a: &str, b: &str) -> bool {
+        module(a).alpha_eq(&module(b))
+    }
+
+    const CLASS: &str = "module M;
+class C(Int p) {
+    Int f = p;
+    Int m(Int x) {
+        Int y = x + this.f;
+        Int z = let (Int w) = y in w * p;
+        case y {
+            0 => skip;
+            v => z = v;
+        }
+        return z;
+    }
+}
+";
+
+    #[test]
+    fn renamed_variables_are_alpha_equivalent() {
+        let renamed = "module M;
+class C(Int q) {
+    Int g = q;
+    Int m(Int a) {
+        Int b = a + this.g;
+        Int c = let (Int d) = b in d * q;
+        case b {
+            0 => skip;
+            e => c = e;
+        }
+        return c;
+    }
+}
+";
+        assert!(alpha_eq(CLASS, renamed));
+        assert!(alpha_eq(CLASS, CLASS));
+    }
+
+    #[test]
+    fn different_uses_are_not_alpha_equivalent() {
+        // Swapping two variables changes which one is used where
+        let swapped = CLASS.replace("x + this.f", "this.f + x");
+        assert!(!alpha_eq(CLASS, &swapped));
+        let other_var = CLASS.replace("w * p", "y * p");
+        assert!(!alpha_eq(CLASS, &other_var));
+        // Names that are not declared in the tree have to be equal
+        let method = CLASS.replace("Int m(", "Int n(");
+        assert!(!alpha_eq(CLASS, &method));
+        let class = CLASS.replace("class C(", "class D(");
+        assert!(!alpha_eq(CLASS, &class));
+    }
+
+    #[test]
+    fn a_pattern_compares_with_a_bound_variable() {
+        // `y` is bound, so the branch compares with it instead of binding
+        // a new variable
+        let bound = CLASS.replace("v => z = v;", "y => z = y;");
+        assert!(!alpha_eq(CLASS, &bound));
+    }
+}
@@ -1,27 +1,40 @@
 use super::*;
 
-#[derive(Clone)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Annotations(Vec<Annotation>);
 
 impl Annotations {
     pub fn push(&mut self, a: Annotation) {
         self.0.push(a)
     }
-}
 
-impl Default for Annotations {
-    fn default() -> Self {
-        Annotations(Vec::new())
+    pub fn iter(&self) -> impl Iterator<Item = &Annotation> {
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Annotation> {
+        self.0.iter_mut()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Puts every annotation on its own line, as is usual for classes and methods
+    pub fn to_abs_lines(&self, f: &mut ABSFormatter) {
+        f.list_fn(self.0.iter(), |_, _| {}, |_, f| f.new_line())
     }
 }
 
 impl DisplayABS for Annotations {
     fn to_abs(&self, f: &mut ABSFormatter) {
-        f.list(self.0.iter(), " ")
+        f.list_fn(self.0.iter(), |_, _| {}, |_, f| f.add(" "))
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Annotation {
     Typed(TypedAnnotation),
     Untyped(UntypedAnnotation),
@@ -36,7 +49,8 @@ impl DisplayABS for Annotation {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypedAnnotation {
     pub ty: Type,
     pub expr: PureExpr,
@@ -52,7 +66,8 @@ impl DisplayABS for TypedAnnotation {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UntypedAnnotation(pub PureExpr);
 
 impl DisplayABS for UntypedAnnotation {
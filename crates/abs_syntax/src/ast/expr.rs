@@ -2,8 +2,9 @@ use std::fmt;
 
 use crate::{add_fmt, fmt::ABSFormatter};
 
-use super::{CaseBranch, DisplayABS, Ident, Literal, Type};
-#[derive(Clone)]
+use super::{CaseBranch, DisplayABS, Ident, Literal, Param, Type};
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Pure(PureExpr),
     Eff(EffExpr),
@@ -39,18 +40,25 @@ impl From<EffExpr> for Expr {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PureExpr {
     Ident(IdentExpr),
     ThisIdent(IdentExpr),
     This,
     Null,
     Literal(Literal),
-    TemplateString,
+    TemplateString(TemplateStringExpr),
+    /// The future of the current process
+    Destiny,
     Let(LetExpr),
     DataConstr(DataConstrExpr),
     FnApp(FnAppExpr),
+    /// `list[1, 2, 3]`
+    VariadicFnApp(FnAppExpr),
+    /// `map((Int x) => x + 1)(list[1])`
     ParFnApp(ParFnAppExpr),
+    If(IfExpr),
     When(WhenExpr),
     Case(CaseExpr),
     Operator(OperatorExpr),
@@ -58,19 +66,98 @@ pub enum PureExpr {
     TypeCast(TypeCastExpr),
 }
 
+impl PureExpr {
+    /// The names of all kinds of pure expressions, as returned by `kind`
+    pub const KINDS: [&'static str; 19] = [
+        "IdentExpr",
+        "ThisIdentExpr",
+        "ThisExpr",
+        "NullExpr",
+        "LiteralExpr",
+        "TemplateString",
+        "DestinyExpr",
+        "LetExpr",
+        "DataConstrExpr",
+        "FnAppExpr",
+        "VariadicFnAppExpr",
+        "ParFnAppExpr",
+        "IfExpr",
+        "WhenExpr",
+        "CaseExpr",
+        "UnaryExpr",
+        "BinaryExpr",
+        "TypeCheckExpr",
+        "TypeCastExpr",
+    ];
+
+    /// The name of the kind of expression, e.g. `CaseExpr`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PureExpr::Ident(_) => "IdentExpr",
+            PureExpr::ThisIdent(_) => "ThisIdentExpr",
+            PureExpr::This => "ThisExpr",
+            PureExpr::Null => "NullExpr",
+            PureExpr::Literal(_) => "LiteralExpr",
+            PureExpr::TemplateString(_) => "TemplateString",
+            PureExpr::Destiny => "DestinyExpr",
+            PureExpr::Let(_) => "LetExpr",
+            PureExpr::DataConstr(_) => "DataConstrExpr",
+            PureExpr::FnApp(_) => "FnAppExpr",
+            PureExpr::VariadicFnApp(_) => "VariadicFnAppExpr",
+            PureExpr::ParFnApp(_) => "ParFnAppExpr",
+            PureExpr::If(_) => "IfExpr",
+            PureExpr::When(_) => "WhenExpr",
+            PureExpr::Case(_) => "CaseExpr",
+            PureExpr::Operator(OperatorExpr::Unary(_)) => "UnaryExpr",
+            PureExpr::Operator(OperatorExpr::Binary(_)) => "BinaryExpr",
+            PureExpr::TypeCheck(_) => "TypeCheckExpr",
+            PureExpr::TypeCast(_) => "TypeCastExpr",
+        }
+    }
+
+    /// Binding strength when printed, higher binds tighter
+    pub fn precedence(&self) -> u8 {
+        match self {
+            PureExpr::Let(_) | PureExpr::If(_) | PureExpr::When(_) | PureExpr::Case(_) => 0,
+            PureExpr::Operator(OperatorExpr::Binary(b)) => b.op.precedence(),
+            PureExpr::Operator(OperatorExpr::Unary(_)) => 7,
+            PureExpr::TypeCheck(_) | PureExpr::TypeCast(_) => 8,
+            _ => 9,
+        }
+    }
+
+    /// Prints the expression in parentheses if it binds weaker than `prec`
+    pub fn to_abs_prec(&self, prec: u8, f: &mut ABSFormatter) {
+        if self.precedence() < prec {
+            f.parenthesized(|f| self.to_abs(f))
+        } else {
+            self.to_abs(f)
+        }
+    }
+}
+
 impl DisplayABS for PureExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
         match self {
             PureExpr::Ident(e) => e.to_abs(f),
-            PureExpr::ThisIdent(e) => e.to_abs(f),
+            PureExpr::ThisIdent(e) => {
+                f.add("this.");
+                e.to_abs(f)
+            }
             PureExpr::This => f.add("this"),
             PureExpr::Null => f.add("null"),
             PureExpr::Literal(e) => e.to_abs(f),
-            PureExpr::TemplateString => todo!(),
+            PureExpr::TemplateString(e) => e.to_abs(f),
+            PureExpr::Destiny => f.add("destiny"),
             PureExpr::Let(e) => e.to_abs(f),
             PureExpr::DataConstr(e) => e.to_abs(f),
             PureExpr::FnApp(e) => e.to_abs(f),
+            PureExpr::VariadicFnApp(e) => {
+                e.ident.to_abs(f);
+                f.group(|f| f.bracketed(|f| f.broken_list(e.args.iter())))
+            }
             PureExpr::ParFnApp(e) => e.to_abs(f),
+            PureExpr::If(e) => e.to_abs(f),
             PureExpr::When(e) => e.to_abs(f),
             PureExpr::Case(e) => e.to_abs(f),
             PureExpr::Operator(e) => e.to_abs(f),
@@ -82,23 +169,9 @@ impl DisplayABS for PureExpr {
 
 impl fmt::Display for PureExpr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            PureExpr::Ident(i) => fmt::Display::fmt(i, f),
-            PureExpr::ThisIdent(i) => write!(f, "this.{}", i),
-            PureExpr::This => write!(f, "this"),
-            PureExpr::Literal(i) => fmt::Display::fmt(i, f),
-            PureExpr::TemplateString => todo!(),
-            PureExpr::Let(i) => fmt::Display::fmt(i, f),
-            PureExpr::DataConstr(i) => fmt::Display::fmt(i, f),
-            PureExpr::FnApp(i) => fmt::Display::fmt(i, f),
-            PureExpr::ParFnApp(i) => fmt::Display::fmt(i, f),
-            PureExpr::When(i) => fmt::Display::fmt(i, f),
-            PureExpr::Case(i) => fmt::Display::fmt(i, f),
-            PureExpr::Operator(i) => fmt::Display::fmt(i, f),
-            PureExpr::TypeCheck(i) => fmt::Display::fmt(i, f),
-            PureExpr::TypeCast(i) => fmt::Display::fmt(i, f),
-            PureExpr::Null => write!(f, "null"),
-        }
+        let mut af = ABSFormatter::new();
+        self.to_abs(&mut af);
+        fmt::Display::fmt(&af.abs_code(), f)
     }
 }
 
@@ -116,7 +189,7 @@ impl From<IdentExpr> for PureExpr {
 
 impl From<Ident> for PureExpr {
     fn from(e: Ident) -> Self {
-        let e: PureExpr = e.into();
+        let e: IdentExpr = e.into();
         e.into()
     }
 }
@@ -139,7 +212,8 @@ impl From<DataConstrExpr> for PureExpr {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IdentExpr {
     pub ident: Ident,
 }
@@ -164,7 +238,43 @@ impl From<Ident> for IdentExpr {
     }
 }
 
-#[derive(Clone)]
+/// `` `Hello $name$!` ``
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemplateStringExpr {
+    /// The text around the expressions, as written, one more than there are
+    /// expressions
+    pub parts: Vec<String>,
+    pub exprs: Vec<PureExpr>,
+}
+
+impl fmt::Display for TemplateStringExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut af = ABSFormatter::new();
+        self.to_abs(&mut af);
+        fmt::Display::fmt(&af.abs_code(), f)
+    }
+}
+
+impl DisplayABS for TemplateStringExpr {
+    fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
+        f.add("`");
+        for (i, part) in self.parts.iter().enumerate() {
+            if i > 0 {
+                f.add("$");
+            }
+            f.add(part);
+            if let Some(e) = self.exprs.get(i) {
+                f.add("$");
+                e.to_abs(f);
+            }
+        }
+        f.add("`");
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LetExpr {
     pub ty: Type,
     pub ident: Ident,
@@ -182,21 +292,22 @@ impl fmt::Display for LetExpr {
 
 impl DisplayABS for LetExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        f.add("let ");
-        self.ty.to_abs(f);
-        f.add(" ");
-        self.ident.to_abs(f);
-        f.add(" = ");
-        self.value.to_abs(f);
-        f.add_indent();
-        f.new_line();
-        f.add("in ");
-        self.inner.to_abs(f);
-        f.sub_indent();
+        f.group(|f| {
+            f.add("let ");
+            self.ty.to_abs(f);
+            f.add(" ");
+            self.ident.to_abs(f);
+            f.add(" = ");
+            self.value.to_abs(f);
+            f.soft_line();
+            f.add("in ");
+            self.inner.to_abs(f);
+        })
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataConstrExpr {
     pub ident: Ident,
     pub args: Vec<PureExpr>,
@@ -213,11 +324,14 @@ impl fmt::Display for DataConstrExpr {
 impl DisplayABS for DataConstrExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
         self.ident.to_abs(f);
-        f.parenthesized(|f| f.list(self.args.iter(), ", "))
+        if !self.args.is_empty() {
+            f.args(self.args.iter())
+        }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FnAppExpr {
     pub ident: Ident,
     pub args: Vec<PureExpr>,
@@ -234,12 +348,18 @@ impl fmt::Display for FnAppExpr {
 impl DisplayABS for FnAppExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
         self.ident.to_abs(f);
-        f.parenthesized(|f| f.list(self.args.iter(), ", "))
+        f.args(self.args.iter())
     }
 }
 
-#[derive(Clone)]
-pub struct ParFnAppExpr {}
+/// A higher-order function applied to functions and then to arguments
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParFnAppExpr {
+    pub ident: Ident,
+    pub funcs: Vec<FnArg>,
+    pub args: Vec<PureExpr>,
+}
 
 impl fmt::Display for ParFnAppExpr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -250,12 +370,86 @@ impl fmt::Display for ParFnAppExpr {
 }
 
 impl DisplayABS for ParFnAppExpr {
-    fn to_abs(&self, _f: &mut crate::fmt::ABSFormatter) {
-        todo!()
+    fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
+        self.ident.to_abs(f);
+        f.args(self.funcs.iter());
+        f.args(self.args.iter())
+    }
+}
+
+/// A function passed to a higher-order function
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FnArg {
+    Named(Ident),
+    /// `(Int x) => x + 1`
+    Anon(AnonFn),
+}
+
+impl DisplayABS for FnArg {
+    fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
+        match self {
+            FnArg::Named(i) => i.to_abs(f),
+            FnArg::Anon(a) => a.to_abs(f),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnonFn {
+    pub params: Vec<Param>,
+    pub body: Box<PureExpr>,
+}
+
+impl DisplayABS for AnonFn {
+    fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
+        f.group(|f| {
+            f.args(self.params.iter());
+            f.add(" =>");
+            f.nest(|f| {
+                f.soft_line();
+                self.body.to_abs(f)
+            })
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IfExpr {
+    pub condition: Box<PureExpr>,
+    pub then: Box<PureExpr>,
+    pub r#else: Box<PureExpr>,
+}
+
+impl fmt::Display for IfExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut af = ABSFormatter::new();
+        self.to_abs(&mut af);
+        fmt::Display::fmt(&af.abs_code(), f)
     }
 }
 
-#[derive(Clone)]
+impl DisplayABS for IfExpr {
+    fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
+        f.group(|f| {
+            f.add("if ");
+            self.condition.to_abs(f);
+            f.nest(|f| {
+                f.soft_line();
+                f.add("then ");
+                self.then.to_abs(f);
+                f.soft_line();
+                f.add("else ");
+                self.r#else.to_abs(f)
+            })
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WhenExpr {
     pub condition: Box<PureExpr>,
     pub then: Box<PureExpr>,
@@ -272,16 +466,23 @@ impl fmt::Display for WhenExpr {
 
 impl DisplayABS for WhenExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        f.add("when ");
-        self.condition.to_abs(f);
-        f.add(" then ");
-        self.then.to_abs(f);
-        f.add(" else ");
-        self.r#else.to_abs(f)
+        f.group(|f| {
+            f.add("when ");
+            self.condition.to_abs(f);
+            f.nest(|f| {
+                f.soft_line();
+                f.add("then ");
+                self.then.to_abs(f);
+                f.soft_line();
+                f.add("else ");
+                self.r#else.to_abs(f)
+            })
+        })
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CaseExpr {
     pub expr: Box<PureExpr>,
     pub branches: Vec<CaseBranch<PureExpr>>,
@@ -305,16 +506,16 @@ impl DisplayABS for CaseExpr {
                 |i, f| {
                     if i > 0 {
                         f.new_line();
-                        f.add("|")
                     }
                 },
-                |_, _| {},
+                |_, f| f.add(";"),
             )
         })
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeCheckExpr {
     pub expr: Box<PureExpr>,
     pub ty: Ident,
@@ -330,13 +531,14 @@ impl fmt::Display for TypeCheckExpr {
 
 impl DisplayABS for TypeCheckExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        self.expr.to_abs(f);
+        self.expr.to_abs_prec(9, f);
         f.add(" implements ");
         self.ty.to_abs(f)
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeCastExpr {
     pub expr: Box<PureExpr>,
     pub ty: Ident,
@@ -352,13 +554,14 @@ impl fmt::Display for TypeCastExpr {
 
 impl DisplayABS for TypeCastExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        self.expr.to_abs(f);
+        self.expr.to_abs_prec(9, f);
         f.add(" as ");
         self.ty.to_abs(f)
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OperatorExpr {
     Unary(UnaryExpr),
     Binary(BinaryExpr),
@@ -381,7 +584,8 @@ impl DisplayABS for OperatorExpr {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UnaryExpr {
     pub op: UnaryOp,
     pub expr: Box<PureExpr>,
@@ -397,12 +601,13 @@ impl fmt::Display for UnaryExpr {
 
 impl DisplayABS for UnaryExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        add_fmt!(f, "{} ", self.op);
-        self.expr.to_abs(f);
+        add_fmt!(f, "{}", self.op);
+        self.expr.to_abs_prec(7, f);
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOp {
     Not,
     Minus,
@@ -417,7 +622,8 @@ impl fmt::Display for UnaryOp {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BinaryExpr {
     pub op: BinaryOp,
     pub left: Box<PureExpr>,
@@ -434,13 +640,21 @@ impl fmt::Display for BinaryExpr {
 
 impl DisplayABS for BinaryExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        self.left.to_abs(f);
-        add_fmt!(f, " {} ", self.op);
-        self.right.to_abs(f);
+        let prec = self.op.precedence();
+        f.group(|f| {
+            self.left.to_abs_prec(prec, f);
+            f.nest(|f| {
+                f.soft_line();
+                add_fmt!(f, "{} ", self.op);
+                // All binary operators are left-associative
+                self.right.to_abs_prec(prec + 1, f);
+            })
+        })
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOp {
     Or,
     And,
@@ -457,6 +671,19 @@ pub enum BinaryOp {
     Mod,
 }
 
+impl BinaryOp {
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::Ne => 3,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 4,
+            BinaryOp::Plus | BinaryOp::Minus => 5,
+            BinaryOp::Mult | BinaryOp::Div | BinaryOp::Mod => 6,
+        }
+    }
+}
+
 impl fmt::Display for BinaryOp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
@@ -478,7 +705,8 @@ impl fmt::Display for BinaryOp {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EffExpr {
     New(NewExpr),
     SyncCall(SyncCallExpr),
@@ -487,6 +715,28 @@ pub enum EffExpr {
     Await(AwaitExpr),
 }
 
+impl EffExpr {
+    /// The names of all kinds of effectful expressions, as returned by `kind`
+    pub const KINDS: [&'static str; 5] = [
+        "NewExpr",
+        "SyncCallExpr",
+        "AsyncCallExpr",
+        "GetExpr",
+        "AwaitExpr",
+    ];
+
+    /// The name of the kind of expression, e.g. `GetExpr`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EffExpr::New(_) => "NewExpr",
+            EffExpr::SyncCall(_) => "SyncCallExpr",
+            EffExpr::AsyncCall(_) => "AsyncCallExpr",
+            EffExpr::Get(_) => "GetExpr",
+            EffExpr::Await(_) => "AwaitExpr",
+        }
+    }
+}
+
 impl fmt::Display for EffExpr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut af = ABSFormatter::new();
@@ -537,7 +787,8 @@ impl From<AwaitExpr> for EffExpr {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NewExpr {
     pub local: bool,
     pub ty: Ident,
@@ -559,11 +810,12 @@ impl DisplayABS for NewExpr {
             f.add("local ");
         }
         self.ty.to_abs(f);
-        f.parenthesized(|f| f.list(self.args.iter(), ", "))
+        f.args(self.args.iter())
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SyncCallExpr {
     pub callee: PureExpr,
     pub method: Ident,
@@ -580,14 +832,15 @@ impl fmt::Display for SyncCallExpr {
 
 impl DisplayABS for SyncCallExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        self.callee.to_abs(f);
+        self.callee.to_abs_prec(9, f);
         f.add(".");
         self.method.to_abs(f);
-        f.parenthesized(|f| f.list(self.args.iter(), ", "))
+        f.args(self.args.iter())
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AsyncCallExpr {
     pub callee: PureExpr,
     pub method: Ident,
@@ -604,14 +857,15 @@ impl fmt::Display for AsyncCallExpr {
 
 impl DisplayABS for AsyncCallExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        self.callee.to_abs(f);
+        self.callee.to_abs_prec(9, f);
         f.add("!");
         self.method.to_abs(f);
-        f.parenthesized(|f| f.list(self.args.iter(), ", "))
+        f.args(self.args.iter())
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetExpr {
     pub expr: PureExpr,
 }
@@ -626,12 +880,13 @@ impl fmt::Display for GetExpr {
 
 impl DisplayABS for GetExpr {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        self.expr.to_abs(f);
+        self.expr.to_abs_prec(9, f);
         f.add(".get")
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AwaitExpr {
     pub call: AsyncCallExpr,
 }
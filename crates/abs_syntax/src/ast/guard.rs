@@ -4,7 +4,8 @@ use crate::fmt::ABSFormatter;
 
 use super::{DisplayABS, Ident, PureExpr};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Guard {
     Claim { this: bool, ident: Ident },
     Expr(PureExpr),
@@ -1,12 +1,43 @@
-use std::fmt;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
 
-use crate::fmt::ABSFormatter;
+use crate::{fmt::ABSFormatter, parse::Span};
 
 use super::DisplayABS;
 
-#[derive(Clone)]
+/// Identifiers are equal if they have the same name, wherever they were read
+/// from, so that a parsed tree equals the tree it was printed from.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ident {
     pub str: String,
+    /// Where the identifier was read from, if it was parsed
+    pub span: Option<Span>,
+}
+
+impl Ident {
+    pub fn new<S: Into<String>>(s: S) -> Self {
+        Ident {
+            str: s.into(),
+            span: None,
+        }
+    }
+}
+
+impl PartialEq for Ident {
+    fn eq(&self, other: &Self) -> bool {
+        self.str == other.str
+    }
+}
+
+impl Eq for Ident {}
+
+impl Hash for Ident {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.str.hash(state)
+    }
 }
 
 impl fmt::Display for Ident {
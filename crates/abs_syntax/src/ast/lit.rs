@@ -4,7 +4,8 @@ use crate::fmt::ABSFormatter;
 
 use super::DisplayABS;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Literal {
     pub s: String,
 }
@@ -1,5 +1,6 @@
-use std::fmt;
+use std::{fmt, io};
 
+mod alpha;
 mod annotation;
 mod expr;
 mod guard;
@@ -8,7 +9,9 @@ mod lit;
 mod pattern;
 mod stmt;
 mod ty;
+pub mod visit;
 
+pub use alpha::AlphaEq;
 pub use annotation::*;
 pub use expr::*;
 pub use guard::*;
@@ -22,14 +25,22 @@ use crate::fmt::ABSFormatter;
 
 pub trait DisplayABS {
     fn to_abs(&self, f: &mut ABSFormatter);
+
+    /// Writes the code to `out` with the default options, without building
+    /// it as a string first
+    fn write_abs(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        let mut f = ABSFormatter::new();
+        self.to_abs(&mut f);
+        f.write_abs(out)
+    }
 }
 
 impl<T> DisplayABS for &T
 where
-    T: DisplayABS + Clone,
+    T: DisplayABS,
 {
     fn to_abs(&self, f: &mut ABSFormatter) {
-        self.clone().to_abs(f)
+        (*self).to_abs(f)
     }
 }
 
@@ -40,7 +51,8 @@ macro_rules! add_fmt {
     };
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Module {
     pub name: Ident,
     pub children: Vec<ModuleItem>,
@@ -54,23 +66,60 @@ impl fmt::Display for Module {
     }
 }
 
+/// What goes in front of `item`: a line break between imports and exports,
+/// the separator between members otherwise
+fn item_sep(prev: Option<&ModuleItem>, item: &ModuleItem, f: &mut ABSFormatter) {
+    match (prev, item) {
+        (
+            Some(ModuleItem::Import(_)) | Some(ModuleItem::Export(_)),
+            ModuleItem::Import(_) | ModuleItem::Export(_),
+        ) => f.new_line(),
+        _ => f.member_sep(),
+    }
+}
+
 impl DisplayABS for Module {
     fn to_abs(&self, f: &mut ABSFormatter) {
         add_fmt!(f, "module {};", self.name);
-        f.new_line();
-        f.new_line();
 
+        let mut prev: Option<&ModuleItem> = None;
         for c in &self.children {
+            item_sep(prev, c, f);
             c.to_abs(f);
-            f.new_line();
-            f.new_line()
+            prev = Some(c);
         }
+        f.new_line();
+    }
+
+    /// Writes one item at a time, so that only the item being written is
+    /// held as a document. Items start on lines of their own, so this is the
+    /// same as writing the whole module at once.
+    fn write_abs(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        let mut f = ABSFormatter::new();
+        add_fmt!(f, "module {};", self.name);
+        f.write_abs(out)?;
+
+        let mut prev: Option<&ModuleItem> = None;
+        for c in &self.children {
+            let mut f = ABSFormatter::new();
+            item_sep(prev, c, &mut f);
+            c.to_abs(&mut f);
+            f.write_abs(out)?;
+            prev = Some(c);
+        }
+        out.write_all(b"\n")
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ModuleItem {
+    Import(ImportDecl),
+    Export(ExportDecl),
     DataTypeDecl(DataTypeDecl),
+    TypeSynDecl(TypeSynDecl),
+    ExceptionDecl(ExceptionDecl),
+    FunctionDecl(FunctionDecl),
     InterfaceDecl(InterfaceDecl),
     ClassDecl(ClassDecl),
     MainBlock(Block),
@@ -87,14 +136,49 @@ impl fmt::Display for ModuleItem {
 impl DisplayABS for ModuleItem {
     fn to_abs(&self, f: &mut ABSFormatter) {
         match self {
+            ModuleItem::Import(i) => i.to_abs(f),
+            ModuleItem::Export(e) => e.to_abs(f),
             ModuleItem::InterfaceDecl(i) => i.to_abs(f),
             ModuleItem::ClassDecl(c) => c.to_abs(f),
             ModuleItem::MainBlock(b) => b.to_abs(f),
             ModuleItem::DataTypeDecl(d) => d.to_abs(f),
+            ModuleItem::TypeSynDecl(t) => t.to_abs(f),
+            ModuleItem::ExceptionDecl(e) => e.to_abs(f),
+            ModuleItem::FunctionDecl(d) => d.to_abs(f),
         }
     }
 }
 
+impl From<ImportDecl> for ModuleItem {
+    fn from(i: ImportDecl) -> Self {
+        ModuleItem::Import(i)
+    }
+}
+
+impl From<ExportDecl> for ModuleItem {
+    fn from(e: ExportDecl) -> Self {
+        ModuleItem::Export(e)
+    }
+}
+
+impl From<TypeSynDecl> for ModuleItem {
+    fn from(t: TypeSynDecl) -> Self {
+        ModuleItem::TypeSynDecl(t)
+    }
+}
+
+impl From<ExceptionDecl> for ModuleItem {
+    fn from(e: ExceptionDecl) -> Self {
+        ModuleItem::ExceptionDecl(e)
+    }
+}
+
+impl From<FunctionDecl> for ModuleItem {
+    fn from(d: FunctionDecl) -> Self {
+        ModuleItem::FunctionDecl(d)
+    }
+}
+
 impl From<DataTypeDecl> for ModuleItem {
     fn from(d: DataTypeDecl) -> Self {
         ModuleItem::DataTypeDecl(d)
@@ -119,7 +203,69 @@ impl From<Block> for ModuleItem {
     }
 }
 
-#[derive(Clone)]
+/// `import * from M;`, `import A, B from M;` or `import M.A;`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportDecl {
+    pub star: bool,
+    pub idents: Vec<Ident>,
+    pub from: Option<Ident>,
+}
+
+impl fmt::Display for ImportDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut af = ABSFormatter::new();
+        self.to_abs(&mut af);
+        fmt::Display::fmt(&af.abs_code(), f)
+    }
+}
+
+impl DisplayABS for ImportDecl {
+    fn to_abs(&self, f: &mut ABSFormatter) {
+        f.add("import ");
+        import_export_to_abs(self.star, &self.idents, &self.from, f)
+    }
+}
+
+/// `export *;`, `export A, B;` or `export * from M;`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportDecl {
+    pub star: bool,
+    pub idents: Vec<Ident>,
+    pub from: Option<Ident>,
+}
+
+impl fmt::Display for ExportDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut af = ABSFormatter::new();
+        self.to_abs(&mut af);
+        fmt::Display::fmt(&af.abs_code(), f)
+    }
+}
+
+impl DisplayABS for ExportDecl {
+    fn to_abs(&self, f: &mut ABSFormatter) {
+        f.add("export ");
+        import_export_to_abs(self.star, &self.idents, &self.from, f)
+    }
+}
+
+fn import_export_to_abs(star: bool, idents: &[Ident], from: &Option<Ident>, f: &mut ABSFormatter) {
+    if star {
+        f.add("*");
+    } else {
+        f.list(idents.iter(), ", ");
+    }
+    if let Some(m) = from {
+        f.add(" from ");
+        m.to_abs(f);
+    }
+    f.add(";")
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataTypeDecl {
     pub ident: Ident,
     pub params: Vec<Ident>,
@@ -142,14 +288,27 @@ impl DisplayABS for DataTypeDecl {
             f.angle_bracketed(|f| f.list(self.params.iter(), ", "));
         }
         if !self.constr.is_empty() {
-            f.add(" = ");
-            f.list(self.constr.iter(), " | ");
+            f.group(|f| {
+                f.add(" =");
+                f.nest(|f| {
+                    for (i, c) in self.constr.iter().enumerate() {
+                        if i > 0 {
+                            f.soft_line();
+                            f.add("| ");
+                        } else {
+                            f.add(" ");
+                        }
+                        c.to_abs(f);
+                    }
+                })
+            });
         }
         f.add(";")
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataConstr {
     pub ident: Ident,
     pub params: Vec<DataConstrParam>,
@@ -167,12 +326,13 @@ impl DisplayABS for DataConstr {
     fn to_abs(&self, f: &mut ABSFormatter) {
         self.ident.to_abs(f);
         if !self.params.is_empty() {
-            f.parenthesized(|f| f.list(self.params.iter(), ", "));
+            f.args(self.params.iter());
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataConstrParam {
     pub ty: Type,
     pub ident: Option<Ident>,
@@ -197,7 +357,121 @@ impl DisplayABS for DataConstrParam {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypeSynDecl {
+    pub ident: Ident,
+    pub ty: Type,
+}
+
+impl fmt::Display for TypeSynDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut af = ABSFormatter::new();
+        self.to_abs(&mut af);
+        fmt::Display::fmt(&af.abs_code(), f)
+    }
+}
+
+impl DisplayABS for TypeSynDecl {
+    fn to_abs(&self, f: &mut ABSFormatter) {
+        f.add("type ");
+        self.ident.to_abs(f);
+        f.add(" = ");
+        self.ty.to_abs(f);
+        f.add(";")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExceptionDecl {
+    pub ident: Ident,
+    pub params: Vec<DataConstrParam>,
+}
+
+impl fmt::Display for ExceptionDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut af = ABSFormatter::new();
+        self.to_abs(&mut af);
+        fmt::Display::fmt(&af.abs_code(), f)
+    }
+}
+
+impl DisplayABS for ExceptionDecl {
+    fn to_abs(&self, f: &mut ABSFormatter) {
+        f.add("exception ");
+        self.ident.to_abs(f);
+        if !self.params.is_empty() {
+            f.args(self.params.iter());
+        }
+        f.add(";")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionDecl {
+    pub annotations: Annotations,
+    pub ret: Type,
+    pub ident: Ident,
+    pub type_params: Vec<Ident>,
+    pub params: Vec<Param>,
+    pub body: FunctionBody,
+}
+
+impl fmt::Display for FunctionDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut af = ABSFormatter::new();
+        self.to_abs(&mut af);
+        fmt::Display::fmt(&af.abs_code(), f)
+    }
+}
+
+impl DisplayABS for FunctionDecl {
+    fn to_abs(&self, f: &mut ABSFormatter) {
+        self.annotations.to_abs_lines(f);
+        f.add("def ");
+        self.ret.to_abs(f);
+        f.add(" ");
+        self.ident.to_abs(f);
+        if !self.type_params.is_empty() {
+            f.angle_bracketed(|f| f.list(self.type_params.iter(), ", "));
+        }
+        f.args(self.params.iter());
+        f.add(" =");
+        f.group(|f| {
+            f.nest(|f| {
+                f.soft_line();
+                self.body.to_abs(f);
+            })
+        });
+        f.add(";")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FunctionBody {
+    Builtin(Vec<PureExpr>),
+    Expr(PureExpr),
+}
+
+impl DisplayABS for FunctionBody {
+    fn to_abs(&self, f: &mut ABSFormatter) {
+        match self {
+            FunctionBody::Builtin(args) => {
+                f.add("builtin");
+                if !args.is_empty() {
+                    f.args(args.iter());
+                }
+            }
+            FunctionBody::Expr(e) => e.to_abs(f),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InterfaceDecl {
     pub ident: Ident,
     pub extends: Vec<Ident>,
@@ -216,19 +490,16 @@ impl DisplayABS for InterfaceDecl {
     fn to_abs(&self, f: &mut ABSFormatter) {
         f.add("interface ");
         self.ident.to_abs(f);
-        f.add(" ");
         if !self.extends.is_empty() {
-            f.add("extends ");
+            f.add(" extends ");
             f.list(self.extends.iter(), ", ");
-            f.add(" ");
         }
         f.braced(|f| {
             f.list_fn(
                 self.sigs.iter(),
                 |i, f| {
                     if i > 0 {
-                        f.new_line();
-                        f.new_line()
+                        f.member_sep()
                     }
                 },
                 |_, f| f.add(";"),
@@ -237,7 +508,8 @@ impl DisplayABS for InterfaceDecl {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClassDecl {
     pub annotations: Annotations,
     pub ident: Ident,
@@ -259,31 +531,46 @@ impl fmt::Display for ClassDecl {
 
 impl DisplayABS for ClassDecl {
     fn to_abs(&self, f: &mut ABSFormatter) {
-        self.annotations.to_abs(f);
+        self.annotations.to_abs_lines(f);
         f.add("class ");
         self.ident.to_abs(f);
         if !self.params.is_empty() {
-            f.parenthesized(|f| f.list(self.params.iter(), ", "));
+            f.args(self.params.iter());
         }
         if !self.implements.is_empty() {
             f.add(" implements ");
             f.list(self.implements.iter(), ", ");
         }
-        f.add(" ");
         f.braced(|f| {
-            f.list_fn(self.fields.iter(), |_, _| {}, |_, f| f.new_line());
-
-            if !self.fields.is_empty() && !self.methods.is_empty() {
-                f.new_line();
+            let mut sections = 0;
+            let mut section = |f: &mut ABSFormatter| {
+                if sections > 0 {
+                    f.member_sep();
+                }
+                sections += 1;
+            };
+
+            if !self.fields.is_empty() {
+                section(f);
+                f.list_fn(
+                    self.fields.iter(),
+                    |i, f| {
+                        if i > 0 {
+                            f.new_line()
+                        }
+                    },
+                    |_, _| {},
+                );
             }
 
             if let Some(init) = &self.init {
+                section(f);
                 init.to_abs(f);
-                f.new_line();
             }
 
             if !self.recover.is_empty() {
-                f.add("recover ");
+                section(f);
+                f.add("recover");
                 f.braced(|f| {
                     f.list_fn(
                         self.recover.iter(),
@@ -292,26 +579,21 @@ impl DisplayABS for ClassDecl {
                                 f.new_line()
                             }
                         },
-                        |_, f| f.add(";"),
+                        |_, _| {},
                     )
                 })
             }
 
-            f.list_fn(
-                self.methods.iter(),
-                |i, f| {
-                    if i > 0 {
-                        f.new_line();
-                        f.new_line();
-                    }
-                },
-                |_, _| {},
-            )
+            for m in &self.methods {
+                section(f);
+                m.to_abs(f);
+            }
         });
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MethodSig {
     pub annotations: Annotations,
     pub ret: Type,
@@ -329,15 +611,16 @@ impl fmt::Display for MethodSig {
 
 impl DisplayABS for MethodSig {
     fn to_abs(&self, f: &mut ABSFormatter) {
-        self.annotations.to_abs(f);
+        self.annotations.to_abs_lines(f);
         self.ret.to_abs(f);
         f.add(" ");
         self.ident.to_abs(f);
-        f.parenthesized(|f| f.list(self.params.iter(), ", "))
+        f.args(self.params.iter())
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Param {
     pub annotations: Annotations,
     pub ty: Type,
@@ -361,7 +644,8 @@ impl DisplayABS for Param {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FieldDecl {
     pub annotations: Annotations,
     pub ty: Type,
@@ -391,7 +675,8 @@ impl DisplayABS for FieldDecl {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MethodDecl {
     pub sig: MethodSig,
     pub body: Block,
@@ -408,7 +693,6 @@ impl fmt::Display for MethodDecl {
 impl DisplayABS for MethodDecl {
     fn to_abs(&self, f: &mut ABSFormatter) {
         self.sig.to_abs(f);
-        f.add(" ");
         self.body.to_abs(f);
     }
 }
@@ -2,9 +2,10 @@ use std::fmt;
 
 use crate::fmt::ABSFormatter;
 
-use super::DisplayABS;
+use super::{DisplayABS, Ident, Literal};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CaseBranch<K> {
     pub pattern: Pattern,
     pub right: K,
@@ -32,8 +33,14 @@ where
     }
 }
 
-#[derive(Clone)]
-pub struct Pattern;
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern {
+    Wildcard,
+    Ident(Ident),
+    Literal(Literal),
+    Constr { ident: Ident, args: Vec<Pattern> },
+}
 
 impl fmt::Display for Pattern {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -45,6 +52,16 @@ impl fmt::Display for Pattern {
 
 impl DisplayABS for Pattern {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        todo!()
+        match self {
+            Pattern::Wildcard => f.add("_"),
+            Pattern::Ident(i) => i.to_abs(f),
+            Pattern::Literal(l) => l.to_abs(f),
+            Pattern::Constr { ident, args } => {
+                ident.to_abs(f);
+                if !args.is_empty() {
+                    f.args(args.iter());
+                }
+            }
+        }
     }
 }
@@ -4,7 +4,8 @@ use crate::fmt::ABSFormatter;
 
 use super::{Annotations, CaseBranch, DisplayABS, Expr, Guard, Ident, PureExpr, Type};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Stmt {
     Skip,
     VarDecl(VarDeclStmt),
@@ -23,6 +24,48 @@ pub enum Stmt {
     TryCatchFinally(TryCatchFinallyStmt),
 }
 
+impl Stmt {
+    /// The names of all kinds of statements, as returned by `kind`
+    pub const KINDS: [&'static str; 15] = [
+        "SkipStmt",
+        "VarDeclStmt",
+        "AssignStmt",
+        "ExprStmt",
+        "AssertStmt",
+        "AwaitStmt",
+        "SuspendStmt",
+        "ThrowStmt",
+        "ReturnStmt",
+        "Block",
+        "IfStmt",
+        "SwitchStmt",
+        "WhileStmt",
+        "ForeachStmt",
+        "TryCatchFinallyStmt",
+    ];
+
+    /// The name of the kind of statement, e.g. `IfStmt`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Stmt::Skip => "SkipStmt",
+            Stmt::VarDecl(_) => "VarDeclStmt",
+            Stmt::Assign(_) => "AssignStmt",
+            Stmt::Expr(_) => "ExprStmt",
+            Stmt::Assert(_) => "AssertStmt",
+            Stmt::Await(_) => "AwaitStmt",
+            Stmt::Suspend => "SuspendStmt",
+            Stmt::Throw(_) => "ThrowStmt",
+            Stmt::Return(_) => "ReturnStmt",
+            Stmt::Block(_) => "Block",
+            Stmt::If(_) => "IfStmt",
+            Stmt::Switch(_) => "SwitchStmt",
+            Stmt::While(_) => "WhileStmt",
+            Stmt::Foreach(_) => "ForeachStmt",
+            Stmt::TryCatchFinally(_) => "TryCatchFinallyStmt",
+        }
+    }
+}
+
 impl fmt::Display for Stmt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut af = ABSFormatter::new();
@@ -33,7 +76,7 @@ impl fmt::Display for Stmt {
 
 impl DisplayABS for Stmt {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        f.stmt(|f| match self {
+        match self {
             Stmt::Skip => f.add("skip;"),
             Stmt::VarDecl(s) => s.to_abs(f),
             Stmt::Assign(s) => s.to_abs(f),
@@ -49,7 +92,7 @@ impl DisplayABS for Stmt {
             Stmt::While(s) => s.to_abs(f),
             Stmt::Foreach(s) => s.to_abs(f),
             Stmt::TryCatchFinally(s) => s.to_abs(f),
-        })
+        }
     }
 }
 
@@ -95,7 +138,8 @@ impl From<ReturnStmt> for Stmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VarDeclStmt {
     pub annotations: Annotations,
     pub ty: Type,
@@ -125,7 +169,8 @@ impl DisplayABS for VarDeclStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssignStmt {
     pub this: bool,
     pub ident: Ident,
@@ -152,7 +197,8 @@ impl DisplayABS for AssignStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExprStmt {
     pub expr: Expr,
 }
@@ -172,7 +218,8 @@ impl DisplayABS for ExprStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssertStmt {
     pub condition: PureExpr,
 }
@@ -193,7 +240,8 @@ impl DisplayABS for AssertStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AwaitStmt {
     pub guard: Guard,
 }
@@ -214,7 +262,8 @@ impl DisplayABS for AwaitStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReturnStmt {
     pub expr: Expr,
 }
@@ -235,7 +284,8 @@ impl DisplayABS for ReturnStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ThrowStmt {
     pub expr: PureExpr,
 }
@@ -256,7 +306,8 @@ impl DisplayABS for ThrowStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IfStmt {
     pub condition: PureExpr,
     pub then: Box<Stmt>,
@@ -278,16 +329,24 @@ impl DisplayABS for IfStmt {
         f.add(" ");
         self.then.to_abs(f);
         if let Some(e) = &self.r#else {
-            f.add(" else ");
+            if let Stmt::Block(_) = *self.then {
+                f.after_brace();
+            } else {
+                f.add(" ");
+            }
+            f.add("else ");
             e.to_abs(f);
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SwitchStmt {
     pub expr: PureExpr,
     pub branches: Vec<CaseBranch<Stmt>>,
+    /// Written as the older `case e { ... }` rather than `switch (e) { ... }`
+    pub case: bool,
 }
 
 impl fmt::Display for SwitchStmt {
@@ -300,9 +359,13 @@ impl fmt::Display for SwitchStmt {
 
 impl DisplayABS for SwitchStmt {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        f.add("switch ");
-        self.expr.to_abs(f);
-        f.add(" ");
+        if self.case {
+            f.add("case ");
+            self.expr.to_abs(f);
+        } else {
+            f.add("switch ");
+            f.parenthesized(|f| self.expr.to_abs(f));
+        }
         f.braced(|f| {
             f.list_fn(
                 self.branches.iter(),
@@ -317,7 +380,8 @@ impl DisplayABS for SwitchStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
     pub stmts: Vec<Stmt>,
 }
@@ -332,7 +396,6 @@ impl fmt::Display for Block {
 
 impl DisplayABS for Block {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
-        f.block();
         f.braced(|f| {
             f.list_fn(
                 self.stmts.iter(),
@@ -347,8 +410,11 @@ impl DisplayABS for Block {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WhileStmt {
+    /// Loop invariants, like Crowbar's `[Spec: WhileInv(..)]`
+    pub annotations: Annotations,
     pub condition: PureExpr,
     pub body: Box<Stmt>,
 }
@@ -363,6 +429,7 @@ impl fmt::Display for WhileStmt {
 
 impl DisplayABS for WhileStmt {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
+        self.annotations.to_abs(f);
         f.add("while ");
         f.parenthesized(|f| self.condition.to_abs(f));
         f.add(" ");
@@ -370,7 +437,8 @@ impl DisplayABS for WhileStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ForeachStmt {
     pub loop_var: Ident,
     pub iter: PureExpr,
@@ -398,7 +466,8 @@ impl DisplayABS for ForeachStmt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TryCatchFinallyStmt {
     pub r#try: Box<Stmt>,
     pub catch_branches: Vec<CaseBranch<Stmt>>,
@@ -417,7 +486,12 @@ impl DisplayABS for TryCatchFinallyStmt {
     fn to_abs(&self, f: &mut crate::fmt::ABSFormatter) {
         f.add("try ");
         self.r#try.to_abs(f);
-        f.add(" catch ");
+        if let Stmt::Block(_) = *self.r#try {
+            f.after_brace();
+        } else {
+            f.add(" ");
+        }
+        f.add("catch");
         f.braced(|f| {
             f.list_fn(
                 self.catch_branches.iter(),
@@ -430,7 +504,8 @@ impl DisplayABS for TryCatchFinallyStmt {
             )
         });
         if let Some(finally) = &self.finally {
-            f.add(" finally ");
+            f.after_brace();
+            f.add("finally ");
             finally.to_abs(f);
         }
     }
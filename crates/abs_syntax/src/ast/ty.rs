@@ -1,9 +1,10 @@
-use rand::seq::SliceRandom;
+use rand::{seq::SliceRandom, Rng};
 use std::fmt;
 
 use super::*;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Type {
     pub ident: Ident,
     pub args: Vec<Type>,
@@ -30,16 +31,13 @@ impl Type {
         self.is_int() || self.is_bool()
     }
 
-    pub fn get_lit(&self) -> Option<Literal> {
+    pub fn get_lit<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Literal> {
         if self.is_int() {
-            let n = (rand::random::<f64>() * 1000.0) as i32 - 500;
+            let n = (rng.gen::<f64>() * 1000.0) as i32 - 500;
             Some(Literal { s: n.to_string() })
         } else if self.is_bool() {
             Some(Literal {
-                s: ["True", "False"]
-                    .choose(&mut rand::thread_rng())
-                    .unwrap()
-                    .to_string(),
+                s: ["True", "False"].choose(rng).unwrap().to_string(),
             })
         } else {
             None
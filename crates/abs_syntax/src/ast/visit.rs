@@ -0,0 +1,246 @@
+//! Walking a tree mutably, e.g. to rewrite parts of it.
+//!
+//! Every `visit_` method walks the children of its node by default. An
+//! implementation overrides the methods of the nodes it is interested in and
+//! calls the matching `walk_` function to go on below them. Annotations,
+//! types and patterns are not walked.
+
+use super::*;
+
+pub trait VisitMut {
+    fn visit_module(&mut self, m: &mut Module) {
+        walk_module(self, m)
+    }
+
+    fn visit_item(&mut self, i: &mut ModuleItem) {
+        walk_item(self, i)
+    }
+
+    fn visit_function(&mut self, f: &mut FunctionDecl) {
+        walk_function(self, f)
+    }
+
+    fn visit_interface(&mut self, _i: &mut InterfaceDecl) {}
+
+    fn visit_class(&mut self, c: &mut ClassDecl) {
+        walk_class(self, c)
+    }
+
+    fn visit_method(&mut self, m: &mut MethodDecl) {
+        walk_method(self, m)
+    }
+
+    fn visit_block(&mut self, b: &mut Block) {
+        walk_block(self, b)
+    }
+
+    fn visit_stmt(&mut self, s: &mut Stmt) {
+        walk_stmt(self, s)
+    }
+
+    fn visit_expr(&mut self, e: &mut Expr) {
+        walk_expr(self, e)
+    }
+
+    fn visit_pure_expr(&mut self, e: &mut PureExpr) {
+        walk_pure_expr(self, e)
+    }
+
+    fn visit_eff_expr(&mut self, e: &mut EffExpr) {
+        walk_eff_expr(self, e)
+    }
+}
+
+pub fn walk_module<V: VisitMut + ?Sized>(v: &mut V, m: &mut Module) {
+    for c in &mut m.children {
+        v.visit_item(c);
+    }
+}
+
+pub fn walk_item<V: VisitMut + ?Sized>(v: &mut V, i: &mut ModuleItem) {
+    match i {
+        ModuleItem::FunctionDecl(f) => v.visit_function(f),
+        ModuleItem::InterfaceDecl(i) => v.visit_interface(i),
+        ModuleItem::ClassDecl(c) => v.visit_class(c),
+        ModuleItem::MainBlock(b) => v.visit_block(b),
+        ModuleItem::Import(_)
+        | ModuleItem::Export(_)
+        | ModuleItem::DataTypeDecl(_)
+        | ModuleItem::TypeSynDecl(_)
+        | ModuleItem::ExceptionDecl(_) => {}
+    }
+}
+
+pub fn walk_function<V: VisitMut + ?Sized>(v: &mut V, f: &mut FunctionDecl) {
+    match &mut f.body {
+        FunctionBody::Builtin(args) => args.iter_mut().for_each(|a| v.visit_pure_expr(a)),
+        FunctionBody::Expr(e) => v.visit_pure_expr(e),
+    }
+}
+
+pub fn walk_class<V: VisitMut + ?Sized>(v: &mut V, c: &mut ClassDecl) {
+    for f in &mut c.fields {
+        if let Some(e) = &mut f.init {
+            v.visit_pure_expr(e);
+        }
+    }
+    if let Some(b) = &mut c.init {
+        v.visit_block(b);
+    }
+    for b in &mut c.recover {
+        v.visit_stmt(&mut b.right);
+    }
+    for m in &mut c.methods {
+        v.visit_method(m);
+    }
+}
+
+pub fn walk_method<V: VisitMut + ?Sized>(v: &mut V, m: &mut MethodDecl) {
+    v.visit_block(&mut m.body)
+}
+
+pub fn walk_block<V: VisitMut + ?Sized>(v: &mut V, b: &mut Block) {
+    for s in &mut b.stmts {
+        v.visit_stmt(s);
+    }
+}
+
+pub fn walk_stmt<V: VisitMut + ?Sized>(v: &mut V, s: &mut Stmt) {
+    match s {
+        Stmt::VarDecl(d) => {
+            if let Some(e) = &mut d.init {
+                v.visit_expr(e);
+            }
+        }
+        Stmt::Assign(a) => v.visit_expr(&mut a.expr),
+        Stmt::Expr(e) => v.visit_expr(&mut e.expr),
+        Stmt::Assert(a) => v.visit_pure_expr(&mut a.condition),
+        Stmt::Await(a) => walk_guard(v, &mut a.guard),
+        Stmt::Throw(t) => v.visit_pure_expr(&mut t.expr),
+        Stmt::Return(r) => v.visit_expr(&mut r.expr),
+        Stmt::Block(b) => v.visit_block(b),
+        Stmt::If(i) => {
+            v.visit_pure_expr(&mut i.condition);
+            v.visit_stmt(&mut i.then);
+            if let Some(e) = &mut i.r#else {
+                v.visit_stmt(e);
+            }
+        }
+        Stmt::Switch(s) => {
+            v.visit_pure_expr(&mut s.expr);
+            for b in &mut s.branches {
+                v.visit_stmt(&mut b.right);
+            }
+        }
+        Stmt::While(w) => {
+            v.visit_pure_expr(&mut w.condition);
+            v.visit_stmt(&mut w.body);
+        }
+        Stmt::Foreach(f) => {
+            v.visit_pure_expr(&mut f.iter);
+            v.visit_stmt(&mut f.body);
+        }
+        Stmt::TryCatchFinally(t) => {
+            v.visit_stmt(&mut t.r#try);
+            for b in &mut t.catch_branches {
+                v.visit_stmt(&mut b.right);
+            }
+            if let Some(f) = &mut t.finally {
+                v.visit_stmt(f);
+            }
+        }
+        Stmt::Skip | Stmt::Suspend => {}
+    }
+}
+
+fn walk_guard<V: VisitMut + ?Sized>(v: &mut V, g: &mut Guard) {
+    match g {
+        Guard::Expr(e) => v.visit_pure_expr(e),
+        Guard::And(a, b) => {
+            walk_guard(v, a);
+            walk_guard(v, b);
+        }
+        Guard::Duration(min, max) => {
+            v.visit_pure_expr(min);
+            v.visit_pure_expr(max);
+        }
+        Guard::Claim { .. } => {}
+    }
+}
+
+pub fn walk_expr<V: VisitMut + ?Sized>(v: &mut V, e: &mut Expr) {
+    match e {
+        Expr::Pure(e) => v.visit_pure_expr(e),
+        Expr::Eff(e) => v.visit_eff_expr(e),
+    }
+}
+
+pub fn walk_pure_expr<V: VisitMut + ?Sized>(v: &mut V, e: &mut PureExpr) {
+    match e {
+        PureExpr::Let(l) => {
+            v.visit_pure_expr(&mut l.value);
+            v.visit_pure_expr(&mut l.inner);
+        }
+        PureExpr::DataConstr(d) => d.args.iter_mut().for_each(|a| v.visit_pure_expr(a)),
+        PureExpr::FnApp(f) | PureExpr::VariadicFnApp(f) => {
+            f.args.iter_mut().for_each(|a| v.visit_pure_expr(a))
+        }
+        PureExpr::If(i) => {
+            v.visit_pure_expr(&mut i.condition);
+            v.visit_pure_expr(&mut i.then);
+            v.visit_pure_expr(&mut i.r#else);
+        }
+        PureExpr::When(w) => {
+            v.visit_pure_expr(&mut w.condition);
+            v.visit_pure_expr(&mut w.then);
+            v.visit_pure_expr(&mut w.r#else);
+        }
+        PureExpr::Case(c) => {
+            v.visit_pure_expr(&mut c.expr);
+            for b in &mut c.branches {
+                v.visit_pure_expr(&mut b.right);
+            }
+        }
+        PureExpr::Operator(OperatorExpr::Unary(u)) => v.visit_pure_expr(&mut u.expr),
+        PureExpr::Operator(OperatorExpr::Binary(b)) => {
+            v.visit_pure_expr(&mut b.left);
+            v.visit_pure_expr(&mut b.right);
+        }
+        PureExpr::TypeCheck(t) => v.visit_pure_expr(&mut t.expr),
+        PureExpr::TypeCast(t) => v.visit_pure_expr(&mut t.expr),
+        PureExpr::TemplateString(t) => t.exprs.iter_mut().for_each(|e| v.visit_pure_expr(e)),
+        PureExpr::Ident(_)
+        | PureExpr::ThisIdent(_)
+        | PureExpr::This
+        | PureExpr::Null
+        | PureExpr::Literal(_)
+        | PureExpr::Destiny => {}
+        PureExpr::ParFnApp(p) => {
+            for func in &mut p.funcs {
+                if let FnArg::Anon(a) = func {
+                    v.visit_pure_expr(&mut a.body);
+                }
+            }
+            p.args.iter_mut().for_each(|a| v.visit_pure_expr(a));
+        }
+    }
+}
+
+pub fn walk_eff_expr<V: VisitMut + ?Sized>(v: &mut V, e: &mut EffExpr) {
+    match e {
+        EffExpr::New(n) => n.args.iter_mut().for_each(|a| v.visit_pure_expr(a)),
+        EffExpr::SyncCall(c) => {
+            v.visit_pure_expr(&mut c.callee);
+            c.args.iter_mut().for_each(|a| v.visit_pure_expr(a));
+        }
+        EffExpr::AsyncCall(c) => {
+            v.visit_pure_expr(&mut c.callee);
+            c.args.iter_mut().for_each(|a| v.visit_pure_expr(a));
+        }
+        EffExpr::Get(g) => v.visit_pure_expr(&mut g.expr),
+        EffExpr::Await(a) => {
+            v.visit_pure_expr(&mut a.call.callee);
+            a.call.args.iter_mut().for_each(|a| v.visit_pure_expr(a));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Saving syntax trees and loading them back, as JSON or in a compact binary
+//! form (bincode).
+//!
+//! Every node of the tree can be saved this way, not just whole modules.
+//! Spans of identifiers and annotations are kept, so a loaded tree equals the
+//! saved one even when compared with `{:?}`.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+pub use bincode::Error as BinaryError;
+pub use serde_json::Error as JsonError;
+
+pub fn to_json<T: Serialize + ?Sized>(tree: &T) -> Result<String, JsonError> {
+    serde_json::to_string(tree)
+}
+
+pub fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, JsonError> {
+    serde_json::from_str(json)
+}
+
+pub fn to_binary<T: Serialize + ?Sized>(tree: &T) -> Result<Vec<u8>, BinaryError> {
+    bincode::serialize(tree)
+}
+
+pub fn from_binary<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BinaryError> {
+    bincode::deserialize(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast, parse::parse_modules};
+
+    const SRC: &str = "module M;
+data D = A(Int) | B;
+[Near] class C(Int p) {
+    Int m(D d) {
+        Int y = case d { A(x) => x + p; B => 0; };
+        return y;
+    }
+}
+{ C c = new C(1); Int r = c.m(A(2)); }
+";
+
+    /// Spans are not compared by `==`, but they are part of `{:?}`
+    fn assert_same<T: PartialEq + std::fmt::Debug>(a: &T, b: &T) {
+        assert_eq!(a, b);
+        assert_eq!(format!("{:?}", a), format!("{:?}", b));
+    }
+
+    #[test]
+    fn json_round_trip_keeps_spans() {
+        let m = parse_modules(SRC).unwrap().remove(0);
+        assert!(format!("{:?}", m).contains("span: Some(Span"));
+        let loaded: ast::Module = from_json(&to_json(&m).unwrap()).unwrap();
+        assert_same(&m, &loaded);
+    }
+
+    #[test]
+    fn binary_round_trip_keeps_spans() {
+        let m = parse_modules(SRC).unwrap().remove(0);
+        let loaded: ast::Module = from_binary(&to_binary(&m).unwrap()).unwrap();
+        assert_same(&m, &loaded);
+
+        // Parts of a tree can be saved on their own
+        let class = &m.children[1];
+        let loaded: ast::ModuleItem = from_binary(&to_binary(class).unwrap()).unwrap();
+        assert_same(class, &loaded);
+    }
+}
@@ -0,0 +1,367 @@
+use std::io;
+
+use super::{BraceStyle, FormatOptions, IndentStyle};
+
+/// A document in the sense of Wadler's "A prettier printer".
+///
+/// Documents are built by `ABSFormatter` and only turned into text once the
+/// whole tree is known, so the printer can decide for every group whether it
+/// fits on the current line.
+#[derive(Clone, Debug)]
+pub(crate) enum Doc {
+    Text(String),
+    Line(Line),
+    Nest(Vec<Doc>),
+    Group(Vec<Doc>),
+    /// What goes between code and an opening brace, or between a closing
+    /// brace and more code, according to the `BraceStyle`. Dropped at the
+    /// start of a line.
+    BraceSep,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Line {
+    /// Always a line break
+    Hard,
+    /// A space if the enclosing group fits, a line break otherwise
+    Soft,
+    /// Nothing if the enclosing group fits, a line break otherwise
+    SoftBreak,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+type Cmd<'a> = (usize, Mode, &'a Doc);
+
+pub(crate) fn render(docs: &[Doc], opts: &FormatOptions) -> String {
+    let mut out = Vec::new();
+    render_to(docs, opts, &mut out).expect("Writing to a Vec cannot fail");
+    String::from_utf8(out).expect("The documents are made of strings")
+}
+
+/// Like `render`, but writes every line to `out` as soon as it is complete
+pub(crate) fn render_to(
+    docs: &[Doc],
+    opts: &FormatOptions,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let mut printer = Printer {
+        opts,
+        out,
+        line: String::new(),
+        started: false,
+        col: 0,
+        pending_indent: None,
+    };
+
+    let mut stack: Vec<Cmd> = docs.iter().rev().map(|d| (0, Mode::Break, d)).collect();
+
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => printer.text(s),
+            Doc::Line(l) => match (mode, l) {
+                (Mode::Flat, Line::Soft) => printer.text(" "),
+                (Mode::Flat, Line::SoftBreak) => {}
+                _ => printer.line(indent)?,
+            },
+            Doc::BraceSep => {
+                if !printer.at_line_start() {
+                    match opts.brace_style {
+                        BraceStyle::SameLine if printer.line.ends_with(' ') => {}
+                        BraceStyle::SameLine => printer.text(" "),
+                        BraceStyle::NextLine => printer.line(indent)?,
+                    }
+                }
+            }
+            Doc::Nest(ds) => stack.extend(ds.iter().rev().map(|d| (indent + 1, mode, d))),
+            Doc::Group(ds) => {
+                let mode = if mode == Mode::Flat {
+                    Mode::Flat
+                } else {
+                    let rem = opts.max_width as isize - printer.col as isize;
+                    let group = ds.iter().rev().map(|d| (indent, Mode::Flat, d)).collect();
+                    if fits(rem, group, &stack, opts.brace_style) {
+                        Mode::Flat
+                    } else {
+                        Mode::Break
+                    }
+                };
+                stack.extend(ds.iter().rev().map(|d| (indent, mode, d)))
+            }
+        }
+    }
+
+    printer.out.write_all(printer.line.as_bytes())
+}
+
+/// Renders the documents on one line, however long. A line break is left
+/// out where a group that fits leaves it out and is a space otherwise.
+pub(crate) fn render_flat(docs: &[Doc]) -> String {
+    let mut out = String::new();
+    let mut stack: Vec<&Doc> = docs.iter().rev().collect();
+
+    while let Some(doc) = stack.pop() {
+        match doc {
+            Doc::Text(s) => out.push_str(s),
+            Doc::Line(Line::SoftBreak) => {}
+            Doc::Line(_) | Doc::BraceSep => {
+                if !out.is_empty() && !out.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+            Doc::Nest(ds) | Doc::Group(ds) => stack.extend(ds.iter().rev()),
+        }
+    }
+
+    out.truncate(out.trim_end().len());
+    out
+}
+
+/// Checks whether `group` fits in `rem` columns, taking into account the text
+/// that follows it up to the next possible line break.
+fn fits<'a>(mut rem: isize, mut group: Vec<Cmd<'a>>, rest: &[Cmd<'a>], braces: BraceStyle) -> bool {
+    let mut rest = rest.iter().rev();
+
+    loop {
+        if rem < 0 {
+            return false;
+        }
+
+        let (indent, mode, doc) = match group.pop() {
+            Some(c) => c,
+            None => match rest.next() {
+                Some(c) => *c,
+                None => return true,
+            },
+        };
+
+        match doc {
+            Doc::Text(s) => rem -= s.chars().count() as isize,
+            Doc::Line(l) => match (mode, l) {
+                (Mode::Flat, Line::Hard) => return false,
+                (Mode::Flat, Line::Soft) => rem -= 1,
+                (Mode::Flat, Line::SoftBreak) => {}
+                (Mode::Break, _) => return true,
+            },
+            Doc::BraceSep => match (mode, braces) {
+                (_, BraceStyle::SameLine) => rem -= 1,
+                (Mode::Flat, BraceStyle::NextLine) => return false,
+                (Mode::Break, BraceStyle::NextLine) => return true,
+            },
+            Doc::Nest(ds) => group.extend(ds.iter().rev().map(|d| (indent + 1, mode, d))),
+            Doc::Group(ds) => group.extend(ds.iter().rev().map(|d| (indent, mode, d))),
+        }
+    }
+}
+
+struct Printer<'a> {
+    opts: &'a FormatOptions,
+    out: &'a mut dyn io::Write,
+    /// The current line, written once it is complete
+    line: String,
+    /// Whether a line was written
+    started: bool,
+    col: usize,
+    /// Indentation is only written once text follows, so empty lines carry
+    /// no trailing whitespace.
+    pending_indent: Option<usize>,
+}
+
+impl Printer<'_> {
+    fn at_line_start(&self) -> bool {
+        (!self.started && self.line.is_empty()) || self.pending_indent.is_some()
+    }
+
+    fn text(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        if let Some(level) = self.pending_indent.take() {
+            self.line.push_str(&self.opts.indent_str(level));
+        }
+        self.line.push_str(s);
+        self.col += s.chars().count();
+    }
+
+    fn line(&mut self, indent: usize) -> io::Result<()> {
+        let trimmed = self.line.trim_end_matches(' ').len();
+        self.line.truncate(trimmed);
+        self.line.push('\n');
+        self.out.write_all(self.line.as_bytes())?;
+        self.line.clear();
+        self.started = true;
+        self.pending_indent = Some(indent);
+        self.col = indent * self.opts.indent_width();
+        Ok(())
+    }
+}
+
+impl FormatOptions {
+    pub(crate) fn indent_width(&self) -> usize {
+        match self.indent_style {
+            IndentStyle::Tabs => self.tab_width as usize,
+            IndentStyle::Spaces(n) => n as usize,
+        }
+    }
+
+    pub(crate) fn indent_str(&self, level: usize) -> String {
+        match self.indent_style {
+            IndentStyle::Tabs => "\t".repeat(level),
+            IndentStyle::Spaces(n) => " ".repeat(level * n as usize),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::DisplayABS, fmt::ABSFormatter, parse::parse_module};
+
+    const SRC: &str = "module M;
+interface I { Unit m(); Unit n(); }
+class C implements I { Unit m() { if (True) { skip; } else { skip; } } Unit n() { } }
+";
+
+    /// The module rendered with `opts`
+    fn module(opts: FormatOptions) -> String {
+        let mut f = ABSFormatter::with_options(opts);
+        parse_module(SRC).unwrap().to_abs(&mut f);
+        render(&f.frames[0], &opts)
+    }
+
+    /// `f(a, b, ...)` as built by `ABSFormatter::args`
+    fn call(args: &[&str]) -> Doc {
+        let mut list = Vec::new();
+        for (i, a) in args.iter().enumerate() {
+            if i > 0 {
+                list.push(Doc::Text(",".to_string()));
+                list.push(Doc::Line(Line::Soft));
+            } else {
+                list.push(Doc::Line(Line::SoftBreak));
+            }
+            list.push(Doc::Text(a.to_string()));
+        }
+        Doc::Group(vec![
+            Doc::Text("f(".to_string()),
+            Doc::Nest(list),
+            Doc::Line(Line::SoftBreak),
+            Doc::Text(")".to_string()),
+        ])
+    }
+
+    fn with_width(max_width: usize) -> FormatOptions {
+        FormatOptions {
+            max_width,
+            ..FormatOptions::default()
+        }
+    }
+
+    #[test]
+    fn groups_break_past_the_maximum_width() {
+        let docs = [
+            call(&["alpha", "beta", "gamma"]),
+            Doc::Text(";".to_string()),
+        ];
+        // The text after the group counts as well
+        assert_eq!(render(&docs, &with_width(22)), "f(alpha, beta, gamma);");
+        assert_eq!(
+            render(&docs, &with_width(21)),
+            "f(\n\talpha,\n\tbeta,\n\tgamma\n);"
+        );
+    }
+
+    #[test]
+    fn indentation_is_tabs_or_spaces() {
+        let docs = [Doc::Nest(vec![Doc::Line(Line::Hard), call(&["a", "b"])])];
+        let spaces = FormatOptions {
+            indent_style: IndentStyle::Spaces(2),
+            ..with_width(10)
+        };
+        assert_eq!(render(&docs, &spaces), "\n  f(a, b)");
+
+        // A tab counts as `tab_width` columns when measuring the line
+        let tabs = FormatOptions {
+            tab_width: 4,
+            ..with_width(10)
+        };
+        assert_eq!(render(&docs, &tabs), "\n\tf(\n\t\ta,\n\t\tb\n\t)");
+        assert_eq!(render(&docs, &with_width(11)), "\n\tf(a, b)");
+    }
+
+    #[test]
+    fn braces_on_the_same_line() {
+        assert_eq!(
+            module(FormatOptions::default()),
+            "module M;
+
+interface I {
+\tUnit m();
+
+\tUnit n();
+}
+
+class C implements I {
+\tUnit m() {
+\t\tif (True) {
+\t\t\tskip;
+\t\t} else {
+\t\t\tskip;
+\t\t}
+\t}
+
+\tUnit n() { }
+}
+"
+        );
+    }
+
+    #[test]
+    fn braces_on_the_next_line() {
+        let opts = FormatOptions {
+            brace_style: BraceStyle::NextLine,
+            indent_style: IndentStyle::Spaces(2),
+            blank_lines_between_members: 0,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            module(opts),
+            "module M;
+interface I
+{
+  Unit m();
+  Unit n();
+}
+class C implements I
+{
+  Unit m()
+  {
+    if (True)
+    {
+      skip;
+    }
+    else
+    {
+      skip;
+    }
+  }
+  Unit n()
+  { }
+}
+"
+        );
+    }
+
+    #[test]
+    fn members_are_separated_by_blank_lines() {
+        let opts = FormatOptions {
+            blank_lines_between_members: 2,
+            ..FormatOptions::default()
+        };
+        let interface = "module M;\n\n\ninterface I {\n\tUnit m();\n\n\n\tUnit n();\n}\n\n\n";
+        assert!(module(opts).starts_with(interface));
+    }
+}
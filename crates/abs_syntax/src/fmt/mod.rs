@@ -1,45 +1,146 @@
+use std::io;
+
 use crate::ast::DisplayABS;
 
+mod doc;
+mod source;
+
+use doc::{Doc, Line};
+pub use source::format_source;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces(u8),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BraceStyle {
+    /// `class C {`
+    SameLine,
+    /// The opening brace gets a line of its own
+    NextLine,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct FormatOptions {
+    pub indent_style: IndentStyle,
+    /// Columns a tab counts as when measuring line width
+    pub tab_width: u8,
+    pub max_width: usize,
+    pub brace_style: BraceStyle,
+    /// Empty lines between module items and between class or interface members
+    pub blank_lines_between_members: u8,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_style: IndentStyle::Tabs,
+            tab_width: 4,
+            max_width: 100,
+            brace_style: BraceStyle::SameLine,
+            blank_lines_between_members: 1,
+        }
+    }
+}
+
 pub struct ABSFormatter {
-    indent: u32,
-    out: String,
-    break_on_stmt: bool,
+    opts: FormatOptions,
+    /// The innermost open nest or group is last
+    frames: Vec<Vec<Doc>>,
 }
 
 impl ABSFormatter {
     pub fn new() -> Self {
+        Self::with_options(FormatOptions::default())
+    }
+
+    pub fn with_options(opts: FormatOptions) -> Self {
         Self {
-            indent: 0,
-            out: String::new(),
-            break_on_stmt: true,
+            opts,
+            frames: vec![Vec::new()],
         }
     }
 
-    pub fn add_indent(&mut self) {
-        self.indent += 1;
+    pub fn options(&self) -> &FormatOptions {
+        &self.opts
     }
 
-    pub fn sub_indent(&mut self) {
-        if self.indent == 0 {
-            panic!("Negative indentation!")
-        }
-        self.indent -= 1;
+    fn push(&mut self, d: Doc) {
+        self.frames.last_mut().unwrap().push(d)
     }
 
-    pub fn add(&mut self, s: &str) {
-        self.out.push_str(s)
+    fn frame<F>(&mut self, f: F) -> Vec<Doc>
+    where
+        F: FnOnce(&mut ABSFormatter),
+    {
+        self.frames.push(Vec::new());
+        f(self);
+        self.frames.pop().unwrap()
     }
 
-    pub fn indent_str(&self) -> String {
-        "\t".repeat(self.indent as usize)
+    pub fn add(&mut self, s: &str) {
+        self.push(Doc::Text(s.to_string()))
     }
 
     pub fn new_line(&mut self) {
-        self.add(&format!("\n{}", self.indent_str()))
+        self.push(Doc::Line(Line::Hard))
+    }
+
+    /// A space, or a line break if the enclosing group is too long
+    pub fn soft_line(&mut self) {
+        self.push(Doc::Line(Line::Soft))
+    }
+
+    /// Nothing, or a line break if the enclosing group is too long
+    pub fn soft_break(&mut self) {
+        self.push(Doc::Line(Line::SoftBreak))
+    }
+
+    pub fn member_sep(&mut self) {
+        self.new_line();
+        for _ in 0..self.opts.blank_lines_between_members {
+            self.new_line();
+        }
     }
 
     pub fn abs_code(self) -> String {
-        self.out
+        let mut frames = self.frames;
+        assert_eq!(frames.len(), 1, "Unclosed group or nest!");
+        doc::render(&frames.pop().unwrap(), &self.opts)
+    }
+
+    /// The code on one line, e.g. to quote it in a message
+    pub fn one_line_code(self) -> String {
+        let mut frames = self.frames;
+        assert_eq!(frames.len(), 1, "Unclosed group or nest!");
+        doc::render_flat(&frames.pop().unwrap())
+    }
+
+    /// Writes the code to `out` line by line instead of building a string
+    pub fn write_abs(self, out: &mut dyn io::Write) -> io::Result<()> {
+        let mut frames = self.frames;
+        assert_eq!(frames.len(), 1, "Unclosed group or nest!");
+        doc::render_to(&frames.pop().unwrap(), &self.opts, out)
+    }
+
+    /// Lines broken inside of `f` are indented one level deeper
+    pub fn nest<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut ABSFormatter),
+    {
+        let docs = self.frame(f);
+        self.push(Doc::Nest(docs))
+    }
+
+    /// Soft lines inside of `f` are either all kept flat or all broken
+    pub fn group<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut ABSFormatter),
+    {
+        let docs = self.frame(f);
+        self.push(Doc::Group(docs))
     }
 
     pub fn list<I, E>(&mut self, lst: I, sep: &str)
@@ -55,6 +156,27 @@ impl ABSFormatter {
         }
     }
 
+    /// A comma separated list that puts every element on its own line if
+    /// it does not fit on the current one
+    pub fn broken_list<I, E>(&mut self, lst: I)
+    where
+        I: Iterator<Item = E>,
+        E: DisplayABS,
+    {
+        self.nest(|f| {
+            for (i, e) in lst.enumerate() {
+                if i > 0 {
+                    f.add(",");
+                    f.soft_line();
+                } else {
+                    f.soft_break();
+                }
+                e.to_abs(f);
+            }
+        });
+        self.soft_break();
+    }
+
     pub fn list_fn<I, E, F1, F2>(&mut self, lst: I, mut before: F1, mut after: F2)
     where
         I: Iterator<Item = E>,
@@ -78,19 +200,47 @@ impl ABSFormatter {
         self.add(")");
     }
 
+    /// An argument list, e.g. `(a, b, c)`
+    pub fn args<I, E>(&mut self, lst: I)
+    where
+        I: Iterator<Item = E>,
+        E: DisplayABS,
+    {
+        self.group(|f| f.parenthesized(|f| f.broken_list(lst)))
+    }
+
+    /// A block in braces, including the separator in front of the opening
+    /// brace as configured by the `BraceStyle`
     pub fn braced<F>(&mut self, f: F)
     where
         F: FnOnce(&mut ABSFormatter),
     {
+        let body = self.frame(f);
+
+        self.push(Doc::BraceSep);
+
+        if body.is_empty() {
+            self.add("{ }");
+            return;
+        }
+
         self.add("{");
-        self.add_indent();
-        self.new_line();
-        f(self);
-        self.sub_indent();
+        self.nest(|f| {
+            f.new_line();
+            for d in body {
+                f.push(d)
+            }
+        });
         self.new_line();
         self.add("}");
     }
 
+    /// The separator after a closing brace that is followed by more code on
+    /// the same line, e.g. `} else {`
+    pub fn after_brace(&mut self) {
+        self.push(Doc::BraceSep)
+    }
+
     pub fn angle_bracketed<F>(&mut self, f: F)
     where
         F: FnOnce(&mut ABSFormatter),
@@ -108,19 +258,10 @@ impl ABSFormatter {
         f(self);
         self.add("]");
     }
+}
 
-    pub fn stmt<F>(&mut self, f: F)
-    where
-        F: FnOnce(&mut ABSFormatter),
-    {
-        f(self)
-    }
-
-    pub fn start_stmt(&mut self) {
-        if self.break_on_stmt {
-            self.new_line();
-        }
+impl Default for ABSFormatter {
+    fn default() -> Self {
+        Self::new()
     }
-
-    pub fn block(&mut self) {}
 }
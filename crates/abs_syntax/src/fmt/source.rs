@@ -0,0 +1,277 @@
+use crate::{
+    ast::DisplayABS,
+    parse::{parse_modules, tokenize, ParseError, Token, TokenKind},
+};
+
+use super::{ABSFormatter, FormatOptions};
+
+/// A comment of the original source, positioned relative to the code tokens
+/// around it
+struct Comment<'a> {
+    text: &'a str,
+    /// Number of code tokens in front of the comment
+    before: usize,
+    /// The parentheses between the code token in front of the comment and
+    /// the comment, which the printer may have kept or not
+    parens: String,
+    /// Whether the comment starts its own line, as opposed to trailing code
+    own_line: bool,
+    blank_before: bool,
+    blank_after: bool,
+}
+
+/// Formats ABS source code, keeping its comments.
+///
+/// The AST has no place for comments, so the source is printed from the AST
+/// and the comments are put back afterwards: a comment on a line of its own
+/// goes in front of the line holding the code that followed it, a comment
+/// behind code goes right behind the code token in front of it. If code
+/// follows a `//` comment on the printed line, the line is broken after the
+/// comment.
+pub fn format_source(src: &str, opts: &FormatOptions) -> Result<String, ParseError> {
+    let modules = parse_modules(src)?;
+
+    let mut printed = String::new();
+    for (i, m) in modules.iter().enumerate() {
+        if i > 0 {
+            printed.push('\n');
+        }
+        let mut f = ABSFormatter::with_options(*opts);
+        m.to_abs(&mut f);
+        printed.push_str(&f.abs_code());
+    }
+
+    let tokens = tokenize(src)?;
+    let comments = collect_comments(src, &tokens);
+    if comments.is_empty() {
+        return Ok(printed);
+    }
+
+    let code: Vec<_> = tokens.iter().filter(|t| is_code(t)).collect();
+    let printed_tokens = tokenize(&printed)?;
+    let printed_code: Vec<_> = printed_tokens.iter().filter(|t| is_code(t)).collect();
+
+    // Parentheses are the only tokens the printer adds or drops
+    let mismatch = code.len() != printed_code.len()
+        || code
+            .iter()
+            .zip(&printed_code)
+            .any(|(a, b)| a.text != b.text);
+    if mismatch {
+        return Err(ParseError::new(
+            src,
+            0,
+            "Cannot keep comments: the formatted code does not match the source",
+        ));
+    }
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(printed.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_of = |offset: usize| match line_starts.binary_search(&offset) {
+        Ok(l) => l,
+        Err(l) => l - 1,
+    };
+
+    let lines: Vec<&str> = printed.lines().collect();
+    let mut leading: Vec<Vec<&Comment>> = (0..=lines.len()).map(|_| Vec::new()).collect();
+    // Comments behind code, by line and where in the line they go
+    let mut trailing: Vec<Vec<(usize, &Comment)>> = (0..lines.len()).map(|_| Vec::new()).collect();
+
+    for c in &comments {
+        if !c.own_line && c.before > 0 {
+            let anchor = printed_code[c.before - 1];
+            let line = line_of(anchor.span.start);
+            let at = skip_parens(&printed, anchor.span.end, &c.parens) - line_starts[line];
+            trailing[line].push((at.min(lines[line].len()), c));
+        } else if c.before < printed_code.len() {
+            leading[line_of(printed_code[c.before].span.start)].push(c);
+        } else {
+            leading[lines.len()].push(c);
+        }
+    }
+
+    let mut out: Vec<String> = Vec::new();
+    for (i, line) in lines.iter().chain(std::iter::once(&"")).enumerate() {
+        let code_indent = &line[..line.len() - line.trim_start().len()];
+        let indent = if line.trim_start().starts_with('}') {
+            format!("{}{}", code_indent, opts.indent_str(1))
+        } else {
+            code_indent.to_string()
+        };
+
+        for c in &leading[i] {
+            let prev_blank = out.last().is_none_or(|l| l.is_empty());
+            if c.blank_before && !prev_blank {
+                out.push(String::new());
+            }
+            out.push(format!("{}{}", indent, c.text));
+            if c.blank_after {
+                out.push(String::new());
+            }
+        }
+
+        if i == lines.len() {
+            break;
+        }
+
+        let mut line = String::new();
+        let mut cut = 0;
+        for (at, c) in &trailing[i] {
+            line.push_str(&lines[i][cut..*at]);
+            cut = *at;
+            line.push(' ');
+            line.push_str(c.text);
+            let rest = lines[i][cut..].trim_start();
+            if c.text.starts_with("//") && !rest.is_empty() {
+                out.push(line);
+                line = format!("{}{}", code_indent, opts.indent_str(1));
+                cut = lines[i].len() - rest.len();
+            }
+        }
+        line.push_str(&lines[i][cut..]);
+        // A comment asking for a blank line after it must not double one
+        // that is already there
+        if line.is_empty() && out.last().is_some_and(|l| l.is_empty()) {
+            continue;
+        }
+        out.push(line);
+    }
+
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+
+    let mut res = out.join("\n");
+    res.push('\n');
+    Ok(res)
+}
+
+/// Where a comment goes that followed the parentheses `parens` in the
+/// source, looking from `from` on in the printed code
+fn skip_parens(printed: &str, from: usize, parens: &str) -> usize {
+    let mut at = from;
+    for p in parens.chars() {
+        let rest = &printed[at..];
+        let trimmed = rest.trim_start();
+        if trimmed.starts_with(p) {
+            at += rest.len() - trimmed.len() + p.len_utf8();
+        }
+    }
+    at
+}
+
+fn is_code(t: &Token) -> bool {
+    !t.is_comment() && !(t.kind == TokenKind::Symbol && (t.text == "(" || t.text == ")"))
+}
+
+fn collect_comments<'a>(src: &'a str, tokens: &[Token<'a>]) -> Vec<Comment<'a>> {
+    let mut comments = Vec::new();
+    let mut before = 0;
+    let mut parens = String::new();
+    let mut prev_end = 0;
+
+    for (i, t) in tokens.iter().enumerate() {
+        if !t.is_comment() {
+            if is_code(t) {
+                before += 1;
+                parens.clear();
+            } else {
+                parens.push_str(t.text);
+            }
+            prev_end = t.span.end;
+            continue;
+        }
+
+        let gap = &src[prev_end..t.span.start];
+        let next_start = tokens.get(i + 1).map_or(src.len(), |n| n.span.start);
+        let gap_after = &src[t.span.end..next_start];
+
+        comments.push(Comment {
+            text: t.text.trim_end(),
+            before,
+            parens: parens.clone(),
+            own_line: prev_end == 0 || gap.contains('\n'),
+            blank_before: prev_end > 0 && gap.matches('\n').count() > 1,
+            blank_after: i + 1 < tokens.len() && gap_after.matches('\n').count() > 1,
+        });
+        prev_end = t.span.end;
+    }
+
+    comments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "\
+module M;
+// A class
+class C /* no params */ {
+    Unit m() {
+        Int x = 1; // one
+        Int y = 2; // two
+
+        /* swap */
+        x = bar(y, // first
+            x); // second
+        y = (x + /* inner */ y);
+    }
+}
+";
+
+    fn format(src: &str) -> String {
+        format_source(src, &FormatOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let once = format(SRC);
+        assert_eq!(format(&once), once);
+    }
+
+    #[test]
+    fn comments_stay_next_to_their_code() {
+        let out = format(SRC);
+        let comments: Vec<_> = tokenize(&out)
+            .unwrap()
+            .into_iter()
+            .filter(|t| t.is_comment())
+            .map(|t| t.text.trim_end().to_string())
+            .collect();
+        assert_eq!(
+            comments,
+            [
+                "// A class",
+                "/* no params */",
+                "// one",
+                "// two",
+                "/* swap */",
+                "// first",
+                "// second",
+                "/* inner */"
+            ]
+        );
+
+        assert!(out.contains("class C /* no params */ {"));
+        assert!(out.contains("Int x = 1; // one\n"));
+        assert!(out.contains("Int y = 2; // two\n"));
+        assert!(out.contains("x = bar(y, // first\n"));
+        assert!(out.contains("x); // second\n"));
+        assert!(out.contains("y = x + /* inner */ y;"));
+        assert!(out.contains("\n\n\t\t/* swap */\n"));
+    }
+
+    #[test]
+    fn older_and_rarer_forms_are_kept() {
+        let src = "module M;\n\n{\n\tcase x {\n\t\t_ => skip;\n\t}\n\tString s = `a $x$ b \\$ $x + 1$`;\n\tFut<Int> f = destiny;\n}\n";
+        assert_eq!(format(src), src);
+    }
+
+    #[test]
+    fn partial_function_applications_are_kept() {
+        let src = "module M;\n\n{\n\tList<Int> l = map(inc, Prelude.id)(list[1]);\n\tList<Int> m = map((Int x, Int y) => x + y)(l, 2);\n\tInt n = f(x)(y);\n}\n";
+        assert_eq!(format(src), src);
+    }
+}
@@ -1,2 +1,5 @@
 pub mod ast;
-pub(crate) mod fmt;
+#[cfg(feature = "serde")]
+pub mod dump;
+pub mod fmt;
+pub mod parse;
@@ -0,0 +1,235 @@
+use std::fmt;
+
+use super::ParseError;
+
+/// Reserved words of ABS, including those of the SPL extension
+pub const KEYWORDS: &[&str] = &[
+    "adds",
+    "after",
+    "as",
+    "assert",
+    "await",
+    "builtin",
+    "case",
+    "catch",
+    "class",
+    "core",
+    "data",
+    "def",
+    "delta",
+    "destiny",
+    "die",
+    "duration",
+    "else",
+    "exception",
+    "export",
+    "extends",
+    "features",
+    "finally",
+    "foreach",
+    "from",
+    "get",
+    "hasField",
+    "hasInterface",
+    "hasMethod",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "interface",
+    "let",
+    "local",
+    "modifies",
+    "module",
+    "movecogto",
+    "new",
+    "null",
+    "original",
+    "product",
+    "productline",
+    "recover",
+    "removes",
+    "return",
+    "skip",
+    "suspend",
+    "switch",
+    "then",
+    "this",
+    "throw",
+    "trait",
+    "try",
+    "type",
+    "uses",
+    "when",
+    "while",
+];
+
+pub fn is_keyword(s: &str) -> bool {
+    KEYWORDS.contains(&s)
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TokenKind {
+    Ident,
+    Keyword,
+    IntLit,
+    FloatLit,
+    StringLit,
+    /// The text of a template string from its start or the end of an
+    /// expression in it to its end or the start of the next expression, with
+    /// these delimiters, e.g. `` `Hello $`` and `` $!` ``
+    TemplatePart,
+    /// Operators and punctuation
+    Symbol,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+impl Token<'_> {
+    pub fn is_comment(&self) -> bool {
+        self.kind == TokenKind::LineComment || self.kind == TokenKind::BlockComment
+    }
+
+    pub fn is(&self, text: &str) -> bool {
+        self.kind != TokenKind::StringLit
+            && self.kind != TokenKind::TemplatePart
+            && !self.is_comment()
+            && self.text == text
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.text, f)
+    }
+}
+
+/// Longest symbols first, so `==` is not read as two `=`
+const SYMBOLS: &[&str] = &[
+    "=>", "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", "[", "]", "<", ">", ",", ";",
+    ":", ".", "!", "?", "=", "+", "-", "*", "/", "%", "&", "|", "_",
+];
+
+/// Splits `src` into tokens, including comments
+pub fn tokenize(src: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    // Whether the tokens are those of an expression in a template string
+    let mut in_template = false;
+
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        let start = pos;
+
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+
+        let kind = if src[pos..].starts_with("//") {
+            pos = src[pos..].find('\n').map_or(bytes.len(), |i| pos + i);
+            TokenKind::LineComment
+        } else if src[pos..].starts_with("/*") {
+            match src[pos + 2..].find("*/") {
+                Some(i) => pos += i + 4,
+                None => return Err(ParseError::new(src, start, "Unterminated comment")),
+            }
+            TokenKind::BlockComment
+        } else if c.is_ascii_alphabetic() || (c == b'_' && is_ident_char(bytes, pos + 1)) {
+            while is_ident_char(bytes, pos) {
+                pos += 1;
+            }
+            if is_keyword(&src[start..pos]) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Ident
+            }
+        } else if c.is_ascii_digit() {
+            while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                pos += 1;
+            }
+            if pos + 1 < bytes.len() && bytes[pos] == b'.' && bytes[pos + 1].is_ascii_digit() {
+                pos += 1;
+                while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                    pos += 1;
+                }
+                TokenKind::FloatLit
+            } else {
+                TokenKind::IntLit
+            }
+        } else if c == b'"' {
+            pos += 1;
+            loop {
+                match bytes.get(pos) {
+                    None => return Err(ParseError::new(src, start, "Unterminated string")),
+                    Some(b'\\') => pos += 2,
+                    Some(b'"') => {
+                        pos += 1;
+                        break;
+                    }
+                    Some(_) => pos += 1,
+                }
+            }
+            TokenKind::StringLit
+        } else if c == b'`' || (c == b'$' && in_template) {
+            pos += 1;
+            loop {
+                match bytes.get(pos) {
+                    None => {
+                        return Err(ParseError::new(src, start, "Unterminated template string"))
+                    }
+                    Some(b'\\') => pos += 2,
+                    Some(b'$') => {
+                        pos += 1;
+                        in_template = true;
+                        break;
+                    }
+                    Some(b'`') => {
+                        pos += 1;
+                        in_template = false;
+                        break;
+                    }
+                    Some(_) => pos += 1,
+                }
+            }
+            TokenKind::TemplatePart
+        } else if let Some(s) = SYMBOLS.iter().find(|s| src[pos..].starts_with(*s)) {
+            pos += s.len();
+            TokenKind::Symbol
+        } else {
+            let c = src[pos..].chars().next().unwrap();
+            return Err(ParseError::new(
+                src,
+                start,
+                format!("Unexpected character `{}`", c),
+            ));
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &src[start..pos],
+            span: Span { start, end: pos },
+        });
+    }
+
+    Ok(tokens)
+}
+
+fn is_ident_char(bytes: &[u8], pos: usize) -> bool {
+    pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_')
+}
@@ -0,0 +1,58 @@
+use std::{error, fmt};
+
+use crate::ast;
+
+mod lexer;
+mod parser;
+
+pub use lexer::{is_keyword, tokenize, Span, Token, TokenKind, KEYWORDS};
+
+use parser::Parser;
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(src: &str, offset: usize, msg: S) -> Self {
+        let before = &src[..offset.min(src.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        ParseError {
+            offset,
+            line,
+            col,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.msg)
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Parses a file containing one or more modules
+pub fn parse_modules(src: &str) -> Result<Vec<ast::Module>, ParseError> {
+    Parser::new(src)?.modules()
+}
+
+/// Parses a file containing exactly one module
+pub fn parse_module(src: &str) -> Result<ast::Module, ParseError> {
+    let mut modules = parse_modules(src)?;
+    match modules.len() {
+        1 => Ok(modules.pop().unwrap()),
+        n => Err(ParseError::new(
+            src,
+            0,
+            format!("Expected exactly one module, found {}", n),
+        )),
+    }
+}
@@ -0,0 +1,1125 @@
+use crate::ast;
+
+use super::{
+    lexer::{tokenize, Token, TokenKind},
+    ParseError,
+};
+
+type PResult<T> = Result<T, ParseError>;
+
+pub(super) struct Parser<'a> {
+    src: &'a str,
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+fn ident(t: &Token) -> ast::Ident {
+    ast::Ident {
+        str: t.text.to_string(),
+        span: Some(t.span),
+    }
+}
+
+fn starts_upper(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_ascii_uppercase())
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(src: &'a str) -> PResult<Self> {
+        let tokens = tokenize(src)?
+            .into_iter()
+            .filter(|t| !t.is_comment())
+            .collect();
+        Ok(Parser {
+            src,
+            tokens,
+            pos: 0,
+        })
+    }
+
+    // Token handling
+
+    fn peek_n(&self, n: usize) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos + n)
+    }
+
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.peek_n(0)
+    }
+
+    fn at(&self, text: &str) -> bool {
+        self.at_n(0, text)
+    }
+
+    fn at_n(&self, n: usize, text: &str) -> bool {
+        self.peek_n(n).is_some_and(|t| t.is(text))
+    }
+
+    fn at_kind(&self, n: usize, kind: TokenKind) -> bool {
+        self.peek_n(n).is_some_and(|t| t.kind == kind)
+    }
+
+    fn at_eof(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn bump(&mut self) -> Token<'a> {
+        let t = self.tokens[self.pos].clone();
+        self.pos += 1;
+        t
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        if self.at(text) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error<S: Into<String>>(&self, msg: S) -> ParseError {
+        let offset = self.peek().map_or(self.src.len(), |t| t.span.start);
+        ParseError::new(self.src, offset, msg)
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(t) => self.error(format!("Expected {}, found `{}`", expected, t)),
+            None => self.error(format!("Expected {}, found end of file", expected)),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> PResult<Token<'a>> {
+        if self.at(text) {
+            Ok(self.bump())
+        } else {
+            Err(self.unexpected(&format!("`{}`", text)))
+        }
+    }
+
+    fn at_ident(&self, n: usize) -> bool {
+        self.at_kind(n, TokenKind::Ident)
+    }
+
+    fn at_lower_ident(&self, n: usize) -> bool {
+        self.at_ident(n) && !starts_upper(self.peek_n(n).unwrap().text)
+    }
+
+    fn at_upper_ident(&self, n: usize) -> bool {
+        self.at_ident(n) && starts_upper(self.peek_n(n).unwrap().text)
+    }
+
+    fn simple_ident(&mut self) -> PResult<ast::Ident> {
+        if self.at_ident(0) {
+            Ok(ident(&self.bump()))
+        } else {
+            Err(self.unexpected("an identifier"))
+        }
+    }
+
+    /// `A.B.c`: module prefixes start with an upper case letter and are
+    /// directly followed by a dot
+    fn qualified_ident(&mut self) -> PResult<ast::Ident> {
+        let mut id = self.simple_ident()?;
+        while starts_upper(&id.str[id.str.rfind('.').map_or(0, |i| i + 1)..])
+            && self.at(".")
+            && self.at_ident(1)
+        {
+            self.pos += 1;
+            let t = self.bump();
+            id.str.push('.');
+            id.str.push_str(t.text);
+            if let Some(span) = &mut id.span {
+                span.end = t.span.end;
+            }
+        }
+        Ok(id)
+    }
+
+    fn comma_list<T, F>(&mut self, close: &str, mut f: F) -> PResult<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> PResult<T>,
+    {
+        let mut v = Vec::new();
+        if self.eat(close) {
+            return Ok(v);
+        }
+        loop {
+            v.push(f(self)?);
+            if self.eat(close) {
+                return Ok(v);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    // Declarations
+
+    pub fn modules(mut self) -> PResult<Vec<ast::Module>> {
+        let mut modules = Vec::new();
+        while !self.at_eof() {
+            modules.push(self.module()?);
+        }
+        Ok(modules)
+    }
+
+    fn module(&mut self) -> PResult<ast::Module> {
+        self.expect("module")?;
+        let name = self.qualified_ident()?;
+        self.expect(";")?;
+
+        let mut children = Vec::new();
+        while !self.at_eof() && !self.at("module") {
+            children.push(self.module_item()?);
+        }
+
+        Ok(ast::Module { name, children })
+    }
+
+    fn module_item(&mut self) -> PResult<ast::ModuleItem> {
+        if self.at("import") || self.at("export") {
+            return self.import_export();
+        }
+        if self.at("{") {
+            return Ok(ast::ModuleItem::MainBlock(self.block()?));
+        }
+
+        let annotations = self.annotations()?;
+
+        let item = match self.peek().map(|t| t.text) {
+            Some("class") => self.class_decl(annotations)?.into(),
+            Some("def") => self.function_decl(annotations)?.into(),
+            _ if !annotations.is_empty() => {
+                return Err(self.unexpected("an annotated class or function"))
+            }
+            Some("data") => self.data_type_decl()?.into(),
+            Some("type") => self.type_syn_decl()?.into(),
+            Some("exception") => self.exception_decl()?.into(),
+            Some("interface") => self.interface_decl()?.into(),
+            Some(t @ "delta")
+            | Some(t @ "productline")
+            | Some(t @ "product")
+            | Some(t @ "trait") => {
+                return Err(self.error(format!("`{}` declarations are not supported", t)))
+            }
+            _ => return Err(self.unexpected("a declaration")),
+        };
+
+        Ok(item)
+    }
+
+    fn import_export(&mut self) -> PResult<ast::ModuleItem> {
+        let import = self.bump().text == "import";
+
+        let star = self.eat("*");
+        let mut idents = Vec::new();
+        if !star {
+            idents.push(self.qualified_ident()?);
+            while self.eat(",") {
+                idents.push(self.qualified_ident()?);
+            }
+        }
+        let from = if self.eat("from") {
+            Some(self.qualified_ident()?)
+        } else {
+            None
+        };
+        self.expect(";")?;
+
+        Ok(if import {
+            ast::ImportDecl { star, idents, from }.into()
+        } else {
+            ast::ExportDecl { star, idents, from }.into()
+        })
+    }
+
+    fn data_type_decl(&mut self) -> PResult<ast::DataTypeDecl> {
+        self.expect("data")?;
+        let ident = self.qualified_ident()?;
+        let params = self.type_params()?;
+
+        let mut constr = Vec::new();
+        if self.eat("=") {
+            constr.push(self.data_constr()?);
+            while self.eat("|") {
+                constr.push(self.data_constr()?);
+            }
+        }
+        self.expect(";")?;
+
+        Ok(ast::DataTypeDecl {
+            ident,
+            params,
+            constr,
+        })
+    }
+
+    fn data_constr(&mut self) -> PResult<ast::DataConstr> {
+        let ident = self.qualified_ident()?;
+        let params = self.data_constr_params()?;
+        Ok(ast::DataConstr { ident, params })
+    }
+
+    fn data_constr_params(&mut self) -> PResult<Vec<ast::DataConstrParam>> {
+        if !self.eat("(") {
+            return Ok(Vec::new());
+        }
+        self.comma_list(")", |p| {
+            let ty = p.ty()?;
+            let ident = if p.at_ident(0) {
+                Some(p.simple_ident()?)
+            } else {
+                None
+            };
+            Ok(ast::DataConstrParam { ty, ident })
+        })
+    }
+
+    fn type_params(&mut self) -> PResult<Vec<ast::Ident>> {
+        if self.eat("<") {
+            self.comma_list(">", Self::simple_ident)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn type_syn_decl(&mut self) -> PResult<ast::TypeSynDecl> {
+        self.expect("type")?;
+        let ident = self.qualified_ident()?;
+        self.expect("=")?;
+        let ty = self.ty()?;
+        self.expect(";")?;
+        Ok(ast::TypeSynDecl { ident, ty })
+    }
+
+    fn exception_decl(&mut self) -> PResult<ast::ExceptionDecl> {
+        self.expect("exception")?;
+        let ident = self.qualified_ident()?;
+        let params = self.data_constr_params()?;
+        self.expect(";")?;
+        Ok(ast::ExceptionDecl { ident, params })
+    }
+
+    fn function_decl(&mut self, annotations: ast::Annotations) -> PResult<ast::FunctionDecl> {
+        self.expect("def")?;
+        let ret = self.ty()?;
+        let ident = self.simple_ident()?;
+        let type_params = self.type_params()?;
+        self.expect("(")?;
+        let params = self.comma_list(")", Self::param)?;
+        self.expect("=")?;
+
+        let body = if self.eat("builtin") {
+            let args = if self.eat("(") {
+                self.comma_list(")", Self::pure_expr)?
+            } else {
+                Vec::new()
+            };
+            ast::FunctionBody::Builtin(args)
+        } else {
+            ast::FunctionBody::Expr(self.pure_expr()?)
+        };
+        self.expect(";")?;
+
+        Ok(ast::FunctionDecl {
+            annotations,
+            ret,
+            ident,
+            type_params,
+            params,
+            body,
+        })
+    }
+
+    fn interface_decl(&mut self) -> PResult<ast::InterfaceDecl> {
+        self.expect("interface")?;
+        let ident = self.qualified_ident()?;
+
+        let mut extends = Vec::new();
+        if self.eat("extends") {
+            extends.push(self.qualified_ident()?);
+            while self.eat(",") {
+                extends.push(self.qualified_ident()?);
+            }
+        }
+
+        self.expect("{")?;
+        let mut sigs = Vec::new();
+        while !self.eat("}") {
+            let annotations = self.annotations()?;
+            sigs.push(self.method_sig(annotations)?);
+            self.expect(";")?;
+        }
+
+        Ok(ast::InterfaceDecl {
+            ident,
+            extends,
+            sigs,
+        })
+    }
+
+    fn class_decl(&mut self, annotations: ast::Annotations) -> PResult<ast::ClassDecl> {
+        self.expect("class")?;
+        let ident = self.qualified_ident()?;
+
+        let params = if self.eat("(") {
+            self.comma_list(")", Self::param)?
+        } else {
+            Vec::new()
+        };
+
+        let mut implements = Vec::new();
+        if self.eat("implements") {
+            implements.push(self.qualified_ident()?);
+            while self.eat(",") {
+                implements.push(self.qualified_ident()?);
+            }
+        }
+
+        self.expect("{")?;
+
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        let mut init = None;
+        let mut recover = Vec::new();
+
+        // Fields come first, then the init block, the recover block and the
+        // methods
+        loop {
+            if self.eat("}") {
+                break;
+            }
+
+            if self.at("{") {
+                if init.is_some() || !recover.is_empty() || !methods.is_empty() {
+                    return Err(self.error("Unexpected init block"));
+                }
+                init = Some(self.block()?);
+                continue;
+            }
+
+            if self.eat("recover") {
+                if !recover.is_empty() || !methods.is_empty() {
+                    return Err(self.error("Unexpected recover block"));
+                }
+                self.expect("{")?;
+                while !self.eat("}") {
+                    recover.push(self.case_branch(Self::stmt)?);
+                }
+                continue;
+            }
+
+            let annotations = self.annotations()?;
+            let ty = self.ty()?;
+            let name = self.simple_ident()?;
+
+            if self.at("(") {
+                let sig = self.method_sig_rest(annotations, ty, name)?;
+                let body = self.block()?;
+                methods.push(ast::MethodDecl { sig, body });
+            } else {
+                if init.is_some() || !recover.is_empty() || !methods.is_empty() {
+                    return Err(self.error("Fields have to be declared before methods"));
+                }
+                let init = if self.eat("=") {
+                    Some(self.pure_expr()?)
+                } else {
+                    None
+                };
+                self.expect(";")?;
+                fields.push(ast::FieldDecl {
+                    annotations,
+                    ty,
+                    ident: name,
+                    init,
+                });
+            }
+        }
+
+        Ok(ast::ClassDecl {
+            annotations,
+            ident,
+            params,
+            implements,
+            fields,
+            init,
+            recover,
+            methods,
+        })
+    }
+
+    fn method_sig(&mut self, annotations: ast::Annotations) -> PResult<ast::MethodSig> {
+        let ret = self.ty()?;
+        let name = self.simple_ident()?;
+        self.method_sig_rest(annotations, ret, name)
+    }
+
+    fn method_sig_rest(
+        &mut self,
+        annotations: ast::Annotations,
+        ret: ast::Type,
+        ident: ast::Ident,
+    ) -> PResult<ast::MethodSig> {
+        self.expect("(")?;
+        let params = self.comma_list(")", Self::param)?;
+        Ok(ast::MethodSig {
+            annotations,
+            ret,
+            ident,
+            params,
+        })
+    }
+
+    fn param(&mut self) -> PResult<ast::Param> {
+        let annotations = self.annotations()?;
+        let ty = self.ty()?;
+        let ident = self.simple_ident()?;
+        Ok(ast::Param {
+            annotations,
+            ty,
+            ident,
+        })
+    }
+
+    fn annotations(&mut self) -> PResult<ast::Annotations> {
+        let mut annos = ast::Annotations::default();
+        while self.eat("[") {
+            let anno = if self.at_upper_ident(0)
+                && (self.at_n(1, ":") || (self.at_n(1, ".") && self.is_typed_annotation()))
+            {
+                let ty = self.ty()?;
+                self.expect(":")?;
+                let expr = self.pure_expr()?;
+                ast::Annotation::Typed(ast::TypedAnnotation { ty, expr })
+            } else {
+                ast::Annotation::Untyped(ast::UntypedAnnotation(self.pure_expr()?))
+            };
+            self.expect("]")?;
+            annos.push(anno);
+        }
+        Ok(annos)
+    }
+
+    /// Distinguishes `[M.T: e]` from `[M.C]`
+    fn is_typed_annotation(&self) -> bool {
+        let mut n = 0;
+        while self.at_ident(n) && self.at_n(n + 1, ".") {
+            n += 2;
+        }
+        self.at_ident(n) && self.at_n(n + 1, ":")
+    }
+
+    fn ty(&mut self) -> PResult<ast::Type> {
+        let ident = self.qualified_ident()?;
+        let args = if self.eat("<") {
+            self.comma_list(">", Self::ty)?
+        } else {
+            Vec::new()
+        };
+        Ok(ast::Type { ident, args })
+    }
+
+    /// Checks whether a type followed by a variable name starts here,
+    /// without consuming anything
+    fn at_var_decl(&mut self) -> bool {
+        if !self.at_upper_ident(0) {
+            return false;
+        }
+        let start = self.pos;
+        let res = self.ty().is_ok() && self.at_lower_ident(0);
+        self.pos = start;
+        res
+    }
+
+    // Statements
+
+    fn block(&mut self) -> PResult<ast::Block> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat("}") {
+            if self.at_eof() {
+                return Err(self.unexpected("`}`"));
+            }
+            stmts.push(self.stmt()?);
+        }
+        Ok(ast::Block { stmts })
+    }
+
+    fn stmt(&mut self) -> PResult<ast::Stmt> {
+        if self.at("[") {
+            let annotations = self.annotations()?;
+            if self.at("while") {
+                return self.while_stmt(annotations);
+            }
+            if !self.at_var_decl() {
+                return Err(self.unexpected("an annotated variable declaration or loop"));
+            }
+            return self.var_decl(annotations);
+        }
+
+        let stmt = match self.peek().map(|t| (t.kind, t.text)) {
+            Some((TokenKind::Symbol, "{")) => self.block()?.into(),
+            Some((TokenKind::Keyword, "skip")) => {
+                self.bump();
+                self.expect(";")?;
+                ast::Stmt::Skip
+            }
+            Some((TokenKind::Keyword, "suspend")) => {
+                self.bump();
+                self.expect(";")?;
+                ast::Stmt::Suspend
+            }
+            Some((TokenKind::Keyword, "return")) => {
+                self.bump();
+                let expr = self.expr()?;
+                self.expect(";")?;
+                ast::ReturnStmt { expr }.into()
+            }
+            Some((TokenKind::Keyword, "assert")) => {
+                self.bump();
+                let condition = self.pure_expr()?;
+                self.expect(";")?;
+                ast::Stmt::Assert(ast::AssertStmt { condition })
+            }
+            Some((TokenKind::Keyword, "throw")) => {
+                self.bump();
+                let expr = self.pure_expr()?;
+                self.expect(";")?;
+                ast::Stmt::Throw(ast::ThrowStmt { expr })
+            }
+            Some((TokenKind::Keyword, "await")) if !self.at_await_call() => {
+                self.bump();
+                let guard = self.guard()?;
+                self.expect(";")?;
+                ast::Stmt::Await(ast::AwaitStmt { guard })
+            }
+            Some((TokenKind::Keyword, "if")) => {
+                self.bump();
+                let condition = self.paren_expr()?;
+                let then = Box::new(self.stmt()?);
+                let r#else = if self.eat("else") {
+                    Some(Box::new(self.stmt()?))
+                } else {
+                    None
+                };
+                ast::IfStmt {
+                    condition,
+                    then,
+                    r#else,
+                }
+                .into()
+            }
+            Some((TokenKind::Keyword, "while")) => self.while_stmt(ast::Annotations::default())?,
+            Some((TokenKind::Keyword, "foreach")) => {
+                self.bump();
+                self.expect("(")?;
+                let loop_var = self.simple_ident()?;
+                self.expect("in")?;
+                let iter = self.pure_expr()?;
+                self.expect(")")?;
+                let body = Box::new(self.stmt()?);
+                ast::Stmt::Foreach(ast::ForeachStmt {
+                    loop_var,
+                    iter,
+                    body,
+                })
+            }
+            Some((TokenKind::Keyword, kw @ ("switch" | "case"))) => {
+                self.bump();
+                let case = kw == "case";
+                let expr = if case {
+                    self.pure_expr()?
+                } else {
+                    self.paren_expr()?
+                };
+                self.expect("{")?;
+                let mut branches = Vec::new();
+                while !self.eat("}") {
+                    branches.push(self.case_branch(Self::stmt)?);
+                }
+                ast::Stmt::Switch(ast::SwitchStmt {
+                    expr,
+                    branches,
+                    case,
+                })
+            }
+            Some((TokenKind::Keyword, "try")) => {
+                self.bump();
+                let r#try = Box::new(self.stmt()?);
+                self.expect("catch")?;
+                let mut catch_branches = Vec::new();
+                if self.eat("{") {
+                    while !self.eat("}") {
+                        catch_branches.push(self.case_branch(Self::stmt)?);
+                    }
+                } else {
+                    catch_branches.push(self.case_branch(Self::stmt)?);
+                }
+                let finally = if self.eat("finally") {
+                    Some(Box::new(self.stmt()?))
+                } else {
+                    None
+                };
+                ast::Stmt::TryCatchFinally(ast::TryCatchFinallyStmt {
+                    r#try,
+                    catch_branches,
+                    finally,
+                })
+            }
+            _ if self.at_var_decl() => self.var_decl(ast::Annotations::default())?,
+            _ if self.at("this") && self.at_n(1, ".") && self.at_ident(2) && self.at_n(3, "=") => {
+                self.pos += 2;
+                self.assign(true)?
+            }
+            _ if self.at_lower_ident(0) && self.at_n(1, "=") => self.assign(false)?,
+            _ => {
+                let expr = self.expr()?;
+                self.expect(";")?;
+                ast::ExprStmt { expr }.into()
+            }
+        };
+
+        Ok(stmt)
+    }
+
+    fn while_stmt(&mut self, annotations: ast::Annotations) -> PResult<ast::Stmt> {
+        self.expect("while")?;
+        let condition = self.paren_expr()?;
+        let body = Box::new(self.stmt()?);
+        Ok(ast::WhileStmt {
+            annotations,
+            condition,
+            body,
+        }
+        .into())
+    }
+
+    fn var_decl(&mut self, annotations: ast::Annotations) -> PResult<ast::Stmt> {
+        let ty = self.ty()?;
+        let ident = self.simple_ident()?;
+        let init = if self.eat("=") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        self.expect(";")?;
+        Ok(ast::VarDeclStmt {
+            annotations,
+            ty,
+            ident,
+            init,
+        }
+        .into())
+    }
+
+    fn assign(&mut self, this: bool) -> PResult<ast::Stmt> {
+        let ident = self.simple_ident()?;
+        self.expect("=")?;
+        let expr = self.expr()?;
+        self.expect(";")?;
+        Ok(ast::AssignStmt { this, ident, expr }.into())
+    }
+
+    fn paren_expr(&mut self) -> PResult<ast::PureExpr> {
+        self.expect("(")?;
+        let e = self.pure_expr()?;
+        self.expect(")")?;
+        Ok(e)
+    }
+
+    fn case_branch<K, F>(&mut self, mut right: F) -> PResult<ast::CaseBranch<K>>
+    where
+        F: FnMut(&mut Self) -> PResult<K>,
+    {
+        let pattern = self.pattern()?;
+        self.expect("=>")?;
+        let right = right(self)?;
+        Ok(ast::CaseBranch { pattern, right })
+    }
+
+    fn pattern(&mut self) -> PResult<ast::Pattern> {
+        if self.eat("_") {
+            return Ok(ast::Pattern::Wildcard);
+        }
+        if self.at_kind(0, TokenKind::IntLit) || self.at_kind(0, TokenKind::StringLit) {
+            let s = self.bump().text.to_string();
+            return Ok(ast::Pattern::Literal(ast::Literal { s }));
+        }
+        if self.at_lower_ident(0) {
+            return Ok(ast::Pattern::Ident(self.simple_ident()?));
+        }
+        if self.at_upper_ident(0) {
+            let ident = self.qualified_ident()?;
+            let args = if self.eat("(") {
+                self.comma_list(")", Self::pattern)?
+            } else {
+                Vec::new()
+            };
+            return Ok(ast::Pattern::Constr { ident, args });
+        }
+        Err(self.unexpected("a pattern"))
+    }
+
+    fn guard(&mut self) -> PResult<ast::Guard> {
+        let mut g = self.guard_atom()?;
+        while self.eat("&") {
+            let r = self.guard_atom()?;
+            g = ast::Guard::And(Box::new(g), Box::new(r));
+        }
+        Ok(g)
+    }
+
+    fn guard_atom(&mut self) -> PResult<ast::Guard> {
+        if self.eat("duration") {
+            self.expect("(")?;
+            let min = self.pure_expr()?;
+            self.expect(",")?;
+            let max = self.pure_expr()?;
+            self.expect(")")?;
+            return Ok(ast::Guard::Duration(min, max));
+        }
+        if self.at_lower_ident(0) && self.at_n(1, "?") {
+            let ident = self.simple_ident()?;
+            self.bump();
+            return Ok(ast::Guard::Claim { this: false, ident });
+        }
+        if self.at("this") && self.at_n(1, ".") && self.at_ident(2) && self.at_n(3, "?") {
+            self.pos += 2;
+            let ident = self.simple_ident()?;
+            self.bump();
+            return Ok(ast::Guard::Claim { this: true, ident });
+        }
+        Ok(ast::Guard::Expr(self.pure_expr()?))
+    }
+
+    // Expressions
+
+    /// `await o!m()` as opposed to `await guard`
+    fn at_await_call(&mut self) -> bool {
+        let start = self.pos;
+        self.pos += 1;
+        let res = self.pure_expr().is_ok() && self.at("!");
+        self.pos = start;
+        res
+    }
+
+    fn expr(&mut self) -> PResult<ast::Expr> {
+        if self.eat("new") {
+            let local = self.eat("local");
+            let ty = self.qualified_ident()?;
+            self.expect("(")?;
+            let args = self.comma_list(")", Self::pure_expr)?;
+            return Ok(ast::EffExpr::New(ast::NewExpr { local, ty, args }).into());
+        }
+
+        if self.eat("await") {
+            let callee = self.pure_expr()?;
+            let call = self.async_call(callee)?;
+            return Ok(ast::EffExpr::Await(ast::AwaitExpr { call }).into());
+        }
+
+        let e = self.pure_expr()?;
+
+        if self.at("!") {
+            return Ok(ast::EffExpr::AsyncCall(self.async_call(e)?).into());
+        }
+        if self.at(".") && self.at_n(1, "get") {
+            self.pos += 2;
+            return Ok(ast::EffExpr::Get(ast::GetExpr { expr: e }).into());
+        }
+        if self.at(".") && self.at_ident(1) && self.at_n(2, "(") {
+            self.bump();
+            let method = self.simple_ident()?;
+            self.expect("(")?;
+            let args = self.comma_list(")", Self::pure_expr)?;
+            return Ok(ast::EffExpr::SyncCall(ast::SyncCallExpr {
+                callee: e,
+                method,
+                args,
+            })
+            .into());
+        }
+
+        Ok(ast::Expr::Pure(e))
+    }
+
+    fn async_call(&mut self, callee: ast::PureExpr) -> PResult<ast::AsyncCallExpr> {
+        self.expect("!")?;
+        let method = self.simple_ident()?;
+        self.expect("(")?;
+        let args = self.comma_list(")", Self::pure_expr)?;
+        Ok(ast::AsyncCallExpr {
+            callee,
+            method,
+            args,
+        })
+    }
+
+    fn pure_expr(&mut self) -> PResult<ast::PureExpr> {
+        self.binary(1)
+    }
+
+    fn binary(&mut self, min_prec: u8) -> PResult<ast::PureExpr> {
+        let mut left = self.unary()?;
+
+        while let Some(op) = self.peek().and_then(binary_op) {
+            let prec = op.precedence();
+            if prec < min_prec {
+                break;
+            }
+            self.bump();
+            let right = self.binary(prec + 1)?;
+            left = ast::BinaryExpr {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            }
+            .into();
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> PResult<ast::PureExpr> {
+        let op = if self.eat("!") {
+            ast::UnaryOp::Not
+        } else if self.eat("-") {
+            ast::UnaryOp::Minus
+        } else {
+            return self.postfix();
+        };
+        let expr = Box::new(self.unary()?);
+        Ok(ast::UnaryExpr { op, expr }.into())
+    }
+
+    fn postfix(&mut self) -> PResult<ast::PureExpr> {
+        let mut e = self.primary()?;
+        loop {
+            if self.eat("implements") {
+                let ty = self.qualified_ident()?;
+                e = ast::PureExpr::TypeCheck(ast::TypeCheckExpr {
+                    expr: Box::new(e),
+                    ty,
+                });
+            } else if self.eat("as") {
+                let ty = self.qualified_ident()?;
+                e = ast::PureExpr::TypeCast(ast::TypeCastExpr {
+                    expr: Box::new(e),
+                    ty,
+                });
+            } else {
+                return Ok(e);
+            }
+        }
+    }
+
+    /// A template string, whose parts are tokens of their own with the
+    /// tokens of the expressions between them
+    fn template_string(&mut self) -> PResult<ast::PureExpr> {
+        let mut parts = Vec::new();
+        let mut exprs = Vec::new();
+        loop {
+            if !self.at_kind(0, TokenKind::TemplatePart) {
+                return Err(self.unexpected("`$` and the rest of the template string"));
+            }
+            let text = &self.bump().text[1..];
+            if let Some(part) = text.strip_suffix('`') {
+                parts.push(part.to_string());
+                break;
+            }
+            parts.push(text[..text.len() - 1].to_string());
+            exprs.push(self.pure_expr()?);
+        }
+        Ok(ast::PureExpr::TemplateString(ast::TemplateStringExpr {
+            parts,
+            exprs,
+        }))
+    }
+
+    fn primary(&mut self) -> PResult<ast::PureExpr> {
+        let t = match self.peek() {
+            Some(t) => t.clone(),
+            None => return Err(self.unexpected("an expression")),
+        };
+
+        match t.kind {
+            TokenKind::IntLit | TokenKind::FloatLit | TokenKind::StringLit => {
+                self.bump();
+                return Ok(ast::Literal {
+                    s: t.text.to_string(),
+                }
+                .into());
+            }
+            TokenKind::TemplatePart if t.text.starts_with('`') => return self.template_string(),
+            TokenKind::Keyword => match t.text {
+                "null" => {
+                    self.bump();
+                    return Ok(ast::PureExpr::Null);
+                }
+                "destiny" => {
+                    self.bump();
+                    return Ok(ast::PureExpr::Destiny);
+                }
+                "this" => {
+                    self.bump();
+                    // `this.m(...)` is a synchronous call on `this`
+                    if self.at(".") && self.at_ident(1) && !self.at_n(2, "(") {
+                        self.bump();
+                        let ident = self.simple_ident()?;
+                        return Ok(ast::PureExpr::ThisIdent(ast::IdentExpr { ident }));
+                    }
+                    return Ok(ast::PureExpr::This);
+                }
+                "let" => return self.let_expr(),
+                "if" | "when" => {
+                    self.bump();
+                    let condition = Box::new(self.pure_expr()?);
+                    self.expect("then")?;
+                    let then = Box::new(self.pure_expr()?);
+                    self.expect("else")?;
+                    let r#else = Box::new(self.pure_expr()?);
+                    return Ok(if t.text == "if" {
+                        ast::PureExpr::If(ast::IfExpr {
+                            condition,
+                            then,
+                            r#else,
+                        })
+                    } else {
+                        ast::PureExpr::When(ast::WhenExpr {
+                            condition,
+                            then,
+                            r#else,
+                        })
+                    });
+                }
+                "case" => {
+                    self.bump();
+                    let expr = Box::new(self.pure_expr()?);
+                    self.expect("{")?;
+                    let mut branches = Vec::new();
+                    while !self.eat("}") {
+                        branches.push(self.case_branch(|p| {
+                            let e = p.pure_expr()?;
+                            p.expect(";")?;
+                            Ok(e)
+                        })?);
+                    }
+                    return Ok(ast::PureExpr::Case(ast::CaseExpr { expr, branches }));
+                }
+                _ => {}
+            },
+            TokenKind::Symbol if t.text == "(" => return self.paren_expr(),
+            TokenKind::Ident => {
+                let ident = self.qualified_ident()?;
+                let name = &ident.str[ident.str.rfind('.').map_or(0, |i| i + 1)..];
+
+                if starts_upper(name) {
+                    let args = if self.eat("(") {
+                        self.comma_list(")", Self::pure_expr)?
+                    } else {
+                        Vec::new()
+                    };
+                    return Ok(ast::DataConstrExpr { ident, args }.into());
+                }
+                if self.at_par_fn_app() {
+                    self.expect("(")?;
+                    let funcs = self.comma_list(")", Self::fn_arg)?;
+                    self.expect("(")?;
+                    let args = self.comma_list(")", Self::pure_expr)?;
+                    return Ok(ast::PureExpr::ParFnApp(ast::ParFnAppExpr {
+                        ident,
+                        funcs,
+                        args,
+                    }));
+                }
+                if self.eat("(") {
+                    let args = self.comma_list(")", Self::pure_expr)?;
+                    return Ok(ast::PureExpr::FnApp(ast::FnAppExpr { ident, args }));
+                }
+                if self.eat("[") {
+                    let args = self.comma_list("]", Self::pure_expr)?;
+                    return Ok(ast::PureExpr::VariadicFnApp(ast::FnAppExpr { ident, args }));
+                }
+                return Ok(ast::IdentExpr { ident }.into());
+            }
+            _ => {}
+        }
+
+        Err(self.unexpected("an expression"))
+    }
+
+    /// Checks whether two lists in parentheses follow, as in `f(g)(x)`,
+    /// without consuming anything
+    fn at_par_fn_app(&self) -> bool {
+        if !self.at("(") {
+            return false;
+        }
+        let mut depth = 0;
+        for (i, t) in self.tokens[self.pos..].iter().enumerate() {
+            if t.is("(") {
+                depth += 1;
+            } else if t.is(")") {
+                depth -= 1;
+                if depth == 0 {
+                    return self.at_n(i + 1, "(");
+                }
+            }
+        }
+        false
+    }
+
+    /// A function name or `(Int x) => x + 1`
+    fn fn_arg(&mut self) -> PResult<ast::FnArg> {
+        if !self.eat("(") {
+            return Ok(ast::FnArg::Named(self.qualified_ident()?));
+        }
+        let params = self.comma_list(")", Self::param)?;
+        self.expect("=>")?;
+        let body = Box::new(self.pure_expr()?);
+        Ok(ast::FnArg::Anon(ast::AnonFn { params, body }))
+    }
+
+    fn let_expr(&mut self) -> PResult<ast::PureExpr> {
+        self.expect("let")?;
+        let parens = self.eat("(");
+        let ty = self.ty()?;
+        let ident = self.simple_ident()?;
+        if parens {
+            self.expect(")")?;
+        }
+        self.expect("=")?;
+        let value = Box::new(self.pure_expr()?);
+        self.expect("in")?;
+        let inner = Box::new(self.pure_expr()?);
+        Ok(ast::PureExpr::Let(ast::LetExpr {
+            ty,
+            ident,
+            value,
+            inner,
+        }))
+    }
+}
+
+fn binary_op(t: &Token) -> Option<ast::BinaryOp> {
+    if t.kind != TokenKind::Symbol {
+        return None;
+    }
+    let op = match t.text {
+        "||" => ast::BinaryOp::Or,
+        "&&" => ast::BinaryOp::And,
+        "==" => ast::BinaryOp::Eq,
+        "!=" => ast::BinaryOp::Ne,
+        "<" => ast::BinaryOp::Lt,
+        "<=" => ast::BinaryOp::Le,
+        ">" => ast::BinaryOp::Gt,
+        ">=" => ast::BinaryOp::Ge,
+        "+" => ast::BinaryOp::Plus,
+        "-" => ast::BinaryOp::Minus,
+        "*" => ast::BinaryOp::Mult,
+        "/" => ast::BinaryOp::Div,
+        "%" => ast::BinaryOp::Mod,
+        _ => return None,
+    };
+    Some(op)
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use abs_syntax::fmt::{BraceStyle, FormatOptions, IndentStyle};
+use serde::Deserialize;
+
+const CONFIG_NAMES: &[&str] = &["absfmt.toml", ".absfmt.toml"];
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum IndentKind {
+    Tabs,
+    Spaces,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Braces {
+    SameLine,
+    NextLine,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    indent_style: Option<IndentKind>,
+    indent_width: Option<u8>,
+    max_width: Option<usize>,
+    brace_style: Option<Braces>,
+    blank_lines_between_members: Option<u8>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let s = fs::read_to_string(path)?;
+        toml::from_str(&s).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Looks for a config file in `dir` and its parents, stopping at the root
+    /// of the project
+    pub fn find(dir: &Path) -> io::Result<Option<PathBuf>> {
+        let dir = dir.canonicalize()?;
+        for d in dir.ancestors() {
+            for name in CONFIG_NAMES {
+                let p = d.join(name);
+                if p.is_file() {
+                    return Ok(Some(p));
+                }
+            }
+            if d.join(".git").exists() {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn options(&self) -> FormatOptions {
+        let mut opts = FormatOptions::default();
+        let width = self.indent_width.unwrap_or(opts.tab_width);
+
+        opts.indent_style = match self.indent_style {
+            Some(IndentKind::Spaces) => IndentStyle::Spaces(width),
+            Some(IndentKind::Tabs) | None => IndentStyle::Tabs,
+        };
+        opts.tab_width = width;
+        if let Some(w) = self.max_width {
+            opts.max_width = w;
+        }
+        if let Some(b) = self.brace_style {
+            opts.brace_style = match b {
+                Braces::SameLine => BraceStyle::SameLine,
+                Braces::NextLine => BraceStyle::NextLine,
+            };
+        }
+        if let Some(n) = self.blank_lines_between_members {
+            opts.blank_lines_between_members = n;
+        }
+        opts
+    }
+}
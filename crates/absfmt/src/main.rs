@@ -0,0 +1,173 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use abs_syntax::fmt::{format_source, FormatOptions};
+
+mod config;
+
+use config::Config;
+
+const USAGE: &str = "\
+Usage: absfmt [--check] [--config <file>] [<file or dir>...]
+
+Formats the given ABS files in place. Directories are searched for .abs
+files. Without files, or with `-`, stdin is formatted to stdout.
+
+Options:
+    --check          Do not write anything, fail if a file is not formatted
+    --config <file>  Use this config instead of looking for absfmt.toml
+    -h, --help       Print this message";
+
+struct Args {
+    check: bool,
+    config: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        check: false,
+        config: None,
+        paths: Vec::new(),
+    };
+
+    let mut it = std::env::args().skip(1);
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--check" => args.check = true,
+            "--config" => match it.next() {
+                Some(p) => args.config = Some(p.into()),
+                None => return Err("--config needs a file".to_string()),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-" => args.paths.push(a.into()),
+            _ if a.starts_with('-') => return Err(format!("Unknown option `{}`", a)),
+            _ => args.paths.push(a.into()),
+        }
+    }
+
+    Ok(args)
+}
+
+/// Collects the `.abs` files below `path`
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries: Vec<_> = fs::read_dir(path)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for p in entries {
+        if p.is_dir() {
+            collect_files(&p, files)?;
+        } else if p.extension().is_some_and(|e| e == "abs") {
+            files.push(p);
+        }
+    }
+    Ok(())
+}
+
+fn options_for(args: &Args, dir: &Path) -> io::Result<FormatOptions> {
+    let config = match &args.config {
+        Some(p) => Some(p.clone()),
+        None => Config::find(dir)?,
+    };
+    match config {
+        Some(p) => Ok(Config::load(&p)?.options()),
+        None => Ok(FormatOptions::default()),
+    }
+}
+
+fn format_stdin(args: &Args) -> io::Result<bool> {
+    let mut src = String::new();
+    io::stdin().read_to_string(&mut src)?;
+
+    let opts = options_for(args, Path::new("."))?;
+    let formatted = match format_source(&src, &opts) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("<stdin>:{}", e);
+            return Ok(false);
+        }
+    };
+
+    if args.check {
+        return Ok(formatted == src);
+    }
+    io::stdout().write_all(formatted.as_bytes())?;
+    Ok(true)
+}
+
+fn format_file(args: &Args, path: &Path) -> io::Result<bool> {
+    let src = fs::read_to_string(path)?;
+
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+    let opts = options_for(args, dir.unwrap_or_else(|| Path::new(".")))?;
+    let formatted = match format_source(&src, &opts) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}:{}", path.display(), e);
+            return Ok(false);
+        }
+    };
+
+    if formatted == src {
+        return Ok(true);
+    }
+    if args.check {
+        println!("{} is not formatted", path.display());
+        return Ok(false);
+    }
+    fs::write(path, formatted)?;
+    Ok(true)
+}
+
+fn run(args: &Args) -> io::Result<bool> {
+    if args.paths.is_empty() || args.paths.iter().any(|p| p == Path::new("-")) {
+        if args.paths.len() > 1 {
+            eprintln!("`-` cannot be combined with other files");
+            return Ok(false);
+        }
+        return format_stdin(args);
+    }
+
+    let mut files = Vec::new();
+    for p in &args.paths {
+        collect_files(p, &mut files)?;
+    }
+
+    let mut ok = true;
+    for f in &files {
+        ok &= format_file(args, f)?;
+    }
+    Ok(ok)
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+use abs_syntax::parse::parse_modules;
+
+mod passes;
+mod reduce;
+
+use reduce::Reducer;
+
+const USAGE: &str = "\
+Usage: absreduce [--out <file>] <file> <predicate> [<arg>...]
+
+Shrinks an ABS file for as long as the predicate accepts it. The predicate
+is run as `<predicate> <arg>... <candidate file>` and accepts the candidate
+by exiting with 0, e.g. a script that runs a tool on it and checks that the
+tool still fails in the same way. The smallest accepted file is written to
+<file>.min.abs, with the extension of <file> replaced.
+
+Options:
+    --out <file>  Write the smallest accepted file here
+    -h, --help    Print this message";
+
+struct Args {
+    file: PathBuf,
+    out: Option<PathBuf>,
+    /// The command and its arguments
+    predicate: Vec<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut out = None;
+    let mut rest = Vec::new();
+
+    let mut it = std::env::args().skip(1);
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "-h" | "--help" if rest.is_empty() => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--out" if rest.is_empty() => match it.next() {
+                Some(p) => out = Some(p.into()),
+                None => return Err("--out needs a file".to_string()),
+            },
+            _ if a.starts_with('-') && rest.is_empty() => {
+                return Err(format!("Unknown option `{}`", a))
+            }
+            _ => {
+                // Everything after the predicate belongs to it
+                rest.push(a);
+                if rest.len() == 2 {
+                    rest.extend(&mut it);
+                }
+            }
+        }
+    }
+
+    if rest.len() < 2 {
+        return Err("A file and a predicate are needed".to_string());
+    }
+    Ok(Args {
+        file: rest.remove(0).into(),
+        out,
+        predicate: rest,
+    })
+}
+
+/// Runs the predicate on `code`, written to `candidate`
+fn accepts(predicate: &[String], candidate: &Path, code: &str) -> Result<bool, String> {
+    fs::write(candidate, code).map_err(|e| format!("{}: {}", candidate.display(), e))?;
+    let status = Command::new(&predicate[0])
+        .args(&predicate[1..])
+        .arg(candidate)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("{}: {}", predicate[0], e))?;
+    Ok(status.success())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let src =
+        fs::read_to_string(&args.file).map_err(|e| format!("{}: {}", args.file.display(), e))?;
+    let modules = parse_modules(&src).map_err(|e| format!("{}: {}", args.file.display(), e))?;
+
+    let out = match &args.out {
+        Some(o) => o.clone(),
+        None => args.file.with_extension("min.abs"),
+    };
+    let candidate = std::env::temp_dir().join(format!("absreduce-{}.abs", process::id()));
+
+    let predicate = |code: &str| accepts(&args.predicate, &candidate, code);
+    let reduced = Reducer::new(modules, predicate).and_then(|mut reducer| {
+        if !reducer.accepted() {
+            return Err(format!(
+                "The predicate does not accept {} as printed by absreduce",
+                args.file.display()
+            ));
+        }
+        let start = reducer.code().len();
+        reducer.reduce()?;
+        Ok((start, reducer))
+    });
+    // The candidate is removed however the reduction ended
+    let _ = fs::remove_file(&candidate);
+    let (start, reducer) = reduced?;
+
+    fs::write(&out, reducer.code()).map_err(|e| format!("{}: {}", out.display(), e))?;
+    eprintln!(
+        "Reduced {} from {} to {} bytes with {} runs of the predicate, written to {}",
+        args.file.display(),
+        start,
+        reducer.code().len(),
+        reducer.runs(),
+        out.display()
+    );
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
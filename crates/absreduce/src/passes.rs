@@ -0,0 +1,280 @@
+//! The edits the reducer tries, each numbered by where it applies in the
+//! modules, in the order the modules are walked.
+//!
+//! An edit either removes elements from a list, like the classes of a
+//! module or the statements of a block, or replaces a node by a smaller one.
+//! Every edit makes the modules smaller or removes a declaration, so that
+//! the reducer cannot go on forever.
+
+use std::ops::Range;
+
+use abs_syntax::ast::{
+    self,
+    visit::{self, VisitMut},
+};
+
+/// Counts the places an edit could be made at and tells when the wanted one
+/// is reached
+struct Site {
+    wanted: usize,
+    seen: usize,
+}
+
+impl Site {
+    fn new(wanted: usize) -> Self {
+        Site { wanted, seen: 0 }
+    }
+
+    fn hit(&mut self) -> bool {
+        self.seen += 1;
+        self.seen == self.wanted + 1
+    }
+
+    /// Whether the wanted place was reached
+    fn found(&self) -> bool {
+        self.seen > self.wanted
+    }
+}
+
+/// A list whose elements can be removed
+trait Shrink {
+    fn len(&self) -> usize;
+    fn remove(&mut self, r: Range<usize>);
+}
+
+impl<T> Shrink for Vec<T> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn remove(&mut self, r: Range<usize>) {
+        self.drain(r);
+    }
+}
+
+/// Calls `f` with the `n`th list: the items of a module, the fields and the
+/// methods of a class, the signatures of an interface or the statements of
+/// a block
+struct NthList<F> {
+    site: Site,
+    f: Option<F>,
+}
+
+impl<F: FnOnce(&mut dyn Shrink)> NthList<F> {
+    fn list(&mut self, l: &mut dyn Shrink) {
+        if self.site.hit() {
+            if let Some(f) = self.f.take() {
+                f(l)
+            }
+        }
+    }
+}
+
+impl<F: FnOnce(&mut dyn Shrink)> VisitMut for NthList<F> {
+    fn visit_module(&mut self, m: &mut ast::Module) {
+        self.list(&mut m.children);
+        visit::walk_module(self, m)
+    }
+
+    fn visit_interface(&mut self, i: &mut ast::InterfaceDecl) {
+        self.list(&mut i.sigs);
+    }
+
+    fn visit_class(&mut self, c: &mut ast::ClassDecl) {
+        self.list(&mut c.fields);
+        self.list(&mut c.methods);
+        visit::walk_class(self, c)
+    }
+
+    fn visit_block(&mut self, b: &mut ast::Block) {
+        self.list(&mut b.stmts);
+        visit::walk_block(self, b)
+    }
+}
+
+fn with_list(ms: &mut [ast::Module], n: usize, f: impl FnOnce(&mut dyn Shrink)) -> bool {
+    let mut v = NthList {
+        site: Site::new(n),
+        f: Some(f),
+    };
+    ms.iter_mut().for_each(|m| v.visit_module(m));
+    v.site.found()
+}
+
+/// The length of the `n`th list, if there is one
+pub fn list_len(ms: &mut [ast::Module], n: usize) -> Option<usize> {
+    let mut len = None;
+    with_list(ms, n, |l| len = Some(l.len()));
+    len
+}
+
+/// Removes a range of elements from the `n`th list
+pub fn remove_range(ms: &mut [ast::Module], n: usize, r: Range<usize>) {
+    with_list(ms, n, |l| l.remove(r));
+}
+
+/// Replaces an `if`, `while`, `foreach`, `switch` or `try` by one of the
+/// statements in it, or splices a nested block into its block
+pub fn unwrap_stmt(ms: &mut [ast::Module], n: usize) -> bool {
+    struct Unwrap(Site);
+
+    impl VisitMut for Unwrap {
+        fn visit_block(&mut self, b: &mut ast::Block) {
+            for i in 0..b.stmts.len() {
+                if let ast::Stmt::Block(inner) = &b.stmts[i] {
+                    if self.0.hit() {
+                        let inner = inner.stmts.clone();
+                        b.stmts.splice(i..=i, inner);
+                        return;
+                    }
+                }
+            }
+            visit::walk_block(self, b)
+        }
+
+        fn visit_stmt(&mut self, s: &mut ast::Stmt) {
+            let inner: Vec<ast::Stmt> = match s {
+                ast::Stmt::If(i) => std::iter::once(&*i.then)
+                    .chain(i.r#else.as_deref())
+                    .cloned()
+                    .collect(),
+                ast::Stmt::While(w) => vec![(*w.body).clone()],
+                ast::Stmt::Foreach(f) => vec![(*f.body).clone()],
+                ast::Stmt::Switch(sw) => sw.branches.iter().map(|b| b.right.clone()).collect(),
+                ast::Stmt::TryCatchFinally(t) => std::iter::once(&*t.r#try)
+                    .chain(t.finally.as_deref())
+                    .cloned()
+                    .collect(),
+                _ => Vec::new(),
+            };
+            for i in inner {
+                if self.0.hit() {
+                    *s = i;
+                    return;
+                }
+            }
+            visit::walk_stmt(self, s)
+        }
+    }
+
+    let mut v = Unwrap(Site::new(n));
+    ms.iter_mut().for_each(|m| v.visit_module(m));
+    v.0.found()
+}
+
+/// Replaces an expression by one of its operands or branches
+pub fn sub_expr(ms: &mut [ast::Module], n: usize) -> bool {
+    struct Sub(Site);
+
+    impl VisitMut for Sub {
+        fn visit_pure_expr(&mut self, e: &mut ast::PureExpr) {
+            for c in children(e) {
+                if self.0.hit() {
+                    *e = c;
+                    return;
+                }
+            }
+            visit::walk_pure_expr(self, e)
+        }
+    }
+
+    let mut v = Sub(Site::new(n));
+    ms.iter_mut().for_each(|m| v.visit_module(m));
+    v.0.found()
+}
+
+fn children(e: &ast::PureExpr) -> Vec<ast::PureExpr> {
+    match e {
+        ast::PureExpr::Let(l) => vec![(*l.inner).clone(), (*l.value).clone()],
+        ast::PureExpr::DataConstr(ast::DataConstrExpr { args, .. })
+        | ast::PureExpr::FnApp(ast::FnAppExpr { args, .. })
+        | ast::PureExpr::VariadicFnApp(ast::FnAppExpr { args, .. }) => args.clone(),
+        ast::PureExpr::If(ast::IfExpr {
+            condition,
+            then,
+            r#else,
+        })
+        | ast::PureExpr::When(ast::WhenExpr {
+            condition,
+            then,
+            r#else,
+        }) => vec![(**then).clone(), (**r#else).clone(), (**condition).clone()],
+        ast::PureExpr::Case(c) => c
+            .branches
+            .iter()
+            .map(|b| b.right.clone())
+            .chain(std::iter::once((*c.expr).clone()))
+            .collect(),
+        ast::PureExpr::Operator(ast::OperatorExpr::Unary(u)) => vec![(*u.expr).clone()],
+        ast::PureExpr::Operator(ast::OperatorExpr::Binary(b)) => {
+            vec![(*b.left).clone(), (*b.right).clone()]
+        }
+        ast::PureExpr::TypeCheck(ast::TypeCheckExpr { expr, .. })
+        | ast::PureExpr::TypeCast(ast::TypeCastExpr { expr, .. }) => vec![(**expr).clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// Replaces the uses of a variable declared with a pure expression by that
+/// expression and removes the declaration, if the variable is not assigned
+/// to later in its block
+pub fn inline_var(ms: &mut [ast::Module], n: usize) -> bool {
+    struct Inline(Site);
+
+    impl VisitMut for Inline {
+        fn visit_block(&mut self, b: &mut ast::Block) {
+            for i in 0..b.stmts.len() {
+                let (ident, init) = match &b.stmts[i] {
+                    ast::Stmt::VarDecl(ast::VarDeclStmt {
+                        ident,
+                        init: Some(ast::Expr::Pure(e)),
+                        ..
+                    }) => (ident.str.clone(), e.clone()),
+                    _ => continue,
+                };
+                let mut rest = ast::Block {
+                    stmts: b.stmts[i + 1..].to_vec(),
+                };
+                let mut assigned = Assigned(&ident, false);
+                assigned.visit_block(&mut rest);
+                if assigned.1 || !self.0.hit() {
+                    continue;
+                }
+
+                Subst(&ident, &init).visit_block(&mut rest);
+                b.stmts.truncate(i);
+                b.stmts.extend(rest.stmts);
+                return;
+            }
+            visit::walk_block(self, b)
+        }
+    }
+
+    let mut v = Inline(Site::new(n));
+    ms.iter_mut().for_each(|m| v.visit_module(m));
+    v.0.found()
+}
+
+/// Whether a variable is assigned to
+struct Assigned<'a>(&'a str, bool);
+
+impl VisitMut for Assigned<'_> {
+    fn visit_stmt(&mut self, s: &mut ast::Stmt) {
+        if let ast::Stmt::Assign(a) = s {
+            self.1 |= !a.this && a.ident.str == self.0;
+        }
+        visit::walk_stmt(self, s)
+    }
+}
+
+/// Replaces the uses of a variable by an expression
+struct Subst<'a>(&'a str, &'a ast::PureExpr);
+
+impl VisitMut for Subst<'_> {
+    fn visit_pure_expr(&mut self, e: &mut ast::PureExpr) {
+        match e {
+            ast::PureExpr::Ident(i) if i.ident.str == self.0 => *e = self.1.clone(),
+            _ => visit::walk_pure_expr(self, e),
+        }
+    }
+}
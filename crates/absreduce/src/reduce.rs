@@ -0,0 +1,229 @@
+//! Delta debugging over the tree of a module.
+//!
+//! Lists are shrunk by removing ever smaller chunks of them, as in ddmin.
+//! The other edits are tried one place at a time. Every round tries all of
+//! them, and rounds are repeated until one no longer makes the code smaller.
+
+use std::collections::HashSet;
+
+use abs_syntax::ast;
+
+use crate::passes;
+
+pub struct Reducer<P> {
+    /// The smallest modules accepted so far, and their code
+    best: Vec<ast::Module>,
+    code: String,
+    accepted: bool,
+    predicate: P,
+    /// The code of every candidate, so that none is run twice
+    tried: HashSet<String>,
+    runs: usize,
+}
+
+fn print(ms: &[ast::Module]) -> String {
+    ms.iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The predicate fails with an error if it cannot be run at all, and the
+/// reduction stops with it
+impl<P: FnMut(&str) -> Result<bool, String>> Reducer<P> {
+    /// Runs the predicate on the modules as they are
+    pub fn new(modules: Vec<ast::Module>, mut predicate: P) -> Result<Self, String> {
+        let code = print(&modules);
+        let accepted = predicate(&code)?;
+        Ok(Reducer {
+            best: modules,
+            tried: std::iter::once(code.clone()).collect(),
+            code,
+            accepted,
+            predicate,
+            runs: 1,
+        })
+    }
+
+    /// Whether the predicate accepts the code
+    pub fn accepted(&self) -> bool {
+        self.accepted
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// How often the predicate was run
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    pub fn reduce(&mut self) -> Result<(), String> {
+        loop {
+            let size = self.code.len();
+            self.shrink_lists()?;
+            self.edit(passes::unwrap_stmt)?;
+            self.edit(passes::inline_var)?;
+            self.edit(passes::sub_expr)?;
+            if self.code.len() >= size {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Keeps the candidate if the predicate accepts it
+    fn test(&mut self, candidate: Vec<ast::Module>) -> Result<bool, String> {
+        let code = print(&candidate);
+        if !self.tried.insert(code.clone()) {
+            return Ok(false);
+        }
+        self.runs += 1;
+        if !(self.predicate)(&code)? {
+            return Ok(false);
+        }
+        self.best = candidate;
+        self.code = code;
+        Ok(true)
+    }
+
+    fn shrink_lists(&mut self) -> Result<(), String> {
+        let mut n = 0;
+        while let Some(len) = passes::list_len(&mut self.best, n) {
+            self.shrink_list(n, len)?;
+            n += 1;
+        }
+        Ok(())
+    }
+
+    /// Removes chunks of half the list, then of a quarter and so on
+    fn shrink_list(&mut self, n: usize, mut len: usize) -> Result<(), String> {
+        let mut chunk = len.div_ceil(2);
+        while chunk > 0 && len > 0 {
+            let mut start = 0;
+            while start < len {
+                let end = (start + chunk).min(len);
+                let mut candidate = self.best.clone();
+                passes::remove_range(&mut candidate, n, start..end);
+                if self.test(candidate)? {
+                    len -= end - start;
+                } else {
+                    start = end;
+                }
+            }
+            chunk /= 2;
+        }
+        Ok(())
+    }
+
+    /// Tries the edit at every place it applies to
+    fn edit(&mut self, pass: fn(&mut [ast::Module], usize) -> bool) -> Result<(), String> {
+        let mut n = 0;
+        loop {
+            let mut candidate = self.best.clone();
+            if !pass(&mut candidate, n) {
+                return Ok(());
+            }
+            // Another edit is now the `n`th one if this one was kept
+            if !self.test(candidate)? {
+                n += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abs_syntax::parse::parse_modules;
+
+    use super::*;
+
+    /// The call is nested in compound statements and gets its argument from
+    /// a variable
+    const SRC: &str = "module M;
+interface I { Unit b(Int n); }
+class A { Unit a() { skip; } }
+class C(I i) {
+    Int f = 0;
+    Unit m(Int x) {
+        Int y = x + 1;
+        this.f = 2;
+        if (x > 0) {
+            while (True) {
+                i.b(y);
+                skip;
+            }
+        } else {
+            skip;
+        }
+    }
+}
+";
+
+    fn reducer<P>(predicate: P) -> Result<Reducer<P>, String>
+    where
+        P: FnMut(&str) -> Result<bool, String>,
+    {
+        Reducer::new(parse_modules(SRC).unwrap(), predicate)
+    }
+
+    #[test]
+    fn everything_but_the_call_is_removed() {
+        let mut seen = Vec::new();
+        let mut r = reducer(|code: &str| {
+            seen.push(code.to_string());
+            Ok(code.contains("i.b(y)"))
+        })
+        .unwrap();
+        assert!(r.accepted());
+        r.reduce().unwrap();
+        assert_eq!(
+            r.code(),
+            "module M;
+
+class C(I i) {
+\tUnit m(Int x) {
+\t\ti.b(y);
+\t}
+}
+"
+        );
+        let runs = r.runs();
+        drop(r);
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), runs, "No candidate is tried twice");
+    }
+
+    #[test]
+    fn variables_are_inlined() {
+        // Only once `y` is inlined
+        let r = reducer(|code: &str| Ok(code.contains("i.b(x + 1)"))).unwrap();
+        assert!(!r.accepted());
+
+        let mut r = reducer(|code: &str| Ok(code.contains("i.b(x + 1)"))).unwrap();
+        r.reduce().unwrap();
+        assert_eq!(
+            r.code(),
+            "module M;
+
+class C(I i) {
+\tUnit m(Int x) {
+\t\ti.b(x + 1);
+\t}
+}
+"
+        );
+    }
+
+    #[test]
+    fn a_predicate_rejecting_the_printed_original_is_reported() {
+        // The printer normalizes the layout
+        let r = reducer(|code: &str| Ok(code == SRC)).unwrap();
+        assert!(!r.accepted());
+        assert_eq!(r.runs(), 1);
+
+        let err = reducer(|_: &str| Err("cannot run".to_string()));
+        assert_eq!(err.err(), Some("cannot run".to_string()));
+    }
+}